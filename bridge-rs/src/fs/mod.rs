@@ -1,22 +1,38 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+
 use crate::rpc::RpcError;
 
-pub async fn read(_params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-  Err(RpcError {
-    code: -32002,
-    message: "Filesystem access not implemented".to_string(),
-  })
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PathParams {
+  pub path: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct WriteParams {
+  pub path: String,
+  pub content: String,
+}
+
+fn parse_params<T: serde::de::DeserializeOwned>(params: serde_json::Value) -> Result<T, RpcError> {
+  serde_json::from_value(params).map_err(|e| RpcError::invalid_params(format!("Invalid params: {}", e)))
+}
+
+fn not_implemented(message: String) -> RpcError {
+  RpcError::new(-32002, format!("Filesystem access not implemented: {}", message))
+}
+
+pub async fn read(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+  let params: PathParams = parse_params(params)?;
+  Err(not_implemented(format!("can't read {}", params.path)))
 }
 
-pub async fn write(_params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-  Err(RpcError {
-    code: -32002,
-    message: "Filesystem access not implemented".to_string(),
-  })
+pub async fn write(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+  let params: WriteParams = parse_params(params)?;
+  Err(not_implemented(format!("can't write {} bytes to {}", params.content.len(), params.path)))
 }
 
-pub async fn list(_params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-  Err(RpcError {
-    code: -32002,
-    message: "Filesystem access not implemented".to_string(),
-  })
+pub async fn list(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+  let params: PathParams = parse_params(params)?;
+  Err(not_implemented(format!("can't list {}", params.path)))
 }
@@ -10,7 +10,7 @@ use axum::{
         ws::{Message, WebSocket, WebSocketUpgrade},
         State,
     },
-    http::{header, Method, StatusCode},
+    http::{header, HeaderMap, Method, StatusCode},
     response::IntoResponse,
     routing::{get, post},
     Json, Router,
@@ -21,7 +21,10 @@ use std::sync::Arc;
 use tokio::sync::{broadcast, RwLock};
 use tower_http::cors::{Any, CorsLayer};
 
-use crate::rpc;
+use crate::rpc::audit::Transport;
+use crate::rpc::policy::{self, Caller};
+use crate::rpc::Source;
+use crate::{llm, rpc};
 
 /// Default port for the HTTP server
 pub const DEFAULT_PORT: u16 = 8766;
@@ -49,6 +52,8 @@ pub struct HttpRpcResponse {
 pub struct HttpRpcErrorResponse {
     pub code: i64,
     pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
 }
 
 /// WebSocket message types (bidirectional)
@@ -88,6 +93,12 @@ pub enum WsMessage {
         level: String,
         message: String,
     },
+    /// Provider went up or down (from the health monitor)
+    #[serde(rename = "provider_status")]
+    ProviderStatus {
+        #[serde(flatten)]
+        status: serde_json::Value,
+    },
     /// Ping/pong for keepalive
     #[serde(rename = "ping")]
     Ping,
@@ -165,9 +176,19 @@ async fn health_handler() -> Json<serde_json::Value> {
     }))
 }
 
+/// `Origin` header of a request, which identifies the caller to the
+/// permission policy.
+fn request_origin(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::ORIGIN)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
 /// HTTP RPC endpoint - handles the same RPC calls as native messaging
 async fn rpc_handler(
     State(_state): State<Arc<RwLock<ServerState>>>,
+    headers: HeaderMap,
     Json(request): Json<HttpRpcRequest>,
 ) -> (StatusCode, Json<HttpRpcResponse>) {
     tracing::info!(
@@ -184,7 +205,8 @@ async fn rpc_handler(
     };
 
     // Handle the request using the same RPC handler as native messaging
-    let result = rpc::handle(internal_request).await;
+    let source = Source::new(Transport::Http, request_origin(&headers));
+    let result = rpc::handle_from(&source, internal_request).await;
 
     let response = HttpRpcResponse {
         id: request.id,
@@ -192,6 +214,7 @@ async fn rpc_handler(
         error: result.error.map(|e| HttpRpcErrorResponse {
             code: e.code,
             message: e.message,
+            data: e.data,
         }),
     };
 
@@ -201,14 +224,17 @@ async fn rpc_handler(
 /// WebSocket upgrade handler
 async fn ws_handler(
     ws: WebSocketUpgrade,
+    headers: HeaderMap,
     State(state): State<Arc<RwLock<ServerState>>>,
 ) -> impl IntoResponse {
     tracing::info!("WebSocket connection request");
-    ws.on_upgrade(move |socket| handle_websocket(socket, state))
+    // Every call on the connection is attributed to the upgrade's origin
+    let source = Source::new(Transport::WebSocket, request_origin(&headers));
+    ws.on_upgrade(move |socket| handle_websocket(socket, state, source))
 }
 
 /// Handle a WebSocket connection
-async fn handle_websocket(socket: WebSocket, state: Arc<RwLock<ServerState>>) {
+async fn handle_websocket(socket: WebSocket, state: Arc<RwLock<ServerState>>, source: Source) {
     tracing::info!("WebSocket client connected");
 
     let (mut sender, mut receiver) = socket.split();
@@ -228,10 +254,34 @@ async fn handle_websocket(socket: WebSocket, state: Arc<RwLock<ServerState>>) {
         let _ = sender.send(Message::Text(json)).await;
     }
 
+    // Provider status is what `llm.health` reports, so it only goes to
+    // clients the policy lets call that
+    let no_params = serde_json::json!({});
+    let sees_status = policy::check(&Caller::identify(&source, &no_params), "llm.health", &no_params).is_ok();
+    let mut status_rx = llm::health::subscribe();
+
     // Spawn task to forward broadcast messages to this client
     let mut send_task = tokio::spawn(async move {
         loop {
             tokio::select! {
+                // Forward provider up/down transitions
+                result = status_rx.recv() => {
+                    match result {
+                        Ok(_) if !sees_status => continue,
+                        Ok(event) => {
+                            let msg = WsMessage::ProviderStatus {
+                                status: serde_json::to_value(&event).unwrap_or_default(),
+                            };
+                            if let Ok(json) = serde_json::to_string(&msg) {
+                                if sender.send(Message::Text(json)).await.is_err() {
+                                    break;
+                                }
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    }
+                }
                 // Forward broadcast messages
                 result = broadcast_rx.recv() => {
                     match result {
@@ -259,7 +309,7 @@ async fn handle_websocket(socket: WebSocket, state: Arc<RwLock<ServerState>>) {
                 Ok(Message::Text(text)) => {
                     match serde_json::from_str::<WsMessage>(&text) {
                         Ok(msg) => {
-                            handle_ws_message(msg, &state_clone).await;
+                            handle_ws_message(msg, &state_clone, &source).await;
                         }
                         Err(e) => {
                             tracing::warn!("Invalid WebSocket message: {}", e);
@@ -298,7 +348,7 @@ async fn handle_websocket(socket: WebSocket, state: Arc<RwLock<ServerState>>) {
 }
 
 /// Handle an incoming WebSocket message
-async fn handle_ws_message(msg: WsMessage, state: &Arc<RwLock<ServerState>>) {
+async fn handle_ws_message(msg: WsMessage, state: &Arc<RwLock<ServerState>>, source: &Source) {
     match msg {
         WsMessage::Rpc { id, method, params } => {
             tracing::info!("WebSocket RPC request: {} (id: {:?})", method, id);
@@ -308,7 +358,7 @@ async fn handle_ws_message(msg: WsMessage, state: &Arc<RwLock<ServerState>>) {
 
             if is_stream {
                 // For streaming, we'll send multiple messages
-                handle_streaming_rpc(id, method, params, state).await;
+                handle_streaming_rpc(id, method, params, state, source).await;
             } else {
                 // Standard request/response
                 let internal_request = rpc::RpcRequest {
@@ -317,7 +367,7 @@ async fn handle_ws_message(msg: WsMessage, state: &Arc<RwLock<ServerState>>) {
                     params,
                 };
 
-                let result = rpc::handle(internal_request).await;
+                let result = rpc::handle_from(source, internal_request).await;
 
                 let response = WsMessage::RpcResponse {
                     id,
@@ -325,6 +375,7 @@ async fn handle_ws_message(msg: WsMessage, state: &Arc<RwLock<ServerState>>) {
                     error: result.error.map(|e| HttpRpcErrorResponse {
                         code: e.code,
                         message: e.message,
+                        data: e.data,
                     }),
                 };
 
@@ -349,6 +400,7 @@ async fn handle_streaming_rpc(
     method: String,
     params: serde_json::Value,
     state: &Arc<RwLock<ServerState>>,
+    source: &Source,
 ) {
     let request_id = id.as_str().unwrap_or("unknown").to_string();
     
@@ -363,7 +415,7 @@ async fn handle_streaming_rpc(
     };
 
     // Get the result (for now, this is non-streaming, we'll enhance later)
-    let result = rpc::handle(internal_request).await;
+    let result = rpc::handle_from(source, internal_request).await;
 
     // If it's an LLM response with content, simulate streaming by sending the content
     if let Some(ref result_value) = result.result {
@@ -408,6 +460,7 @@ async fn handle_streaming_rpc(
         error: result.error.map(|e| HttpRpcErrorResponse {
             code: e.code,
             message: e.message,
+            data: e.data,
         }),
     };
 
@@ -0,0 +1,263 @@
+//! Event loop state for a JS server.
+//!
+//! Timers live in a min-heap ordered by due time, so the server thread can
+//! sleep until the next one instead of polling. Work that runs off the JS
+//! thread (fetches, host requests) is spawned on tokio and hands back a JS
+//! promise; its result comes back over a channel and settles the promise
+//! directly. `run_server` drives all of this and parks while nothing is due.
+
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::future::Future;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use rquickjs::{Ctx, Function, Persistent, Promise, TypedArray};
+use tokio::sync::mpsc;
+
+/// What spawned work settles its promise with.
+pub enum Payload {
+    Json(serde_json::Value),
+    /// Handed to JS as a Uint8Array
+    Bytes(Vec<u8>),
+}
+
+impl From<serde_json::Value> for Payload {
+    fn from(value: serde_json::Value) -> Self {
+        Payload::Json(value)
+    }
+}
+
+/// Result of spawned work, for the promise with the same id.
+pub type Completion = (u64, Payload);
+
+/// JS side of the timer API.
+const TIMERS_JS: &str = r#"
+    (function() {
+        const native = globalThis.__timers_native;
+        delete globalThis.__timers_native;
+
+        const schedule = (repeat) => (callback, delay, ...args) => {
+            if (typeof callback !== 'function') throw new TypeError('Timer callback must be a function');
+            return native.set(() => callback(...args), Number(delay) || 0, repeat);
+        };
+        globalThis.setTimeout = schedule(false);
+        globalThis.setInterval = schedule(true);
+        globalThis.clearTimeout = (id) => native.clear(Number(id) || 0);
+        globalThis.clearInterval = globalThis.clearTimeout;
+    })();
+"#;
+
+struct Timer {
+    callback: Persistent<Function<'static>>,
+    /// Set for intervals
+    interval: Option<Duration>,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    /// (due, id) of scheduled timers; entries for cleared timers are skipped
+    queue: BinaryHeap<Reverse<(Instant, u64)>>,
+    timers: HashMap<u64, Timer>,
+    /// Resolve functions of promises waiting on spawned work
+    pending: HashMap<u64, Persistent<Function<'static>>>,
+    /// Lines written with MCP.writeLine, not yet collected
+    output: Vec<String>,
+}
+
+impl State {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+/// Timers, pending promises and output of one server. Cheap to clone; the
+/// clones installed in the JS globals share state with the server loop.
+#[derive(Clone)]
+pub struct EventLoop {
+    server_id: Rc<str>,
+    state: Rc<RefCell<State>>,
+    completion_tx: mpsc::UnboundedSender<Completion>,
+    rt: tokio::runtime::Handle,
+}
+
+impl EventLoop {
+    /// Create the loop and the receiver its spawned work reports to.
+    pub fn new(server_id: &str, rt: tokio::runtime::Handle) -> (Self, mpsc::UnboundedReceiver<Completion>) {
+        let (completion_tx, completion_rx) = mpsc::unbounded_channel();
+        let event_loop = EventLoop {
+            server_id: server_id.into(),
+            state: Rc::default(),
+            completion_tx,
+            rt,
+        };
+        (event_loop, completion_rx)
+    }
+
+    /// Install setTimeout, setInterval, clearTimeout and clearInterval.
+    pub fn install(&self, ctx: &Ctx<'_>) -> Result<(), String> {
+        let native = rquickjs::Object::new(ctx.clone()).map_err(|e| e.to_string())?;
+
+        let this = self.clone();
+        let set = Function::new(ctx.clone(), move |ctx, callback, delay: f64, repeat: bool| {
+            this.set_timer(&ctx, callback, delay, repeat)
+        });
+        native.set("set", set).map_err(|e| e.to_string())?;
+
+        let this = self.clone();
+        let clear = Function::new(ctx.clone(), move |id: u64| {
+            this.state.borrow_mut().timers.remove(&id);
+        });
+        native.set("clear", clear).map_err(|e| e.to_string())?;
+
+        ctx.globals().set("__timers_native", native).map_err(|e| e.to_string())?;
+        ctx.eval::<(), _>(TIMERS_JS).map_err(|e| e.to_string())
+    }
+
+    fn set_timer<'js>(&self, ctx: &Ctx<'js>, callback: Function<'js>, delay: f64, repeat: bool) -> u64 {
+        let delay = Duration::from_millis(delay.max(0.0) as u64);
+        let mut state = self.state.borrow_mut();
+        let id = state.next_id();
+        state.timers.insert(
+            id,
+            Timer {
+                callback: Persistent::save(ctx, callback),
+                // A zero interval would fire on every turn of the loop
+                interval: repeat.then(|| delay.max(Duration::from_millis(1))),
+            },
+        );
+        state.queue.push(Reverse((Instant::now() + delay, id)));
+        id
+    }
+
+    /// Run `work` on tokio and return a promise resolved with its result.
+    pub fn spawn<'js, F>(&self, ctx: &Ctx<'js>, work: F) -> rquickjs::Result<Promise<'js>>
+    where
+        F: Future + Send + 'static,
+        F::Output: Into<Payload>,
+    {
+        let (promise, resolve, _reject) = ctx.promise()?;
+        let id = {
+            let mut state = self.state.borrow_mut();
+            let id = state.next_id();
+            state.pending.insert(id, Persistent::save(ctx, resolve));
+            id
+        };
+        let completion_tx = self.completion_tx.clone();
+        self.rt.spawn(async move {
+            let _ = completion_tx.send((id, work.await.into()));
+        });
+        Ok(promise)
+    }
+
+    /// Resolve the promise waiting on a completion. Completions for work
+    /// started before a restart find nothing pending and are dropped.
+    pub fn complete(&self, ctx: &Ctx<'_>, (id, payload): Completion) {
+        let resolve = match self.state.borrow_mut().pending.remove(&id) {
+            Some(resolve) => resolve,
+            None => return,
+        };
+        let result = resolve.restore(ctx).and_then(|resolve| {
+            let value = match payload {
+                Payload::Json(value) => ctx.json_parse(value.to_string())?,
+                Payload::Bytes(bytes) => TypedArray::<u8>::new(ctx.clone(), bytes)?.into_value(),
+            };
+            resolve.call::<_, ()>((value,))
+        });
+        if let Err(e) = result {
+            self.report(ctx, "Promise resolution", e);
+        }
+    }
+
+    /// Fire the earliest timer due by `now`. Returns whether one fired.
+    pub fn fire_timer(&self, ctx: &Ctx<'_>, now: Instant) -> bool {
+        let (id, callback, interval) = {
+            let mut state = self.state.borrow_mut();
+            loop {
+                match state.queue.peek() {
+                    Some(Reverse((due, _))) if *due <= now => {}
+                    _ => return false,
+                }
+                let Reverse((_, id)) = state.queue.pop().unwrap();
+                let interval = match state.timers.get(&id) {
+                    Some(timer) => timer.interval,
+                    None => continue,
+                };
+                let callback = match interval {
+                    Some(_) => state.timers[&id].callback.clone(),
+                    None => state.timers.remove(&id).unwrap().callback,
+                };
+                break (id, callback, interval);
+            }
+        };
+
+        if let Err(e) = callback.restore(ctx).and_then(|callback| callback.call::<_, ()>(())) {
+            self.report(ctx, "Timer callback", e);
+        }
+
+        // Reschedule an interval unless its callback cleared it
+        if let Some(interval) = interval {
+            let mut state = self.state.borrow_mut();
+            if state.timers.contains_key(&id) {
+                state.queue.push(Reverse((Instant::now() + interval, id)));
+            }
+        }
+        true
+    }
+
+    /// When the earliest live timer is due.
+    pub fn next_timer(&self) -> Option<Instant> {
+        let mut state = self.state.borrow_mut();
+        while let Some(Reverse((due, id))) = state.queue.peek().copied() {
+            if state.timers.contains_key(&id) {
+                return Some(due);
+            }
+            state.queue.pop();
+        }
+        None
+    }
+
+    /// Record a line written with MCP.writeLine.
+    pub fn write_line(&self, line: String) {
+        self.state.borrow_mut().output.push(line);
+    }
+
+    /// Take the lines written since the last call.
+    pub fn take_output(&self) -> Vec<String> {
+        std::mem::take(&mut self.state.borrow_mut().output)
+    }
+
+    /// Drop every timer and pending promise. Must run before the runtime
+    /// they belong to is dropped. Ids keep counting, so completions from
+    /// before the reset can't settle a later promise.
+    pub fn reset(&self) {
+        let mut state = self.state.borrow_mut();
+        state.queue.clear();
+        state.timers.clear();
+        state.pending.clear();
+        state.output.clear();
+    }
+
+    fn report(&self, ctx: &Ctx<'_>, what: &str, error: rquickjs::Error) {
+        tracing::warn!("[JS:{}] {} error: {}", self.server_id, what, error_message(ctx, error));
+    }
+}
+
+/// Describe an error from a JS call, taking the pending exception's message
+/// for a thrown exception.
+pub fn error_message(ctx: &Ctx<'_>, error: rquickjs::Error) -> String {
+    if !error.is_exception() {
+        return error.to_string();
+    }
+    let exception = ctx.catch();
+    match exception.as_exception() {
+        Some(e) => e.message().unwrap_or_else(|| format!("{:?}", e)),
+        None => exception
+            .as_string()
+            .and_then(|s| s.to_string().ok())
+            .unwrap_or_else(|| format!("{:?}", exception)),
+    }
+}
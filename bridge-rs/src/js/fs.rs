@@ -0,0 +1,385 @@
+//! Sandboxed `fs` global for JS servers.
+//!
+//! Exposes promise-based `readFile`, `writeFile`, `readdir`, `stat`, `mkdir`
+//! and `unlink`. Every call is checked against the server's
+//! `FilesystemCapabilities` before touching the disk, and denied calls are
+//! logged. The check yields the canonical path, and the I/O is done on that
+//! rather than on the path JS passed in. Paths must be absolute or start with
+//! `~/`. `readFile` and `writeFile` run on tokio so large files don't block
+//! the server thread, and `readFile` refuses files over `max_read_bytes`.
+//! Errors reject with an `Error` whose `code` is a Node-style errno name
+//! (`EACCES`, `ENOENT`, ...).
+
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use rquickjs::{Ctx, Exception, Function, Object, Promise, TypedArray, Value};
+use tokio::io::AsyncReadExt;
+
+use super::event_loop::{EventLoop, Payload};
+use super::sandbox::FilesystemCapabilities;
+
+/// JS side of the API: wraps the native functions in promises and turns
+/// their Node-style messages into errors with a `code`. Work done off the
+/// JS thread resolves with `{ error }` on failure.
+const FS_JS: &str = r#"
+    (function() {
+        const native = globalThis.__fs_native;
+        delete globalThis.__fs_native;
+
+        const fail = (message) => {
+            const err = new Error(message);
+            err.code = message.split(':', 1)[0];
+            return err;
+        };
+
+        const call = (fn) => new Promise((resolve, reject) => {
+            try {
+                resolve(fn());
+            } catch (e) {
+                reject(fail(String(e && e.message || e)));
+            }
+        });
+
+        const settle = (result) => {
+            if (result !== null && typeof result === 'object' && typeof result.error === 'string') {
+                throw fail(result.error);
+            }
+            return result;
+        };
+
+        const encodingOf = (options) =>
+            typeof options === 'string' ? options : (options && options.encoding) || undefined;
+
+        const toBytes = (data) => {
+            if (typeof data === 'string' || data instanceof Uint8Array) return data;
+            if (data instanceof ArrayBuffer) return new Uint8Array(data);
+            if (ArrayBuffer.isView(data)) return new Uint8Array(data.buffer, data.byteOffset, data.byteLength);
+            return String(data);
+        };
+
+        globalThis.fs = {
+            readFile: (path, options) => call(() => native.readFile(String(path), encodingOf(options))).then(settle),
+            writeFile: (path, data) => call(() => native.writeFile(String(path), toBytes(data))).then(settle).then(() => {}),
+            readdir: (path) => call(() => native.readdir(String(path))),
+            stat: (path) => call(() => {
+                const s = native.stat(String(path));
+                return {
+                    size: s.size,
+                    mtimeMs: s.mtimeMs,
+                    isFile: () => s.isFile,
+                    isDirectory: () => s.isDirectory,
+                };
+            }),
+            mkdir: (path, options) => call(() => native.mkdir(String(path), !!(options && options.recursive))),
+            unlink: (path) => call(() => native.unlink(String(path))),
+        };
+    })();
+"#;
+
+/// Checks calls from one server against its grants.
+struct Guard {
+    server_id: String,
+    capabilities: FilesystemCapabilities,
+    event_loop: EventLoop,
+}
+
+impl Guard {
+    /// Resolve `path` and check it may be read (or written), returning the
+    /// canonical path to do the I/O on.
+    fn check(&self, op: &str, path: &str, write: bool) -> Result<PathBuf, String> {
+        let resolved = resolve(path).ok_or_else(|| {
+            format!("EINVAL: path must be absolute or start with ~/, {} '{}'", op, path)
+        })?;
+        let canonical = if write {
+            self.capabilities.check_write(&resolved)
+        } else {
+            self.capabilities.check_read(&resolved)
+        };
+        canonical.ok_or_else(|| {
+            tracing::warn!(
+                "[JS:{}] fs.{} denied: {} is not in {}",
+                self.server_id,
+                op,
+                resolved.display(),
+                if write { "write_paths" } else { "read_paths" }
+            );
+            format!("EACCES: permission denied, {} '{}'", op, path)
+        })
+    }
+}
+
+/// Expand `~/` and require an absolute path.
+fn resolve(path: &str) -> Option<PathBuf> {
+    let path = match path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir()?.join(rest),
+        None => PathBuf::from(path),
+    };
+    path.is_absolute().then_some(path)
+}
+
+/// Node-style message for an I/O error, e.g. "ENOENT: No such file..., open '/x'".
+fn io_error(op: &str, path: &str, e: std::io::Error) -> String {
+    let code = match e.kind() {
+        std::io::ErrorKind::NotFound => "ENOENT",
+        std::io::ErrorKind::PermissionDenied => "EACCES",
+        std::io::ErrorKind::AlreadyExists => "EEXIST",
+        std::io::ErrorKind::InvalidData => "EILSEQ",
+        _ => "EIO",
+    };
+    format!("{}: {}, {} '{}'", code, e, op, path)
+}
+
+fn throw<T>(ctx: &Ctx<'_>, message: String) -> rquickjs::Result<T> {
+    Err(Exception::throw_message(ctx, &message))
+}
+
+fn failed(message: String) -> Payload {
+    serde_json::json!({ "error": message }).into()
+}
+
+fn read_file<'js>(guard: &Guard, ctx: Ctx<'js>, path: String, encoding: Option<String>) -> rquickjs::Result<Promise<'js>> {
+    let utf8 = match encoding.as_deref() {
+        None => false,
+        Some("utf8" | "utf-8") => true,
+        Some(other) => return throw(&ctx, format!("EINVAL: unsupported encoding '{}'", other)),
+    };
+    let canonical = match guard.check("open", &path, false) {
+        Ok(p) => p,
+        Err(e) => return throw(&ctx, e),
+    };
+    let max_bytes = guard.capabilities.max_read_bytes;
+    guard.event_loop.spawn(&ctx, async move {
+        let bytes = match read_limited(&canonical, max_bytes).await {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => return failed(format!("EFBIG: file is larger than {} bytes, open '{}'", max_bytes, path)),
+            Err(e) => return failed(io_error("open", &path, e)),
+        };
+        if !utf8 {
+            return Payload::Bytes(bytes);
+        }
+        match String::from_utf8(bytes) {
+            Ok(text) => serde_json::Value::String(text).into(),
+            Err(e) => failed(io_error("read", &path, std::io::Error::new(std::io::ErrorKind::InvalidData, e))),
+        }
+    })
+}
+
+/// Read a whole file, or `None` if it's larger than `max_bytes` (checked
+/// while reading too, in case it grows).
+async fn read_limited(path: &Path, max_bytes: u64) -> std::io::Result<Option<Vec<u8>>> {
+    let file = tokio::fs::File::open(path).await?;
+    if file.metadata().await?.len() > max_bytes {
+        return Ok(None);
+    }
+    let mut bytes = Vec::new();
+    file.take(max_bytes + 1).read_to_end(&mut bytes).await?;
+    Ok((bytes.len() as u64 <= max_bytes).then_some(bytes))
+}
+
+fn write_file<'js>(guard: &Guard, ctx: Ctx<'js>, path: String, data: Value<'js>) -> rquickjs::Result<Promise<'js>> {
+    let canonical = match guard.check("open", &path, true) {
+        Ok(p) => p,
+        Err(e) => return throw(&ctx, e),
+    };
+    let bytes = if let Some(text) = data.as_string() {
+        text.to_string()?.into_bytes()
+    } else {
+        match TypedArray::<u8>::from_value(data).ok().and_then(|a| a.as_bytes().map(<[u8]>::to_vec)) {
+            Some(bytes) => bytes,
+            None => return throw(&ctx, "EINVAL: data must be a string or Uint8Array".to_string()),
+        }
+    };
+    guard.event_loop.spawn(&ctx, async move {
+        match tokio::fs::write(&canonical, bytes).await {
+            Ok(()) => serde_json::Value::Null.into(),
+            Err(e) => failed(io_error("open", &path, e)),
+        }
+    })
+}
+
+fn readdir(guard: &Guard, ctx: Ctx<'_>, path: String) -> rquickjs::Result<Vec<String>> {
+    let canonical = match guard.check("scandir", &path, false) {
+        Ok(p) => p,
+        Err(e) => return throw(&ctx, e),
+    };
+    let entries = match std::fs::read_dir(&canonical) {
+        Ok(entries) => entries,
+        Err(e) => return throw(&ctx, io_error("scandir", &path, e)),
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+fn stat<'js>(guard: &Guard, ctx: Ctx<'js>, path: String) -> rquickjs::Result<Object<'js>> {
+    let canonical = match guard.check("stat", &path, false) {
+        Ok(p) => p,
+        Err(e) => return throw(&ctx, e),
+    };
+    let metadata = match std::fs::metadata(&canonical) {
+        Ok(metadata) => metadata,
+        Err(e) => return throw(&ctx, io_error("stat", &path, e)),
+    };
+    let mtime_ms = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as f64)
+        .unwrap_or(0.0);
+
+    let obj = Object::new(ctx)?;
+    obj.set("size", metadata.len() as f64)?;
+    obj.set("mtimeMs", mtime_ms)?;
+    obj.set("isFile", metadata.is_file())?;
+    obj.set("isDirectory", metadata.is_dir())?;
+    Ok(obj)
+}
+
+fn mkdir(guard: &Guard, ctx: Ctx<'_>, path: String, recursive: bool) -> rquickjs::Result<()> {
+    let canonical = match guard.check("mkdir", &path, true) {
+        Ok(p) => p,
+        Err(e) => return throw(&ctx, e),
+    };
+    let result = if recursive {
+        std::fs::create_dir_all(&canonical)
+    } else {
+        std::fs::create_dir(&canonical)
+    };
+    result.or_else(|e| throw(&ctx, io_error("mkdir", &path, e)))
+}
+
+fn unlink(guard: &Guard, ctx: Ctx<'_>, path: String) -> rquickjs::Result<()> {
+    let canonical = match guard.check("unlink", &path, true) {
+        Ok(p) => p,
+        Err(e) => return throw(&ctx, e),
+    };
+    std::fs::remove_file(&canonical).or_else(|e| throw(&ctx, io_error("unlink", &path, e)))
+}
+
+/// Install the `fs` global for a server.
+pub fn install<'js>(
+    ctx: &Ctx<'js>,
+    server_id: &str,
+    capabilities: &FilesystemCapabilities,
+    event_loop: &EventLoop,
+) -> Result<(), String> {
+    let guard = Rc::new(Guard {
+        server_id: server_id.to_string(),
+        capabilities: capabilities.clone(),
+        event_loop: event_loop.clone(),
+    });
+
+    let native = Object::new(ctx.clone()).map_err(|e| e.to_string())?;
+    let set = |name: &str, f: rquickjs::Result<Function<'js>>| {
+        native.set(name, f.map_err(|e| e.to_string())?).map_err(|e| e.to_string())
+    };
+
+    let g = guard.clone();
+    set("readFile", Function::new(ctx.clone(), move |ctx, path, encoding| read_file(&g, ctx, path, encoding)))?;
+    let g = guard.clone();
+    set("writeFile", Function::new(ctx.clone(), move |ctx, path, data| write_file(&g, ctx, path, data)))?;
+    let g = guard.clone();
+    set("readdir", Function::new(ctx.clone(), move |ctx, path| readdir(&g, ctx, path)))?;
+    let g = guard.clone();
+    set("stat", Function::new(ctx.clone(), move |ctx, path| stat(&g, ctx, path)))?;
+    let g = guard.clone();
+    set("mkdir", Function::new(ctx.clone(), move |ctx, path, recursive| mkdir(&g, ctx, path, recursive)))?;
+    let g = guard;
+    set("unlink", Function::new(ctx.clone(), move |ctx, path| unlink(&g, ctx, path)))?;
+
+    ctx.globals().set("__fs_native", native).map_err(|e| e.to_string())?;
+    ctx.eval::<(), _>(FS_JS).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rquickjs::{Context, Runtime};
+
+    #[test]
+    fn test_fs_global() {
+        let root = std::env::temp_dir().join(format!("harbor-js-fs-test-{}", std::process::id()));
+        let data = root.join("data");
+        std::fs::create_dir_all(&data).unwrap();
+        std::fs::write(root.join("big.txt"), "x".repeat(17)).unwrap();
+        let caps = FilesystemCapabilities {
+            read_paths: vec![root.to_string_lossy().to_string()],
+            write_paths: vec![data.to_string_lossy().to_string()],
+            max_read_bytes: 16,
+        };
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let (event_loop, mut completion_rx) = EventLoop::new("test", rt.handle().clone());
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            install(&ctx, "test", &caps, &event_loop).unwrap();
+            let script = format!(
+                r#"
+                globalThis.result = null;
+                (async () => {{
+                    const root = {root:?};
+                    await fs.mkdir(root + '/data/sub', {{ recursive: true }});
+                    await fs.writeFile(root + '/data/sub/a.txt', 'hello');
+                    await fs.writeFile(root + '/data/sub/b.bin', new Uint8Array([1, 2, 3]));
+                    const text = await fs.readFile(root + '/data/sub/a.txt', 'utf8');
+                    const bytes = await fs.readFile(root + '/data/sub/b.bin');
+                    const names = await fs.readdir(root + '/data/sub');
+                    const stat = await fs.stat(root + '/data/sub/a.txt');
+                    await fs.unlink(root + '/data/sub/a.txt');
+                    let denied = null;
+                    try {{ await fs.writeFile(root + '/outside.txt', 'x'); }} catch (e) {{ denied = e.code; }}
+                    let missing = null;
+                    try {{ await fs.readFile(root + '/data/sub/a.txt'); }} catch (e) {{ missing = e.code; }}
+                    let tooBig = null;
+                    try {{ await fs.readFile(root + '/big.txt'); }} catch (e) {{ tooBig = e.code; }}
+                    globalThis.result = JSON.stringify({{
+                        text, bytes: Array.from(bytes), names, size: stat.size, isFile: stat.isFile(), denied, missing, tooBig,
+                    }});
+                }})();
+                "#,
+                root = root.to_string_lossy()
+            );
+            ctx.eval::<(), _>(script).unwrap();
+        });
+        // Run jobs, settling reads and writes as they complete
+        loop {
+            while runtime.is_job_pending() {
+                runtime.execute_pending_job().unwrap();
+            }
+            if context.with(|ctx| ctx.eval::<bool, _>("globalThis.result !== null").unwrap()) {
+                break;
+            }
+            let completion = rt
+                .block_on(async {
+                    tokio::time::timeout(std::time::Duration::from_secs(5), completion_rx.recv()).await
+                })
+                .unwrap()
+                .unwrap();
+            context.with(|ctx| event_loop.complete(&ctx, completion));
+        }
+
+        let result: String = context.with(|ctx| ctx.eval("globalThis.result").unwrap());
+        let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(
+            result,
+            serde_json::json!({
+                "text": "hello",
+                "bytes": [1, 2, 3],
+                "names": ["a.txt", "b.bin"],
+                "size": 5,
+                "isFile": true,
+                "denied": "EACCES",
+                "missing": "ENOENT",
+                "tooBig": "EFBIG",
+            })
+        );
+
+        event_loop.reset();
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
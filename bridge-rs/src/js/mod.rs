@@ -7,44 +7,91 @@
 //! - MCP stdio interface
 //! - MCP.requestHost (ask host to open tab / get content; bridge → extension → Web Agents)
 
+mod event_loop;
+mod fs;
+mod modules;
 mod runtime;
 mod sandbox;
+mod storage;
+mod web;
 
-pub use runtime::{JsServer, JsServerConfig, ServerHandle};
-pub use sandbox::Capabilities;
+pub use modules::ModuleBundle;
+pub use runtime::{CallError, JsServer, JsServerConfig, ServerHandle, ServerSource};
+pub use sandbox::{Capabilities, ResourceLimits};
 
 use crate::native_messaging::HostRequestSender;
-use crate::rpc::RpcError;
+use crate::rpc::{to_result, RpcError};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
+
+/// Error code for a call that pushed a server over its resource limits.
+pub const LIMIT_EXCEEDED: i64 = -32004;
 
 // Global registry of running JS servers
 lazy_static::lazy_static! {
     static ref SERVERS: Arc<RwLock<HashMap<String, ServerHandle>>> = Arc::new(RwLock::new(HashMap::new()));
+    static ref MESSAGE_TX: broadcast::Sender<ServerMessage> = {
+        let (tx, _) = broadcast::channel(100);
+        tx
+    };
+}
+
+/// Something a JS server wrote that isn't a response to a call, such as a
+/// progress or logging notification. Pushed to the extension as `server_message`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerMessage {
+    pub server_id: String,
+    pub message: serde_json::Value,
+}
+
+/// Subscribe to `server_message` events.
+pub fn subscribe() -> broadcast::Receiver<ServerMessage> {
+    MESSAGE_TX.subscribe()
+}
+
+fn publish_message(server_id: &str, message: serde_json::Value) {
+    let _ = MESSAGE_TX.send(ServerMessage {
+        server_id: server_id.to_string(),
+        message,
+    });
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct StartServerParams {
     /// Unique server ID
     pub id: String,
-    /// JavaScript code to run
+    /// JavaScript code to run as a script
+    #[serde(default)]
     pub code: String,
+    /// ES modules to run instead of `code`
+    #[serde(default)]
+    pub bundle: Option<ModuleBundle>,
     /// Environment variables to inject
     #[serde(default)]
     pub env: HashMap<String, String>,
     /// Capabilities/permissions
     #[serde(default)]
     pub capabilities: Capabilities,
+    /// Memory, stack and time limits
+    #[serde(default)]
+    pub limits: ResourceLimits,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct StopServerParams {
     pub id: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct StorageParams {
+    /// Server whose storage to use; it doesn't need to be running
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct CallServerParams {
     pub id: String,
     pub request: serde_json::Value,
@@ -53,48 +100,103 @@ pub struct CallServerParams {
     pub context: Option<serde_json::Value>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct ServerInfo {
     pub id: String,
     pub running: bool,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerStatus {
+    Running,
+    Stopped,
+}
+
+/// Result of `js.start_server` and `js.stop_server`.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ServerStatusResponse {
+    pub id: String,
+    pub status: ServerStatus,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ListServersResponse {
+    pub servers: Vec<ServerInfo>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct StorageResponse {
+    pub id: String,
+    /// Bytes taken by keys and serialized values
+    pub size_bytes: usize,
+    pub entries: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ClearStorageResponse {
+    pub id: String,
+    /// Number of keys removed
+    pub cleared: usize,
+}
+
 /// Start a new JS MCP server
 pub async fn start_server(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
     let params: StartServerParams = serde_json::from_value(params).map_err(|e| RpcError {
         code: -32602,
         message: format!("Invalid params: {}", e),
+        data: None,
     })?;
 
+    let source = match params.bundle {
+        Some(_) if !params.code.is_empty() => {
+            return Err(RpcError::invalid_params("Give either code or bundle, not both"));
+        }
+        // Loading may read a whole bundle directory, so keep it off the async threads
+        Some(bundle) => ServerSource::Bundle(
+            tokio::task::spawn_blocking(move || bundle.load())
+                .await
+                .map_err(|e| RpcError::internal(format!("Bundle load failed: {}", e)))?
+                .map_err(RpcError::invalid_params)?,
+        ),
+        None if params.code.is_empty() => {
+            return Err(RpcError::invalid_params("Missing code or bundle"));
+        }
+        None => ServerSource::Script(params.code),
+    };
+
     let mut servers = SERVERS.write().await;
     
     if servers.contains_key(&params.id) {
         return Err(RpcError {
             code: -32000,
             message: format!("Server '{}' is already running", params.id),
+            data: None,
         });
     }
 
     let config = JsServerConfig {
         id: params.id.clone(),
-        code: params.code,
+        source,
         env: params.env,
         capabilities: params.capabilities,
+        limits: params.limits,
     };
 
     let handle = JsServer::start(config).await.map_err(|e| RpcError {
         code: -32000,
         message: format!("Failed to start server: {}", e),
+        data: None,
     })?;
 
     servers.insert(params.id.clone(), handle);
 
     tracing::info!("Started JS MCP server: {}", params.id);
 
-    Ok(serde_json::json!({
-        "id": params.id,
-        "status": "running"
-    }))
+    to_result(ServerStatusResponse {
+        id: params.id,
+        status: ServerStatus::Running,
+    })
 }
 
 /// Stop a running JS MCP server
@@ -102,6 +204,7 @@ pub async fn stop_server(params: serde_json::Value) -> Result<serde_json::Value,
     let params: StopServerParams = serde_json::from_value(params).map_err(|e| RpcError {
         code: -32602,
         message: format!("Invalid params: {}", e),
+        data: None,
     })?;
 
     let mut servers = SERVERS.write().await;
@@ -109,14 +212,15 @@ pub async fn stop_server(params: serde_json::Value) -> Result<serde_json::Value,
     if let Some(handle) = servers.remove(&params.id) {
         handle.stop().await;
         tracing::info!("Stopped JS MCP server: {}", params.id);
-        Ok(serde_json::json!({
-            "id": params.id,
-            "status": "stopped"
-        }))
+        to_result(ServerStatusResponse {
+            id: params.id,
+            status: ServerStatus::Stopped,
+        })
     } else {
         Err(RpcError {
             code: -32000,
             message: format!("Server '{}' not found", params.id),
+            data: None,
         })
     }
 }
@@ -126,19 +230,21 @@ pub async fn call_server(params: serde_json::Value) -> Result<serde_json::Value,
     let params: CallServerParams = serde_json::from_value(params).map_err(|e| RpcError {
         code: -32602,
         message: format!("Invalid params: {}", e),
+        data: None,
     })?;
 
-    let servers = SERVERS.read().await;
-    
-    let handle = servers.get(&params.id).ok_or_else(|| RpcError {
+    // The lock isn't held while the call runs, so servers can be started
+    // and stopped meanwhile
+    let caller = SERVERS.read().await.get(&params.id).map(ServerHandle::caller).ok_or_else(|| RpcError {
         code: -32000,
         message: format!("Server '{}' not found", params.id),
+        data: None,
     })?;
 
-    handle.call(params.request).await.map_err(|e| RpcError {
-        code: -32000,
-        message: format!("Server call failed: {}", e),
-    })
+    caller
+        .call(params.request)
+        .await
+        .map_err(|e| call_error(&params.id, e))
 }
 
 /// Send an MCP request to a running JS server with host request capability (browser capture).
@@ -150,22 +256,36 @@ pub async fn call_server_with_host(
     let params: CallServerParams = serde_json::from_value(params).map_err(|e| RpcError {
         code: -32602,
         message: format!("Invalid params: {}", e),
+        data: None,
     })?;
 
-    let servers = SERVERS.read().await;
-    
-    let handle = servers.get(&params.id).ok_or_else(|| RpcError {
+    // The lock isn't held while the call runs, so servers can be started
+    // and stopped meanwhile
+    let caller = SERVERS.read().await.get(&params.id).map(ServerHandle::caller).ok_or_else(|| RpcError {
         code: -32000,
         message: format!("Server '{}' not found", params.id),
+        data: None,
     })?;
 
-    handle
+    caller
         .call_with_host(params.request, params.context, Some(host_request_tx))
         .await
-        .map_err(|e| RpcError {
+        .map_err(|e| call_error(&params.id, e))
+}
+
+fn call_error(server_id: &str, error: CallError) -> RpcError {
+    match error {
+        CallError::LimitExceeded(violation) => RpcError {
+            code: LIMIT_EXCEEDED,
+            message: format!("Limit exceeded: server '{}' {} and was restarted", server_id, violation),
+            data: None,
+        },
+        CallError::Failed(message) => RpcError {
             code: -32000,
-            message: format!("Server call failed: {}", e),
-        })
+            message: format!("Server call failed: {}", message),
+            data: None,
+        },
+    }
 }
 
 /// List all running JS servers
@@ -180,5 +300,49 @@ pub async fn list_servers() -> Result<serde_json::Value, RpcError> {
         })
         .collect();
 
-    Ok(serde_json::json!({ "servers": list }))
+    to_result(ListServersResponse { servers: list })
+}
+
+/// Run `f` on a server's store. Opening and saving a store read and write
+/// its file under the store's lock, so this happens on the blocking pool.
+async fn with_store<T, F>(id: &str, f: F) -> Result<T, RpcError>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut storage::Store) -> Result<T, String> + Send + 'static,
+{
+    let id = id.to_string();
+    tokio::task::spawn_blocking(move || f(&mut storage::open(&id)?.lock().unwrap()))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result)
+        .map_err(|e| RpcError::new(-32000, e))
+}
+
+/// Get everything a JS server has in its storage
+pub async fn get_storage(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+    let params: StorageParams = serde_json::from_value(params).map_err(|e| RpcError {
+        code: -32602,
+        message: format!("Invalid params: {}", e),
+        data: None,
+    })?;
+
+    let (size_bytes, entries) = with_store(&params.id, |store| Ok((store.size(), store.entries()))).await?;
+    to_result(StorageResponse {
+        id: params.id,
+        size_bytes,
+        entries,
+    })
+}
+
+/// Remove everything from a JS server's storage
+pub async fn clear_storage(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+    let params: StorageParams = serde_json::from_value(params).map_err(|e| RpcError {
+        code: -32602,
+        message: format!("Invalid params: {}", e),
+        data: None,
+    })?;
+
+    let cleared = with_store(&params.id, |store| store.clear()).await?;
+    tracing::info!("Cleared {} storage keys of JS server {}", cleared, params.id);
+    to_result(ClearStorageResponse { id: params.id, cleared })
 }
@@ -0,0 +1,298 @@
+//! ES module bundles for JS servers.
+//!
+//! A server can be started from a bundle of modules instead of a single
+//! script: sources keyed by path (or a directory under
+//! `~/.harbor/js_bundles` to read them from) plus the entry module to run.
+//! Imports resolve only within the bundle, using relative (`./util.js`,
+//! `../lib/index.js`) or bundle-absolute (`/lib/util.js`) specifiers; there
+//! is no access to the host filesystem or to bare package names. The entry runs as a module, so it can use
+//! `import`/`export` and top-level `await`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rquickjs::loader::{Loader, Resolver};
+use rquickjs::module::Declared;
+use rquickjs::{Ctx, Error, Module};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// Most files read from a bundle directory.
+const MAX_FILES: usize = 1000;
+/// Most source bytes read from a bundle directory.
+const MAX_BYTES: usize = 16 * 1024 * 1024;
+/// Extensions tried, in order, for a specifier without one.
+const EXTENSIONS: &[&str] = &[".js", ".mjs"];
+
+/// A multi-file server as given to `js.start_server`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct ModuleBundle {
+    /// Module sources by path within the bundle (e.g. `lib/util.js`)
+    #[serde(default)]
+    pub files: HashMap<String, String>,
+    /// Directory under `~/.harbor/js_bundles` to read `.js` and `.mjs` files
+    /// from, instead of `files`
+    #[serde(default)]
+    pub dir: Option<String>,
+    /// Path of the module to run
+    pub entry: String,
+}
+
+/// A loaded bundle with normalized paths.
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    files: Arc<HashMap<String, String>>,
+    entry: String,
+}
+
+impl ModuleBundle {
+    /// Directory that bundle directories are read from (`~/.harbor/js_bundles`).
+    pub fn default_root() -> PathBuf {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".harbor")
+            .join("js_bundles")
+    }
+
+    /// Read and check the bundle.
+    pub fn load(self) -> Result<Bundle, String> {
+        self.load_in(&Self::default_root())
+    }
+
+    /// Read and check the bundle, with `dir` relative to `root`.
+    pub fn load_in(self, root: &Path) -> Result<Bundle, String> {
+        let raw = match &self.dir {
+            Some(dir) if !self.files.is_empty() => {
+                return Err(format!("Bundle has both files and dir '{}'", dir));
+            }
+            Some(dir) => read_dir(&bundle_dir(root, dir)?)?,
+            None => self.files,
+        };
+
+        let mut files = HashMap::new();
+        for (path, source) in raw {
+            let normalized = normalize(&path).ok_or_else(|| format!("Invalid bundle path '{}'", path))?;
+            files.insert(normalized, source);
+        }
+        let entry = normalize(&self.entry)
+            .filter(|entry| files.contains_key(entry))
+            .ok_or_else(|| format!("Entry '{}' is not in the bundle", self.entry))?;
+
+        Ok(Bundle {
+            files: Arc::new(files),
+            entry,
+        })
+    }
+}
+
+impl Bundle {
+    /// Path of the entry module.
+    pub fn entry(&self) -> &str {
+        &self.entry
+    }
+
+    /// Source of the entry module.
+    pub fn entry_source(&self) -> &str {
+        &self.files[&self.entry]
+    }
+
+    /// Resolver and loader for a runtime, restricted to this bundle.
+    pub fn loader(&self) -> (BundleResolver, BundleLoader) {
+        (
+            BundleResolver { files: self.files.clone() },
+            BundleLoader { files: self.files.clone() },
+        )
+    }
+}
+
+/// Resolve a bundle directory name to a directory inside `root`. Absolute
+/// names, names that climb out, and symlinks that lead out are refused.
+fn bundle_dir(root: &Path, dir: &str) -> Result<PathBuf, String> {
+    let invalid = || format!("Bundle dir '{}' must be a directory under {}", dir, root.display());
+    if Path::new(dir).is_absolute() || Path::new(dir).has_root() {
+        return Err(invalid());
+    }
+    let name = normalize(dir).ok_or_else(invalid)?;
+    let root = root.canonicalize().map_err(|e| format!("Failed to read {}: {}", root.display(), e))?;
+    let path = root
+        .join(name)
+        .canonicalize()
+        .map_err(|e| format!("Failed to read bundle dir '{}': {}", dir, e))?;
+    if path == root || !path.starts_with(&root) || !path.is_dir() {
+        return Err(invalid());
+    }
+    Ok(path)
+}
+
+/// Read every `.js`/`.mjs` file under `dir`, keyed by relative path.
+/// Symlinks are skipped rather than followed.
+fn read_dir(dir: &Path) -> Result<HashMap<String, String>, String> {
+    let mut files = HashMap::new();
+    let mut total = 0;
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let entries = std::fs::read_dir(&current).map_err(|e| format!("Failed to read {}: {}", current.display(), e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| e.to_string())?;
+            let path = entry.path();
+            // DirEntry::file_type doesn't follow symlinks
+            let file_type = entry.file_type().map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            if file_type.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let is_module = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| ext == "js" || ext == "mjs");
+            if !file_type.is_file() || !is_module {
+                continue;
+            }
+            let len = entry.metadata().map_err(|e| format!("Failed to read {}: {}", path.display(), e))?.len();
+            total += len as usize;
+            if files.len() >= MAX_FILES || total > MAX_BYTES {
+                return Err(format!("Bundle directory {} is too large", dir.display()));
+            }
+            let source = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let relative = path.strip_prefix(dir).map_err(|e| e.to_string())?;
+            files.insert(relative.to_string_lossy().replace('\\', "/"), source);
+        }
+    }
+    Ok(files)
+}
+
+/// Normalize a bundle path: `/`-separated, no leading `/` or `./`, with
+/// `.` and `..` applied. `None` if it climbs out of the bundle.
+fn normalize(path: &str) -> Option<String> {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// Resolves relative and bundle-absolute specifiers against the bundle.
+pub struct BundleResolver {
+    files: Arc<HashMap<String, String>>,
+}
+
+impl Resolver for BundleResolver {
+    fn resolve<'js>(&mut self, _ctx: &Ctx<'js>, base: &str, name: &str) -> rquickjs::Result<String> {
+        let joined = if name.starts_with('/') {
+            name.to_string()
+        } else if name.starts_with("./") || name.starts_with("../") {
+            let dir = base.rsplit_once('/').map_or("", |(dir, _)| dir);
+            format!("{}/{}", dir, name)
+        } else {
+            return Err(Error::new_resolving_message(base, name, "only relative imports within the bundle are supported"));
+        };
+
+        let path = normalize(&joined).ok_or_else(|| Error::new_resolving_message(base, name, "outside the bundle"))?;
+        let candidates = std::iter::once(path.clone())
+            .chain(EXTENSIONS.iter().map(|ext| format!("{}{}", path, ext)))
+            .chain(EXTENSIONS.iter().map(|ext| format!("{}/index{}", path, ext)));
+        for candidate in candidates {
+            if self.files.contains_key(&candidate) {
+                return Ok(candidate);
+            }
+        }
+        Err(Error::new_resolving_message(base, name, "not in the bundle"))
+    }
+}
+
+/// Loads modules from the bundle's sources.
+pub struct BundleLoader {
+    files: Arc<HashMap<String, String>>,
+}
+
+impl Loader for BundleLoader {
+    fn load<'js>(&mut self, ctx: &Ctx<'js>, name: &str) -> rquickjs::Result<Module<'js, Declared>> {
+        match self.files.get(name) {
+            Some(source) => Module::declare(ctx.clone(), name, source.as_str()),
+            None => Err(Error::new_loading(name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_paths() {
+        assert_eq!(normalize("./lib/../util.js").as_deref(), Some("util.js"));
+        assert_eq!(normalize("/lib/util.js").as_deref(), Some("lib/util.js"));
+        assert_eq!(normalize("../secret.js"), None);
+
+        let bundle = ModuleBundle {
+            files: HashMap::from([
+                ("main.js".to_string(), String::new()),
+                ("./lib/util.js".to_string(), String::new()),
+                ("lib/index.mjs".to_string(), String::new()),
+            ]),
+            dir: None,
+            entry: "./main.js".to_string(),
+        }
+        .load()
+        .unwrap();
+        assert_eq!(bundle.entry(), "main.js");
+
+        let rt = rquickjs::Runtime::new().unwrap();
+        let ctx = rquickjs::Context::full(&rt).unwrap();
+        ctx.with(|ctx| {
+            let (mut resolver, _) = bundle.loader();
+            assert_eq!(resolver.resolve(&ctx, "main.js", "./lib/util.js").unwrap(), "lib/util.js");
+            assert_eq!(resolver.resolve(&ctx, "lib/util.js", "./index").unwrap(), "lib/index.mjs");
+            assert_eq!(resolver.resolve(&ctx, "lib/util.js", "/main").unwrap(), "main.js");
+            assert!(resolver.resolve(&ctx, "main.js", "../../etc/passwd").is_err());
+            assert!(resolver.resolve(&ctx, "main.js", "fs").is_err());
+        });
+    }
+
+    #[test]
+    fn test_bundle_dir() {
+        let root = std::env::temp_dir().join(format!("harbor-js-bundles-test-{}", std::process::id()));
+        let outside = root.with_extension("outside");
+        std::fs::create_dir_all(root.join("server/lib")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(root.join("server/main.js"), "import './lib/util.js';").unwrap();
+        std::fs::write(root.join("server/lib/util.js"), "").unwrap();
+        std::fs::write(outside.join("secret.js"), "").unwrap();
+
+        let load = |dir: &str| {
+            ModuleBundle {
+                files: HashMap::new(),
+                dir: Some(dir.to_string()),
+                entry: "main.js".to_string(),
+            }
+            .load_in(&root)
+        };
+        let bundle = load("server").unwrap();
+        let mut paths: Vec<_> = bundle.files.keys().cloned().collect();
+        paths.sort();
+        assert_eq!(paths, ["lib/util.js", "main.js"]);
+
+        // Only directories inside the root can be read
+        assert!(load("../outside").is_err());
+        assert!(load(&outside.to_string_lossy()).is_err());
+
+        // Symlinks are neither followed out of the root nor read as modules
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&outside, root.join("escape")).unwrap();
+            std::os::unix::fs::symlink(outside.join("secret.js"), root.join("server/secret.js")).unwrap();
+            assert!(load("escape").is_err());
+            assert!(!load("server").unwrap().files.contains_key("secret.js"));
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+        std::fs::remove_dir_all(&outside).unwrap();
+    }
+}
@@ -1,16 +1,25 @@
 //! QuickJS runtime for executing JS MCP servers.
 
-use super::sandbox::Capabilities;
+use super::event_loop::{error_message, Completion, EventLoop, Payload};
+use super::modules::Bundle;
+use super::sandbox::{Capabilities, FetchBlocked, NetworkCapabilities, OAuthCapabilities, ResourceLimits};
 use crate::native_messaging::{get_console_log_sender, ConsoleLogMessage, HostRequestSender};
-use rquickjs::{Context, Object, Runtime};
+use rquickjs::allocator::{Allocator, RustAllocator};
+use rquickjs::promise::PromiseState;
+use rquickjs::{Context, Ctx, Exception, Function, Module, Object, Persistent, Promise, Runtime, TypedArray};
 use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use tokio::sync::{mpsc, oneshot};
-
-/// A pending fetch request from JS
+use std::fmt;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, watch};
+
+/// A fetch request from JS
 #[derive(Debug, Deserialize)]
 struct FetchRequest {
-    id: u64,
     url: String,
     options: FetchOptions,
 }
@@ -19,37 +28,150 @@ struct FetchRequest {
 struct FetchOptions {
     method: Option<String>,
     headers: Option<HashMap<String, String>>,
-    body: Option<String>,
 }
 
-/// Response to inject back into JS
+/// Response head to inject back into JS; the body is read separately
 #[derive(Debug, Serialize)]
 struct FetchResponse {
     status: u16,
     #[serde(rename = "statusText")]
     status_text: String,
     headers: HashMap<String, String>,
-    body: String,
     error: Option<String>,
 }
 
+impl FetchResponse {
+    fn failed(message: String) -> Self {
+        FetchResponse {
+            status: 0,
+            status_text: String::new(),
+            headers: HashMap::new(),
+            error: Some(message),
+        }
+    }
+}
+
+/// A response body being read by JS.
+struct BodyReader {
+    response: reqwest::Response,
+    received: usize,
+}
+
+/// A fetch started by JS. Dropping it aborts the request and any read of
+/// its body.
+struct OpenFetch {
+    abort: watch::Sender<()>,
+    /// Filled in when the response arrives
+    body: Arc<tokio::sync::Mutex<Option<BodyReader>>>,
+    /// When JS last used it; bodies left unread are dropped after the
+    /// fetch timeout
+    last_used: Instant,
+}
+
+/// Makes a server's fetches under its network capabilities.
+struct Fetcher {
+    server_id: String,
+    client: reqwest::Client,
+    network: NetworkCapabilities,
+    oauth: Option<OAuthCapabilities>,
+}
+
+/// What a JS server runs.
+pub enum ServerSource {
+    /// A single script
+    Script(String),
+    /// ES modules, run from the bundle's entry
+    Bundle(Bundle),
+}
+
 /// Configuration for starting a JS server
 pub struct JsServerConfig {
     pub id: String,
-    pub code: String,
+    pub source: ServerSource,
     pub env: HashMap<String, String>,
     pub capabilities: Capabilities,
+    pub limits: ResourceLimits,
+}
+
+/// A resource limit a server went over.
+#[derive(Debug, Clone, Copy)]
+pub enum LimitViolation {
+    Time { limit_ms: u64 },
+    Memory { limit_bytes: usize },
+}
+
+impl fmt::Display for LimitViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitViolation::Time { limit_ms } => write!(f, "ran longer than its {} ms time limit", limit_ms),
+            LimitViolation::Memory { limit_bytes } => write!(f, "used up its {} byte memory limit", limit_bytes),
+        }
+    }
+}
+
+/// Why a call to a JS server failed.
+#[derive(Debug)]
+pub enum CallError {
+    /// The server went over a resource limit and was restarted
+    LimitExceeded(LimitViolation),
+    Failed(String),
+}
+
+impl From<String> for CallError {
+    fn from(message: String) -> Self {
+        CallError::Failed(message)
+    }
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CallError::LimitExceeded(violation) => write!(f, "server {}", violation),
+            CallError::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Wall-clock budget for the work in progress. QuickJS polls it through the
+/// interrupt handler, so a runaway loop is stopped mid-execution.
+#[derive(Default)]
+struct Budget {
+    deadline: Mutex<Option<Instant>>,
+    /// Set once the deadline has passed during the current work
+    exceeded: AtomicBool,
+}
+
+impl Budget {
+    fn start(&self, timeout: Duration) {
+        *self.deadline.lock().unwrap() = Some(Instant::now() + timeout);
+        self.exceeded.store(false, Ordering::Relaxed);
+    }
+
+    fn clear(&self) {
+        *self.deadline.lock().unwrap() = None;
+    }
+
+    /// True once the deadline has passed. Also the interrupt handler,
+    /// where true stops the running JS.
+    fn check(&self) -> bool {
+        let expired = self.deadline.lock().unwrap().is_some_and(|d| Instant::now() >= d);
+        if expired {
+            self.exceeded.store(true, Ordering::Relaxed);
+        }
+        expired
+    }
 }
 
 /// Handle to a running JS server
 pub struct ServerHandle {
-    request_tx: mpsc::Sender<ServerRequest>,
+    caller: ServerCaller,
     shutdown_tx: Option<oneshot::Sender<()>>,
+    thread: Option<tokio::task::JoinHandle<()>>,
 }
 
 struct ServerRequest {
     payload: serde_json::Value,
-    response_tx: oneshot::Sender<Result<serde_json::Value, String>>,
+    response_tx: oneshot::Sender<Result<serde_json::Value, CallError>>,
     /// When set, the server can use MCP.requestHost(method, params) for browser capture.
     host_request_tx: Option<HostRequestSender>,
     /// Context (origin, tabId) to attach to host_request.
@@ -59,9 +181,33 @@ struct ServerRequest {
 /// Represents a running JS MCP server
 pub struct JsServer;
 
+/// Cheap, cloneable side of a [`ServerHandle`] for sending calls, so a call
+/// can be awaited without holding on to the handle.
+#[derive(Clone)]
+pub struct ServerCaller {
+    request_tx: mpsc::Sender<ServerRequest>,
+}
+
 impl ServerHandle {
+    /// A caller for the server that can outlive a borrow of the handle
+    pub fn caller(&self) -> ServerCaller {
+        self.caller.clone()
+    }
+
+    /// Stop the server and wait for its thread to exit
+    pub async fn stop(mut self) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.await;
+        }
+    }
+}
+
+impl ServerCaller {
     /// Send an MCP request to the server and wait for response
-    pub async fn call(&self, request: serde_json::Value) -> Result<serde_json::Value, String> {
+    pub async fn call(&self, request: serde_json::Value) -> Result<serde_json::Value, CallError> {
         self.call_with_host(request, None, None).await
     }
 
@@ -71,7 +217,7 @@ impl ServerHandle {
         request: serde_json::Value,
         context: Option<serde_json::Value>,
         host_request_tx: Option<HostRequestSender>,
-    ) -> Result<serde_json::Value, String> {
+    ) -> Result<serde_json::Value, CallError> {
         let (response_tx, response_rx) = oneshot::channel();
         
         self.request_tx
@@ -88,15 +234,115 @@ impl ServerHandle {
             .await
             .map_err(|_| "Response channel closed".to_string())?
     }
+}
+
+/// Calls a server works on at once; further requests wait in the channel.
+const MAX_IN_FLIGHT: usize = 64;
+
+/// A call waiting for its response.
+struct InFlight {
+    request: ServerRequest,
+    /// The caller's own JSON-RPC id, put back on the response
+    id: serde_json::Value,
+    deadline: Instant,
+}
+
+/// What woke the server thread.
+enum Wake {
+    Request(ServerRequest),
+    Completion(Completion),
+    /// A timer or a call's deadline came due
+    Timer,
+    Shutdown,
+}
+
+/// A booted server: its runtime and context. Dropping it clears the event
+/// loop first, since persistent JS values must not outlive their runtime.
+struct Instance {
+    // Dropped in this order: the context before its runtime
+    context: Context,
+    runtime: Runtime,
+    event_loop: EventLoop,
+    /// Set while the runtime is torn down, see [`TeardownAllocator`]
+    tearing_down: Arc<AtomicBool>,
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        self.event_loop.reset();
+        self.tearing_down.store(true, Ordering::Relaxed);
+    }
+}
 
-    /// Stop the server
-    pub async fn stop(mut self) {
-        if let Some(tx) = self.shutdown_tx.take() {
-            let _ = tx.send(());
+/// Allocator of a server's runtime, which holds frees back while the
+/// runtime is torn down and does them once it is gone.
+///
+/// QuickJS frees a module that is still suspended in a top-level await
+/// before the functions that would resume it, which then release their
+/// reference to the freed module. Holding frees back keeps that memory
+/// valid until the runtime has finished freeing everything else.
+struct TeardownAllocator {
+    tearing_down: Arc<AtomicBool>,
+    held: Vec<*mut u8>,
+}
+
+unsafe impl Allocator for TeardownAllocator {
+    fn alloc(&mut self, size: usize) -> *mut u8 {
+        RustAllocator.alloc(size)
+    }
+
+    fn calloc(&mut self, count: usize, size: usize) -> *mut u8 {
+        RustAllocator.calloc(count, size)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8) {
+        if self.tearing_down.load(Ordering::Relaxed) {
+            self.held.push(ptr);
+        } else {
+            RustAllocator.dealloc(ptr);
+        }
+    }
+
+    unsafe fn realloc(&mut self, ptr: *mut u8, new_size: usize) -> *mut u8 {
+        RustAllocator.realloc(ptr, new_size)
+    }
+
+    unsafe fn usable_size(ptr: *mut u8) -> usize {
+        RustAllocator::usable_size(ptr)
+    }
+}
+
+impl Drop for TeardownAllocator {
+    fn drop(&mut self) {
+        for ptr in self.held.drain(..) {
+            unsafe { RustAllocator.dealloc(ptr) };
         }
     }
 }
 
+/// Where MCP.requestHost calls go: the extension connection of a call in
+/// flight, with that call's context (origin, tabId).
+#[derive(Clone)]
+struct HostTarget {
+    tx: HostRequestSender,
+    context: serde_json::Value,
+}
+
+/// What the native functions installed in a server's runtime share with
+/// the server loop. Survives restarts.
+#[derive(Clone)]
+struct Bindings {
+    event_loop: EventLoop,
+    fetcher: Arc<Fetcher>,
+    /// Host targets of the calls in flight, by bridge id
+    host: Rc<RefCell<HashMap<String, HostTarget>>>,
+    host_ids: Rc<Cell<u64>>,
+    /// Last id given to a call; callers pick their ids independently
+    call_ids: Rc<Cell<u64>>,
+    fetches: Rc<RefCell<HashMap<u64, OpenFetch>>>,
+    fetch_ids: Rc<Cell<u64>>,
+}
+
 impl JsServer {
     /// Start a new JS server in a background task
     pub async fn start(config: JsServerConfig) -> Result<ServerHandle, String> {
@@ -106,7 +352,7 @@ impl JsServer {
         let server_id = config.id.clone();
 
         // Spawn the JS runtime in a blocking task (QuickJS is not async)
-        tokio::task::spawn_blocking(move || {
+        let thread = tokio::task::spawn_blocking(move || {
             let result = Self::run_server(config, &mut request_rx, &mut shutdown_rx);
             if let Err(e) = result {
                 tracing::error!("JS server '{}' error: {}", server_id, e);
@@ -114,118 +360,336 @@ impl JsServer {
         });
 
         Ok(ServerHandle {
-            request_tx,
+            caller: ServerCaller { request_tx },
             shutdown_tx: Some(shutdown_tx),
+            thread: Some(thread),
         })
     }
 
+    /// The server's event loop. Each turn runs JS until it is idle, then
+    /// parks the thread until a request, a completion, a timer or a call's
+    /// deadline wakes it. Any number of calls can be in flight; responses
+    /// are matched to them by JSON-RPC id.
     fn run_server(
         config: JsServerConfig,
         request_rx: &mut mpsc::Receiver<ServerRequest>,
         shutdown_rx: &mut oneshot::Receiver<()>,
     ) -> Result<(), String> {
-        // Create QuickJS runtime
-        let runtime = Runtime::new().map_err(|e| format!("Failed to create runtime: {}", e))?;
-        let context = Context::full(&runtime).map_err(|e| format!("Failed to create context: {}", e))?;
-
-        context.with(|ctx| {
-            // Set up the sandbox environment
-            Self::setup_sandbox(&ctx, &config.env, &config.capabilities)?;
+        let rt = tokio::runtime::Handle::current();
+        let (event_loop, mut completion_rx) = EventLoop::new(&config.id, rt.clone());
+        let bindings = Bindings {
+            event_loop,
+            fetcher: Arc::new(Fetcher {
+                server_id: config.id.clone(),
+                client: config.capabilities.network.http_client()?,
+                network: config.capabilities.network.clone(),
+                oauth: config.capabilities.oauth.clone(),
+            }),
+            host: Rc::default(),
+            host_ids: Rc::default(),
+            call_ids: Rc::default(),
+            fetches: Rc::default(),
+            fetch_ids: Rc::default(),
+        };
+        let budget = Arc::new(Budget::default());
+        let timeout = Duration::from_millis(config.limits.call_timeout_ms);
+        let mut instance = Self::boot(&config, &bindings, &budget)?;
 
-            // Execute the server code
-            ctx.eval::<(), _>(config.code.as_str())
-                .map_err(|e| format!("Failed to execute server code: {}", e))?;
+        // Calls waiting for their response, by bridge id
+        let mut in_flight: HashMap<String, InFlight> = HashMap::new();
 
-            Ok::<(), String>(())
-        })?;
+        loop {
+            let next_deadline = in_flight.values().map(|call| call.deadline).min();
+            let wake_at = match (bindings.event_loop.next_timer(), next_deadline) {
+                (Some(timer), Some(deadline)) => Some(timer.min(deadline)),
+                (timer, deadline) => timer.or(deadline),
+            };
+            let wake = rt.block_on(async {
+                tokio::select! {
+                    biased;
+                    _ = &mut *shutdown_rx => Wake::Shutdown,
+                    Some(completion) = completion_rx.recv() => Wake::Completion(completion),
+                    request = request_rx.recv(), if in_flight.len() < MAX_IN_FLIGHT => {
+                        request.map_or(Wake::Shutdown, Wake::Request)
+                    }
+                    _ = Self::sleep_until(wake_at) => Wake::Timer,
+                }
+            });
 
-        // Run pending jobs to start the async main() function
-        // This allows the server to set up __mcp_pendingRead before we send requests
-        let mut jobs_executed = 0;
-        for _ in 0..1000 {
-            if !runtime.is_job_pending() {
-                break;
-            }
-            match runtime.execute_pending_job() {
-                Ok(_) => jobs_executed += 1,
-                Err(e) => {
-                    tracing::warn!("[JS:{}] Startup job error: {:?}", config.id, e);
+            // Each turn of JS work gets the time budget of a call
+            budget.start(timeout);
+            match wake {
+                Wake::Shutdown => {
+                    tracing::info!("JS server '{}' shutting down", config.id);
                     break;
                 }
+                Wake::Request(request) => Self::accept(&instance.context, &bindings, &mut in_flight, request, timeout),
+                Wake::Completion(completion) => instance.context.with(|ctx| {
+                    bindings.event_loop.complete(&ctx, completion);
+                    while let Ok(completion) = completion_rx.try_recv() {
+                        bindings.event_loop.complete(&ctx, completion);
+                    }
+                }),
+                Wake::Timer => {}
+            }
+            Self::run_until_idle(&instance, &budget);
+            instance.context.with(|ctx| Self::flush_console_logs(&ctx, &config.id));
+
+            let mut answered = Self::route_output(&bindings.event_loop, &config.id, &mut in_flight);
+            if !answered.is_empty() || budget.exceeded.load(Ordering::Relaxed) {
+                // A server over its limits is in an unknown state; start it afresh
+                if let Some(violation) = Self::check_limits(&instance.runtime, &budget, &config.limits) {
+                    tracing::warn!("[JS:{}] Server {}; restarting", config.id, violation);
+                    // Calls still in flight were lost with the old runtime
+                    answered = answered
+                        .into_iter()
+                        .map(|(request, _)| request)
+                        .chain(in_flight.drain().map(|(_, call)| call.request))
+                        .map(|request| (request, Err(CallError::LimitExceeded(violation))))
+                        .collect();
+                    drop(instance);
+                    instance = Self::boot(&config, &bindings, &budget)?;
+                }
+            }
+            for (request, response) in answered {
+                let _ = request.response_tx.send(response);
+            }
+            Self::expire(&mut in_flight, &config);
+            bindings.host.borrow_mut().retain(|key, _| in_flight.contains_key(key));
+            budget.clear();
+        }
+
+        Ok(())
+    }
+
+    async fn sleep_until(at: Option<Instant>) {
+        match at {
+            Some(at) => tokio::time::sleep_until(at.into()).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Hand a request to the server. A request without an id is a
+    /// notification and is answered with null once delivered.
+    fn accept(
+        context: &Context,
+        bindings: &Bindings,
+        in_flight: &mut HashMap<String, InFlight>,
+        mut request: ServerRequest,
+        timeout: Duration,
+    ) {
+        // The server sees an id of the bridge's, so that calls from
+        // different callers using the same id don't collide
+        let id = request.payload.get("id").cloned();
+        if id.is_some() {
+            bindings.call_ids.set(bindings.call_ids.get() + 1);
+            request.payload["id"] = bindings.call_ids.get().into();
+        }
+        if let Err(e) = Self::deliver(context, bindings, &request) {
+            let _ = request.response_tx.send(Err(e.into()));
+            return;
+        }
+        match id {
+            Some(id) => {
+                let key = request.payload["id"].to_string();
+                let deadline = Instant::now() + timeout;
+                in_flight.insert(key, InFlight { request, id, deadline });
+            }
+            None => {
+                let _ = request.response_tx.send(Ok(serde_json::Value::Null));
             }
         }
-        tracing::info!("[JS:{}] Executed {} startup jobs", config.id, jobs_executed);
+    }
 
-        // Check if __mcp_pendingRead is set up
+    /// Hand a request to the server's MCP.readLine.
+    fn deliver(context: &Context, bindings: &Bindings, request: &ServerRequest) -> Result<(), String> {
+        // A notification is answered on delivery, so it has no call to
+        // make host requests for
+        if let (Some(tx), Some(id)) = (&request.host_request_tx, request.payload.get("id")) {
+            bindings.host.borrow_mut().insert(id.to_string(), HostTarget {
+                tx: tx.clone(),
+                context: request.context.clone().unwrap_or_else(|| serde_json::json!({})),
+            });
+        }
+        let line = serde_json::to_string(&request.payload).map_err(|e| e.to_string())?;
         context.with(|ctx| {
-            let has_pending: bool = ctx.eval("!!globalThis.__mcp_pendingRead").unwrap_or(false);
-            tracing::info!("[JS:{}] __mcp_pendingRead set up: {}", config.id, has_pending);
-            Self::flush_console_logs(&ctx, &config.id);
-        });
+            let deliver: Function = ctx.globals().get("__mcp_deliver")?;
+            deliver.call::<_, ()>((line,))
+        })
+        .map_err(|e| format!("Failed to inject request: {}", e))
+    }
 
-        // Message processing loop
-        let rt = tokio::runtime::Handle::current();
-        loop {
-            // Check for shutdown signal
-            match shutdown_rx.try_recv() {
-                Ok(_) | Err(oneshot::error::TryRecvError::Closed) => {
-                    tracing::info!("JS server '{}' shutting down", config.id);
+    /// Run jobs and the timers due now until the server has nothing left to
+    /// do or runs out of budget.
+    fn run_until_idle(instance: &Instance, budget: &Budget) {
+        let now = Instant::now();
+        while !budget.check() {
+            while instance.runtime.is_job_pending() {
+                if let Err(e) = Self::execute_job(&instance.runtime) {
+                    tracing::warn!("Job execution error: {}", e);
                     break;
                 }
-                Err(oneshot::error::TryRecvError::Empty) => {}
             }
+            if !instance.context.with(|ctx| instance.event_loop.fire_timer(&ctx, now)) {
+                break;
+            }
+        }
+    }
 
-            // Try to receive a request (non-blocking)
-            match rt.block_on(async {
-                tokio::select! {
-                    req = request_rx.recv() => req,
-                    _ = tokio::time::sleep(std::time::Duration::from_millis(10)) => None,
-                }
-            }) {
-                Some(request) => {
-                    let response = Self::handle_mcp_request_with_jobs(
-                        &context,
-                        &runtime,
-                        &rt,
-                        request.payload,
-                        &config.id,
-                        request.host_request_tx,
-                        request.context,
-                    );
-                    let _ = request.response_tx.send(response);
+    /// Match what the server wrote to the calls in flight by JSON-RPC id.
+    /// Everything else (notifications such as progress or logging, and
+    /// requests of the server's own) is published as a server message.
+    fn route_output(
+        event_loop: &EventLoop,
+        server_id: &str,
+        in_flight: &mut HashMap<String, InFlight>,
+    ) -> Vec<(ServerRequest, Result<serde_json::Value, CallError>)> {
+        let mut answered = Vec::new();
+        for line in event_loop.take_output() {
+            let message: serde_json::Value = match serde_json::from_str(&line) {
+                Ok(message) => message,
+                Err(e) => {
+                    tracing::warn!("[JS:{}] Invalid JSON from server: {}", server_id, e);
+                    continue;
                 }
+            };
+            let is_response = message.get("result").is_some() || message.get("error").is_some();
+            match message.get("id").filter(|_| is_response) {
+                Some(id) => match in_flight.remove(&id.to_string()) {
+                    Some(call) => {
+                        let mut message = message;
+                        message["id"] = call.id;
+                        answered.push((call.request, Ok(message)));
+                    }
+                    None => tracing::warn!("[JS:{}] Response to unknown request id {}", server_id, id),
+                },
                 None => {
-                    // No request, continue loop
-                    std::thread::sleep(std::time::Duration::from_millis(1));
+                    tracing::debug!("[JS:{}] Server message: {}", server_id, line);
+                    super::publish_message(server_id, message);
                 }
             }
         }
+        answered
+    }
 
-        Ok(())
+    /// Fail the calls whose response didn't arrive in time.
+    fn expire(in_flight: &mut HashMap<String, InFlight>, config: &JsServerConfig) {
+        let now = Instant::now();
+        let expired: Vec<String> = in_flight
+            .iter()
+            .filter(|(_, call)| call.deadline <= now)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in expired {
+            if let Some(call) = in_flight.remove(&id) {
+                tracing::warn!("[JS:{}] No response to request {} in time", config.id, id);
+                let message = format!("No response within {} ms", config.limits.call_timeout_ms);
+                let _ = call.request.response_tx.send(Err(message.into()));
+            }
+        }
     }
 
-    fn setup_sandbox(
-        ctx: &rquickjs::Ctx,
-        env: &HashMap<String, String>,
-        capabilities: &Capabilities,
-    ) -> Result<(), String> {
-        let globals = ctx.globals();
+    /// Create a runtime under the server's limits and run its code.
+    fn boot(config: &JsServerConfig, bindings: &Bindings, budget: &Arc<Budget>) -> Result<Instance, String> {
+        // Fetches of a previous instance have no one left to read them
+        bindings.fetches.borrow_mut().clear();
 
-        // MCP.writeLine - will be called by JS to send responses
-        // We store responses in a global array that Rust will read
-        ctx.eval::<(), _>(r#"
-            globalThis.__mcp_responses = [];
-            globalThis.__mcp_requests = [];
-        "#).map_err(|e| e.to_string())?;
+        // Create QuickJS runtime
+        let tearing_down = Arc::new(AtomicBool::new(false));
+        let allocator = TeardownAllocator { tearing_down: tearing_down.clone(), held: Vec::new() };
+        let runtime = Runtime::new_with_alloc(allocator).map_err(|e| format!("Failed to create runtime: {}", e))?;
+        runtime.set_memory_limit(config.limits.memory_bytes);
+        runtime.set_max_stack_size(config.limits.stack_bytes);
+        let handler_budget = budget.clone();
+        runtime.set_interrupt_handler(Some(Box::new(move || handler_budget.check())));
+        if let ServerSource::Bundle(bundle) = &config.source {
+            let (resolver, loader) = bundle.loader();
+            runtime.set_loader(resolver, loader);
+        }
+        let instance = Instance {
+            context: Context::full(&runtime).map_err(|e| format!("Failed to create context: {}", e))?,
+            runtime,
+            event_loop: bindings.event_loop.clone(),
+            tearing_down,
+        };
+
+        // Server code gets the same budget as a call to reach its first await
+        budget.start(Duration::from_millis(config.limits.call_timeout_ms));
+        let mut evaluation = None;
+        let mut result = instance.context.with(|ctx| {
+            Self::setup_sandbox(&ctx, config, bindings)?;
+            let run = match &config.source {
+                ServerSource::Script(code) => ctx.eval::<(), _>(code.as_str()),
+                ServerSource::Bundle(bundle) => Module::declare(ctx.clone(), bundle.entry(), bundle.entry_source())
+                    .and_then(|module| module.eval())
+                    .map(|(_, promise)| evaluation = Some(Persistent::save(&ctx, promise))),
+            };
+            run.map_err(|e| format!("Failed to execute server code: {}", error_message(&ctx, e)))
+        });
+        if result.is_ok() {
+            Self::run_until_idle(&instance, budget);
+        }
+        // A module still awaiting at top level is fine; one that threw is not
+        if let Some(evaluation) = evaluation {
+            result = instance.context.with(|ctx| match evaluation.restore(&ctx) {
+                Ok(promise) if promise.state() == PromiseState::Rejected => {
+                    let error = promise.result::<rquickjs::Value>().and_then(Result::err);
+                    let message = error.map(|e| error_message(&ctx, e)).unwrap_or_default();
+                    Err(format!("Failed to execute server code: {}", message))
+                }
+                _ => Ok(()),
+            });
+        }
+        instance.context.with(|ctx| Self::flush_console_logs(&ctx, &config.id));
+        budget.clear();
+
+        let result = match Self::check_limits(&instance.runtime, budget, &config.limits) {
+            Some(violation) => Err(format!("Server code {} at startup", violation)),
+            None => result,
+        };
+        result.map(|_| instance)
+    }
+
+    /// Execute one pending job. Jobs only fail with uncatchable errors, such
+    /// as an interrupt from the time budget.
+    fn execute_job(runtime: &Runtime) -> Result<bool, String> {
+        runtime.execute_pending_job().map_err(|e| {
+            // The error wraps the job's context without taking a reference
+            // to it, so dropping the error would free a context we still
+            // use. Take the reference the wrapper gives back on drop.
+            unsafe {
+                rquickjs::qjs::JS_DupContext(e.0.as_raw().as_ptr());
+            }
+            format!("{:?}", e)
+        })
+    }
+
+    /// Check whether the last piece of work went over a limit. Memory is
+    /// measured after a GC, so only memory the server still holds counts.
+    fn check_limits(runtime: &Runtime, budget: &Budget, limits: &ResourceLimits) -> Option<LimitViolation> {
+        if budget.exceeded.load(Ordering::Relaxed) {
+            return Some(LimitViolation::Time { limit_ms: limits.call_timeout_ms });
+        }
+        runtime.run_gc();
+        let used = runtime.memory_usage().malloc_size.max(0) as usize;
+        // QuickJS fails allocations at the limit; treat being near it as over
+        if used >= limits.memory_bytes / 10 * 9 {
+            return Some(LimitViolation::Memory { limit_bytes: limits.memory_bytes });
+        }
+        None
+    }
+
+    fn setup_sandbox(ctx: &Ctx, config: &JsServerConfig, bindings: &Bindings) -> Result<(), String> {
+        let globals = ctx.globals();
 
         // Create process.env
         let process = Object::new(ctx.clone()).map_err(|e| e.to_string())?;
         let env_obj = Object::new(ctx.clone()).map_err(|e| e.to_string())?;
-        
-        for (key, value) in env {
+
+        for (key, value) in &config.env {
             env_obj.set(key.as_str(), value.as_str()).map_err(|e| e.to_string())?;
         }
-        
+
         process.set("env", env_obj).map_err(|e| e.to_string())?;
         process.set("platform", "harbor-bridge").map_err(|e| e.to_string())?;
         globals.set("process", process).map_err(|e| e.to_string())?;
@@ -252,166 +716,309 @@ impl JsServer {
             };
         "#).map_err(|e| e.to_string())?;
 
-        // Create MCP interface (readLine, writeLine, requestHost for browser capture)
+        // TextEncoder, URL, crypto, atob, Headers, structuredClone and friends
+        super::web::install(ctx)?;
+
+        // setTimeout/setInterval, backed by the event loop's timer heap
+        bindings.event_loop.install(ctx)?;
+
+        // Native halves of MCP.writeLine and MCP.requestHost
+        let event_loop = bindings.event_loop.clone();
+        let write_line = Function::new(ctx.clone(), move |line: String| event_loop.write_line(line));
+        globals.set("__mcp_write", write_line).map_err(|e| e.to_string())?;
+
+        let b = bindings.clone();
+        let server_id = config.id.clone();
+        let host_request = Function::new(
+            ctx.clone(),
+            move |ctx, method: String, params: String, request_id: Option<String>| {
+                Self::host_request(&b, &server_id, ctx, method, params, request_id)
+            },
+        );
+        globals.set("__host_request", host_request).map_err(|e| e.to_string())?;
+
+        // Create MCP interface (readLine, writeLine, requestHost for browser capture).
+        // Rust hands each request to __mcp_deliver, which wakes a waiting readLine.
         ctx.eval::<(), _>(r#"
-            globalThis.__host_requests = [];
-            globalThis.__host_responses = {};
-            globalThis.__host_id = 0;
-            globalThis.__requestHostContext = {};
-            globalThis.MCP = {
-                readLine: function() {
-                    return new Promise((resolve) => {
-                        globalThis.__mcp_pendingRead = resolve;
-                    });
-                },
-                writeLine: function(json) {
-                    globalThis.__mcp_responses.push(json);
-                },
-                requestHost: function(method, params) {
-                    const id = String(++globalThis.__host_id);
-                    globalThis.__host_requests.push({ id: id, method: method, params: params || {} });
-                    return new Promise((resolve, reject) => {
-                        const check = function() {
-                            const r = globalThis.__host_responses[id];
-                            if (r !== undefined) {
-                                delete globalThis.__host_responses[id];
-                                if (r.err) reject(new Error(r.err)); else resolve(r.result);
-                            } else {
-                                setTimeout(check, 1);
-                            }
-                        };
-                        check();
-                    });
-                },
-            };
+            (function() {
+                const write = globalThis.__mcp_write;
+                const hostRequest = globalThis.__host_request;
+                delete globalThis.__mcp_write;
+                delete globalThis.__host_request;
+
+                const queue = [];
+                let pendingRead = null;
+                globalThis.__mcp_deliver = (line) => {
+                    if (pendingRead) {
+                        const resolve = pendingRead;
+                        pendingRead = null;
+                        resolve(line);
+                    } else {
+                        queue.push(line);
+                    }
+                };
+                globalThis.MCP = {
+                    readLine: function() {
+                        if (queue.length > 0) return Promise.resolve(queue.shift());
+                        return new Promise((resolve) => { pendingRead = resolve; });
+                    },
+                    writeLine: function(json) {
+                        write(typeof json === 'string' ? json : JSON.stringify(json));
+                    },
+                    requestHost: function(method, params, options) {
+                        const requestId = options && options.requestId !== undefined
+                            ? JSON.stringify(options.requestId)
+                            : undefined;
+                        return hostRequest(String(method), JSON.stringify(params || {}), requestId).then((r) => {
+                            if (r.err) throw new Error(r.err);
+                            return r.result;
+                        });
+                    },
+                };
+            })();
         "#).map_err(|e| e.to_string())?;
 
+        // Filesystem access; every call is checked against the grants
+        super::fs::install(ctx, &config.id, &config.capabilities.filesystem, &bindings.event_loop)?;
+
+        // Persistent key-value storage, if granted
+        if let Some(storage) = &config.capabilities.storage {
+            super::storage::install(ctx, super::storage::open(&config.id)?, storage.quota_bytes)?;
+        }
+
         // Remove dangerous globals
         ctx.eval::<(), _>(r#"
             delete globalThis.eval;
         "#).map_err(|e| e.to_string())?;
 
-        // Add setTimeout/setInterval polyfills (QuickJS doesn't have these by default)
-        ctx.eval::<(), _>(r#"
-            globalThis.__timers = [];
-            globalThis.__timer_id = 0;
-            
-            globalThis.setTimeout = function(callback, delay) {
-                const id = ++globalThis.__timer_id;
-                globalThis.__timers.push({
-                    id: id,
-                    callback: callback,
-                    delay: delay || 0,
-                    scheduled: Date.now(),
-                    type: 'timeout'
-                });
-                return id;
-            };
-            
-            globalThis.clearTimeout = function(id) {
-                const idx = globalThis.__timers.findIndex(t => t.id === id);
-                if (idx !== -1) {
-                    globalThis.__timers.splice(idx, 1);
-                }
-            };
-            
-            globalThis.setInterval = function(callback, delay) {
-                const id = ++globalThis.__timer_id;
-                globalThis.__timers.push({
-                    id: id,
-                    callback: callback,
-                    delay: delay || 0,
-                    scheduled: Date.now(),
-                    type: 'interval'
-                });
-                return id;
-            };
-            
-            globalThis.clearInterval = globalThis.clearTimeout;
-            
-            // Process timers (called by Rust in the event loop)
-            globalThis.__processTimers = function() {
-                const now = Date.now();
-                const ready = [];
-                const keep = [];
-                
-                for (const timer of globalThis.__timers) {
-                    if (now >= timer.scheduled + timer.delay) {
-                        ready.push(timer);
-                        if (timer.type === 'interval') {
-                            // Reschedule interval
-                            keep.push({
-                                ...timer,
-                                scheduled: now
-                            });
-                        }
-                    } else {
-                        keep.push(timer);
-                    }
-                }
-                
-                globalThis.__timers = keep;
-                
-                for (const timer of ready) {
-                    try {
-                        timer.callback();
-                    } catch (e) {
-                        console.error('Timer callback error:', e);
-                    }
-                }
-                
-                return ready.length;
-            };
-        "#).map_err(|e| e.to_string())?;
-
         // Set up fetch if network access is allowed
-        if !capabilities.network.allowed_hosts.is_empty() {
-            // Fetch will be handled synchronously via Rust callbacks
-            // For now, create a placeholder that stores requests
+        if !config.capabilities.network.allowed_hosts.is_empty() {
+            let native = Object::new(ctx.clone()).map_err(|e| e.to_string())?;
+            let b = bindings.clone();
+            let start = Function::new(ctx.clone(), move |ctx, request, body| Self::fetch_start(&b, ctx, request, body));
+            native.set("start", start).map_err(|e| e.to_string())?;
+            let b = bindings.clone();
+            let read = Function::new(ctx.clone(), move |ctx, id| Self::fetch_read(&b, ctx, id));
+            native.set("read", read).map_err(|e| e.to_string())?;
+            let b = bindings.clone();
+            let close = Function::new(ctx.clone(), move |id: u64| {
+                b.fetches.borrow_mut().remove(&id);
+            });
+            native.set("close", close).map_err(|e| e.to_string())?;
+            globals.set("__fetch_native", native).map_err(|e| e.to_string())?;
+
             ctx.eval::<(), _>(r#"
-                globalThis.__fetch_requests = [];
-                globalThis.__fetch_responses = {};
-                globalThis.__fetch_id = 0;
-                
-                globalThis.fetch = async function(url, options) {
-                    const id = ++globalThis.__fetch_id;
-                    globalThis.__fetch_requests.push({
-                        id: id,
-                        url: url,
-                        options: options || {}
-                    });
-                    
-                    // Wait for response (will be filled by Rust)
-                    return new Promise((resolve, reject) => {
-                        const check = () => {
-                            const resp = globalThis.__fetch_responses[id];
-                            if (resp) {
-                                delete globalThis.__fetch_responses[id];
-                                if (resp.error) {
-                                    reject(new Error(resp.error));
-                                } else {
-                                    resolve({
-                                        ok: resp.status >= 200 && resp.status < 300,
-                                        status: resp.status,
-                                        statusText: resp.statusText || '',
-                                        headers: new Map(Object.entries(resp.headers || {})),
-                                        text: async () => resp.body,
-                                        json: async () => JSON.parse(resp.body),
-                                    });
+                (function() {
+                    const native = globalThis.__fetch_native;
+                    delete globalThis.__fetch_native;
+
+                    globalThis.fetch = async function(input, init) {
+                        const request = new Request(input, init);
+                        const signal = request.signal;
+                        signal.throwIfAborted();
+                        const body = request.method === 'GET' || request.method === 'HEAD'
+                            ? undefined
+                            : new Uint8Array(await request.arrayBuffer());
+                        const options = { method: request.method, headers: Object.fromEntries(request.headers) };
+                        const { id, response } = native.start(JSON.stringify({ url: request.url, options }), body);
+
+                        // Aborting closes the fetch in Rust and rejects whatever waits on it
+                        const aborted = new Promise((resolve, reject) => {
+                            signal.addEventListener('abort', () => {
+                                native.close(id);
+                                reject(signal.reason);
+                            }, { once: true });
+                        });
+                        aborted.catch(() => {});
+
+                        const head = await Promise.race([response, aborted]);
+                        if (head.error) {
+                            native.close(id);
+                            throw new TypeError(head.error);
+                        }
+                        const stream = new ReadableStream({
+                            async pull(controller) {
+                                const chunk = await Promise.race([native.read(id), aborted]);
+                                if (chunk instanceof Uint8Array) {
+                                    controller.enqueue(chunk);
+                                    return;
                                 }
-                            } else {
-                                setTimeout(check, 1);
-                            }
-                        };
-                        check();
-                    });
-                };
+                                native.close(id);
+                                if (chunk === null) controller.close();
+                                else controller.error(new TypeError(chunk.error));
+                            },
+                            cancel() {
+                                native.close(id);
+                            },
+                        });
+                        return new Response(stream, {
+                            status: head.status,
+                            statusText: head.statusText || '',
+                            headers: head.headers || {},
+                            url: request.url,
+                        });
+                    };
+                })();
             "#).map_err(|e| e.to_string())?;
         }
 
         Ok(())
     }
 
+    /// `__fetch_native.start`: send a request. Returns `{ id, response }`,
+    /// where `response` resolves with a `FetchResponse` once the head
+    /// arrives; the body is then read with `read`.
+    fn fetch_start<'js>(
+        bindings: &Bindings,
+        ctx: Ctx<'js>,
+        request: String,
+        body: Option<TypedArray<'js, u8>>,
+    ) -> rquickjs::Result<Object<'js>> {
+        let request: FetchRequest = serde_json::from_str(&request)
+            .map_err(|e| Exception::throw_type(&ctx, &format!("Invalid fetch request: {}", e)))?;
+        let body = body.and_then(|body| body.as_bytes().map(<[u8]>::to_vec));
+        let timeout = Duration::from_millis(bindings.fetcher.network.timeout_ms);
+
+        let mut fetches = bindings.fetches.borrow_mut();
+        // Drop bodies left unread, unless a read is waiting on one
+        fetches.retain(|_, fetch| fetch.last_used.elapsed() < timeout || fetch.body.try_lock().is_err());
+        bindings.fetch_ids.set(bindings.fetch_ids.get() + 1);
+        let id = bindings.fetch_ids.get();
+        let (abort, aborted) = watch::channel(());
+        let reader = Arc::new(tokio::sync::Mutex::new(None));
+        fetches.insert(
+            id,
+            OpenFetch {
+                abort,
+                body: reader.clone(),
+                last_used: Instant::now(),
+            },
+        );
+        drop(fetches);
+
+        let fetcher = bindings.fetcher.clone();
+        let response = bindings.event_loop.spawn(&ctx, async move {
+            let head = tokio::select! {
+                head = Self::execute_fetch(&fetcher, &request, body, &reader) => head,
+                _ = Self::aborted(aborted) => FetchResponse::failed("The fetch was aborted".to_string()),
+            };
+            serde_json::to_value(head).unwrap_or_default()
+        })?;
+        let started = Object::new(ctx)?;
+        started.set("id", id)?;
+        started.set("response", response)?;
+        Ok(started)
+    }
+
+    /// `__fetch_native.read`: read the next chunk of a response body. The
+    /// promise resolves with a Uint8Array, null at the end, or `{ error }`.
+    fn fetch_read<'js>(bindings: &Bindings, ctx: Ctx<'js>, id: u64) -> rquickjs::Result<Promise<'js>> {
+        let (body, aborted) = match bindings.fetches.borrow_mut().get_mut(&id) {
+            Some(fetch) => {
+                fetch.last_used = Instant::now();
+                (fetch.body.clone(), fetch.abort.subscribe())
+            }
+            None => {
+                let error = serde_json::json!({ "error": "The response body is no longer available" });
+                return bindings.event_loop.spawn(&ctx, async move { error });
+            }
+        };
+        let network = &bindings.fetcher.network;
+        let max_bytes = network.max_response_bytes;
+        let timeout = Duration::from_millis(network.timeout_ms);
+        bindings.event_loop.spawn(&ctx, async move {
+            tokio::select! {
+                chunk = Self::read_chunk(&body, max_bytes, timeout) => chunk,
+                _ = Self::aborted(aborted) => serde_json::json!({ "error": "The fetch was aborted" }).into(),
+            }
+        })
+    }
+
+    /// Read one chunk of a body, enforcing the size limit and timeout. The
+    /// body is dropped once it ends or fails.
+    async fn read_chunk(body: &tokio::sync::Mutex<Option<BodyReader>>, max_bytes: usize, timeout: Duration) -> Payload {
+        let failed = |message: String| Payload::from(serde_json::json!({ "error": message }));
+        let mut body = body.lock().await;
+        let reader = match body.as_mut() {
+            Some(reader) => reader,
+            None => return serde_json::Value::Null.into(),
+        };
+        let chunk = match tokio::time::timeout(timeout, reader.response.chunk()).await {
+            Ok(Ok(chunk)) => chunk,
+            Ok(Err(e)) => {
+                *body = None;
+                return failed(e.to_string());
+            }
+            Err(_) => {
+                *body = None;
+                return failed(format!("No response data within {} ms", timeout.as_millis()));
+            }
+        };
+        match chunk {
+            Some(bytes) => {
+                reader.received += bytes.len();
+                if reader.received > max_bytes {
+                    *body = None;
+                    return failed(format!("Response body is larger than {} bytes", max_bytes));
+                }
+                Payload::Bytes(bytes.to_vec())
+            }
+            None => {
+                *body = None;
+                serde_json::Value::Null.into()
+            }
+        }
+    }
+
+    /// Resolves once the fetch is closed from JS.
+    async fn aborted(mut abort: watch::Receiver<()>) {
+        while abort.changed().await.is_ok() {}
+    }
+
+    /// `__host_request`: send a host_request to the extension on behalf of
+    /// the call with `request_id` (JSON-encoded), or of the only call in
+    /// flight with a host; the promise resolves with `{ result }` or `{ err }`.
+    fn host_request<'js>(
+        bindings: &Bindings,
+        server_id: &str,
+        ctx: Ctx<'js>,
+        method: String,
+        params: String,
+        request_id: Option<String>,
+    ) -> rquickjs::Result<Promise<'js>> {
+        let params: serde_json::Value = serde_json::from_str(&params).unwrap_or_default();
+        let target = {
+            let host = bindings.host.borrow();
+            match request_id {
+                Some(key) => host.get(&key).cloned().ok_or("Host requests are not available"),
+                None if host.len() > 1 => {
+                    Err("Several calls with host access are in flight; pass { requestId }")
+                }
+                None => host.values().next().cloned().ok_or("Host requests are not available"),
+            }
+        };
+        // Ids are matched against host_response across all servers
+        bindings.host_ids.set(bindings.host_ids.get() + 1);
+        let id = format!("{}:{}", server_id, bindings.host_ids.get());
+        tracing::info!("[JS:{}] Host request {}", server_id, method);
+
+        bindings.event_loop.spawn(&ctx, async move {
+            let target = match target {
+                Ok(target) => target,
+                Err(e) => return serde_json::json!({ "err": e }),
+            };
+            let (response_tx, response_rx) = oneshot::channel();
+            if let Err(e) = target.tx.send((id, method, params, target.context, response_tx)).await {
+                return serde_json::json!({ "err": format!("host_request_tx send: {}", e) });
+            }
+            match response_rx.await {
+                Ok(Ok(result)) => serde_json::json!({ "result": result }),
+                Ok(Err(e)) => serde_json::json!({ "err": e.as_str().map(str::to_string).unwrap_or_else(|| e.to_string()) }),
+                Err(_) => serde_json::json!({ "err": "host_response timeout" }),
+            }
+        })
+    }
+
     /// Flush any pending console logs from JS and emit them via tracing + broadcast
     fn flush_console_logs(ctx: &rquickjs::Ctx, server_id: &str) {
         // Get logs as JSON string and parse on Rust side
@@ -422,7 +1029,7 @@ impl JsServer {
         if let Ok(json) = logs_json {
             if let Ok(logs) = serde_json::from_str::<Vec<serde_json::Value>>(&json) {
                 let console_tx = get_console_log_sender();
-                
+
                 for log in logs {
                     let level = log.get("level").and_then(|v| v.as_str()).unwrap_or("log");
                     let args = log.get("args")
@@ -432,7 +1039,7 @@ impl JsServer {
                             .collect::<Vec<_>>()
                             .join(" "))
                         .unwrap_or_default();
-                    
+
                     // Log to tracing (file)
                     match level {
                         "error" => tracing::error!("[JS:{}] {}", server_id, args),
@@ -441,7 +1048,7 @@ impl JsServer {
                         "debug" => tracing::debug!("[JS:{}] {}", server_id, args),
                         _ => tracing::info!("[JS:{}] {}", server_id, args),
                     }
-                    
+
                     // Broadcast to extension via native messaging
                     let _ = console_tx.send(ConsoleLogMessage {
                         server_id: server_id.to_string(),
@@ -453,288 +1060,24 @@ impl JsServer {
         }
     }
 
-    /// Handle an MCP request, properly separating context access from job execution
-    fn handle_mcp_request_with_jobs(
-        context: &Context,
-        runtime: &Runtime,
-        rt: &tokio::runtime::Handle,
-        request: serde_json::Value,
-        server_id: &str,
-        host_request_tx: Option<HostRequestSender>,
-        request_context: Option<serde_json::Value>,
-    ) -> Result<serde_json::Value, String> {
-        tracing::info!("[JS:{}] Handling MCP request", server_id);
-        
-        let request_json = serde_json::to_string(&request).map_err(|e| e.to_string())?;
-        let context_json = request_context
-            .as_ref()
-            .and_then(|c| serde_json::to_string(c).ok())
-            .unwrap_or_else(|| "{}".to_string());
-
-        // Step 1: Set host request context and inject the MCP request (inside context lock)
-        context.with(|ctx| {
-            let has_pending: bool = ctx.eval("!!globalThis.__mcp_pendingRead").unwrap_or(false);
-            tracing::info!("[JS:{}] __mcp_pendingRead before: {}", server_id, has_pending);
-
-            let escaped_context = context_json
-                .replace('\\', "\\\\")
-                .replace('\'', "\\'")
-                .replace('\n', "\\n")
-                .replace('\r', "\\r")
-                .replace('\t', "\\t");
-            let _: Result<(), _> = ctx.eval(
-                format!(
-                    "globalThis.__requestHostContext = JSON.parse('{}');",
-                    escaped_context
-                )
-                .into_bytes(),
-            );
-
-            // Properly escape for JavaScript string literal
-            let escaped_json = request_json
-                .replace('\\', "\\\\")  // Must be first!
-                .replace('\'', "\\'")
-                .replace('\n', "\\n")
-                .replace('\r', "\\r")
-                .replace('\t', "\\t");
-
-            let code = format!(r#"
-                try {{
-                    const req = '{}';
-                    if (globalThis.__mcp_pendingRead) {{
-                        const resolve = globalThis.__mcp_pendingRead;
-                        globalThis.__mcp_pendingRead = null;
-                        resolve(req);
-                    }} else {{
-                        globalThis.__mcp_requests.push(req);
-                    }}
-                }} catch (e) {{
-                    console.error('Inject error:', e, e.stack);
-                    throw e;
-                }}
-            "#, escaped_json);
-
-            ctx.eval::<(), _>(code.as_str()).map_err(|e| {
-                tracing::error!("[JS:{}] Eval error: {:?}", server_id, e);
-                tracing::error!("[JS:{}] Code was: {}", server_id, code);
-                e
-            })
-        }).map_err(|e| format!("Failed to inject request: {}", e))?;
-        
-        tracing::info!("[JS:{}] Request injected", server_id);
-
-        // Step 2: Run job queue and check for responses (alternating context access and job execution)
-        let mut total_jobs = 0;
-        for iteration in 0..10000 {
-            // Execute pending jobs OUTSIDE context lock
-            let jobs_pending = runtime.is_job_pending();
-            if iteration == 0 {
-                tracing::info!("[JS:{}] Jobs pending after injection: {}", server_id, jobs_pending);
-            }
-            
-            let mut jobs_this_round = 0;
-            while runtime.is_job_pending() {
-                match runtime.execute_pending_job() {
-                    Ok(_) => {
-                        jobs_this_round += 1;
-                        total_jobs += 1;
-                    }
-                    Err(e) => {
-                        tracing::warn!("[JS:{}] Job execution error: {:?}", server_id, e);
-                        break;
-                    }
-                }
-            }
-            
-            if iteration == 0 {
-                tracing::info!("[JS:{}] First iteration: executed {} jobs", server_id, jobs_this_round);
-            }
-            
-            // Process timers (setTimeout callbacks)
-            context.with(|ctx| {
-                let _: Result<i32, _> = ctx.eval("globalThis.__processTimers()");
-            });
-            
-            // Process pending fetch requests
-            Self::process_fetch_requests(&context, &rt, server_id);
-            // Process pending host requests (MCP.requestHost → bridge → extension → Web Agents)
-            if let Some(ref tx) = host_request_tx {
-                if let Err(e) = Self::process_host_requests(context, rt, server_id, tx, request_context.as_ref()) {
-                    tracing::warn!("[JS:{}] process_host_requests error: {}", server_id, e);
-                }
-            }
-            
-            // Check for response INSIDE context lock
-            let response_result: Result<Option<String>, String> = context.with(|ctx| {
-                // Get responses as JSON string to avoid type conversion issues
-                let responses_json: String = ctx.eval(r#"
-                    JSON.stringify(globalThis.__mcp_responses.splice(0))
-                "#).map_err(|e| format!("Failed to get responses: {}", e))?;
-
-                Self::flush_console_logs(&ctx, server_id);
-
-                let responses: Vec<String> = serde_json::from_str(&responses_json)
-                    .map_err(|e| format!("Failed to parse responses: {}", e))?;
-
-                if !responses.is_empty() {
-                    Ok(Some(responses.into_iter().last().unwrap()))
-                } else {
-                    Ok(None)
-                }
-            });
-
-            match response_result {
-                Ok(Some(response_str)) => {
-                    tracing::info!("[JS:{}] Got response after {} iterations, {} total jobs", server_id, iteration, total_jobs);
-                    return serde_json::from_str(&response_str)
-                        .map_err(|e| format!("Invalid response JSON: {}", e));
-                }
-                Ok(None) => {
-                    // No response yet, continue
-                }
-                Err(e) => {
-                    return Err(format!("Error checking response: {}", e));
-                }
-            }
-
-            // Small delay before checking again
-            std::thread::sleep(std::time::Duration::from_millis(1));
-        }
-
-        // Final state check
-        let (has_pending, response_count) = context.with(|ctx| {
-            Self::flush_console_logs(&ctx, server_id);
-            let has_pending: bool = ctx.eval("!!globalThis.__mcp_pendingRead").unwrap_or(false);
-            let response_count: i32 = ctx.eval("globalThis.__mcp_responses.length").unwrap_or(-1);
-            (has_pending, response_count)
-        });
-        
-        tracing::error!("[JS:{}] TIMEOUT after {} jobs. __mcp_pendingRead={}, responses={}", 
-            server_id, total_jobs, has_pending, response_count);
-        
-        Err("Timeout waiting for server response".to_string())
-    }
-
-    /// Process any pending host requests (MCP.requestHost); send to extension, block for response, inject into JS.
-    fn process_host_requests(
-        context: &Context,
-        rt: &tokio::runtime::Handle,
-        server_id: &str,
-        host_request_tx: &HostRequestSender,
-        request_context: Option<&serde_json::Value>,
-    ) -> Result<(), String> {
-        let (requests_json, context_value): (String, serde_json::Value) = context.with(|ctx| {
-            let reqs: String = ctx.eval(r#"
-                JSON.stringify(globalThis.__host_requests ? globalThis.__host_requests.splice(0) : [])
-            "#).map_err(|e| format!("eval host_requests: {}", e))?;
-            let ctx_val: String = ctx.eval("JSON.stringify(globalThis.__requestHostContext || {})")
-                .unwrap_or_else(|_| "{}".to_string());
-            let cv: serde_json::Value = serde_json::from_str(&ctx_val).unwrap_or(serde_json::json!({}));
-            Ok::<(String, serde_json::Value), String>((reqs, cv))
-        })?;
-
-        let requests: Vec<serde_json::Value> = serde_json::from_str(&requests_json).unwrap_or_default();
-        if requests.is_empty() {
-            return Ok(());
-        }
-
-        let context_to_send = request_context.cloned().unwrap_or(context_value);
-        tracing::info!("[JS:{}] Processing {} host requests", server_id, requests.len());
-
-        for req in requests {
-            let id = req.get("id").and_then(|v| v.as_str()).unwrap_or("").to_string();
-            let method = req.get("method").and_then(|v| v.as_str()).unwrap_or("").to_string();
-            let params = req.get("params").cloned().unwrap_or(serde_json::Value::Null);
-            let (response_tx, mut response_rx) = tokio::sync::oneshot::channel();
-            host_request_tx
-                .try_send((id.clone(), method, params, context_to_send.clone(), response_tx))
-                .map_err(|e| format!("host_request_tx send: {}", e))?;
-
-            let outcome = rt.block_on(async {
-                response_rx.await.unwrap_or(Err(serde_json::json!("host_response timeout")))
-            });
-
-            let err_msg = match &outcome {
-                Ok(_) => None,
-                Err(e) => Some(if let Some(s) = e.as_str() {
-                    s.to_string()
-                } else {
-                    e.to_string()
-                }),
-            };
-            let payload = match outcome {
-                Ok(r) => serde_json::json!({ "result": r }),
-                Err(_) => serde_json::json!({ "err": err_msg.unwrap_or_else(|| "host request failed".to_string()) }),
-            };
-            let response_json = payload.to_string();
-            let response_escaped = response_json
-                .replace('\\', "\\\\")
-                .replace('"', "\\\"")
-                .replace('\n', "\\n")
-                .replace('\r', "\\r");
-            let id_escaped = id.replace('\\', "\\\\").replace('"', "\\\"");
-            context.with(|ctx| {
-                let code = format!(
-                    "globalThis.__host_responses[\"{}\"] = JSON.parse(\"{}\");",
-                    id_escaped, response_escaped
-                );
-                let _: Result<(), _> = ctx.eval(code.into_bytes());
-            });
-        }
-        Ok(())
-    }
-
-    /// Process any pending fetch requests from JS
-    fn process_fetch_requests(context: &Context, rt: &tokio::runtime::Handle, server_id: &str) {
-        // Extract pending fetch requests from JS
-        let requests_json: Option<String> = context.with(|ctx| {
-            ctx.eval(r#"
-                JSON.stringify(globalThis.__fetch_requests ? globalThis.__fetch_requests.splice(0) : [])
-            "#).ok()
-        });
-
-        let requests_json = match requests_json {
-            Some(json) => json,
-            None => return,
+    /// Execute a single fetch request, up to the response head. On success
+    /// the response is left in `reader` for its body to be read.
+    async fn execute_fetch(
+        fetcher: &Fetcher,
+        request: &FetchRequest,
+        body: Option<Vec<u8>>,
+        reader: &tokio::sync::Mutex<Option<BodyReader>>,
+    ) -> FetchResponse {
+        let url = match url::Url::parse(&request.url) {
+            Ok(url) => url,
+            Err(e) => return FetchResponse::failed(format!("Invalid URL '{}': {}", request.url, e)),
         };
-
-        let requests: Vec<FetchRequest> = match serde_json::from_str(&requests_json) {
-            Ok(r) => r,
-            Err(_) => return,
-        };
-
-        if requests.is_empty() {
-            return;
-        }
-
-        tracing::info!("[JS:{}] Processing {} fetch requests", server_id, requests.len());
-
-        // Process each request
-        for request in requests {
-            let response = rt.block_on(async {
-                Self::execute_fetch(&request).await
-            });
-
-            // Inject response back into JS
-            let response_json = serde_json::to_string(&response).unwrap_or_else(|_| {
-                r#"{"error":"Failed to serialize response"}"#.to_string()
-            });
-
-            context.with(|ctx| {
-                let code = format!(
-                    "globalThis.__fetch_responses[{}] = {};",
-                    request.id,
-                    response_json
-                );
-                let _: Result<(), _> = ctx.eval(code.as_str());
-            });
+        if let Err(e) = fetcher.network.check_url(&url) {
+            tracing::warn!("[Fetch] {}", e);
+            return FetchResponse::failed(e.to_string());
         }
-    }
+        let client = &fetcher.client;
 
-    /// Execute a single fetch request
-    async fn execute_fetch(request: &FetchRequest) -> FetchResponse {
-        let client = reqwest::Client::new();
-        
         let method = request.options.method
             .as_deref()
             .unwrap_or("GET")
@@ -749,37 +1092,60 @@ impl JsServer {
             "DELETE" => client.delete(&request.url),
             "PATCH" => client.patch(&request.url),
             "HEAD" => client.head(&request.url),
-            _ => {
-                return FetchResponse {
-                    status: 0,
-                    status_text: String::new(),
-                    headers: HashMap::new(),
-                    body: String::new(),
-                    error: Some(format!("Unsupported method: {}", method)),
-                };
-            }
+            _ => return FetchResponse::failed(format!("Unsupported method: {}", method)),
         };
 
+        // Hosts covered by the server's OAuth grant get its bearer token,
+        // in place of any Authorization header from JS
+        let oauth = fetcher.oauth.as_ref().filter(|oauth| oauth.covers(&url));
+
         // Add headers
         if let Some(headers) = &request.options.headers {
             for (key, value) in headers {
+                if oauth.is_some() && key.eq_ignore_ascii_case("authorization") {
+                    continue;
+                }
                 req_builder = req_builder.header(key.as_str(), value.as_str());
             }
         }
 
+        if let Some(oauth) = oauth {
+            match crate::oauth::access_token(&fetcher.server_id, &oauth.provider).await {
+                Ok(token) => req_builder = req_builder.bearer_auth(token),
+                Err(e) => {
+                    tracing::warn!("[Fetch] No {} token for {}: {}", oauth.provider, fetcher.server_id, e);
+                    return FetchResponse::failed(format!("OAuth: {}", e));
+                }
+            }
+        }
+
         // Add body
-        if let Some(body) = &request.options.body {
-            req_builder = req_builder.body(body.clone());
+        if let Some(body) = body {
+            req_builder = req_builder.body(body);
         }
 
         // Execute request
-        match req_builder.send().await {
+        let timeout = Duration::from_millis(fetcher.network.timeout_ms);
+        let sent = match tokio::time::timeout(timeout, req_builder.send()).await {
+            Ok(sent) => sent,
+            Err(_) => {
+                tracing::warn!("[Fetch] No response from {} within {} ms", request.url, timeout.as_millis());
+                return FetchResponse::failed(format!("No response within {} ms", timeout.as_millis()));
+            }
+        };
+        match sent {
             Ok(response) => {
                 let status = response.status().as_u16();
                 let status_text = response.status().canonical_reason()
                     .unwrap_or("")
                     .to_string();
-                
+
+                let max_bytes = fetcher.network.max_response_bytes;
+                if response.content_length().is_some_and(|len| len > max_bytes as u64) {
+                    tracing::warn!("[Fetch] Response from {} is larger than {} bytes", request.url, max_bytes);
+                    return FetchResponse::failed(format!("Response body is larger than {} bytes", max_bytes));
+                }
+
                 let mut headers = HashMap::new();
                 for (key, value) in response.headers() {
                     if let Ok(v) = value.to_str() {
@@ -787,28 +1153,463 @@ impl JsServer {
                     }
                 }
 
-                let body = response.text().await.unwrap_or_default();
-
-                tracing::info!("[Fetch] Response: {} {} ({} bytes)", status, status_text, body.len());
+                tracing::info!("[Fetch] Response: {} {}", status, status_text);
+                *reader.lock().await = Some(BodyReader { response, received: 0 });
 
                 FetchResponse {
                     status,
                     status_text,
                     headers,
-                    body,
                     error: None,
                 }
             }
             Err(e) => {
-                tracing::error!("[Fetch] Error: {}", e);
-                FetchResponse {
-                    status: 0,
-                    status_text: String::new(),
-                    headers: HashMap::new(),
-                    body: String::new(),
-                    error: Some(e.to_string()),
+                // Redirect hops and DNS answers refused by the sandbox come
+                // back wrapped in reqwest errors; report the refusal itself
+                let mut source: Option<&(dyn std::error::Error + 'static)> = Some(&e);
+                while let Some(err) = source {
+                    if let Some(refused) = err.downcast_ref::<FetchBlocked>() {
+                        tracing::warn!("[Fetch] {}", refused);
+                        return FetchResponse::failed(refused.to_string());
+                    }
+                    source = err.source();
                 }
+                tracing::error!("[Fetch] Error: {}", e);
+                FetchResponse::failed(e.to_string())
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER: &str = r#"
+        const sleep = (ms) => new Promise((resolve) => setTimeout(resolve, ms));
+
+        async function handle(req) {
+            if (req.method === 'spin') {
+                while (true) {}
+            }
+            if (req.method === 'sleep') {
+                MCP.writeLine({ jsonrpc: '2.0', method: 'notifications/progress', params: { request: req.id } });
+                await sleep(req.params.ms);
+                MCP.writeLine({ jsonrpc: '2.0', id: req.id, result: { slept: req.params.ms } });
+                return;
+            }
+            if (req.method === 'host') {
+                await sleep(20);
+                const unnamed = await MCP.requestHost('get_tab').catch((e) => e.message);
+                const tab = await MCP.requestHost('get_tab', {}, { requestId: req.id });
+                MCP.writeLine({ jsonrpc: '2.0', id: req.id, result: { unnamed, tab } });
+                return;
+            }
+            if (req.method === 'timers') {
+                const order = [];
+                clearTimeout(setTimeout(() => order.push('cleared'), 5));
+                setTimeout(() => order.push('late'), 30);
+                setTimeout((tag) => order.push(tag), 10, 'early');
+                let ticks = 0;
+                await new Promise((resolve) => {
+                    const id = setInterval(() => {
+                        if (++ticks === 3) {
+                            clearInterval(id);
+                            resolve();
+                        }
+                    }, 5);
+                });
+                await sleep(40);
+                const host = await MCP.requestHost('open_tab').catch((e) => e.message);
+                MCP.writeLine({ jsonrpc: '2.0', id: req.id, result: { order, ticks, host } });
+                return;
+            }
+            MCP.writeLine(JSON.stringify({ jsonrpc: '2.0', id: req.id, result: { ok: true } }));
+        }
+
+        (async function main() {
+            while (true) {
+                handle(JSON.parse(await MCP.readLine()));
+            }
+        })();
+    "#;
+
+    async fn start(id: &str, limits: ResourceLimits) -> ServerHandle {
+        JsServer::start(JsServerConfig {
+            id: id.to_string(),
+            source: ServerSource::Script(SERVER.to_string()),
+            env: HashMap::new(),
+            capabilities: Capabilities::default(),
+            limits,
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_timers_and_promises() {
+        let handle = start("test-timers", ResourceLimits::default()).await;
+
+        let response = handle
+            .caller()
+            .call(serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "timers" }))
+            .await
+            .unwrap();
+        assert_eq!(
+            response["result"],
+            serde_json::json!({ "order": ["early", "late"], "ticks": 3, "host": "Host requests are not available" })
+        );
+
+        handle.stop().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_calls() {
+        let handle = start("test-concurrent", ResourceLimits::default()).await;
+        let mut messages = crate::js::subscribe();
+
+        let call = |id: &'static str, ms: u64| {
+            let request = serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": "sleep", "params": { "ms": ms } });
+            let handle = &handle;
+            async move {
+                let response = handle.caller().call(request).await.unwrap();
+                (response, Instant::now())
+            }
+        };
+        let ((slow, slow_at), (fast, fast_at)) = tokio::join!(call("slow", 200), call("fast", 0));
+
+        // The fast call isn't held up by the slow one, and each gets its own response
+        assert!(fast_at < slow_at);
+        assert_eq!(slow["id"], "slow");
+        assert_eq!(slow["result"]["slept"], 200);
+        assert_eq!(fast["id"], "fast");
+
+        // Progress notifications are published rather than taken as responses
+        let mut progress = Vec::new();
+        while let Ok(message) = messages.try_recv() {
+            if message.server_id == "test-concurrent" {
+                progress.push(message.message["params"]["request"].clone());
+            }
+        }
+        assert_eq!(progress.len(), 2);
+
+        handle.stop().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_calls_with_same_id() {
+        let handle = start("test-same-id", ResourceLimits::default()).await;
+
+        // Two callers that both number their requests from 1
+        let call = |ms: u64| {
+            let request = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "sleep", "params": { "ms": ms } });
+            let caller = handle.caller();
+            async move { caller.call(request).await }
+        };
+        let (slow, fast) = tokio::join!(call(50), call(0));
+
+        let (slow, fast) = (slow.unwrap(), fast.unwrap());
+        assert_eq!(slow["id"], 1);
+        assert_eq!(slow["result"]["slept"], 50);
+        assert_eq!(fast["id"], 1);
+        assert_eq!(fast["result"]["slept"], 0);
+
+        handle.stop().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_host_requests_per_call() {
+        let handle = start("test-host", ResourceLimits::default()).await;
+
+        // The extension answers with the tab of the call the request was made for
+        let (host_tx, mut host_rx) = mpsc::channel::<crate::native_messaging::HostRequestItem>(8);
+        tokio::spawn(async move {
+            while let Some((_, _, _, context, response_tx)) = host_rx.recv().await {
+                let _ = response_tx.send(Ok(context["tabId"].clone()));
+            }
+        });
+
+        let call = |id: &'static str, tab: u64| {
+            let request = serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": "host" });
+            let context = serde_json::json!({ "tabId": tab });
+            let (caller, host_tx) = (handle.caller(), host_tx.clone());
+            async move { caller.call_with_host(request, Some(context), Some(host_tx)).await }
+        };
+        let (a, b) = tokio::join!(call("a", 1), call("b", 2));
+
+        // With two calls in flight, a request has to say which one it is for
+        let ambiguous = "Several calls with host access are in flight; pass { requestId }";
+        assert_eq!(a.unwrap()["result"], serde_json::json!({ "unnamed": ambiguous, "tab": 1 }));
+        assert_eq!(b.unwrap()["result"], serde_json::json!({ "unnamed": ambiguous, "tab": 2 }));
+
+        handle.stop().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_module_bundle() {
+        let bundle = crate::js::ModuleBundle {
+            files: HashMap::from([
+                ("main.js".to_string(), r#"
+                    import { add } from './lib/math.js';
+                    import greeting from './lib';
+
+                    const offset = await new Promise((resolve) => setTimeout(() => resolve(1), 5));
+                    while (true) {
+                        const req = JSON.parse(await MCP.readLine());
+                        MCP.writeLine({ jsonrpc: '2.0', id: req.id, result: { sum: add(2, 3) + offset, greeting } });
+                    }
+                "#.to_string()),
+                ("lib/math.js".to_string(), "export const add = (a, b) => a + b;".to_string()),
+                ("lib/index.js".to_string(), "export default 'hello';".to_string()),
+            ]),
+            dir: None,
+            entry: "main.js".to_string(),
+        };
+        let handle = JsServer::start(JsServerConfig {
+            id: "test-bundle".to_string(),
+            source: ServerSource::Bundle(bundle.load().unwrap()),
+            env: HashMap::new(),
+            capabilities: Capabilities::default(),
+            limits: ResourceLimits::default(),
+        })
+        .await
+        .unwrap();
+
+        let response = handle
+            .caller()
+            .call(serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "ping" }))
+            .await
+            .unwrap();
+        assert_eq!(response["result"], serde_json::json!({ "sum": 6, "greeting": "hello" }));
+
+        handle.stop().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_bodies() {
+        use axum::routing::{get, post};
+        use futures::StreamExt;
+
+        let app = axum::Router::new()
+            .route(
+                "/echo",
+                post(|headers: axum::http::HeaderMap, body: axum::body::Bytes| async move {
+                    let content_type = headers.get("content-type").and_then(|v| v.to_str().ok()).unwrap_or("");
+                    ([("x-request-type", content_type.to_string())], body)
+                }),
+            )
+            .route(
+                "/stream",
+                get(|| async {
+                    let chunks = futures::stream::iter(["one ", "two ", "three"]).then(|chunk| async move {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        Ok::<_, std::io::Error>(axum::body::Bytes::from(chunk))
+                    });
+                    axum::body::Body::from_stream(chunks)
+                }),
+            )
+            .route("/big", get(|| async { vec![b'x'; 2048] }))
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    "late"
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let code = r#"
+            const base = process.env.BASE;
+            async function run() {
+                const echoed = await fetch(base + '/echo', { method: 'POST', body: new Uint8Array([0, 255, 7]) });
+                const bytes = Array.from(new Uint8Array(await echoed.arrayBuffer()));
+
+                const form = new FormData();
+                form.append('name', 'harbor');
+                form.append('file', new Blob([new Uint8Array([1, 2, 3])]), 'a.bin');
+                const formEcho = await fetch(base + '/echo', { method: 'POST', body: form });
+                const formType = formEcho.headers.get('x-request-type');
+                const formText = await formEcho.text();
+
+                const blob = await (await fetch(base + '/echo', { method: 'POST', body: 'hi' })).blob();
+
+                const streamed = await fetch(base + '/stream');
+                const decoder = new TextDecoder();
+                let text = '';
+                for await (const chunk of streamed.body) text += decoder.decode(chunk);
+
+                let big = null;
+                try { await fetch(base + '/big'); } catch (e) { big = e.message; }
+                let timedOut = null;
+                try { await fetch(base + '/slow'); } catch (e) { timedOut = e.message; }
+                let aborted = null;
+                try { await fetch(base + '/slow', { signal: AbortSignal.timeout(20) }); } catch (e) { aborted = e.name; }
+
+                return {
+                    bytes,
+                    multipart: formType.startsWith('multipart/form-data; boundary=') && formText.includes('filename="a.bin"'),
+                    blob: { size: blob.size, type: blob.type },
+                    text,
+                    big,
+                    timedOut,
+                    aborted,
+                };
+            }
+            (async () => {
+                while (true) {
+                    const req = JSON.parse(await MCP.readLine());
+                    const result = await run().catch((e) => ({ error: String(e) }));
+                    MCP.writeLine({ jsonrpc: '2.0', id: req.id, result });
+                }
+            })();
+        "#;
+        let capabilities = Capabilities {
+            network: NetworkCapabilities {
+                allowed_hosts: vec!["127.0.0.1".to_string()],
+                allow_http: true,
+                allow_private_network: true,
+                max_response_bytes: 1024,
+                timeout_ms: 200,
+            },
+            ..Default::default()
+        };
+        let handle = JsServer::start(JsServerConfig {
+            id: "test-fetch".to_string(),
+            source: ServerSource::Script(code.to_string()),
+            env: HashMap::from([("BASE".to_string(), base)]),
+            capabilities,
+            limits: ResourceLimits::default(),
+        })
+        .await
+        .unwrap();
+
+        let response = handle
+            .caller()
+            .call(serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "run" }))
+            .await
+            .unwrap();
+        assert_eq!(
+            response["result"],
+            serde_json::json!({
+                "bytes": [0, 255, 7],
+                "multipart": true,
+                "blob": { "size": 2, "type": "application/octet-stream" },
+                "text": "one two three",
+                "big": "Response body is larger than 1024 bytes",
+                "timedOut": "No response within 200 ms",
+                "aborted": "TimeoutError",
+            })
+        );
+
+        handle.stop().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_oauth() {
+        let tokens = crate::oauth::StoredTokens {
+            server_id: "test-oauth".to_string(),
+            provider: "github".to_string(),
+            tokens: crate::oauth::OAuthTokens {
+                access_token: "secret-token".to_string(),
+                refresh_token: None,
+                expires_at: None,
+                token_type: "Bearer".to_string(),
+                scope: None,
+            },
+            scopes: Vec::new(),
+            created_at: 0,
+            updated_at: 0,
+        };
+        crate::oauth::get_token_store_mut()
+            .await
+            .get_or_insert_with(crate::oauth::TokenStore::new)
+            .set_tokens("test-oauth", tokens);
+
+        let app = axum::Router::new().route(
+            "/whoami",
+            axum::routing::get(|headers: axum::http::HeaderMap| async move {
+                headers.get("authorization").and_then(|v| v.to_str().ok()).unwrap_or("none").to_string()
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let code = r#"
+            (async () => {
+                while (true) {
+                    const req = JSON.parse(await MCP.readLine());
+                    const get = (host) => fetch(`http://${host}:${process.env.PORT}/whoami`, {
+                        headers: { Authorization: 'Bearer from-js' },
+                    }).then((r) => r.text());
+                    const result = { covered: await get('127.0.0.1'), other: await get('localhost') };
+                    MCP.writeLine({ jsonrpc: '2.0', id: req.id, result });
+                }
+            })();
+        "#;
+        let capabilities = Capabilities {
+            network: NetworkCapabilities {
+                allowed_hosts: vec!["127.0.0.1".to_string(), "localhost".to_string()],
+                allow_http: true,
+                allow_private_network: true,
+                ..Default::default()
+            },
+            oauth: Some(OAuthCapabilities {
+                provider: "github".to_string(),
+                hosts: vec!["127.0.0.1".to_string()],
+            }),
+            ..Default::default()
+        };
+        let handle = JsServer::start(JsServerConfig {
+            id: "test-oauth".to_string(),
+            source: ServerSource::Script(code.to_string()),
+            env: HashMap::from([("PORT".to_string(), port.to_string())]),
+            capabilities,
+            limits: ResourceLimits::default(),
+        })
+        .await
+        .unwrap();
+
+        let response = handle
+            .caller()
+            .call(serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "run" }))
+            .await
+            .unwrap();
+        // The token is only sent over https, so the covered host doesn't get
+        // it over plain http either
+        assert_eq!(
+            response["result"],
+            serde_json::json!({ "covered": "Bearer from-js", "other": "Bearer from-js" })
+        );
+
+        handle.stop().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_time_limit_restarts_server() {
+        let limits = ResourceLimits {
+            call_timeout_ms: 200,
+            ..Default::default()
+        };
+        let handle = start("test-limits", limits).await;
+
+        let err = handle
+            .caller()
+            .call(serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "spin" }))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CallError::LimitExceeded(LimitViolation::Time { limit_ms: 200 })));
+
+        // The restarted server answers again
+        let response = handle
+            .caller()
+            .call(serde_json::json!({ "jsonrpc": "2.0", "id": 2, "method": "ping" }))
+            .await
+            .unwrap();
+        assert_eq!(response["result"]["ok"], true);
+
+        handle.stop().await;
+    }
+}
@@ -1,10 +1,17 @@
 //! Sandbox capabilities and permission checking for JS servers.
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Redirects followed by a single fetch before giving up.
+const MAX_REDIRECTS: usize = 10;
 
 /// Capabilities that can be granted to a JS server.
-#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
 pub struct Capabilities {
     /// Network access permissions
     #[serde(default)]
@@ -13,31 +20,140 @@ pub struct Capabilities {
     /// Filesystem access permissions
     #[serde(default)]
     pub filesystem: FilesystemCapabilities,
+
+    /// OAuth token to attach to fetches
+    #[serde(default)]
+    pub oauth: Option<OAuthCapabilities>,
+
+    /// Persistent key-value storage
+    #[serde(default)]
+    pub storage: Option<StorageCapabilities>,
 }
 
 /// Network access capabilities
-#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
 pub struct NetworkCapabilities {
     /// List of allowed host patterns (e.g., "*.googleapis.com", "api.example.com")
-    #[serde(default)]
     pub allowed_hosts: Vec<String>,
+
+    /// Also allow plain `http://` URLs (only `https://` otherwise)
+    pub allow_http: bool,
+
+    /// Allow loopback, private and link-local addresses
+    pub allow_private_network: bool,
+
+    /// Largest response body a fetch may read, in bytes
+    pub max_response_bytes: usize,
+
+    /// How long a fetch waits for the response, and then for each chunk of
+    /// its body, in milliseconds
+    pub timeout_ms: u64,
+}
+
+impl Default for NetworkCapabilities {
+    fn default() -> Self {
+        Self {
+            allowed_hosts: Vec::new(),
+            allow_http: false,
+            allow_private_network: false,
+            max_response_bytes: 10 * 1024 * 1024,
+            timeout_ms: 30_000,
+        }
+    }
+}
+
+/// OAuth for a server's fetches. Requests to matching hosts get an
+/// `Authorization: Bearer` header with the token the server was authorized
+/// with, refreshed as needed. The token is never visible to JS.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct OAuthCapabilities {
+    /// Provider the server's tokens come from (e.g., "google", "github")
+    pub provider: String,
+
+    /// Host patterns, as in `allowed_hosts`, whose requests get the token
+    pub hosts: Vec<String>,
+}
+
+impl OAuthCapabilities {
+    /// Check if requests to a URL get the token. Only `https://` URLs do,
+    /// even when `allow_http` lets the server fetch plain `http://` ones.
+    pub fn covers(&self, url: &url::Url) -> bool {
+        url.scheme() == "https"
+            && url
+                .host_str()
+                .is_some_and(|host| self.hosts.iter().any(|pattern| host_matches(pattern, host)))
+    }
+}
+
+/// Persistent `storage` for a server, kept on disk across restarts
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct StorageCapabilities {
+    /// Most bytes the server's keys and JSON-serialized values may take up
+    pub quota_bytes: usize,
+}
+
+impl Default for StorageCapabilities {
+    fn default() -> Self {
+        Self {
+            quota_bytes: 1024 * 1024,
+        }
+    }
 }
 
 /// Filesystem access capabilities
-#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
 pub struct FilesystemCapabilities {
     /// List of allowed path prefixes for reading
-    #[serde(default)]
     pub read_paths: Vec<String>,
     
     /// List of allowed path prefixes for writing
-    #[serde(default)]
     pub write_paths: Vec<String>,
+
+    /// Largest file `fs.readFile` will read, in bytes
+    pub max_read_bytes: u64,
+}
+
+impl Default for FilesystemCapabilities {
+    fn default() -> Self {
+        Self {
+            read_paths: Vec::new(),
+            write_paths: Vec::new(),
+            max_read_bytes: 10 * 1024 * 1024,
+        }
+    }
+}
+
+/// Resource limits for a JS server. A server that exceeds them is
+/// restarted and the call fails with `LIMIT_EXCEEDED`.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /// Maximum JS heap size in bytes
+    pub memory_bytes: usize,
+
+    /// Maximum JS stack size in bytes
+    pub stack_bytes: usize,
+
+    /// Wall-clock budget for one call, and for running the server code at
+    /// startup, in milliseconds
+    pub call_timeout_ms: u64,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        Self {
+            memory_bytes: 64 * 1024 * 1024,
+            stack_bytes: 1024 * 1024,
+            call_timeout_ms: 30_000,
+        }
+    }
 }
 
 impl NetworkCapabilities {
     /// Check if a URL's host is allowed
-    #[allow(dead_code)]
     pub fn is_host_allowed(&self, url: &str) -> bool {
         if self.allowed_hosts.is_empty() {
             return false;
@@ -53,66 +169,168 @@ impl NetworkCapabilities {
             None => return false,
         };
 
-        for pattern in &self.allowed_hosts {
-            if pattern == "*" {
-                return true;
+        self.allowed_hosts.iter().any(|pattern| host_matches(pattern, host))
+    }
+
+    /// Check a URL against every network rule: scheme, host allowlist and,
+    /// for IP literals, private addresses. Applied to each redirect hop too.
+    pub fn check_url(&self, url: &url::Url) -> Result<(), FetchBlocked> {
+        match url.scheme() {
+            "https" => {}
+            "http" if self.allow_http => {}
+            scheme => return Err(FetchBlocked(format!("{}:// URLs are not allowed", scheme))),
+        }
+
+        let host = match url.host() {
+            Some(host) => host,
+            None => return Err(FetchBlocked(format!("{} has no host", url))),
+        };
+
+        if !self.is_host_allowed(url.as_str()) {
+            return Err(FetchBlocked(format!("host '{}' is not in allowed_hosts", host)));
+        }
+
+        if !self.allow_private_network {
+            let private = match host {
+                url::Host::Ipv4(ip) => is_private_ip(ip.into()),
+                url::Host::Ipv6(ip) => is_private_ip(ip.into()),
+                url::Host::Domain(name) => name == "localhost" || name.ends_with(".localhost"),
+            };
+            if private {
+                return Err(FetchBlocked(format!("{} is a loopback or private address", host)));
             }
-            
-            if pattern.starts_with("*.") {
-                // Wildcard subdomain match
-                let suffix = &pattern[1..]; // ".example.com"
-                if host.ends_with(suffix) || host == &pattern[2..] {
-                    return true;
-                }
-            } else if host == pattern {
-                return true;
+        }
+
+        Ok(())
+    }
+
+    /// HTTP client for a server's fetches. Redirects are re-checked with
+    /// `check_url`, and hostnames resolving to private addresses are refused
+    /// unless `allow_private_network` is set. System proxies are ignored,
+    /// since a proxy would resolve the host where this resolver can't see it.
+    pub fn http_client(&self) -> Result<reqwest::Client, String> {
+        let network = self.clone();
+        let redirect = reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error(FetchBlocked(format!("more than {} redirects", MAX_REDIRECTS)));
+            }
+            match network.check_url(attempt.url()) {
+                Ok(()) => attempt.follow(),
+                Err(e) => attempt.error(e),
             }
+        });
+
+        let mut builder = reqwest::Client::builder().redirect(redirect).no_proxy();
+        if !self.allow_private_network {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
         }
+        builder.build().map_err(|e| format!("Failed to create HTTP client: {}", e))
+    }
+}
+
+/// Match a host against a pattern: `*`, `*.example.com` (the domain and its
+/// subdomains) or an exact host.
+fn host_matches(pattern: &str, host: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+
+    if pattern.starts_with("*.") {
+        // Wildcard subdomain match
+        let suffix = &pattern[1..]; // ".example.com"
+        host.ends_with(suffix) || host == &pattern[2..]
+    } else {
+        host == pattern
+    }
+}
+
+/// A fetch refused by the sandbox.
+#[derive(Debug, Clone)]
+pub struct FetchBlocked(pub String);
 
-        false
+impl fmt::Display for FetchBlocked {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Fetch blocked: {}", self.0)
+    }
+}
+
+impl std::error::Error for FetchBlocked {}
+
+/// Loopback, private (RFC 1918, unique local), link-local, carrier-grade
+/// NAT and unspecified addresses.
+pub fn is_private_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || (a == 100 && (b & 0xc0) == 64)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_private_ip(v4.into()),
+            None => {
+                let first = ip.segments()[0];
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80
+            }
+        },
+    }
+}
+
+/// DNS resolver that refuses names resolving to private addresses, so an
+/// allowed hostname can't be pointed at the local network.
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0)).await?.collect();
+            if let Some(addr) = addrs.iter().find(|addr| is_private_ip(addr.ip())) {
+                let blocked = FetchBlocked(format!("{} resolves to private address {}", host, addr.ip()));
+                return Err(blocked.into());
+            }
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
     }
 }
 
 impl FilesystemCapabilities {
-    /// Check if a path is allowed for reading
-    #[allow(dead_code)]
-    pub fn can_read(&self, path: &Path) -> bool {
-        self.is_path_allowed(path, &self.read_paths)
+    /// Canonical form of a path allowed for reading. I/O should use it
+    /// rather than the original path, whose symlinks may since have changed.
+    pub fn check_read(&self, path: &Path) -> Option<PathBuf> {
+        self.allowed_path(path, &self.read_paths)
     }
 
-    /// Check if a path is allowed for writing
-    #[allow(dead_code)]
-    pub fn can_write(&self, path: &Path) -> bool {
-        self.is_path_allowed(path, &self.write_paths)
+    /// Canonical form of a path allowed for writing.
+    pub fn check_write(&self, path: &Path) -> Option<PathBuf> {
+        self.allowed_path(path, &self.write_paths)
     }
 
-    #[allow(dead_code)]
-    fn is_path_allowed(&self, path: &Path, allowed: &[String]) -> bool {
+    fn allowed_path(&self, path: &Path, allowed: &[String]) -> Option<PathBuf> {
         if allowed.is_empty() {
-            return false;
+            return None;
+        }
+
+        // `..` in a part of the path that doesn't exist yet can't be
+        // resolved by canonicalize, and could climb out of an allowed prefix
+        if path.components().any(|c| c == Component::ParentDir) {
+            return None;
         }
 
         // Canonicalize to prevent path traversal attacks
-        let canonical = match path.canonicalize() {
-            Ok(p) => p,
-            // If path doesn't exist yet (for write), check parent
-            Err(_) => {
-                if let Some(parent) = path.parent() {
-                    match parent.canonicalize() {
-                        Ok(p) => p.join(path.file_name().unwrap_or_default()),
-                        Err(_) => return false,
-                    }
-                } else {
-                    return false;
-                }
-            }
-        };
+        let canonical = canonicalize_existing(path)?;
 
         for prefix in allowed {
             let allowed_path = PathBuf::from(prefix);
             if let Ok(allowed_canonical) = allowed_path.canonicalize() {
                 if canonical.starts_with(&allowed_canonical) {
-                    return true;
+                    return Some(canonical);
                 }
             }
             // Also check if the prefix is a home-relative path
@@ -121,14 +339,36 @@ impl FilesystemCapabilities {
                     let expanded = home.join(&prefix[2..]);
                     if let Ok(expanded_canonical) = expanded.canonicalize() {
                         if canonical.starts_with(&expanded_canonical) {
-                            return true;
+                            return Some(canonical);
                         }
                     }
                 }
             }
         }
 
-        false
+        None
+    }
+}
+
+/// Canonicalize the longest existing ancestor of `path` and re-append the
+/// rest, so paths that don't exist yet (files to write, directories to
+/// create) can still be checked. Only components that are really absent
+/// count as missing: a dangling symlink fails to canonicalize too, but
+/// writing through it would create its target outside the allowed prefix,
+/// so it's refused.
+fn canonicalize_existing(path: &Path) -> Option<PathBuf> {
+    let mut missing = Vec::new();
+    let mut current = path;
+    loop {
+        if let Ok(canonical) = current.canonicalize() {
+            return Some(missing.iter().rev().fold(canonical, |p, name| p.join(name)));
+        }
+        match current.symlink_metadata() {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            _ => return None,
+        }
+        missing.push(current.file_name()?);
+        current = current.parent()?;
     }
 }
 
@@ -143,6 +383,7 @@ mod tests {
                 "api.example.com".to_string(),
                 "*.googleapis.com".to_string(),
             ],
+            ..Default::default()
         };
 
         assert!(caps.is_host_allowed("https://api.example.com/foo"));
@@ -152,11 +393,90 @@ mod tests {
         assert!(!caps.is_host_allowed("https://example.com/foo")); // exact match required
     }
 
+    #[test]
+    fn test_oauth_hosts() {
+        let oauth = OAuthCapabilities {
+            provider: "google".to_string(),
+            hosts: vec!["*.googleapis.com".to_string()],
+        };
+        let url = |s: &str| url::Url::parse(s).unwrap();
+        assert!(oauth.covers(&url("https://gmail.googleapis.com/gmail/v1/users/me")));
+        assert!(oauth.covers(&url("https://googleapis.com/")));
+        assert!(!oauth.covers(&url("https://googleapis.com.evil.com/")));
+        assert!(!oauth.covers(&url("https://example.com/")));
+        assert!(!oauth.covers(&url("http://gmail.googleapis.com/gmail/v1/users/me")));
+    }
+
     #[test]
     fn test_wildcard_all() {
         let caps = NetworkCapabilities {
             allowed_hosts: vec!["*".to_string()],
+            ..Default::default()
         };
         assert!(caps.is_host_allowed("https://anything.com/foo"));
     }
+
+    #[test]
+    fn test_check_url() {
+        let mut caps = NetworkCapabilities {
+            allowed_hosts: vec!["*".to_string()],
+            ..Default::default()
+        };
+        let check = |caps: &NetworkCapabilities, url: &str| caps.check_url(&url::Url::parse(url).unwrap());
+
+        assert!(check(&caps, "https://api.example.com/").is_ok());
+        assert!(check(&caps, "http://api.example.com/").is_err());
+        assert!(check(&caps, "file:///etc/passwd").is_err());
+        assert!(check(&caps, "https://127.0.0.1/").is_err());
+        assert!(check(&caps, "https://10.1.2.3/").is_err());
+        assert!(check(&caps, "https://[::1]/").is_err());
+        assert!(check(&caps, "https://[::ffff:192.168.0.1]/").is_err());
+        assert!(check(&caps, "https://localhost/").is_err());
+        assert!(check(&caps, "https://169.254.169.254/").is_err());
+
+        caps.allow_http = true;
+        caps.allow_private_network = true;
+        assert!(check(&caps, "http://localhost:8080/").is_ok());
+        assert!(check(&caps, "http://192.168.1.10/").is_ok());
+
+        caps.allowed_hosts = vec!["api.example.com".to_string()];
+        let err = check(&caps, "https://evil.com/").unwrap_err();
+        assert_eq!(err.to_string(), "Fetch blocked: host 'evil.com' is not in allowed_hosts");
+    }
+
+    #[test]
+    fn test_path_checks() {
+        let root = std::env::temp_dir().join(format!("harbor-sandbox-test-{}", std::process::id()));
+        let allowed = root.join("allowed");
+        std::fs::create_dir_all(&allowed).unwrap();
+
+        let caps = FilesystemCapabilities {
+            read_paths: vec![root.to_string_lossy().to_string()],
+            write_paths: vec![allowed.to_string_lossy().to_string()],
+            ..Default::default()
+        };
+
+        assert!(caps.check_read(&root.join("anything.txt")).is_some());
+        assert!(caps.check_write(&allowed.join("new/nested/file.txt")).is_some());
+        assert!(caps.check_write(&root.join("outside.txt")).is_none());
+        assert!(caps.check_write(&allowed.join("new/../../outside.txt")).is_none());
+        assert!(caps.check_read(Path::new("/etc/passwd")).is_none());
+
+        // The checked path is the canonical one, with symlinks resolved
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&allowed, root.join("link")).unwrap();
+            assert_eq!(
+                caps.check_write(&root.join("link/file.txt")),
+                Some(allowed.canonicalize().unwrap().join("file.txt"))
+            );
+
+            // A dangling symlink isn't a missing file: writing would follow it
+            std::os::unix::fs::symlink(root.join("outside.txt"), allowed.join("dangling")).unwrap();
+            assert!(caps.check_write(&allowed.join("dangling")).is_none());
+            assert!(caps.check_write(&allowed.join("dangling/file.txt")).is_none());
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
 }
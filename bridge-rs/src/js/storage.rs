@@ -0,0 +1,351 @@
+//! Persistent key-value `storage` global for JS servers.
+//!
+//! Each server gets its own namespace, kept as one JSON file under
+//! `~/.harbor/js_storage`. Values are anything `JSON.stringify` accepts, and
+//! the bytes taken by keys and serialized values are capped by the quota in
+//! `StorageCapabilities`. Stores are shared through a registry so the
+//! `js.get_storage` and `js.clear_storage` RPCs see the same data as a
+//! running server.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+
+use rquickjs::{Ctx, Exception, Function, Object};
+
+lazy_static::lazy_static! {
+    // Open stores by file path. Entries go away once no server or RPC
+    // holds the store.
+    static ref STORES: Mutex<HashMap<PathBuf, Weak<Mutex<Store>>>> = Mutex::new(HashMap::new());
+}
+
+/// JS side of the API: wraps the native functions in promises, converts
+/// values to and from JSON, and turns quota errors into `DOMException`s.
+const STORAGE_JS: &str = r#"
+    (function() {
+        const native = globalThis.__storage_native;
+        delete globalThis.__storage_native;
+
+        const call = (fn) => new Promise((resolve, reject) => {
+            try {
+                resolve(fn());
+            } catch (e) {
+                const message = String(e && e.message || e);
+                if (message.startsWith('QuotaExceededError:')) {
+                    reject(new DOMException(message, 'QuotaExceededError'));
+                } else {
+                    reject(e);
+                }
+            }
+        });
+
+        globalThis.storage = {
+            get: (key) => call(() => {
+                const json = native.get(String(key));
+                return json === undefined ? undefined : JSON.parse(json);
+            }),
+            set: (key, value) => call(() => {
+                const json = JSON.stringify(value);
+                if (json === undefined) throw new TypeError(`storage.set: ${typeof value} is not JSON`);
+                native.set(String(key), json);
+            }),
+            delete: (key) => call(() => native.delete(String(key))),
+            list: (prefix) => call(() => native.list(prefix === undefined ? '' : String(prefix))),
+        };
+    })();
+"#;
+
+/// One server's storage. Values are kept as serialized JSON, and the whole
+/// map is rewritten to disk after every change.
+#[derive(Debug)]
+pub struct Store {
+    path: PathBuf,
+    entries: BTreeMap<String, String>,
+    size: usize,
+}
+
+impl Store {
+    /// Default storage directory (`~/.harbor/js_storage`).
+    pub fn default_dir() -> PathBuf {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".harbor")
+            .join("js_storage")
+    }
+
+    /// Load a store file, starting empty if it doesn't exist yet.
+    fn load(path: PathBuf) -> Result<Self, String> {
+        let entries: BTreeMap<String, serde_json::Value> = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        let entries: BTreeMap<String, String> = entries
+            .into_iter()
+            .map(|(key, value)| (key, value.to_string()))
+            .collect();
+        let size = entries.iter().map(|(key, json)| key.len() + json.len()).sum();
+        Ok(Self { path, entries, size })
+    }
+
+    /// Write the entries out, replacing the file in one step. An empty store
+    /// removes its file.
+    fn save(&self) -> Result<(), String> {
+        if self.entries.is_empty() {
+            return match std::fs::remove_file(&self.path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(format!("Failed to remove {}: {}", self.path.display(), e))
+                }
+                _ => Ok(()),
+            };
+        }
+
+        // Values are already JSON, so the object can be assembled as text
+        let mut contents = String::from("{");
+        for (i, (key, json)) in self.entries.iter().enumerate() {
+            if i > 0 {
+                contents.push(',');
+            }
+            contents.push_str(&serde_json::Value::from(key.as_str()).to_string());
+            contents.push(':');
+            contents.push_str(json);
+        }
+        contents.push('}');
+
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create storage directory: {}", e))?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // Servers may keep credentials here; keep the file owner-only
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(&tmp)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+        std::fs::rename(&tmp, &self.path).map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+
+    /// Serialized value of a key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(String::as_str)
+    }
+
+    /// Store serialized JSON under a key, unless the store would grow past
+    /// `quota_bytes`.
+    pub fn set(&mut self, key: &str, json: String, quota_bytes: usize) -> Result<(), String> {
+        let old = self.entries.get(key).map_or(0, |old| key.len() + old.len());
+        let size = self.size - old + key.len() + json.len();
+        if size > quota_bytes {
+            return Err(format!(
+                "QuotaExceededError: storage would use {} of its {} bytes",
+                size, quota_bytes
+            ));
+        }
+        let previous = self.entries.insert(key.to_string(), json);
+        let previous_size = std::mem::replace(&mut self.size, size);
+        if let Err(e) = self.save() {
+            // Keep memory in line with what's on disk
+            match previous {
+                Some(json) => self.entries.insert(key.to_string(), json),
+                None => self.entries.remove(key),
+            };
+            self.size = previous_size;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Remove a key, returning whether it was there.
+    pub fn delete(&mut self, key: &str) -> Result<bool, String> {
+        let Some(json) = self.entries.remove(key) else {
+            return Ok(false);
+        };
+        self.size -= key.len() + json.len();
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Keys starting with `prefix`, in order.
+    pub fn keys(&self, prefix: &str) -> Vec<String> {
+        self.entries
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Remove every key, returning how many there were.
+    pub fn clear(&mut self) -> Result<usize, String> {
+        let count = self.entries.len();
+        self.entries.clear();
+        self.size = 0;
+        self.save()?;
+        Ok(count)
+    }
+
+    /// Bytes taken by keys and serialized values.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// All entries with their values parsed.
+    pub fn entries(&self) -> serde_json::Map<String, serde_json::Value> {
+        self.entries
+            .iter()
+            .map(|(key, json)| (key.clone(), serde_json::from_str(json).unwrap_or(serde_json::Value::Null)))
+            .collect()
+    }
+}
+
+/// File name for a server's storage. Server IDs are arbitrary strings, so
+/// anything outside a safe alphabet is percent-encoded.
+fn file_name(server_id: &str) -> String {
+    let mut name = String::new();
+    for byte in server_id.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    name.push_str(".json");
+    name
+}
+
+/// Open a server's store in the default directory.
+pub fn open(server_id: &str) -> Result<Arc<Mutex<Store>>, String> {
+    open_in(&Store::default_dir(), server_id)
+}
+
+/// Open a server's store in `dir`, sharing it with anyone who already has it
+/// open.
+pub fn open_in(dir: &Path, server_id: &str) -> Result<Arc<Mutex<Store>>, String> {
+    let path = dir.join(file_name(server_id));
+    let mut stores = STORES.lock().unwrap();
+    stores.retain(|_, store| store.strong_count() > 0);
+    if let Some(store) = stores.get(&path).and_then(Weak::upgrade) {
+        return Ok(store);
+    }
+    let store = Arc::new(Mutex::new(Store::load(path.clone())?));
+    stores.insert(path, Arc::downgrade(&store));
+    Ok(store)
+}
+
+fn throw<T>(ctx: &Ctx<'_>, message: String) -> rquickjs::Result<T> {
+    Err(Exception::throw_message(ctx, &message))
+}
+
+/// Install the `storage` global for a server.
+pub fn install<'js>(ctx: &Ctx<'js>, store: Arc<Mutex<Store>>, quota_bytes: usize) -> Result<(), String> {
+    let native = Object::new(ctx.clone()).map_err(|e| e.to_string())?;
+    let set = |name: &str, f: rquickjs::Result<Function<'js>>| {
+        native.set(name, f.map_err(|e| e.to_string())?).map_err(|e| e.to_string())
+    };
+
+    let s = store.clone();
+    set("get", Function::new(ctx.clone(), move |key: String| {
+        s.lock().unwrap().get(&key).map(str::to_string)
+    }))?;
+    let s = store.clone();
+    set("set", Function::new(ctx.clone(), move |ctx: Ctx<'_>, key: String, json: String| {
+        s.lock().unwrap().set(&key, json, quota_bytes).or_else(|e| throw(&ctx, e))
+    }))?;
+    let s = store.clone();
+    set("delete", Function::new(ctx.clone(), move |ctx: Ctx<'_>, key: String| {
+        s.lock().unwrap().delete(&key).or_else(|e| throw(&ctx, e))
+    }))?;
+    let s = store;
+    set("list", Function::new(ctx.clone(), move |prefix: String| s.lock().unwrap().keys(&prefix)))?;
+
+    ctx.globals().set("__storage_native", native).map_err(|e| e.to_string())?;
+    ctx.eval::<(), _>(STORAGE_JS).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rquickjs::{Context, Runtime};
+
+    #[test]
+    fn test_storage_global() {
+        let dir = std::env::temp_dir().join(format!("harbor-js-storage-test-{}", std::process::id()));
+        let store = open_in(&dir, "test/server").unwrap();
+
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            crate::js::web::install(&ctx).unwrap();
+            install(&ctx, store.clone(), 64).unwrap();
+            ctx.eval::<(), _>(r#"
+                globalThis.result = null;
+                (async () => {
+                    await storage.set('user:1', { name: 'Ada', tags: ['a'] });
+                    await storage.set('user:2', 2);
+                    await storage.set('other', 'x');
+                    const user = await storage.get('user:1');
+                    const missing = await storage.get('nope');
+                    const keys = await storage.list('user:');
+                    const deleted = await storage.delete('user:2');
+                    const deletedAgain = await storage.delete('user:2');
+                    let notJson = null;
+                    try { await storage.set('f', () => 1); } catch (e) { notJson = e.name; }
+                    let quota = null;
+                    try { await storage.set('big', 'x'.repeat(100)); } catch (e) { quota = e.name; }
+                    globalThis.result = JSON.stringify({
+                        user, missing: missing === undefined, keys, deleted, deletedAgain, notJson, quota,
+                        all: await storage.list(),
+                    });
+                })();
+            "#).unwrap();
+        });
+        while runtime.is_job_pending() {
+            runtime.execute_pending_job().unwrap();
+        }
+
+        let result: String = context.with(|ctx| ctx.eval("globalThis.result").unwrap());
+        let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(
+            result,
+            serde_json::json!({
+                "user": { "name": "Ada", "tags": ["a"] },
+                "missing": true,
+                "keys": ["user:1", "user:2"],
+                "deleted": true,
+                "deletedAgain": false,
+                "notJson": "TypeError",
+                "quota": "QuotaExceededError",
+                "all": ["other", "user:1"],
+            })
+        );
+
+        // What's on disk matches, and clearing removes the file
+        let path = dir.join("test%2Fserver.json");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let reloaded = Store::load(path.clone()).unwrap();
+        assert_eq!(reloaded.entries(), store.lock().unwrap().entries());
+        assert_eq!(reloaded.size(), store.lock().unwrap().size());
+        assert_eq!(store.lock().unwrap().clear().unwrap(), 2);
+        assert!(!path.exists());
+
+        // The store is shared while open and dropped from the registry after
+        assert!(Arc::ptr_eq(&store, &open_in(&dir, "test/server").unwrap()));
+        drop((context, runtime, store));
+        open_in(&dir, "other").unwrap();
+        assert!(!STORES.lock().unwrap().contains_key(&path));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1281 @@
+//! Web-platform globals for JS servers.
+//!
+//! Installs the parts of the browser/Node API servers commonly rely on:
+//! `TextEncoder`/`TextDecoder`, `URL`/`URLSearchParams`, `crypto`
+//! (`getRandomValues`, `randomUUID`, `subtle.digest`), `atob`/`btoa`,
+//! `AbortController`/`AbortSignal`, `Headers`/`Request`/`Response`,
+//! `ReadableStream`, `Blob`/`File`, `FormData`, `DOMException` and
+//! `structuredClone`. Encoding, URL parsing, base64,
+//! randomness and hashing are done in Rust; the classes around them are JS.
+
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+use rand::RngCore;
+use rquickjs::{Ctx, Exception, Function, Object, TypedArray};
+use sha2::Digest;
+use url::Url;
+
+/// Most bytes `crypto.getRandomValues` fills in one call.
+const MAX_RANDOM_BYTES: usize = 65536;
+
+/// Base64 as `atob` reads it: padding optional, trailing bits ignored.
+const FORGIVING: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+        .with_decode_allow_trailing_bits(true),
+);
+
+/// JS side of the API, built on the native functions in `__web_native`.
+const WEB_JS: &str = r#"
+    (function() {
+        const native = globalThis.__web_native;
+        delete globalThis.__web_native;
+
+        class DOMException extends Error {
+            constructor(message = '', name = 'Error') {
+                super(message);
+                this.name = name;
+            }
+        }
+        globalThis.DOMException = DOMException;
+
+        // Bytes of a BufferSource as a Uint8Array, without copying
+        const bytesOf = (data) => {
+            if (data instanceof Uint8Array) return data;
+            if (data instanceof ArrayBuffer) return new Uint8Array(data);
+            if (ArrayBuffer.isView(data)) return new Uint8Array(data.buffer, data.byteOffset, data.byteLength);
+            throw new TypeError('Expected an ArrayBuffer or ArrayBufferView');
+        };
+        const bufferOf = (bytes) => bytes.buffer.slice(bytes.byteOffset, bytes.byteOffset + bytes.byteLength);
+        const concat = (chunks) => {
+            const bytes = new Uint8Array(chunks.reduce((total, chunk) => total + chunk.length, 0));
+            let offset = 0;
+            for (const chunk of chunks) {
+                bytes.set(chunk, offset);
+                offset += chunk.length;
+            }
+            return bytes;
+        };
+
+        // Encoding
+
+        class TextEncoder {
+            get encoding() { return 'utf-8'; }
+            encode(input = '') { return native.encode(String(input)); }
+            encodeInto(input, dest) {
+                const bytes = native.encode(String(input));
+                // Stop at the last whole character that fits
+                let written = Math.min(bytes.length, dest.length);
+                while (written > 0 && written < bytes.length && (bytes[written] & 0xc0) === 0x80) written--;
+                dest.set(bytes.subarray(0, written));
+                const read = native.decode(bytes.subarray(0, written), false).length;
+                return { read, written };
+            }
+        }
+
+        class TextDecoder {
+            constructor(label = 'utf-8', options = {}) {
+                const encoding = String(label).trim().toLowerCase();
+                if (encoding !== 'utf-8' && encoding !== 'utf8' && encoding !== 'unicode-1-1-utf-8') {
+                    throw new RangeError(`Unsupported encoding '${label}'`);
+                }
+                this.fatal = !!options.fatal;
+                this.ignoreBOM = !!options.ignoreBOM;
+            }
+            get encoding() { return 'utf-8'; }
+            decode(input) {
+                if (input === undefined) return '';
+                const text = native.decode(bytesOf(input), this.fatal);
+                if (text === undefined) throw new TypeError('The encoded data was not valid utf-8');
+                return !this.ignoreBOM && text.charCodeAt(0) === 0xfeff ? text.slice(1) : text;
+            }
+        }
+
+        globalThis.TextEncoder = TextEncoder;
+        globalThis.TextDecoder = TextDecoder;
+
+        globalThis.btoa = (data) => {
+            const encoded = native.btoa(String(data));
+            if (encoded === undefined) throw new DOMException('The string contains characters outside of the Latin1 range', 'InvalidCharacterError');
+            return encoded;
+        };
+        globalThis.atob = (data) => {
+            const decoded = native.atob(String(data));
+            if (decoded === undefined) throw new DOMException('The string is not correctly encoded', 'InvalidCharacterError');
+            return decoded;
+        };
+
+        // URL
+
+        class URLSearchParams {
+            #pairs = [];
+            #onChange = null;
+
+            constructor(init = '') {
+                if (typeof init === 'object' && init !== null) {
+                    if (typeof init[Symbol.iterator] === 'function') {
+                        for (const pair of init) {
+                            const [name, value, ...rest] = Array.from(pair);
+                            if (value === undefined || rest.length) throw new TypeError('Each pair must have exactly two items');
+                            this.#pairs.push([String(name), String(value)]);
+                        }
+                    } else {
+                        for (const name of Object.keys(init)) this.#pairs.push([name, String(init[name])]);
+                    }
+                } else {
+                    this.#parse(String(init));
+                }
+            }
+
+            #parse(query) {
+                this.#pairs = native.formParse(query.startsWith('?') ? query.slice(1) : query);
+            }
+            #update() {
+                if (this.#onChange) this.#onChange(this.toString());
+            }
+            // Link to the URL owning these params
+            static _link(params, query, onChange) {
+                params.#parse(query);
+                params.#onChange = onChange;
+            }
+
+            get size() { return this.#pairs.length; }
+            append(name, value) {
+                this.#pairs.push([String(name), String(value)]);
+                this.#update();
+            }
+            delete(name, value) {
+                name = String(name);
+                this.#pairs = this.#pairs.filter(([n, v]) => n !== name || (value !== undefined && v !== String(value)));
+                this.#update();
+            }
+            get(name) {
+                const pair = this.#pairs.find(([n]) => n === String(name));
+                return pair ? pair[1] : null;
+            }
+            getAll(name) {
+                return this.#pairs.filter(([n]) => n === String(name)).map(([, v]) => v);
+            }
+            has(name, value) {
+                return this.#pairs.some(([n, v]) => n === String(name) && (value === undefined || v === String(value)));
+            }
+            set(name, value) {
+                name = String(name);
+                const index = this.#pairs.findIndex(([n]) => n === name);
+                if (index < 0) {
+                    this.#pairs.push([name, String(value)]);
+                } else {
+                    this.#pairs[index][1] = String(value);
+                    this.#pairs = this.#pairs.filter(([n], i) => n !== name || i <= index);
+                }
+                this.#update();
+            }
+            sort() {
+                this.#pairs.sort(([a], [b]) => (a < b ? -1 : a > b ? 1 : 0));
+                this.#update();
+            }
+            forEach(callback, thisArg) {
+                for (const [name, value] of this.#pairs) callback.call(thisArg, value, name, this);
+            }
+            *entries() { for (const [name, value] of this.#pairs) yield [name, value]; }
+            *keys() { for (const [name] of this.#pairs) yield name; }
+            *values() { for (const [, value] of this.#pairs) yield value; }
+            [Symbol.iterator]() { return this.entries(); }
+            toString() { return native.formSerialize(this.#pairs); }
+        }
+
+        class URL {
+            #parts;
+            #searchParams = new URLSearchParams();
+
+            constructor(url, base) {
+                const parts = native.urlParse(String(url), base === undefined ? undefined : String(base));
+                if (parts === undefined) throw new TypeError(`Invalid URL: ${url}`);
+                this.#parts = parts;
+                URLSearchParams._link(this.#searchParams, parts.search, (query) => {
+                    this.#parts = native.urlSet(this.#parts.href, 'search', query);
+                });
+            }
+
+            static canParse(url, base) {
+                return native.urlParse(String(url), base === undefined ? undefined : String(base)) !== undefined;
+            }
+
+            #set(name, value) {
+                this.#parts = native.urlSet(this.#parts.href, name, String(value));
+                if (name === 'href' || name === 'search') {
+                    URLSearchParams._link(this.#searchParams, this.#parts.search, (query) => {
+                        this.#parts = native.urlSet(this.#parts.href, 'search', query);
+                    });
+                }
+            }
+
+            get href() { return this.#parts.href; }
+            set href(value) {
+                if (native.urlParse(String(value)) === undefined) throw new TypeError(`Invalid URL: ${value}`);
+                this.#set('href', value);
+            }
+            get origin() { return this.#parts.origin; }
+            get protocol() { return this.#parts.protocol; }
+            set protocol(value) { this.#set('protocol', value); }
+            get username() { return this.#parts.username; }
+            set username(value) { this.#set('username', value); }
+            get password() { return this.#parts.password; }
+            set password(value) { this.#set('password', value); }
+            get host() { return this.#parts.host; }
+            set host(value) { this.#set('host', value); }
+            get hostname() { return this.#parts.hostname; }
+            set hostname(value) { this.#set('hostname', value); }
+            get port() { return this.#parts.port; }
+            set port(value) { this.#set('port', value); }
+            get pathname() { return this.#parts.pathname; }
+            set pathname(value) { this.#set('pathname', value); }
+            get search() { return this.#parts.search; }
+            set search(value) { this.#set('search', value); }
+            get hash() { return this.#parts.hash; }
+            set hash(value) { this.#set('hash', value); }
+            get searchParams() { return this.#searchParams; }
+            toString() { return this.#parts.href; }
+            toJSON() { return this.#parts.href; }
+        }
+
+        globalThis.URLSearchParams = URLSearchParams;
+        globalThis.URL = URL;
+
+        // Crypto
+
+        const digests = { 'SHA-1': 'sha1', 'SHA-256': 'sha256', 'SHA-384': 'sha384', 'SHA-512': 'sha512' };
+        globalThis.crypto = {
+            getRandomValues(array) {
+                const integer = [Int8Array, Uint8Array, Uint8ClampedArray, Int16Array, Uint16Array, Int32Array, Uint32Array, BigInt64Array, BigUint64Array];
+                if (!integer.some((type) => array instanceof type)) {
+                    throw new DOMException('The array must be an integer typed array', 'TypeMismatchError');
+                }
+                if (array.byteLength > 65536) {
+                    throw new DOMException('The array is larger than 65536 bytes', 'QuotaExceededError');
+                }
+                bytesOf(array).set(native.randomBytes(array.byteLength));
+                return array;
+            },
+            randomUUID: () => native.randomUUID(),
+            subtle: {
+                async digest(algorithm, data) {
+                    const name = String(typeof algorithm === 'object' && algorithm !== null ? algorithm.name : algorithm).toUpperCase();
+                    if (!(name in digests)) throw new DOMException(`Unsupported digest '${name}'`, 'NotSupportedError');
+                    return native.digest(digests[name], bytesOf(data)).buffer;
+                },
+            },
+        };
+
+        // Events and aborting
+
+        class Event {
+            constructor(type, options = {}) {
+                this.type = String(type);
+                this.cancelable = !!options.cancelable;
+                this.defaultPrevented = false;
+                this.target = null;
+                this.timeStamp = Date.now();
+            }
+            preventDefault() {
+                if (this.cancelable) this.defaultPrevented = true;
+            }
+        }
+
+        class EventTarget {
+            #listeners = new Map();
+
+            addEventListener(type, listener, options = {}) {
+                if (!listener) return;
+                const listeners = this.#listeners.get(type) || [];
+                if (listeners.some((l) => l.listener === listener)) return;
+                listeners.push({ listener, once: typeof options === 'object' && !!options.once });
+                this.#listeners.set(type, listeners);
+            }
+            removeEventListener(type, listener) {
+                const listeners = this.#listeners.get(type) || [];
+                this.#listeners.set(type, listeners.filter((l) => l.listener !== listener));
+            }
+            dispatchEvent(event) {
+                event.target = this;
+                const handler = this['on' + event.type];
+                if (typeof handler === 'function') handler.call(this, event);
+                for (const entry of [...(this.#listeners.get(event.type) || [])]) {
+                    if (entry.once) this.removeEventListener(event.type, entry.listener);
+                    if (typeof entry.listener === 'function') entry.listener.call(this, event);
+                    else entry.listener.handleEvent(event);
+                }
+                return !event.defaultPrevented;
+            }
+        }
+
+        class AbortSignal extends EventTarget {
+            aborted = false;
+            reason = undefined;
+            onabort = null;
+
+            static abort(reason) {
+                const controller = new AbortController();
+                controller.abort(reason);
+                return controller.signal;
+            }
+            static timeout(ms) {
+                const controller = new AbortController();
+                setTimeout(() => controller.abort(new DOMException('The operation timed out', 'TimeoutError')), ms);
+                return controller.signal;
+            }
+            static any(signals) {
+                const controller = new AbortController();
+                for (const signal of signals) {
+                    if (signal.aborted) {
+                        controller.abort(signal.reason);
+                        break;
+                    }
+                    signal.addEventListener('abort', () => controller.abort(signal.reason), { once: true });
+                }
+                return controller.signal;
+            }
+            throwIfAborted() {
+                if (this.aborted) throw this.reason;
+            }
+        }
+
+        class AbortController {
+            #signal = new AbortSignal();
+
+            get signal() { return this.#signal; }
+            abort(reason) {
+                const signal = this.#signal;
+                if (signal.aborted) return;
+                signal.aborted = true;
+                signal.reason = reason === undefined ? new DOMException('This operation was aborted', 'AbortError') : reason;
+                signal.dispatchEvent(new Event('abort'));
+            }
+        }
+
+        globalThis.Event = Event;
+        globalThis.EventTarget = EventTarget;
+        globalThis.AbortSignal = AbortSignal;
+        globalThis.AbortController = AbortController;
+
+        // Streams, blobs and forms
+
+        class ReadableStream {
+            #source;
+            #controller;
+            #started;
+            #state = 'readable';
+            #error;
+            #queue = [];
+            #waiting = [];
+            #pulling = false;
+            #locked = false;
+            #disturbed = false;
+
+            constructor(source = {}) {
+                this.#source = source;
+                this.#controller = {
+                    enqueue: (chunk) => {
+                        if (this.#state !== 'readable') throw new TypeError('The stream is closed');
+                        const read = this.#waiting.shift();
+                        if (read) read.resolve({ value: chunk, done: false });
+                        else this.#queue.push(chunk);
+                    },
+                    close: () => {
+                        if (this.#state !== 'readable') throw new TypeError('The stream is closed');
+                        this.#state = 'closed';
+                        for (const read of this.#waiting.splice(0)) read.resolve({ value: undefined, done: true });
+                    },
+                    error: (e) => this.#fail(e),
+                };
+                try {
+                    this.#started = Promise.resolve(source.start ? source.start(this.#controller) : undefined);
+                } catch (e) {
+                    this.#started = Promise.resolve();
+                    this.#fail(e);
+                }
+                this.#started.catch((e) => this.#fail(e));
+            }
+
+            static _disturbed(stream) { return stream.#disturbed; }
+
+            #fail(e) {
+                if (this.#state !== 'readable') return;
+                this.#state = 'errored';
+                this.#error = e;
+                this.#queue = [];
+                for (const read of this.#waiting.splice(0)) read.reject(e);
+            }
+            #pull() {
+                if (this.#pulling || !this.#source.pull) return;
+                this.#pulling = true;
+                this.#started
+                    .then(() => this.#source.pull(this.#controller))
+                    .then(() => {
+                        this.#pulling = false;
+                        if (this.#waiting.length && this.#state === 'readable') this.#pull();
+                    }, (e) => {
+                        this.#pulling = false;
+                        this.#fail(e);
+                    });
+            }
+            #read() {
+                this.#disturbed = true;
+                if (this.#queue.length) return Promise.resolve({ value: this.#queue.shift(), done: false });
+                if (this.#state === 'closed') return Promise.resolve({ value: undefined, done: true });
+                if (this.#state === 'errored') return Promise.reject(this.#error);
+                return new Promise((resolve, reject) => {
+                    this.#waiting.push({ resolve, reject });
+                    this.#pull();
+                });
+            }
+            async #cancel(reason) {
+                this.#disturbed = true;
+                if (this.#state === 'errored') throw this.#error;
+                if (this.#state === 'closed') return;
+                this.#state = 'closed';
+                this.#queue = [];
+                for (const read of this.#waiting.splice(0)) read.resolve({ value: undefined, done: true });
+                if (this.#source.cancel) await this.#source.cancel(reason);
+            }
+
+            get locked() { return this.#locked; }
+            cancel(reason) {
+                if (this.#locked) return Promise.reject(new TypeError('The stream is locked'));
+                return this.#cancel(reason);
+            }
+            getReader() {
+                if (this.#locked) throw new TypeError('The stream is locked');
+                this.#locked = true;
+                let released = false;
+                const check = () => {
+                    if (released) throw new TypeError('The reader has been released');
+                };
+                return {
+                    read: () => {
+                        try { check(); } catch (e) { return Promise.reject(e); }
+                        return this.#read();
+                    },
+                    cancel: (reason) => {
+                        try { check(); } catch (e) { return Promise.reject(e); }
+                        return this.#cancel(reason);
+                    },
+                    releaseLock: () => {
+                        if (!released) this.#locked = false;
+                        released = true;
+                    },
+                };
+            }
+            tee() {
+                const reader = this.getReader();
+                const branches = [];
+                let reading = null;
+                const pull = () => reading || (reading = reader.read().then(({ value, done }) => {
+                    reading = null;
+                    for (const branch of branches) {
+                        try {
+                            if (done) branch.close();
+                            else branch.enqueue(value);
+                        } catch (e) {
+                            // That branch was cancelled
+                        }
+                    }
+                }, (e) => {
+                    for (const branch of branches) branch.error(e);
+                }));
+                const branch = () => new ReadableStream({ start: (controller) => { branches.push(controller); }, pull });
+                return [branch(), branch()];
+            }
+            async *[Symbol.asyncIterator]() {
+                const reader = this.getReader();
+                let done = false;
+                try {
+                    while (true) {
+                        const result = await reader.read();
+                        if (result.done) {
+                            done = true;
+                            return;
+                        }
+                        yield result.value;
+                    }
+                } finally {
+                    if (!done) await reader.cancel();
+                    reader.releaseLock();
+                }
+            }
+        }
+
+        class Blob {
+            #bytes;
+            #type;
+
+            constructor(parts = [], options = {}) {
+                this.#bytes = concat(Array.from(parts, (part) => {
+                    if (part instanceof Blob) return part.#bytes;
+                    if (part instanceof ArrayBuffer || ArrayBuffer.isView(part)) return bytesOf(part);
+                    return native.encode(String(part));
+                }));
+                this.#type = String(options.type || '').toLowerCase();
+            }
+
+            static _bytes(blob) { return blob.#bytes; }
+
+            get size() { return this.#bytes.length; }
+            get type() { return this.#type; }
+            slice(start, end, type = '') {
+                const blob = new Blob([], { type });
+                blob.#bytes = this.#bytes.slice(start, end);
+                return blob;
+            }
+            async arrayBuffer() { return bufferOf(this.#bytes); }
+            async bytes() { return this.#bytes.slice(); }
+            async text() { return native.decode(this.#bytes, false); }
+            stream() {
+                const bytes = this.#bytes.slice();
+                return new ReadableStream({
+                    start(controller) {
+                        if (bytes.length) controller.enqueue(bytes);
+                        controller.close();
+                    },
+                });
+            }
+        }
+
+        class File extends Blob {
+            constructor(parts, name, options = {}) {
+                super(parts, options);
+                this.name = String(name);
+                this.lastModified = options.lastModified === undefined ? Date.now() : Number(options.lastModified);
+            }
+        }
+
+        class FormData {
+            #entries = [];
+
+            static #entry(name, value, filename) {
+                if (!(value instanceof Blob)) return [String(name), String(value)];
+                if (filename === undefined && value instanceof File) return [String(name), value];
+                filename = filename === undefined ? 'blob' : String(filename);
+                return [String(name), new File([value], filename, { type: value.type })];
+            }
+
+            // multipart/form-data encoding: [bytes, content type]
+            static _encode(form) {
+                const boundary = '----HarborFormBoundary' + native.randomUUID().replace(/-/g, '');
+                const escape = (s) => s.replace(/\r/g, '%0D').replace(/\n/g, '%0A').replace(/"/g, '%22');
+                const parts = [];
+                for (const [name, value] of form.#entries) {
+                    let head = `--${boundary}\r\nContent-Disposition: form-data; name="${escape(name)}"`;
+                    if (value instanceof File) {
+                        head += `; filename="${escape(value.name)}"\r\nContent-Type: ${value.type || 'application/octet-stream'}`;
+                    }
+                    const data = typeof value === 'string' ? native.encode(value.replace(/\r?\n|\r/g, '\r\n')) : Blob._bytes(value);
+                    parts.push(native.encode(head + '\r\n\r\n'), data, native.encode('\r\n'));
+                }
+                parts.push(native.encode(`--${boundary}--\r\n`));
+                return [concat(parts), `multipart/form-data; boundary=${boundary}`];
+            }
+
+            append(name, value, filename) { this.#entries.push(FormData.#entry(name, value, filename)); }
+            delete(name) { this.#entries = this.#entries.filter(([n]) => n !== String(name)); }
+            get(name) {
+                const entry = this.#entries.find(([n]) => n === String(name));
+                return entry ? entry[1] : null;
+            }
+            getAll(name) { return this.#entries.filter(([n]) => n === String(name)).map(([, v]) => v); }
+            has(name) { return this.#entries.some(([n]) => n === String(name)); }
+            set(name, value, filename) {
+                const entry = FormData.#entry(name, value, filename);
+                const index = this.#entries.findIndex(([n]) => n === entry[0]);
+                if (index < 0) {
+                    this.#entries.push(entry);
+                } else {
+                    this.#entries[index] = entry;
+                    this.#entries = this.#entries.filter(([n], i) => n !== entry[0] || i <= index);
+                }
+            }
+            forEach(callback, thisArg) {
+                for (const [name, value] of this.#entries) callback.call(thisArg, value, name, this);
+            }
+            *entries() { for (const [name, value] of this.#entries) yield [name, value]; }
+            *keys() { for (const [name] of this.#entries) yield name; }
+            *values() { for (const [, value] of this.#entries) yield value; }
+            [Symbol.iterator]() { return this.entries(); }
+        }
+
+        globalThis.ReadableStream = ReadableStream;
+        globalThis.Blob = Blob;
+        globalThis.File = File;
+        globalThis.FormData = FormData;
+
+        // Fetch types
+
+        class Headers {
+            #map = new Map();
+
+            constructor(init) {
+                if (init instanceof Headers) {
+                    init.forEach((value, name) => this.append(name, value));
+                } else if (init && typeof init[Symbol.iterator] === 'function') {
+                    for (const [name, value] of init) this.append(name, value);
+                } else if (init) {
+                    for (const name of Object.keys(init)) this.append(name, init[name]);
+                }
+            }
+
+            static #name(name) {
+                name = String(name);
+                if (!/^[!#$%&'*+\-.^_`|~0-9A-Za-z]+$/.test(name)) throw new TypeError(`Invalid header name '${name}'`);
+                return name.toLowerCase();
+            }
+
+            append(name, value) {
+                name = Headers.#name(name);
+                value = String(value).trim();
+                const current = this.#map.get(name);
+                this.#map.set(name, current === undefined ? value : `${current}, ${value}`);
+            }
+            delete(name) { this.#map.delete(Headers.#name(name)); }
+            get(name) {
+                const value = this.#map.get(Headers.#name(name));
+                return value === undefined ? null : value;
+            }
+            has(name) { return this.#map.has(Headers.#name(name)); }
+            set(name, value) { this.#map.set(Headers.#name(name), String(value).trim()); }
+            forEach(callback, thisArg) {
+                for (const [name, value] of this.entries()) callback.call(thisArg, value, name, this);
+            }
+            *entries() { yield* [...this.#map.entries()].sort(([a], [b]) => (a < b ? -1 : 1)); }
+            *keys() { for (const [name] of this.entries()) yield name; }
+            *values() { for (const [, value] of this.entries()) yield value; }
+            [Symbol.iterator]() { return this.entries(); }
+        }
+
+        // Body shared by Request and Response: a string, bytes or a stream,
+        // read once
+        class Body {
+            #body;
+            #type = null;
+            #used = false;
+
+            constructor(body) {
+                if (body === undefined || body === null) {
+                    this.#body = null;
+                } else if (typeof body === 'string') {
+                    this.#body = body;
+                    this.#type = 'text/plain;charset=UTF-8';
+                } else if (body instanceof URLSearchParams) {
+                    this.#body = body.toString();
+                    this.#type = 'application/x-www-form-urlencoded;charset=UTF-8';
+                } else if (body instanceof FormData) {
+                    [this.#body, this.#type] = FormData._encode(body);
+                } else if (body instanceof Blob) {
+                    this.#body = Blob._bytes(body);
+                    this.#type = body.type || null;
+                } else if (body instanceof ArrayBuffer || ArrayBuffer.isView(body)) {
+                    this.#body = bytesOf(body).slice();
+                } else if (body instanceof ReadableStream) {
+                    this.#body = body;
+                } else {
+                    this.#body = String(body);
+                    this.#type = 'text/plain;charset=UTF-8';
+                }
+            }
+
+            // Content type implied by the body, if any
+            static _type(message) { return message.#type; }
+            // The body for a copy of the message; a stream is teed
+            static _clone(message) {
+                if (!(message.#body instanceof ReadableStream)) return message.#body;
+                const [mine, theirs] = message.#body.tee();
+                message.#body = mine;
+                return theirs;
+            }
+
+            get body() {
+                if (this.#body === null) return null;
+                if (!(this.#body instanceof ReadableStream)) {
+                    const bytes = typeof this.#body === 'string' ? native.encode(this.#body) : this.#body;
+                    this.#body = new ReadableStream({
+                        start(controller) {
+                            controller.enqueue(bytes);
+                            controller.close();
+                        },
+                    });
+                }
+                return this.#body;
+            }
+            get bodyUsed() {
+                return this.#used || (this.#body instanceof ReadableStream && ReadableStream._disturbed(this.#body));
+            }
+            async #consume() {
+                if (this.bodyUsed) throw new TypeError('Body has already been consumed');
+                this.#used = true;
+                const body = this.#body;
+                if (body === null) return new Uint8Array(0);
+                if (!(body instanceof ReadableStream)) return body;
+                const chunks = [];
+                for await (const chunk of body) chunks.push(bytesOf(chunk));
+                return concat(chunks);
+            }
+            async arrayBuffer() {
+                const body = await this.#consume();
+                return bufferOf(typeof body === 'string' ? native.encode(body) : body);
+            }
+            async bytes() {
+                return new Uint8Array(await this.arrayBuffer());
+            }
+            async blob() {
+                const type = (this.headers && this.headers.get('content-type')) || '';
+                return new Blob([await this.arrayBuffer()], { type });
+            }
+            async text() {
+                const body = await this.#consume();
+                return typeof body === 'string' ? body : native.decode(body, false);
+            }
+            async json() {
+                return JSON.parse(await this.text());
+            }
+        }
+
+        class Request extends Body {
+            constructor(input, init = {}) {
+                const source = input instanceof Request ? input : null;
+                const body = init.body !== undefined ? init.body : source ? Body._clone(source) : null;
+                super(body);
+                this.url = source ? source.url : new URL(String(input)).href;
+                this.method = String(init.method || (source ? source.method : 'GET')).toUpperCase();
+                if ((this.method === 'GET' || this.method === 'HEAD') && body !== null && body !== undefined) {
+                    throw new TypeError('Request with GET/HEAD method cannot have body');
+                }
+                this.headers = new Headers(init.headers || (source ? source.headers : undefined));
+                const type = Body._type(this);
+                if (type && !this.headers.has('content-type')) this.headers.set('content-type', type);
+                this.signal = init.signal || (source ? source.signal : new AbortController().signal);
+                this.redirect = init.redirect || (source ? source.redirect : 'follow');
+            }
+            clone() {
+                if (this.bodyUsed) throw new TypeError('Body has already been consumed');
+                return new Request(this);
+            }
+        }
+
+        class Response extends Body {
+            constructor(body = null, init = {}) {
+                super(body);
+                this.status = init.status === undefined ? 200 : Number(init.status);
+                if (this.status < 200 || this.status > 599) throw new RangeError(`Invalid status ${init.status}`);
+                this.statusText = init.statusText === undefined ? '' : String(init.statusText);
+                this.headers = new Headers(init.headers);
+                const type = Body._type(this);
+                if (type && !this.headers.has('content-type')) this.headers.set('content-type', type);
+                this.type = 'default';
+                this.url = init.url || '';
+                this.redirected = !!init.redirected;
+            }
+            get ok() { return this.status >= 200 && this.status < 300; }
+
+            static json(data, init = {}) {
+                const headers = new Headers(init.headers);
+                if (!headers.has('content-type')) headers.set('content-type', 'application/json');
+                return new Response(JSON.stringify(data), { ...init, headers });
+            }
+            static redirect(url, status = 302) {
+                if (![301, 302, 303, 307, 308].includes(status)) throw new RangeError(`Invalid redirect status ${status}`);
+                return new Response(null, { status, headers: { location: new URL(String(url)).href } });
+            }
+            static error() {
+                const response = new Response(null, { status: 200 });
+                response.type = 'error';
+                response.status = 0;
+                return response;
+            }
+            clone() {
+                if (this.bodyUsed) throw new TypeError('Body has already been consumed');
+                return new Response(Body._clone(this), this);
+            }
+        }
+
+        globalThis.Headers = Headers;
+        globalThis.Request = Request;
+        globalThis.Response = Response;
+
+        // structuredClone
+
+        globalThis.structuredClone = (value) => {
+            const seen = new Map();
+            const clone = (value) => {
+                if (typeof value === 'function' || typeof value === 'symbol') {
+                    throw new DOMException(`${String(value)} could not be cloned`, 'DataCloneError');
+                }
+                if (typeof value !== 'object' || value === null) return value;
+                if (seen.has(value)) return seen.get(value);
+
+                let copy;
+                if (value instanceof Date) copy = new Date(value.getTime());
+                else if (value instanceof RegExp) copy = new RegExp(value.source, value.flags);
+                else if (value instanceof ArrayBuffer) copy = value.slice(0);
+                else if (ArrayBuffer.isView(value)) {
+                    copy = new value.constructor(clone(value.buffer), value.byteOffset, value instanceof DataView ? value.byteLength : value.length);
+                } else if (value instanceof Boolean || value instanceof Number || value instanceof String) {
+                    copy = Object(value.valueOf());
+                } else if (value instanceof Error) {
+                    copy = new (globalThis[value.name] || Error)(value.message);
+                } else if (value instanceof Map) {
+                    copy = new Map();
+                    seen.set(value, copy);
+                    for (const [k, v] of value) copy.set(clone(k), clone(v));
+                    return copy;
+                } else if (value instanceof Set) {
+                    copy = new Set();
+                    seen.set(value, copy);
+                    for (const v of value) copy.add(clone(v));
+                    return copy;
+                } else if (Array.isArray(value)) {
+                    copy = new Array(value.length);
+                    seen.set(value, copy);
+                    for (const key of Object.keys(value)) copy[key] = clone(value[key]);
+                    return copy;
+                } else {
+                    const proto = Object.getPrototypeOf(value);
+                    if (proto !== Object.prototype && proto !== null) {
+                        throw new DOMException(`${value.constructor.name} object could not be cloned`, 'DataCloneError');
+                    }
+                    copy = {};
+                    seen.set(value, copy);
+                    for (const key of Object.keys(value)) copy[key] = clone(value[key]);
+                    return copy;
+                }
+                seen.set(value, copy);
+                return copy;
+            };
+            return clone(value);
+        };
+    })();
+"#;
+
+/// Components of a parsed URL, as `URL` exposes them.
+fn url_parts<'js>(ctx: Ctx<'js>, url: &Url) -> rquickjs::Result<Object<'js>> {
+    use url::quirks;
+    let parts = Object::new(ctx)?;
+    parts.set("href", quirks::href(url))?;
+    parts.set("origin", quirks::origin(url))?;
+    parts.set("protocol", quirks::protocol(url))?;
+    parts.set("username", quirks::username(url))?;
+    parts.set("password", quirks::password(url))?;
+    parts.set("host", quirks::host(url))?;
+    parts.set("hostname", quirks::hostname(url))?;
+    parts.set("port", quirks::port(url))?;
+    parts.set("pathname", quirks::pathname(url))?;
+    parts.set("search", quirks::search(url))?;
+    parts.set("hash", quirks::hash(url))?;
+    Ok(parts)
+}
+
+/// `urlParse`: parse `input` against `base`, or `None` if either is invalid.
+fn url_parse<'js>(ctx: Ctx<'js>, input: String, base: Option<String>) -> rquickjs::Result<Option<Object<'js>>> {
+    let url = match base {
+        Some(base) => Url::parse(&base).and_then(|base| base.join(&input)),
+        None => Url::parse(&input),
+    };
+    match url {
+        Ok(url) => url_parts(ctx, &url).map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+/// `urlSet`: apply a setter to `href`. Like in browsers, invalid values
+/// leave the URL unchanged.
+fn url_set<'js>(ctx: Ctx<'js>, href: String, name: String, value: String) -> rquickjs::Result<Object<'js>> {
+    use url::quirks;
+    let mut url = Url::parse(&href).map_err(|e| Exception::throw_type(&ctx, &e.to_string()))?;
+    let applied = match name.as_str() {
+        "href" => quirks::set_href(&mut url, &value).is_ok(),
+        "protocol" => quirks::set_protocol(&mut url, &value).is_ok(),
+        "username" => quirks::set_username(&mut url, &value).is_ok(),
+        "password" => quirks::set_password(&mut url, &value).is_ok(),
+        "host" => quirks::set_host(&mut url, &value).is_ok(),
+        "hostname" => quirks::set_hostname(&mut url, &value).is_ok(),
+        "port" => quirks::set_port(&mut url, &value).is_ok(),
+        "pathname" => {
+            quirks::set_pathname(&mut url, &value);
+            true
+        }
+        "search" => {
+            quirks::set_search(&mut url, &value);
+            true
+        }
+        "hash" => {
+            quirks::set_hash(&mut url, &value);
+            true
+        }
+        other => return Err(Exception::throw_type(&ctx, &format!("Unknown URL component '{}'", other))),
+    };
+    if !applied {
+        tracing::debug!("Ignored invalid URL {} '{}'", name, value);
+    }
+    url_parts(ctx, &url)
+}
+
+/// `btoa`: base64 of a string of Latin1 characters, or `None` if it has others.
+fn btoa(data: String) -> Option<String> {
+    let bytes: Option<Vec<u8>> = data.chars().map(|c| u8::try_from(c).ok()).collect();
+    bytes.map(|bytes| FORGIVING.encode(bytes))
+}
+
+/// `atob`: decode base64 (ASCII whitespace ignored) into a Latin1 string,
+/// or `None` if it isn't valid base64.
+fn atob(data: String) -> Option<String> {
+    let compact: String = data.chars().filter(|c| !matches!(c, ' ' | '\t' | '\n' | '\x0c' | '\r')).collect();
+    let bytes = FORGIVING.decode(compact).ok()?;
+    Some(bytes.into_iter().map(char::from).collect())
+}
+
+/// `digest`: hash `data` with a SHA algorithm.
+fn digest<'js>(ctx: Ctx<'js>, algorithm: String, data: TypedArray<'js, u8>) -> rquickjs::Result<TypedArray<'js, u8>> {
+    let data = data.as_bytes().unwrap_or_default();
+    let hash = match algorithm.as_str() {
+        "sha1" => sha1::Sha1::digest(data).to_vec(),
+        "sha256" => sha2::Sha256::digest(data).to_vec(),
+        "sha384" => sha2::Sha384::digest(data).to_vec(),
+        "sha512" => sha2::Sha512::digest(data).to_vec(),
+        other => return Err(Exception::throw_type(&ctx, &format!("Unsupported digest '{}'", other))),
+    };
+    TypedArray::new(ctx, hash)
+}
+
+/// A random (version 4) UUID.
+fn random_uuid() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+/// Install the web globals.
+pub fn install<'js>(ctx: &Ctx<'js>) -> Result<(), String> {
+    let native = Object::new(ctx.clone()).map_err(|e| e.to_string())?;
+    let set = |name: &str, f: rquickjs::Result<Function<'js>>| {
+        native.set(name, f.map_err(|e| e.to_string())?).map_err(|e| e.to_string())
+    };
+
+    set("encode", Function::new(ctx.clone(), |ctx, text: String| TypedArray::<u8>::new(ctx, text.into_bytes())))?;
+    set(
+        "decode",
+        Function::new(ctx.clone(), |bytes: TypedArray<'js, u8>, fatal: bool| {
+            let bytes = bytes.as_bytes().unwrap_or_default();
+            match std::str::from_utf8(bytes) {
+                Ok(text) => Some(text.to_string()),
+                Err(_) if fatal => None,
+                Err(_) => Some(String::from_utf8_lossy(bytes).into_owned()),
+            }
+        }),
+    )?;
+    set("btoa", Function::new(ctx.clone(), btoa))?;
+    set("atob", Function::new(ctx.clone(), atob))?;
+    set("urlParse", Function::new(ctx.clone(), url_parse))?;
+    set("urlSet", Function::new(ctx.clone(), url_set))?;
+    set(
+        "formParse",
+        Function::new(ctx.clone(), |query: String| {
+            url::form_urlencoded::parse(query.as_bytes())
+                .map(|(name, value)| vec![name.into_owned(), value.into_owned()])
+                .collect::<Vec<_>>()
+        }),
+    )?;
+    set(
+        "formSerialize",
+        Function::new(ctx.clone(), |pairs: Vec<Vec<String>>| {
+            let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+            for pair in &pairs {
+                if let [name, value] = pair.as_slice() {
+                    serializer.append_pair(name, value);
+                }
+            }
+            serializer.finish()
+        }),
+    )?;
+    set(
+        "randomBytes",
+        Function::new(ctx.clone(), |ctx, len: usize| {
+            let mut bytes = vec![0u8; len.min(MAX_RANDOM_BYTES)];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            TypedArray::<u8>::new(ctx, bytes)
+        }),
+    )?;
+    set("randomUUID", Function::new(ctx.clone(), random_uuid))?;
+    set("digest", Function::new(ctx.clone(), digest))?;
+
+    ctx.globals().set("__web_native", native).map_err(|e| e.to_string())?;
+    ctx.eval::<(), _>(WEB_JS).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rquickjs::{Context, Runtime};
+
+    /// Run `script`, an async function body, and return what it resolves
+    /// to, through JSON.
+    fn run(script: &str) -> serde_json::Value {
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            install(&ctx).unwrap();
+            let script = format!(
+                "globalThis.result = null; (async () => {{ {} }})().then((r) => {{ globalThis.result = JSON.stringify(r); }}, (e) => {{ globalThis.result = JSON.stringify({{ error: String(e) }}); }});",
+                script
+            );
+            ctx.eval::<(), _>(script).unwrap();
+        });
+        while runtime.is_job_pending() {
+            runtime.execute_pending_job().unwrap();
+        }
+        let result: String = context.with(|ctx| ctx.eval("globalThis.result").unwrap());
+        serde_json::from_str(&result).unwrap()
+    }
+
+    #[test]
+    fn test_text_encoding() {
+        let result = run(r#"
+            const bytes = new TextEncoder().encode('héllo €');
+            const decoder = new TextDecoder();
+            let fatal = null;
+            try { new TextDecoder('utf-8', { fatal: true }).decode(new Uint8Array([0xff])); } catch (e) { fatal = e.name; }
+            return {
+                bytes: Array.from(bytes),
+                text: decoder.decode(bytes),
+                bom: decoder.decode(new Uint8Array([0xef, 0xbb, 0xbf, 0x61])),
+                lossy: decoder.decode(new Uint8Array([0x61, 0xff])),
+                fatal,
+            };
+        "#);
+        assert_eq!(
+            result,
+            serde_json::json!({
+                "bytes": [104, 195, 169, 108, 108, 111, 32, 226, 130, 172],
+                "text": "héllo €",
+                "bom": "a",
+                "lossy": "a\u{fffd}",
+                "fatal": "TypeError",
+            })
+        );
+    }
+
+    #[test]
+    fn test_url() {
+        let result = run(r#"
+            const url = new URL('../b/c?x=1&y=a+b#frag', 'https://user@example.com:8080/a/d');
+            url.searchParams.append('z', 'é &');
+            const params = new URLSearchParams({ b: '2', a: '1' });
+            params.sort();
+            let invalid = null;
+            try { new URL('not a url'); } catch (e) { invalid = e.name; }
+            return {
+                href: url.href,
+                origin: url.origin,
+                host: url.host,
+                pathname: url.pathname,
+                y: url.searchParams.get('y'),
+                hash: url.hash,
+                params: params.toString(),
+                canParse: URL.canParse('/relative'),
+                invalid,
+            };
+        "#);
+        assert_eq!(
+            result,
+            serde_json::json!({
+                "href": "https://user@example.com:8080/b/c?x=1&y=a+b&z=%C3%A9+%26#frag",
+                "origin": "https://example.com:8080",
+                "host": "example.com:8080",
+                "pathname": "/b/c",
+                "y": "a b",
+                "hash": "#frag",
+                "params": "a=1&b=2",
+                "canParse": false,
+                "invalid": "TypeError",
+            })
+        );
+    }
+
+    #[test]
+    fn test_crypto() {
+        let result = run(r#"
+            const values = crypto.getRandomValues(new Uint32Array(4));
+            const digest = await crypto.subtle.digest('SHA-256', new TextEncoder().encode('abc'));
+            const hex = Array.from(new Uint8Array(digest), (b) => b.toString(16).padStart(2, '0')).join('');
+            let tooLarge = null;
+            try { crypto.getRandomValues(new Uint8Array(65537)); } catch (e) { tooLarge = e.name; }
+            return {
+                filled: values.some((v) => v !== 0),
+                uuid: /^[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-[89ab][0-9a-f]{3}-[0-9a-f]{12}$/.test(crypto.randomUUID()),
+                hex,
+                tooLarge,
+            };
+        "#);
+        assert_eq!(
+            result,
+            serde_json::json!({
+                "filled": true,
+                "uuid": true,
+                "hex": "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+                "tooLarge": "QuotaExceededError",
+            })
+        );
+    }
+
+    #[test]
+    fn test_base64() {
+        let result = run(r#"
+            let invalid = null;
+            try { btoa('€'); } catch (e) { invalid = e.name; }
+            return { encoded: btoa('hé!'), decoded: atob(' aOkh '), unpadded: atob('YQ'), invalid };
+        "#);
+        assert_eq!(
+            result,
+            serde_json::json!({ "encoded": "aOkh", "decoded": "hé!", "unpadded": "a", "invalid": "InvalidCharacterError" })
+        );
+    }
+
+    #[test]
+    fn test_abort_controller() {
+        let result = run(r#"
+            const controller = new AbortController();
+            const events = [];
+            controller.signal.addEventListener('abort', (e) => events.push(e.type));
+            controller.abort();
+            controller.abort();
+            let thrown = null;
+            try { controller.signal.throwIfAborted(); } catch (e) { thrown = e.name; }
+            return { aborted: controller.signal.aborted, events, thrown, reason: AbortSignal.abort('why').reason };
+        "#);
+        assert_eq!(
+            result,
+            serde_json::json!({ "aborted": true, "events": ["abort"], "thrown": "AbortError", "reason": "why" })
+        );
+    }
+
+    #[test]
+    fn test_fetch_types() {
+        let result = run(r#"
+            const headers = new Headers({ 'Content-Type': 'text/plain' });
+            headers.append('X-Tag', 'a');
+            headers.append('x-tag', 'b');
+            const request = new Request('https://example.com/api', { method: 'post', body: 'hi', headers });
+            const response = Response.json({ ok: 1 }, { status: 201 });
+            const copy = response.clone();
+            const bytes = new Uint8Array(await new Response('hé').arrayBuffer());
+            let reused = null;
+            await response.json();
+            try { await response.text(); } catch (e) { reused = e.name; }
+            return {
+                tag: headers.get('X-TAG'),
+                names: [...headers.keys()],
+                method: request.method,
+                body: await request.text(),
+                status: copy.status,
+                ok: copy.ok,
+                type: copy.headers.get('content-type'),
+                json: await copy.json(),
+                bytes: Array.from(bytes),
+                reused,
+            };
+        "#);
+        assert_eq!(
+            result,
+            serde_json::json!({
+                "tag": "a, b",
+                "names": ["content-type", "x-tag"],
+                "method": "POST",
+                "body": "hi",
+                "status": 201,
+                "ok": true,
+                "type": "application/json",
+                "json": { "ok": 1 },
+                "bytes": [104, 195, 169],
+                "reused": "TypeError",
+            })
+        );
+    }
+
+    #[test]
+    fn test_streams_and_blobs() {
+        let result = run(r#"
+            let n = 0;
+            const stream = new ReadableStream({
+                pull(controller) {
+                    if (++n > 3) controller.close();
+                    else controller.enqueue(new Uint8Array([n]));
+                },
+            });
+            const [a, b] = stream.tee();
+            const chunks = [];
+            for await (const chunk of a) chunks.push(chunk[0]);
+            const teed = Array.from(await new Response(b).bytes());
+
+            const blob = new Blob(['ab', new Uint8Array([99])], { type: 'Text/Plain' });
+            const form = new FormData();
+            form.append('file', blob, 'x.txt');
+            const response = new Response(form);
+            const streamed = new Response('hé');
+            const reader = streamed.body.getReader();
+            return {
+                chunks,
+                teed,
+                blob: [blob.size, blob.type, await blob.slice(1).text()],
+                file: form.get('file').name,
+                multipart: (await response.text()).includes('filename="x.txt"\r\nContent-Type: text/plain\r\n\r\nabc'),
+                first: Array.from((await reader.read()).value),
+                used: streamed.bodyUsed,
+            };
+        "#);
+        assert_eq!(
+            result,
+            serde_json::json!({
+                "chunks": [1, 2, 3],
+                "teed": [1, 2, 3],
+                "blob": [3, "text/plain", "bc"],
+                "file": "x.txt",
+                "multipart": true,
+                "first": [104, 195, 169],
+                "used": true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_structured_clone() {
+        let result = run(r#"
+            const original = { date: new Date(0), map: new Map([['k', [1, 2]]]), bytes: new Uint8Array([1, 2]) };
+            original.self = original;
+            const copy = structuredClone(original);
+            copy.bytes[0] = 9;
+            let uncloneable = null;
+            try { structuredClone({ f() {} }); } catch (e) { uncloneable = e.name; }
+            return {
+                distinct: copy !== original && copy.map !== original.map,
+                cycle: copy.self === copy,
+                date: copy.date instanceof Date && copy.date.getTime() === 0,
+                map: copy.map.get('k'),
+                original: original.bytes[0],
+                uncloneable,
+            };
+        "#);
+        assert_eq!(
+            result,
+            serde_json::json!({
+                "distinct": true,
+                "cycle": true,
+                "date": true,
+                "map": [1, 2],
+                "original": 1,
+                "uncloneable": "DataCloneError",
+            })
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Azure OpenAI provider instances.
+//!
+//! Azure serves each model from a named deployment under the resource URL
+//! (`{base_url}/openai/deployments/{deployment}/chat/completions`), takes the
+//! API version as an `api-version` query parameter and the key in an
+//! `api-key` header. The payloads are OpenAI's, so requests go through the
+//! OpenAI-compatible client with one endpoint per deployment.
+
+use super::config::ProviderInstance;
+use super::openai_compat::{self, Endpoint};
+
+/// Provider type for Azure OpenAI instances.
+pub const PROVIDER_TYPE: &str = "azure_openai";
+
+/// API version used when an instance doesn't set one.
+pub const DEFAULT_API_VERSION: &str = "2024-06-01";
+
+/// Resource URL without a trailing slash or `/openai` suffix.
+fn resource_url(instance: &ProviderInstance) -> Result<String, String> {
+    let base_url = instance
+        .base_url
+        .as_deref()
+        .map(|u| u.trim().trim_end_matches('/'))
+        .filter(|u| !u.is_empty())
+        .ok_or_else(|| format!("Provider '{}' has no base_url", instance.id))?;
+    Ok(base_url.trim_end_matches("/openai").to_string())
+}
+
+/// Endpoint for `{resource}/openai{path}` with the instance's API version and key.
+fn resource_endpoint(instance: &ProviderInstance, path: &str) -> Result<Endpoint, String> {
+    let base_url = format!("{}/openai{}", resource_url(instance)?, path);
+    let mut endpoint = Endpoint::new(instance, &base_url);
+    let api_version = instance.api_version.as_deref().unwrap_or(DEFAULT_API_VERSION);
+    endpoint.query.push(("api-version".to_string(), api_version.to_string()));
+    endpoint.api_key_header = Some("api-key");
+    Ok(endpoint)
+}
+
+/// Deployment serving a model. Models without a mapping are assumed to be
+/// deployed under their own name.
+pub fn deployment_name<'a>(instance: &'a ProviderInstance, model_name: &'a str) -> &'a str {
+    instance
+        .deployments
+        .get(model_name)
+        .map(|d| d.as_str())
+        .unwrap_or(model_name)
+}
+
+/// Endpoint for chat requests to a model's deployment.
+pub fn endpoint(instance: &ProviderInstance, model_name: &str) -> Result<Endpoint, String> {
+    let deployment = deployment_name(instance, model_name);
+    resource_endpoint(instance, &format!("/deployments/{}", deployment))
+}
+
+/// Models of an instance, taken from its configured deployments.
+pub fn list_models(instance: &ProviderInstance) -> Vec<String> {
+    let mut models: Vec<String> = instance.deployments.keys().cloned().collect();
+    models.sort();
+    models
+}
+
+/// Check that the resource is reachable and accepts the key and API version.
+pub async fn check(instance: &ProviderInstance) -> Result<(), String> {
+    let endpoint = resource_endpoint(instance, "")?;
+    let response = endpoint
+        .send(endpoint.request(reqwest::Method::GET, "/models"))
+        .await?;
+    if !response.status().is_success() {
+        return Err(openai_compat::error_from_response(response, "Status check").await);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{ChatMessage, ChatRequest};
+    use axum::extract::{Path, Query};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use std::collections::HashMap;
+
+    /// Serve a minimal Azure chat completions API on a random local port.
+    async fn mock_server() -> String {
+        let app = axum::Router::new().route(
+            "/openai/deployments/:deployment/chat/completions",
+            post(
+                |Path(deployment): Path<String>,
+                 Query(query): Query<HashMap<String, String>>,
+                 headers: HeaderMap| async move {
+                    if headers.get("api-key").and_then(|v| v.to_str().ok()) != Some("secret")
+                        || query.get("api-version").map(String::as_str) != Some("2024-02-01")
+                    {
+                        return Err(StatusCode::UNAUTHORIZED);
+                    }
+                    Ok(axum::Json(serde_json::json!({
+                        "choices": [{ "message": { "role": "assistant", "content": deployment } }]
+                    })))
+                },
+            ),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_chat_against_mock_server() {
+        let mut instance = ProviderInstance::with_id("azure-work", PROVIDER_TYPE, "Azure");
+        instance.base_url = Some(format!("{}/openai/", mock_server().await));
+        instance.api_key = Some("secret".to_string());
+        instance.api_version = Some("2024-02-01".to_string());
+        instance.deployments.insert("gpt-4o".to_string(), "prod-gpt4o".to_string());
+        assert_eq!(list_models(&instance), vec!["gpt-4o".to_string()]);
+
+        let request = ChatRequest {
+            model: Some("azure-work:gpt-4o".to_string()),
+            messages: vec![ChatMessage {
+                role: "user".to_string(),
+                content: "Hi".to_string(),
+                tool_call_id: None,
+            }],
+            temperature: None,
+            max_tokens: None,
+            system_prompt: None,
+            tools: None,
+            session_id: None,
+            context_strategy: None,
+            priority: None,
+        };
+        let deployment = endpoint(&instance, "gpt-4o").unwrap();
+        let response = openai_compat::chat(&deployment, "gpt-4o", &request).await.unwrap();
+        assert_eq!(response.pointer("/choices/0/message/content").unwrap(), "prod-gpt4o");
+
+        instance.api_key = Some("wrong".to_string());
+        let deployment = endpoint(&instance, "gpt-4o").unwrap();
+        assert!(openai_compat::chat(&deployment, "gpt-4o", &request).await.is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! Model capability metadata.
+//!
+//! A capability record says whether a model supports tools, vision, JSON
+//! mode and streaming, and how long its context is. Records come from the
+//! provider where it reports them (Ollama `/api/show`), otherwise from a
+//! built-in table, and user overrides in `LlmConfig.model_capabilities` are
+//! applied last.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use super::config::{CapabilityOverrides, LlmConfig};
+use super::{context, ollama};
+
+/// Capability record for a single model.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct ModelCapabilities {
+    pub tools: bool,
+    pub vision: bool,
+    pub json_mode: bool,
+    pub streaming: bool,
+    pub context_length: Option<usize>,
+    /// Where the record came from: "provider", "builtin" or "default"
+    pub source: &'static str,
+    /// Whether user overrides were applied
+    pub overridden: bool,
+}
+
+impl ModelCapabilities {
+    fn new(tools: bool, vision: bool, json_mode: bool, source: &'static str) -> Self {
+        Self {
+            tools,
+            vision,
+            json_mode,
+            streaming: true,
+            context_length: None,
+            source,
+            overridden: false,
+        }
+    }
+
+    fn apply(&mut self, overrides: &CapabilityOverrides) {
+        if let Some(v) = overrides.tools {
+            self.tools = v;
+        }
+        if let Some(v) = overrides.vision {
+            self.vision = v;
+        }
+        if let Some(v) = overrides.json_mode {
+            self.json_mode = v;
+        }
+        if let Some(v) = overrides.streaming {
+            self.streaming = v;
+        }
+        if overrides.context_length.is_some() {
+            self.context_length = overrides.context_length;
+        }
+        self.overridden = *overrides != CapabilityOverrides::default();
+    }
+}
+
+/// Built-in capabilities by model family: (pattern, tools, vision, json_mode).
+/// The first substring match wins, so more specific names come first.
+const BUILTIN_CAPABILITIES: &[(&str, bool, bool, bool)] = &[
+    ("gpt-4o", true, true, true),
+    ("gpt-4.1", true, true, true),
+    ("gpt-4-turbo", true, true, true),
+    ("gpt-4", true, false, false),
+    ("gpt-3.5-turbo", true, false, true),
+    ("claude", true, true, false),
+    ("gemini", true, true, true),
+    ("llama3.2-vision", false, true, true),
+    ("llava", false, true, true),
+    ("llama3.1", true, false, true),
+    ("llama3.2", true, false, true),
+    ("llama3.3", true, false, true),
+    ("mistral", true, false, true),
+    ("mixtral", true, false, true),
+    ("qwen2.5", true, false, true),
+    ("mock", true, false, false),
+];
+
+/// Capabilities reported by providers, keyed by model ID.
+fn provider_cache() -> &'static Mutex<HashMap<String, ModelCapabilities>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, ModelCapabilities>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Context length reported by the provider, if it has been fetched.
+pub fn cached_context_length(model_id: &str) -> Option<usize> {
+    provider_cache()
+        .lock()
+        .unwrap()
+        .get(model_id)
+        .and_then(|c| c.context_length)
+}
+
+/// Capabilities from the built-in table.
+pub fn builtin(model_id: &str) -> ModelCapabilities {
+    let name = ollama::model_name(model_id).to_lowercase();
+    BUILTIN_CAPABILITIES
+        .iter()
+        .find(|(family, ..)| name.contains(family))
+        .map(|&(_, tools, vision, json_mode)| ModelCapabilities::new(tools, vision, json_mode, "builtin"))
+        .unwrap_or_else(|| ModelCapabilities::new(false, false, false, "default"))
+}
+
+/// Build a capability record from an Ollama `/api/show` response.
+fn from_ollama_show(model_id: &str, show: &serde_json::Value) -> ModelCapabilities {
+    let mut caps = builtin(model_id);
+    caps.source = "provider";
+    // Ollama accepts `format: "json"` for every model
+    caps.json_mode = true;
+
+    // Newer Ollama versions list capabilities explicitly
+    if let Some(list) = show.get("capabilities").and_then(|c| c.as_array()) {
+        let has = |name: &str| list.iter().any(|c| c.as_str() == Some(name));
+        caps.tools = has("tools");
+        caps.vision = has("vision");
+    }
+
+    caps.context_length = show
+        .get("model_info")
+        .and_then(|info| info.as_object())
+        .and_then(|info| {
+            info.iter()
+                .find(|(key, _)| key.ends_with(".context_length"))
+                .and_then(|(_, v)| v.as_u64())
+        })
+        .map(|n| n as usize);
+
+    caps
+}
+
+/// Resolve the capability record for a model without network access.
+pub fn lookup(model_id: &str, config: Option<&LlmConfig>) -> ModelCapabilities {
+    let mut caps = provider_cache()
+        .lock()
+        .unwrap()
+        .get(model_id)
+        .cloned()
+        .unwrap_or_else(|| builtin(model_id));
+
+    if caps.context_length.is_none() {
+        caps.context_length = context::context_window(model_id, config);
+    }
+    if let Some(overrides) = config.and_then(|c| c.model_capabilities.get(model_id)) {
+        caps.apply(overrides);
+    }
+    caps
+}
+
+/// Resolve capability records for several models, first asking Ollama about
+/// any Ollama models that have not been fetched yet.
+pub async fn lookup_all(model_ids: &[String], config: Option<&LlmConfig>) -> Vec<ModelCapabilities> {
+    let missing: Vec<&String> = {
+        let cache = provider_cache().lock().unwrap();
+        model_ids
+            .iter()
+            .filter(|id| is_ollama_model(id, config) && !cache.contains_key(id.as_str()))
+            .collect()
+    };
+
+    if !missing.is_empty() {
+        let fetches = missing.iter().map(|id| {
+            // Ask the instance the model ID is qualified with
+            let provider = id.split(':').next().filter(|p| *p != ollama::PROVIDER_TYPE);
+            let base_url = ollama::instance_base_url(config, provider);
+            async move {
+                let result = match base_url {
+                    Ok(base_url) => ollama::show(&base_url, ollama::model_name(id)).await,
+                    Err(e) => Err(e),
+                };
+                (id.to_string(), result)
+            }
+        });
+        for (id, result) in futures::future::join_all(fetches).await {
+            match result {
+                Ok(show) => {
+                    let caps = from_ollama_show(&id, &show);
+                    provider_cache().lock().unwrap().insert(id, caps);
+                }
+                Err(e) => tracing::debug!("No capability data for {}: {}", id, e),
+            }
+        }
+    }
+
+    model_ids.iter().map(|id| lookup(id, config)).collect()
+}
+
+fn is_ollama_model(model_id: &str, config: Option<&LlmConfig>) -> bool {
+    let provider = model_id.split(':').next().unwrap_or("");
+    let provider_type = config
+        .and_then(|c| c.get_instance(provider))
+        .map(|i| i.provider_type.as_str())
+        .unwrap_or(provider);
+    provider_type == ollama::PROVIDER_TYPE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ollama_show_and_overrides() {
+        let show = serde_json::json!({
+            "capabilities": ["completion", "vision"],
+            "model_info": { "llama.context_length": 4096 }
+        });
+        let caps = from_ollama_show("ollama:llava:7b", &show);
+        assert_eq!(caps.source, "provider");
+        assert!(caps.vision);
+        assert!(!caps.tools);
+        assert_eq!(caps.context_length, Some(4096));
+
+        let mut cfg = LlmConfig::default();
+        cfg.model_capabilities.insert(
+            "openai:gpt-4".to_string(),
+            CapabilityOverrides {
+                vision: Some(true),
+                ..Default::default()
+            },
+        );
+        let caps = lookup("openai:gpt-4", Some(&cfg));
+        assert!(caps.tools && caps.vision && caps.overridden);
+        assert_eq!(caps.context_length, Some(8_192));
+        assert_eq!(lookup("acme:unknown", Some(&cfg)).source, "default");
+    }
+}
@@ -1,5 +1,6 @@
 //! LLM configuration storage.
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -23,6 +24,33 @@ pub struct ProviderInstance {
     /// Custom base URL
     #[serde(skip_serializing_if = "Option::is_none")]
     pub base_url: Option<String>,
+    /// Extra HTTP headers sent with every request (e.g., for API gateways)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+    /// Organization ID (sent as `OpenAI-Organization`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub organization: Option<String>,
+    /// Project ID (sent as `OpenAI-Project`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    /// Request timeout in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// Maximum concurrent chat requests; further requests are queued
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<u32>,
+    /// Seconds a queued request waits for a slot before failing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_timeout_secs: Option<u64>,
+    /// Model name to deployment name (Azure OpenAI only)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub deployments: HashMap<String, String>,
+    /// `api-version` query parameter (Azure OpenAI only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_version: Option<String>,
+    /// Path to a scripted-response fixture (mock provider only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fixture_path: Option<String>,
     /// Whether this is the default instance for its provider type
     #[serde(default)]
     pub is_type_default: bool,
@@ -43,6 +71,15 @@ impl ProviderInstance {
             enabled: true,
             api_key: None,
             base_url: None,
+            headers: HashMap::new(),
+            organization: None,
+            project: None,
+            timeout_secs: None,
+            max_concurrency: None,
+            queue_timeout_secs: None,
+            deployments: HashMap::new(),
+            api_version: None,
+            fixture_path: None,
             is_type_default: false,
         }
     }
@@ -57,9 +94,29 @@ impl ProviderInstance {
             enabled: true,
             api_key: None,
             base_url: None,
+            headers: HashMap::new(),
+            organization: None,
+            project: None,
+            timeout_secs: None,
+            max_concurrency: None,
+            queue_timeout_secs: None,
+            deployments: HashMap::new(),
+            api_version: None,
+            fixture_path: None,
             is_type_default: false,
         }
     }
+
+    /// Whether requests need headers beyond authentication
+    /// (extra headers, organization or project).
+    pub fn has_extra_headers(&self) -> bool {
+        !self.headers.is_empty() || self.organization.is_some() || self.project.is_some()
+    }
+
+    /// Request timeout, if configured.
+    pub fn timeout(&self) -> Option<std::time::Duration> {
+        self.timeout_secs.map(std::time::Duration::from_secs)
+    }
 }
 
 /// Generate a simple unique ID suffix
@@ -89,7 +146,7 @@ struct LegacyLlmConfig {
 
 /// A configured model with a user-friendly name.
 /// Allows the Web Agents API to reference models by name.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ModelAlias {
     /// User-friendly name (e.g., "my-llama", "work-gpt4")
     pub name: String,
@@ -98,6 +155,25 @@ pub struct ModelAlias {
     /// Whether this is the default model
     #[serde(default)]
     pub is_default: bool,
+    /// Context window in tokens, overriding the built-in table
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<usize>,
+}
+
+/// User overrides for a model's capability record.
+/// Unset fields keep the provider-reported or built-in value.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct CapabilityOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vision: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_mode: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streaming: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_length: Option<usize>,
 }
 
 /// Global LLM configuration.
@@ -122,6 +198,27 @@ pub struct LlmConfig {
     /// Configured models with user-friendly names
     #[serde(default)]
     pub models: Vec<ModelAlias>,
+
+    /// Capability overrides keyed by model ID (e.g., "ollama:llava:latest")
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub model_capabilities: HashMap<String, CapabilityOverrides>,
+
+    /// Seconds between background provider health checks (default 60)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_check_interval_secs: Option<u64>,
+
+    /// Also check cloud providers in the background (default false)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_check_cloud: Option<bool>,
+
+    /// Per-caller RPC rate limits, see `rpc::rate_limit`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limits: Option<crate::rpc::rate_limit::RateLimitSettings>,
+
+    /// Model that writes summaries for the `summarize` context strategy when
+    /// the request names none; best a small or local one (e.g. "ollama:llama3.2")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary_model: Option<String>,
 }
 
 fn default_version() -> u32 {
@@ -190,6 +287,11 @@ impl LlmConfig {
             default_provider: None,
             providers: HashMap::new(),
             models: Vec::new(),
+            model_capabilities: HashMap::new(),
+            health_check_interval_secs: None,
+            rate_limits: None,
+            health_check_cloud: None,
+            summary_model: None,
         };
 
         // Convert each legacy provider to a new instance
@@ -203,6 +305,15 @@ impl LlmConfig {
                 enabled: settings.enabled,
                 api_key: settings.api_key,
                 base_url: settings.base_url,
+                headers: HashMap::new(),
+                organization: None,
+                project: None,
+                timeout_secs: None,
+                max_concurrency: None,
+                queue_timeout_secs: None,
+                deployments: HashMap::new(),
+                api_version: None,
+                fixture_path: None,
                 is_type_default: true, // Only instance, so it's the default
             };
             config.providers.insert(provider_type, instance);
@@ -288,7 +399,6 @@ impl LlmConfig {
     }
 
     /// Get the global default provider instance.
-    #[allow(dead_code)]
     pub fn get_global_default(&self) -> Option<&ProviderInstance> {
         self.default_provider
             .as_ref()
@@ -420,6 +530,7 @@ impl LlmConfig {
             name: final_name.clone(),
             model_id: model_id.to_string(),
             is_default,
+            context_window: None,
         });
 
         final_name
@@ -455,6 +566,17 @@ impl LlmConfig {
         true
     }
 
+    /// Set (or clear) a model's context window override.
+    pub fn set_model_context_window(&mut self, name: &str, context_window: Option<usize>) -> bool {
+        match self.models.iter_mut().find(|m| m.name == name) {
+            Some(model) => {
+                model.context_window = context_window;
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Resolve a model reference to an actual model ID.
     /// Accepts either a configured model name or a raw model ID.
     #[allow(dead_code)]
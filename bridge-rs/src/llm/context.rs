@@ -0,0 +1,428 @@
+//! Context-window management for chat requests.
+//!
+//! Estimates how many tokens a request will use and, when it would overflow
+//! the model's context window, shrinks the history according to the
+//! request's strategy before it is sent. The history stored in a session is
+//! never modified; only the outgoing request is.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::capabilities;
+use super::config::LlmConfig;
+use super::{ChatMessage, ChatRequest};
+
+/// Tokens reserved for the reply when the request sets no `max_tokens`.
+const DEFAULT_RESPONSE_RESERVE: usize = 1024;
+
+/// Approximate per-message overhead (role markers, separators).
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// Known context windows by model family. The first substring match wins,
+/// so more specific names must come before their prefixes.
+const CONTEXT_WINDOWS: &[(&str, usize)] = &[
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4-32k", 32_768),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("claude", 200_000),
+    ("gemini-1.5-pro", 2_097_152),
+    ("gemini", 1_048_576),
+    ("llama3.1", 131_072),
+    ("llama3.2", 131_072),
+    ("llama3.3", 131_072),
+    ("llama3", 8_192),
+    ("llama2", 4_096),
+    ("mistral-nemo", 128_000),
+    ("mixtral", 32_768),
+    ("mistral", 32_768),
+    ("qwen2.5", 32_768),
+    ("gemma2", 8_192),
+];
+
+fn default_keep_last() -> usize {
+    6
+}
+
+/// How to shrink a conversation that does not fit the context window.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContextStrategy {
+    /// Send the request unchanged, even if it overflows.
+    None,
+    /// Drop the oldest non-system messages until the request fits.
+    #[default]
+    DropOldest,
+    /// Keep system messages and only the last `count` other messages.
+    KeepLast {
+        #[serde(default = "default_keep_last")]
+        count: usize,
+    },
+    /// Replace older turns with a summary written by `model` (defaults to
+    /// the configured `summary_model`), keeping the last `keep_last` messages
+    /// verbatim. Without either, older turns are dropped instead: the chat
+    /// model is not used, as summarizing with it can cost as much as the
+    /// request being shrunk.
+    Summarize {
+        #[serde(default)]
+        model: Option<String>,
+        #[serde(default = "default_keep_last")]
+        keep_last: usize,
+    },
+}
+
+/// What was done to fit a request into the context window.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContextReport {
+    /// Strategy actually applied ("none" if the request already fit)
+    pub strategy: String,
+    pub truncated: bool,
+    pub context_window: Option<usize>,
+    pub original_tokens: usize,
+    pub estimated_tokens: usize,
+    pub dropped_messages: usize,
+    pub summarized_messages: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Estimate the token count of a piece of text (~4 characters per token).
+pub fn estimate_text_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Estimate the prompt tokens of a full request, including tool schemas.
+pub fn estimate_request_tokens(request: &ChatRequest) -> usize {
+    let system = request
+        .system_prompt
+        .as_deref()
+        .map(|p| estimate_text_tokens(p) + MESSAGE_OVERHEAD_TOKENS)
+        .unwrap_or(0);
+    let messages: usize = request
+        .messages
+        .iter()
+        .map(|m| estimate_text_tokens(&m.content) + MESSAGE_OVERHEAD_TOKENS)
+        .sum();
+    let tools = request
+        .tools
+        .as_ref()
+        .and_then(|t| serde_json::to_string(t).ok())
+        .map(|s| estimate_text_tokens(&s))
+        .unwrap_or(0);
+    system + messages + tools
+}
+
+/// Look up a model's context window: a configured model alias wins, then a
+/// capability override, then the length reported by the provider, then the
+/// built-in table. Returns `None` for unknown models.
+pub fn context_window(model: &str, config: Option<&LlmConfig>) -> Option<usize> {
+    let configured = config.and_then(|cfg| {
+        cfg.models
+            .iter()
+            .find(|m| m.model_id == model || m.name == model)
+            .and_then(|m| m.context_window)
+            .or_else(|| cfg.model_capabilities.get(model).and_then(|c| c.context_length))
+    });
+    if let Some(window) = configured.or_else(|| capabilities::cached_context_length(model)) {
+        return Some(window);
+    }
+
+    // Match on the model name without the provider prefix
+    let name = model.split_once(':').map(|(_, rest)| rest).unwrap_or(model).to_lowercase();
+    CONTEXT_WINDOWS
+        .iter()
+        .find(|(family, _)| name.contains(family))
+        .map(|(_, window)| *window)
+}
+
+/// Prompt tokens available once the reply has been reserved.
+pub fn token_budget(context_window: usize, max_tokens: Option<u32>) -> usize {
+    let reserve = max_tokens
+        .map(|t| t as usize)
+        .unwrap_or(DEFAULT_RESPONSE_RESERVE)
+        .min(context_window / 2);
+    context_window - reserve
+}
+
+/// Remove tool results left at the front of the history after the
+/// assistant message that requested them was dropped.
+fn drop_orphaned_tool_results(messages: &mut Vec<ChatMessage>) -> usize {
+    let mut dropped = 0;
+    while let Some(index) = messages.iter().position(|m| m.role != "system") {
+        if messages[index].role != "tool" || index == messages.len() - 1 {
+            break;
+        }
+        messages.remove(index);
+        dropped += 1;
+    }
+    dropped
+}
+
+/// Drop the oldest non-system messages until the request fits `budget`.
+/// The most recent message is always kept. Returns the number dropped.
+pub fn drop_oldest(request: &mut ChatRequest, budget: usize) -> usize {
+    let mut dropped = 0;
+    while estimate_request_tokens(request) > budget {
+        let last = request.messages.len().saturating_sub(1);
+        match request.messages.iter().position(|m| m.role != "system") {
+            Some(index) if index < last => {
+                request.messages.remove(index);
+                dropped += 1 + drop_orphaned_tool_results(&mut request.messages);
+            }
+            _ => break,
+        }
+    }
+    dropped
+}
+
+/// Remove all but the last `count` non-system messages and return them
+/// in their original order.
+pub fn split_older(request: &mut ChatRequest, count: usize) -> Vec<ChatMessage> {
+    let total = request.messages.iter().filter(|m| m.role != "system").count();
+    let mut to_remove = total.saturating_sub(count.max(1));
+    let mut older = Vec::new();
+    let mut kept = Vec::with_capacity(request.messages.len());
+    for message in request.messages.drain(..) {
+        if to_remove > 0 && message.role != "system" {
+            to_remove -= 1;
+            older.push(message);
+        } else {
+            kept.push(message);
+        }
+    }
+    // Tool results whose request was split off go with it
+    while let Some(index) = kept.iter().position(|m| m.role != "system") {
+        if kept[index].role != "tool" || index == kept.len() - 1 {
+            break;
+        }
+        older.push(kept.remove(index));
+    }
+    request.messages = kept;
+    older
+}
+
+/// Build the request used to summarize older turns.
+pub fn summary_request(model: &str, older: &[ChatMessage]) -> ChatRequest {
+    let transcript: String = older
+        .iter()
+        .map(|m| format!("{}: {}\n", m.role, m.content))
+        .collect();
+
+    ChatRequest {
+        model: Some(model.to_string()),
+        messages: vec![ChatMessage {
+            role: "user".to_string(),
+            content: transcript,
+            tool_call_id: None,
+        }],
+        temperature: Some(0.0),
+        max_tokens: Some(512),
+        system_prompt: Some(
+            "Summarize the following conversation concisely. Preserve facts, decisions, \
+             names and open questions that later messages may depend on."
+                .to_string(),
+        ),
+        tools: None,
+        session_id: None,
+        context_strategy: Some(ContextStrategy::None),
+        priority: Some(super::queue::Priority::Background),
+    }
+}
+
+/// Insert a summary of earlier turns after the leading system messages.
+pub fn insert_summary(request: &mut ChatRequest, summary: &str) {
+    let index = request
+        .messages
+        .iter()
+        .position(|m| m.role != "system")
+        .unwrap_or(request.messages.len());
+    request.messages.insert(
+        index,
+        ChatMessage {
+            role: "system".to_string(),
+            content: format!("Summary of the earlier conversation:\n{}", summary),
+            tool_call_id: None,
+        },
+    );
+}
+
+/// Fit a request into the model's context window using its strategy.
+pub async fn fit(model: &str, request: &mut ChatRequest, config: Option<&LlmConfig>) -> ContextReport {
+    let window = context_window(model, config);
+    let original = estimate_request_tokens(request);
+    let mut report = ContextReport {
+        strategy: "none".to_string(),
+        truncated: false,
+        context_window: window,
+        original_tokens: original,
+        estimated_tokens: original,
+        dropped_messages: 0,
+        summarized_messages: 0,
+        summary_model: None,
+        note: None,
+    };
+
+    let window = match window {
+        Some(w) => w,
+        None => return report,
+    };
+    let budget = token_budget(window, request.max_tokens);
+    if original <= budget {
+        return report;
+    }
+
+    match request.context_strategy.clone().unwrap_or_default() {
+        ContextStrategy::None => {
+            report.note = Some("Request exceeds the context window; no strategy applied".to_string());
+        }
+        ContextStrategy::DropOldest => {
+            report.strategy = "drop_oldest".to_string();
+            report.dropped_messages = drop_oldest(request, budget);
+        }
+        ContextStrategy::KeepLast { count } => {
+            report.strategy = "keep_last".to_string();
+            report.dropped_messages = split_older(request, count).len();
+            report.dropped_messages += drop_oldest(request, budget);
+        }
+        ContextStrategy::Summarize { model: summary_model, keep_last } => {
+            let summary_model = summary_model.or_else(|| config.and_then(|c| c.summary_model.clone()));
+            let older = split_older(request, keep_last);
+            match (older.is_empty(), summary_model) {
+                (true, _) => {}
+                (false, None) => {
+                    report.strategy = "drop_oldest".to_string();
+                    report.dropped_messages = older.len();
+                    report.note = Some("No summary model configured, older messages dropped".to_string());
+                }
+                (false, Some(summary_model)) => {
+                    let summary = super::dispatch_chat(&summary_model, summary_request(&summary_model, &older))
+                        .await
+                        .and_then(|response| {
+                            response
+                                .pointer("/choices/0/message/content")
+                                .and_then(|c| c.as_str())
+                                .map(|c| c.to_string())
+                                .ok_or_else(|| super::RpcError::internal("Summary response had no content"))
+                        });
+                    match summary {
+                        Ok(summary) => {
+                            insert_summary(request, &summary);
+                            report.strategy = "summarize".to_string();
+                            report.summarized_messages = older.len();
+                            report.summary_model = Some(summary_model);
+                        }
+                        Err(e) => {
+                            tracing::warn!("Context summarization with {} failed: {}", summary_model, e.message);
+                            report.strategy = "drop_oldest".to_string();
+                            report.dropped_messages = older.len();
+                            report.note = Some(format!("Summarization failed, older messages dropped: {}", e.message));
+                        }
+                    }
+                }
+            }
+            report.dropped_messages += drop_oldest(request, budget);
+        }
+    }
+
+    report.estimated_tokens = estimate_request_tokens(request);
+    report.truncated = report.dropped_messages > 0 || report.summarized_messages > 0;
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+            tool_call_id: None,
+        }
+    }
+
+    fn request(messages: Vec<ChatMessage>) -> ChatRequest {
+        ChatRequest {
+            model: None,
+            messages,
+            temperature: None,
+            max_tokens: None,
+            system_prompt: None,
+            tools: None,
+            session_id: None,
+            context_strategy: None,
+            priority: None,
+        }
+    }
+
+    #[test]
+    fn test_context_window_lookup() {
+        assert_eq!(context_window("ollama:llama3.2:latest", None), Some(131_072));
+        assert_eq!(context_window("openai:gpt-4o-mini", None), Some(128_000));
+        assert_eq!(context_window("mock:mock-model", None), None);
+
+        let mut cfg = LlmConfig::default();
+        cfg.add_model("mock:mock-model", Some("tiny"));
+        cfg.models[0].context_window = Some(512);
+        assert_eq!(context_window("mock:mock-model", Some(&cfg)), Some(512));
+        assert_eq!(context_window("tiny", Some(&cfg)), Some(512));
+    }
+
+    #[test]
+    fn test_drop_oldest_keeps_system_and_latest() {
+        let long = "x".repeat(400);
+        let mut req = request(vec![
+            message("system", "be brief"),
+            message("user", &long),
+            message("assistant", &long),
+            message("tool", &long),
+            message("user", "latest question"),
+        ]);
+
+        let dropped = drop_oldest(&mut req, 150);
+        assert_eq!(dropped, 3);
+        assert_eq!(req.messages.len(), 2);
+        assert_eq!(req.messages[0].role, "system");
+        assert_eq!(req.messages[1].content, "latest question");
+    }
+
+    #[test]
+    fn test_split_older_and_insert_summary() {
+        let mut req = request(vec![
+            message("system", "be brief"),
+            message("user", "one"),
+            message("assistant", "two"),
+            message("user", "three"),
+        ]);
+
+        let older = split_older(&mut req, 1);
+        assert_eq!(older.len(), 2);
+        assert_eq!(older[0].content, "one");
+
+        insert_summary(&mut req, "counted to two");
+        assert_eq!(req.messages.len(), 3);
+        assert_eq!(req.messages[1].role, "system");
+        assert!(req.messages[1].content.contains("counted to two"));
+        assert_eq!(req.messages[2].content, "three");
+    }
+
+    #[tokio::test]
+    async fn test_summarize_needs_a_summary_model() {
+        let long = "x".repeat(40_000);
+        let mut req = request(vec![message("user", &long), message("assistant", &long), message("user", "next")]);
+        req.context_strategy = Some(ContextStrategy::Summarize { model: None, keep_last: 1 });
+
+        // Nothing names a summary model, so the chat model isn't asked to
+        // summarize; the older turns are dropped
+        let report = fit("openai:gpt-4", &mut req, Some(&LlmConfig::default())).await;
+        assert_eq!(report.strategy, "drop_oldest");
+        assert_eq!(report.summarized_messages, 0);
+        assert_eq!(report.summary_model, None);
+        assert_eq!(req.messages.len(), 1);
+        assert_eq!(req.messages[0].content, "next");
+    }
+}
@@ -0,0 +1,214 @@
+//! Background provider health monitoring.
+//!
+//! A monitor task checks every enabled local provider instance (and local
+//! provider types without an instance) on an interval and caches the result,
+//! so `llm.health` can answer without probing. Cloud providers are only
+//! probed in the background when `health_check_cloud` is set, since every
+//! probe is a request to the provider's API; `llm.check_provider` probes any
+//! provider on demand. When a provider goes up or down a `provider_status`
+//! event is broadcast to the native messaging and WebSocket transports.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use super::{get_config, mock, routing, ProviderInstance, LOCAL_PROVIDER_TYPES};
+
+/// Check interval when `health_check_interval_secs` isn't configured.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Cached health of one provider.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ProviderHealth {
+    /// Instance ID, or provider type for unconfigured local providers
+    pub provider: String,
+    pub provider_type: String,
+    pub available: bool,
+    /// Round-trip time of the last check
+    pub latency_ms: u64,
+    /// RFC 3339 timestamp of the last check
+    pub last_checked: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Pushed to clients when a provider's availability changes.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderStatusEvent {
+    #[serde(flatten)]
+    pub health: ProviderHealth,
+    /// Availability before this check
+    pub previous: bool,
+}
+
+fn cache() -> &'static Mutex<HashMap<String, ProviderHealth>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, ProviderHealth>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+lazy_static::lazy_static! {
+    static ref STATUS_TX: broadcast::Sender<ProviderStatusEvent> = {
+        let (tx, _) = broadcast::channel(100);
+        tx
+    };
+}
+
+/// Subscribe to `provider_status` events.
+pub fn subscribe() -> broadcast::Receiver<ProviderStatusEvent> {
+    STATUS_TX.subscribe()
+}
+
+/// Cache a check result, returning an event if availability changed.
+/// The first result for a provider only seeds the cache.
+fn record(health: ProviderHealth) -> Option<ProviderStatusEvent> {
+    let mut cache = cache().lock().unwrap();
+    let previous = cache
+        .insert(health.provider.clone(), health.clone())
+        .map(|h| h.available);
+    match previous {
+        Some(previous) if previous != health.available => Some(ProviderStatusEvent { health, previous }),
+        _ => None,
+    }
+}
+
+/// Cache a check result from `llm.check_provider` (or the monitor) and
+/// notify subscribers if availability changed.
+pub fn update(provider: &str, provider_type: &str, status: &serde_json::Value, latency: Duration) {
+    let health = ProviderHealth {
+        provider: provider.to_string(),
+        provider_type: provider_type.to_string(),
+        available: status.get("available").and_then(|v| v.as_bool()).unwrap_or(false),
+        latency_ms: latency.as_millis() as u64,
+        last_checked: chrono::Utc::now().to_rfc3339(),
+        error: status.get("error").map(|e| match e.as_str() {
+            Some(s) => s.to_string(),
+            None => e.to_string(),
+        }),
+    };
+    if let Some(event) = record(health) {
+        tracing::info!(
+            "Provider {} is now {}",
+            event.health.provider,
+            if event.health.available { "up" } else { "down" }
+        );
+        // No subscribers is fine
+        let _ = STATUS_TX.send(event);
+    }
+}
+
+/// Whether probing an instance stays on this machine.
+fn is_local(instance: &ProviderInstance) -> bool {
+    LOCAL_PROVIDER_TYPES.contains(&instance.provider_type.as_str())
+        || instance.provider_type == mock::PROVIDER_TYPE
+        || matches!(routing::instance_endpoint(instance), Ok(Some(endpoint)) if endpoint.is_loopback())
+}
+
+/// Providers with health to report: (provider ID, provider type, local).
+fn known_providers() -> Vec<(String, String, bool)> {
+    let cfg = get_config().unwrap_or_default();
+    let mut providers: Vec<(String, String, bool)> = cfg
+        .providers
+        .values()
+        .filter(|i| i.enabled)
+        .map(|i| (i.id.clone(), i.provider_type.clone(), is_local(i)))
+        .collect();
+    for local_type in LOCAL_PROVIDER_TYPES {
+        if !providers.iter().any(|(_, t, _)| t == local_type) {
+            providers.push((local_type.to_string(), local_type.to_string(), true));
+        }
+    }
+    providers.sort();
+    providers
+}
+
+/// Check every monitored provider once, concurrently: the local ones, and
+/// cloud ones too when `health_check_cloud` is set.
+pub async fn check_all() {
+    let providers = known_providers();
+    let check_cloud = get_config().and_then(|c| c.health_check_cloud).unwrap_or(false);
+
+    // Forget providers that were removed or disabled
+    cache()
+        .lock()
+        .unwrap()
+        .retain(|id, _| providers.iter().any(|(p, _, _)| p == id));
+
+    let monitored = providers.iter().filter(|(_, _, local)| *local || check_cloud);
+    let checks = monitored.map(|(provider, provider_type, _)| async move {
+        // Successful checks update the cache themselves
+        let started = Instant::now();
+        if let Err(e) = super::check_provider_status(serde_json::json!({ "provider": provider })).await {
+            let status = serde_json::json!({ "available": false, "error": e.message });
+            update(provider, provider_type, &status, started.elapsed());
+        }
+    });
+    futures::future::join_all(checks).await;
+}
+
+/// Cached health of all monitored providers, sorted by provider ID.
+pub fn cached() -> Vec<ProviderHealth> {
+    let mut providers: Vec<ProviderHealth> = cache().lock().unwrap().values().cloned().collect();
+    providers.sort_by(|a, b| a.provider.cmp(&b.provider));
+    providers
+}
+
+/// Start the background monitor. The first round runs immediately.
+pub fn start_monitor() {
+    tokio::spawn(async {
+        loop {
+            check_all().await;
+            let interval = get_config()
+                .and_then(|c| c.health_check_interval_secs)
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_INTERVAL);
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health(provider: &str, available: bool) -> ProviderHealth {
+        ProviderHealth {
+            provider: provider.to_string(),
+            provider_type: "ollama".to_string(),
+            available,
+            latency_ms: 5,
+            last_checked: chrono::Utc::now().to_rfc3339(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_record_reports_transitions() {
+        assert!(record(health("test-health-ollama", true)).is_none());
+        assert!(record(health("test-health-ollama", true)).is_none());
+
+        let event = record(health("test-health-ollama", false)).unwrap();
+        assert!(event.previous && !event.health.available);
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["provider"], "test-health-ollama");
+        assert_eq!(json["available"], false);
+    }
+
+    #[test]
+    fn test_only_local_providers_are_monitored_by_default() {
+        assert!(is_local(&ProviderInstance::new("ollama", "Ollama")));
+        assert!(!is_local(&ProviderInstance::new("openai", "OpenAI")));
+        assert!(!is_local(&ProviderInstance::new("anthropic", "Anthropic")));
+
+        let mut lm_studio = ProviderInstance::new("openai_compatible", "LM Studio");
+        lm_studio.base_url = Some("http://localhost:1234/v1".to_string());
+        assert!(is_local(&lm_studio));
+        lm_studio.base_url = Some("https://gateway.example.com/v1".to_string());
+        assert!(!is_local(&lm_studio));
+    }
+}
@@ -0,0 +1,555 @@
+//! Deterministic mock LLM provider for offline testing.
+//!
+//! Responses are scripted in a JSON fixture file so that tests can exercise
+//! `llm.chat` and `llm.chat_stream` without a model or network access:
+//!
+//! ```json
+//! {
+//!   "models": ["mock-model"],
+//!   "responses": [
+//!     { "match": "weather", "tool_calls": [{ "name": "get_weather", "arguments": { "city": "Paris" } }] },
+//!     { "match": "fail", "error": { "code": -32001, "message": "Injected failure" } },
+//!     { "content": "Hello from the mock!", "tokens": ["Hello", " from", " the", " mock!"], "token_delay_ms": 20 }
+//!   ]
+//! }
+//! ```
+//!
+//! A response with `match` is used when the last user message contains that
+//! text. Otherwise unmatched responses are returned in order, cycling. With no
+//! fixture (or no applicable response) the mock echoes the last user message.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
+
+use super::{ChatRequest, StreamEvent};
+use crate::rpc::RpcError;
+
+/// Provider type name for the mock provider.
+pub const PROVIDER_TYPE: &str = "mock";
+
+/// Environment variable used when no provider instance specifies a fixture.
+pub const FIXTURE_ENV_VAR: &str = "HARBOR_LLM_MOCK_FIXTURE";
+
+/// A fixture file describing scripted responses.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockFixture {
+    /// Model names reported by `llm.list_models`
+    #[serde(default = "default_models")]
+    pub models: Vec<String>,
+    /// Scripted responses
+    #[serde(default)]
+    pub responses: Vec<MockResponse>,
+}
+
+impl Default for MockFixture {
+    fn default() -> Self {
+        Self {
+            models: default_models(),
+            responses: Vec::new(),
+        }
+    }
+}
+
+fn default_models() -> Vec<String> {
+    vec!["mock-model".to_string()]
+}
+
+/// A single scripted response.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MockResponse {
+    /// Only use this response when the last user message contains this text
+    #[serde(default, rename = "match")]
+    pub match_text: Option<String>,
+    /// Full response text
+    #[serde(default)]
+    pub content: Option<String>,
+    /// Tokens to stream (defaults to `content` split on word boundaries)
+    #[serde(default)]
+    pub tokens: Option<Vec<String>>,
+    /// Delay between streamed tokens
+    #[serde(default)]
+    pub token_delay_ms: u64,
+    /// Delay before the response (or first token) is produced
+    #[serde(default)]
+    pub delay_ms: u64,
+    /// Tool calls to return instead of (or alongside) content
+    #[serde(default)]
+    pub tool_calls: Vec<MockToolCall>,
+    /// Error to inject. When streaming, it is sent after any tokens.
+    #[serde(default)]
+    pub error: Option<MockError>,
+}
+
+/// A scripted tool call.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockToolCall {
+    #[serde(default)]
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+}
+
+impl MockToolCall {
+    /// OpenAI-format tool call; `index` names calls without a scripted id.
+    fn to_json(&self, index: usize) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id.clone().unwrap_or_else(|| format!("call_mock_{}", index)),
+            "type": "function",
+            "function": {
+                "name": self.name,
+                "arguments": self.arguments.to_string(),
+            },
+        })
+    }
+}
+
+/// A scripted error.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockError {
+    #[serde(default = "default_error_code")]
+    pub code: i64,
+    pub message: String,
+}
+
+fn default_error_code() -> i64 {
+    -32001
+}
+
+impl MockResponse {
+    fn echo(prompt: &str) -> Self {
+        Self {
+            content: Some(format!("Mock response to: {}", prompt)),
+            ..Default::default()
+        }
+    }
+
+    fn tokens(&self) -> Vec<String> {
+        if let Some(tokens) = &self.tokens {
+            return tokens.clone();
+        }
+        let content = self.content.as_deref().unwrap_or("");
+        let mut tokens = Vec::new();
+        let mut current = String::new();
+        for c in content.chars() {
+            if c.is_whitespace() && !current.trim().is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+            current.push(c);
+        }
+        if !current.is_empty() {
+            tokens.push(current);
+        }
+        tokens
+    }
+
+    fn content(&self) -> String {
+        match &self.content {
+            Some(c) => c.clone(),
+            None => self.tokens.as_ref().map(|t| t.concat()).unwrap_or_default(),
+        }
+    }
+}
+
+impl MockFixture {
+    /// Load a fixture from a JSON file.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read mock fixture '{}': {}", path, e))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse mock fixture '{}': {}", path, e))
+    }
+
+    /// Pick the response for a prompt, advancing the cursor for unmatched responses.
+    fn select(&self, cursor: &mut usize, prompt: &str) -> MockResponse {
+        let prompt_lower = prompt.to_lowercase();
+        if let Some(matched) = self.responses.iter().find(|r| {
+            r.match_text
+                .as_ref()
+                .is_some_and(|m| prompt_lower.contains(&m.to_lowercase()))
+        }) {
+            return matched.clone();
+        }
+
+        let unmatched: Vec<&MockResponse> =
+            self.responses.iter().filter(|r| r.match_text.is_none()).collect();
+        if unmatched.is_empty() {
+            return MockResponse::echo(prompt);
+        }
+
+        let response = unmatched[*cursor % unmatched.len()].clone();
+        *cursor += 1;
+        response
+    }
+}
+
+/// Loaded fixtures keyed by path, with their sequential-response cursor and
+/// the file's modification time when it was read.
+struct MockState {
+    fixture: MockFixture,
+    cursor: usize,
+    modified: Option<SystemTime>,
+}
+
+fn fixtures() -> &'static Mutex<HashMap<String, MockState>> {
+    static FIXTURES: OnceLock<Mutex<HashMap<String, MockState>>> = OnceLock::new();
+    FIXTURES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Resolve the fixture path: the instance setting wins over the environment.
+pub fn resolve_fixture_path(instance_fixture: Option<String>) -> Option<String> {
+    instance_fixture.or_else(|| std::env::var(FIXTURE_ENV_VAR).ok().filter(|p| !p.is_empty()))
+}
+
+/// Run `f` against the (cached) fixture for a path. The fixture is re-read,
+/// and its cursor reset, when the file has changed since it was loaded.
+fn with_state<T>(
+    fixture_path: Option<&str>,
+    f: impl FnOnce(&mut MockState) -> T,
+) -> Result<T, String> {
+    let key = fixture_path.unwrap_or("").to_string();
+    let modified = fixture_path.and_then(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok());
+    let mut states = fixtures().lock().unwrap();
+    let stale = states.get(&key).is_none_or(|state| state.modified != modified);
+    if stale {
+        let fixture = match fixture_path {
+            Some(path) => MockFixture::load(path)?,
+            None => MockFixture::default(),
+        };
+        states.insert(key.clone(), MockState { fixture, cursor: 0, modified });
+    }
+    Ok(f(states.get_mut(&key).unwrap()))
+}
+
+/// List the models declared by a fixture.
+pub fn list_models(fixture_path: Option<&str>) -> Result<Vec<String>, String> {
+    with_state(fixture_path, |state| state.fixture.models.clone())
+}
+
+fn next_response(fixture_path: Option<&str>, request: &ChatRequest) -> Result<MockResponse, RpcError> {
+    let prompt = request
+        .messages
+        .iter()
+        .rev()
+        .find(|m| m.role == "user")
+        .map(|m| m.content.as_str())
+        .unwrap_or("");
+
+    with_state(fixture_path, |state| state.fixture.select(&mut state.cursor, prompt)).map_err(|e| {
+        RpcError {
+            code: -32001,
+            message: format!("LLM error: {}", e),
+            data: None,
+        }
+    })
+}
+
+fn count_words(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+/// Non-streaming chat completion against the mock provider.
+/// Returns an OpenAI-style completion object, matching what any-llm returns.
+pub async fn chat(
+    model: &str,
+    request: &ChatRequest,
+    fixture_path: Option<String>,
+) -> Result<serde_json::Value, RpcError> {
+    let response = next_response(fixture_path.as_deref(), request)?;
+
+    if response.delay_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(response.delay_ms)).await;
+    }
+
+    if let Some(err) = response.error {
+        return Err(RpcError {
+            code: err.code,
+            message: err.message,
+            data: None,
+        });
+    }
+
+    let content = response.content();
+    let tool_calls: Vec<serde_json::Value> = response
+        .tool_calls
+        .iter()
+        .enumerate()
+        .map(|(i, call)| call.to_json(i))
+        .collect();
+
+    let mut message = serde_json::json!({
+        "role": "assistant",
+        "content": content,
+    });
+    let finish_reason = if tool_calls.is_empty() {
+        "stop"
+    } else {
+        message["tool_calls"] = serde_json::Value::Array(tool_calls);
+        "tool_calls"
+    };
+
+    let prompt_tokens: usize = request.messages.iter().map(|m| count_words(&m.content)).sum();
+    let completion_tokens = count_words(&content);
+
+    Ok(serde_json::json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": finish_reason,
+        }],
+        "usage": {
+            "prompt_tokens": prompt_tokens,
+            "completion_tokens": completion_tokens,
+            "total_tokens": prompt_tokens + completion_tokens,
+        },
+    }))
+}
+
+/// Streaming chat completion against the mock provider.
+pub async fn chat_stream(
+    request_id: serde_json::Value,
+    model: String,
+    request: &ChatRequest,
+    fixture_path: Option<String>,
+    event_tx: mpsc::Sender<StreamEvent>,
+) {
+    let response = match next_response(fixture_path.as_deref(), request) {
+        Ok(r) => r,
+        Err(e) => {
+            let _ = event_tx
+                .send(error_event(request_id, &model, e.code, e.message))
+                .await;
+            return;
+        }
+    };
+
+    if response.delay_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(response.delay_ms)).await;
+    }
+
+    for (i, token) in response.tokens().into_iter().enumerate() {
+        if i > 0 && response.token_delay_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(response.token_delay_ms)).await;
+        }
+        let event = StreamEvent {
+            id: request_id.clone(),
+            event_type: "token".to_string(),
+            token: Some(token),
+            finish_reason: None,
+            model: Some(model.clone()),
+            error: None,
+            tool_calls: None,
+            context: None,
+            queue_position: None,
+        };
+        if event_tx.send(event).await.is_err() {
+            return; // Receiver dropped
+        }
+    }
+
+    // Each tool call goes out whole, as a single OpenAI-style delta
+    for (i, call) in response.tool_calls.iter().enumerate() {
+        let mut delta = call.to_json(i);
+        delta["index"] = serde_json::json!(i);
+        let event = StreamEvent {
+            id: request_id.clone(),
+            event_type: "tool_call".to_string(),
+            token: None,
+            finish_reason: None,
+            model: Some(model.clone()),
+            error: None,
+            tool_calls: Some(serde_json::json!([delta])),
+            context: None,
+            queue_position: None,
+        };
+        if event_tx.send(event).await.is_err() {
+            return; // Receiver dropped
+        }
+    }
+
+    let final_event = match response.error {
+        Some(err) => error_event(request_id, &model, err.code, err.message),
+        None => StreamEvent {
+            id: request_id,
+            event_type: "done".to_string(),
+            token: None,
+            finish_reason: Some(
+                if response.tool_calls.is_empty() { "stop" } else { "tool_calls" }.to_string(),
+            ),
+            model: Some(model),
+            error: None,
+            tool_calls: None,
+            context: None,
+            queue_position: None,
+        },
+    };
+    let _ = event_tx.send(final_event).await;
+}
+
+fn error_event(request_id: serde_json::Value, model: &str, code: i64, message: String) -> StreamEvent {
+    StreamEvent {
+        id: request_id,
+        event_type: "error".to_string(),
+        token: None,
+        finish_reason: None,
+        model: Some(model.to_string()),
+        error: Some(serde_json::json!({
+            "code": code,
+            "message": message,
+        })),
+        tool_calls: None,
+        context: None,
+        queue_position: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::ChatMessage;
+
+    fn fixture() -> MockFixture {
+        serde_json::from_value(serde_json::json!({
+            "responses": [
+                { "match": "weather", "tool_calls": [{ "name": "get_weather", "arguments": { "city": "Paris" } }] },
+                { "content": "first" },
+                { "content": "second" }
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn request(prompt: &str) -> ChatRequest {
+        ChatRequest {
+            model: Some("mock:mock-model".to_string()),
+            messages: vec![ChatMessage {
+                role: "user".to_string(),
+                content: prompt.to_string(),
+                tool_call_id: None,
+            }],
+            temperature: None,
+            max_tokens: None,
+            system_prompt: None,
+            tools: None,
+            session_id: None,
+            context_strategy: None,
+            priority: None,
+        }
+    }
+
+    #[test]
+    fn test_select_match_then_cycle() {
+        let fixture = fixture();
+        let mut cursor = 0;
+
+        let matched = fixture.select(&mut cursor, "What's the WEATHER like?");
+        assert_eq!(matched.tool_calls[0].name, "get_weather");
+        assert_eq!(cursor, 0);
+
+        assert_eq!(fixture.select(&mut cursor, "hi").content(), "first");
+        assert_eq!(fixture.select(&mut cursor, "hi").content(), "second");
+        assert_eq!(fixture.select(&mut cursor, "hi").content(), "first");
+    }
+
+    #[test]
+    fn test_fixture_reloads_when_changed() {
+        let path = std::env::temp_dir().join(format!("harbor-mock-fixture-{}.json", std::process::id()));
+        let write = |models: serde_json::Value, modified: SystemTime| {
+            std::fs::write(&path, serde_json::json!({ "models": models }).to_string()).unwrap();
+            std::fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        };
+        let path_str = path.to_string_lossy().to_string();
+
+        let earlier = SystemTime::now() - Duration::from_secs(60);
+        write(serde_json::json!(["before"]), earlier);
+        assert_eq!(list_models(Some(&path_str)).unwrap(), vec!["before".to_string()]);
+
+        write(serde_json::json!(["after"]), SystemTime::now());
+        assert_eq!(list_models(Some(&path_str)).unwrap(), vec!["after".to_string()]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_select_echo_without_responses() {
+        let fixture = MockFixture::default();
+        let mut cursor = 0;
+        assert_eq!(fixture.select(&mut cursor, "ping").content(), "Mock response to: ping");
+        assert_eq!(fixture.models, vec!["mock-model".to_string()]);
+    }
+
+    #[test]
+    fn test_tokens_split_content() {
+        let response = MockResponse {
+            content: Some("Hello mock world".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(response.tokens(), vec!["Hello", " mock", " world"]);
+        assert_eq!(response.tokens().concat(), "Hello mock world");
+    }
+
+    #[tokio::test]
+    async fn test_chat_echo_shape() {
+        let result = chat("mock:mock-model", &request("hello"), None).await.unwrap();
+        assert_eq!(result["choices"][0]["message"]["content"], "Mock response to: hello");
+        assert_eq!(result["choices"][0]["finish_reason"], "stop");
+        assert_eq!(result["model"], "mock:mock-model");
+    }
+
+    #[tokio::test]
+    async fn test_stream_tokens_then_done() {
+        let (tx, mut rx) = mpsc::channel(32);
+        chat_stream(serde_json::json!(1), "mock:mock-model".to_string(), &request("hi there"), None, tx).await;
+
+        let mut tokens = String::new();
+        let mut last = None;
+        while let Some(event) = rx.recv().await {
+            if event.event_type == "token" {
+                tokens.push_str(event.token.as_deref().unwrap_or(""));
+            }
+            last = Some(event.event_type);
+        }
+        assert_eq!(tokens, "Mock response to: hi there");
+        assert_eq!(last.as_deref(), Some("done"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_tool_calls() {
+        let path = std::env::temp_dir().join(format!("harbor-mock-stream-{}.json", std::process::id()));
+        std::fs::write(&path, serde_json::to_string(&serde_json::json!({
+            "responses": [{ "match": "weather", "tool_calls": [{ "name": "get_weather", "arguments": { "city": "Paris" } }] }]
+        })).unwrap()).unwrap();
+
+        let (tx, mut rx) = mpsc::channel(32);
+        let fixture_path = Some(path.to_string_lossy().to_string());
+        chat_stream(serde_json::json!(1), "mock:mock-model".to_string(), &request("weather?"), fixture_path, tx).await;
+
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+        let last = events.pop().unwrap();
+        assert_eq!(last.event_type, "done");
+        assert_eq!(last.finish_reason.as_deref(), Some("tool_calls"));
+        let deltas: Vec<_> = events.iter().filter(|e| e.event_type == "tool_call").collect();
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(
+            deltas[0].tool_calls,
+            Some(serde_json::json!([{
+                "index": 0,
+                "id": "call_mock_0",
+                "type": "function",
+                "function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" },
+            }]))
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,22 +1,47 @@
 //! LLM module using any-llm for multi-provider support.
 
+mod azure_openai;
+mod capabilities;
 mod config;
-
-pub use config::{LlmConfig, ProviderInstance};
-
-use crate::rpc::RpcError;
+mod context;
+pub mod health;
+mod mock;
+pub mod ollama;
+mod openai_compat;
+mod queue;
+mod routing;
+
+pub use config::{CapabilityOverrides, LlmConfig, ProviderInstance};
+
+use crate::rpc::{to_result, OkResponse, RpcError};
+use crate::sessions;
+use capabilities::ModelCapabilities;
+use config::ModelAlias;
+use health::ProviderHealth;
 use any_llm::{
     check_provider, completion, completion_stream, get_supported_providers, list_models as any_llm_list_models,
     CompletionRequest, Message, ProviderConfig, Tool, ToolFunction,
 };
 use futures::StreamExt;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::RwLock;
 use tokio::sync::mpsc;
 
 // Global configuration store
 static CONFIG: RwLock<Option<LlmConfig>> = RwLock::new(None);
 
+/// Provider types implemented inside the bridge rather than by any-llm.
+const BUILTIN_PROVIDER_TYPES: [&str; 3] = [
+    mock::PROVIDER_TYPE,
+    openai_compat::PROVIDER_TYPE,
+    azure_openai::PROVIDER_TYPE,
+];
+
+/// Provider types that run locally and can be probed without an instance.
+const LOCAL_PROVIDER_TYPES: [&str; 2] = ["ollama", "llamafile"];
+
 /// Initialize or update LLM configuration.
 pub fn set_config(config: LlmConfig) {
     let mut cfg = CONFIG.write().unwrap();
@@ -30,84 +55,221 @@ pub fn get_config() -> Option<LlmConfig> {
 
 /// Check health of LLM providers.
 pub async fn health() -> Result<serde_json::Value, RpcError> {
-    let providers = get_supported_providers();
-    let mut statuses = Vec::new();
-
-    for provider in &providers {
-        let config = get_provider_config(provider);
-        let status = check_provider(provider, config).await;
-        statuses.push(status);
+    // Served from the health monitor's cache; check now if it hasn't run yet
+    let mut statuses = health::cached();
+    if statuses.is_empty() {
+        health::check_all().await;
+        statuses = health::cached();
     }
 
-    Ok(serde_json::json!({
-        "status": "ok",
-        "providers": statuses
-    }))
+    to_result(HealthResponse {
+        status: "ok".to_string(),
+        providers: statuses,
+    })
 }
 
 /// List available models from all configured providers.
 pub async fn list_models() -> Result<serde_json::Value, RpcError> {
-    let mut all_models: Vec<serde_json::Value> = Vec::new();
+    let mut all_models: Vec<ModelInfo> = Vec::new();
+
+    let cfg = get_config();
+    let mut instances: Vec<ProviderInstance> = cfg
+        .iter()
+        .flat_map(|c| c.providers.values())
+        .filter(|i| i.enabled)
+        .cloned()
+        .collect();
+    // Local providers first, then a stable order by instance ID
+    instances.sort_by_key(|i| (!LOCAL_PROVIDER_TYPES.contains(&i.provider_type.as_str()), i.id.clone()));
 
-    // Check local providers first (Ollama, Llamafile)
-    for provider in &["ollama", "llamafile"] {
-        let config = get_provider_config(provider);
-        if let Ok(models) = any_llm_list_models(provider, config).await {
+    // Probe local providers that have no configured instance (Ollama, Llamafile)
+    for provider in LOCAL_PROVIDER_TYPES {
+        if instances.iter().any(|i| i.provider_type == provider) {
+            continue;
+        }
+        if let Ok(models) = any_llm_list_models(provider, None).await {
             // Prefix model IDs with provider name for routing
             for model in models {
-                all_models.push(serde_json::json!({
-                    "id": format!("{}:{}", provider, model.id),
-                    "provider": provider,
-                    "owned_by": model.owned_by,
-                }));
+                all_models.push(ModelInfo {
+                    id: format!("{}:{}", provider, model.id),
+                    provider: provider.to_string(),
+                    instance: None,
+                    owned_by: serde_json::json!(model.owned_by),
+                    deployment: None,
+                    capabilities: None,
+                });
             }
         }
     }
 
-    // Check remote providers if configured
-    let cfg = get_config();
-    if let Some(config) = &cfg {
-        for (_instance_id, settings) in &config.providers {
-            // Use provider_type (e.g., "openai") not instance_id (e.g., "openai-3090e2")
-            let provider_type = &settings.provider_type;
-            if settings.enabled && settings.api_key.is_some() {
-                let provider_config = Some(ProviderConfig {
-                    api_key: Some(settings.api_key.clone()),
-                    base_url: settings.base_url.clone(),
-                    ..Default::default()
+    // Mock provider models (configured instances, or the fixture from the environment)
+    let mut mock_fixtures: Vec<Option<String>> = instances
+        .iter()
+        .filter(|i| i.provider_type == mock::PROVIDER_TYPE)
+        .map(|i| mock::resolve_fixture_path(i.fixture_path.clone()))
+        .collect();
+    if mock_fixtures.is_empty() && std::env::var(mock::FIXTURE_ENV_VAR).is_ok() {
+        mock_fixtures.push(mock::resolve_fixture_path(None));
+    }
+    for fixture_path in mock_fixtures {
+        match mock::list_models(fixture_path.as_deref()) {
+            Ok(models) => {
+                for model in models {
+                    all_models.push(ModelInfo {
+                        id: format!("{}:{}", mock::PROVIDER_TYPE, model),
+                        provider: mock::PROVIDER_TYPE.to_string(),
+                        instance: None,
+                        owned_by: serde_json::json!("harbor"),
+                        deployment: None,
+                        capabilities: None,
+                    });
+                }
+            }
+            Err(e) => tracing::warn!("Failed to list mock models: {}", e),
+        }
+    }
+
+    // Every other enabled instance, with model IDs qualified by instance ID
+    // so they route back to the same instance (and don't collide)
+    for instance in &instances {
+        let provider_type = instance.provider_type.as_str();
+        if provider_type == mock::PROVIDER_TYPE {
+            continue;
+        }
+
+        // Azure models are the configured deployments
+        if provider_type == azure_openai::PROVIDER_TYPE {
+            for model in azure_openai::list_models(instance) {
+                all_models.push(ModelInfo {
+                    id: format!("{}:{}", instance.id, model),
+                    provider: provider_type.to_string(),
+                    instance: Some(instance.id.clone()),
+                    owned_by: serde_json::json!("azure"),
+                    deployment: Some(azure_openai::deployment_name(instance, &model).to_string()),
+                    capabilities: None,
                 });
-                if let Ok(models) = any_llm_list_models(provider_type, provider_config).await {
-                    // Prefix model IDs with provider type for routing
-                    for model in models {
-                        all_models.push(serde_json::json!({
-                            "id": format!("{}:{}", provider_type, model.id),
-                            "provider": provider_type,
-                            "owned_by": model.owned_by,
-                        }));
-                    }
+            }
+            continue;
+        }
+
+        let endpoint = match routing::instance_endpoint(instance) {
+            Ok(endpoint) => endpoint,
+            Err(e) => {
+                tracing::warn!("Failed to list models for {}: {}", instance.id, e);
+                continue;
+            }
+        };
+        let models: Vec<(String, serde_json::Value)> = if let Some(endpoint) = endpoint {
+            match openai_compat::list_models(&endpoint).await {
+                Ok(models) => models.into_iter().map(|m| (m.id, serde_json::json!(m.owned_by))).collect(),
+                Err(e) => {
+                    tracing::warn!("Failed to list models for {}: {}", instance.id, e);
+                    continue;
                 }
             }
+        } else {
+            // Cloud providers need an API key; local ones don't
+            if !LOCAL_PROVIDER_TYPES.contains(&provider_type) && instance.api_key.is_none() {
+                continue;
+            }
+            let provider_config = Some(ProviderConfig {
+                api_key: Some(instance.api_key.clone()),
+                base_url: instance.base_url.clone(),
+                ..Default::default()
+            });
+            match any_llm_list_models(provider_type, provider_config).await {
+                Ok(models) => models.into_iter().map(|m| (m.id, serde_json::json!(m.owned_by))).collect(),
+                Err(_) => continue,
+            }
+        };
+
+        for (model_id, owned_by) in models {
+            all_models.push(ModelInfo {
+                id: format!("{}:{}", instance.id, model_id),
+                provider: provider_type.to_string(),
+                instance: Some(instance.id.clone()),
+                owned_by,
+                deployment: None,
+                capabilities: None,
+            });
         }
     }
 
-    Ok(serde_json::json!({ "models": all_models }))
+    // Attach capability records (fetched from Ollama where possible)
+    let ids: Vec<String> = all_models.iter().map(|m| m.id.clone()).collect();
+    let caps = capabilities::lookup_all(&ids, cfg.as_ref()).await;
+    for (model, caps) in all_models.iter_mut().zip(caps) {
+        model.capabilities = Some(caps);
+    }
+
+    to_result(ListModelsResponse { models: all_models })
 }
 
 /// Chat completion request.
 pub async fn chat(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let request: ChatRequest = serde_json::from_value(params).map_err(|e| RpcError {
+    let mut request: ChatRequest = serde_json::from_value(params).map_err(|e| RpcError {
         code: -32602,
         message: format!("Invalid params: {}", e),
+        data: None,
     })?;
 
-    // Use provided model or fall back to default
-    let model = request.model.or_else(|| {
-        get_config().and_then(|c| c.default_model.clone())
-    }).ok_or_else(|| RpcError {
-        code: -32602,
-        message: "No model specified and no default model configured".to_string(),
-    })?;
+    let session = match &request.session_id {
+        Some(id) => Some(sessions::load(id).await?),
+        None => None,
+    };
+
+    // Use provided model, then the session's model, then the default
+    let model = request.model.clone()
+        .or_else(|| session.as_ref().and_then(|s| s.model.clone()))
+        .or_else(|| get_config().and_then(|c| c.default_model.clone()))
+        .ok_or_else(|| RpcError {
+            code: -32602,
+            message: "No model specified and no default model configured".to_string(),
+            data: None,
+        })?;
 
+    let turn = request.messages.clone();
+    if let Some(session) = &session {
+        apply_session_history(&mut request, session);
+    }
+
+    let report = context::fit(&model, &mut request, get_config().as_ref()).await;
+
+    let mut response = dispatch_chat(&model, request).await?;
+    if let Some(obj) = response.as_object_mut() {
+        obj.insert("context".to_string(), serde_json::to_value(&report).unwrap_or_default());
+    }
+
+    if let Some(session) = &session {
+        let assistant = response
+            .pointer("/choices/0/message")
+            .cloned()
+            .unwrap_or(serde_json::Value::Null);
+        record_session_turn(session, turn, assistant, &model).await;
+    }
+
+    Ok(response)
+}
+
+/// Run a non-streaming completion against an already-resolved model.
+async fn dispatch_chat(model: &str, request: ChatRequest) -> Result<serde_json::Value, RpcError> {
+    if is_mock_model(model) {
+        return mock::chat(model, &request, mock_fixture_for_model(model)).await;
+    }
+    let route = routing::resolve(model)?;
+    // Held until the response is complete
+    let _permit = match &route.instance {
+        Some(instance) => queue::acquire(instance, request.priority.unwrap_or_default(), |_| {}).await?,
+        None => None,
+    };
+    if let Some(endpoint) = route.openai_endpoint()? {
+        return openai_compat::chat(&endpoint, &route.model_name, &request).await;
+    }
+    complete(&route, request).await
+}
+
+/// Send a chat request through any-llm.
+async fn complete(route: &routing::ModelRoute, request: ChatRequest) -> Result<serde_json::Value, RpcError> {
     // Build messages, prepending system prompt if provided
     let mut messages: Vec<Message> = Vec::new();
     
@@ -140,26 +302,89 @@ pub async fn chat(params: serde_json::Value) -> Result<serde_json::Value, RpcErr
             .collect()
     });
 
-    let provider_config = get_provider_config_for_model(&model);
-
     let completion_request = CompletionRequest {
-        model,
+        model: route.any_llm_model(),
         messages,
         tools,
         temperature: request.temperature,
         max_tokens: request.max_tokens,
-        api_key: provider_config.and_then(|c| c.api_key.flatten()),
+        api_key: route.api_key(),
         ..Default::default()
     };
 
-    let response = completion(completion_request).await.map_err(|e| RpcError {
+    let result = match route.timeout() {
+        Some(timeout) => tokio::time::timeout(timeout, completion(completion_request))
+            .await
+            .map_err(|_| RpcError {
+                code: -32001,
+                message: format!("LLM error: request timed out after {}s", timeout.as_secs()),
+                data: None,
+            })?,
+        None => completion(completion_request).await,
+    };
+    let response = result.map_err(|e| RpcError {
         code: -32001,
         message: format!("LLM error: {}", e),
+        data: None,
     })?;
 
     Ok(serde_json::to_value(response).unwrap())
 }
 
+/// Prepend a session's stored history to a request. The session's system
+/// prompt fills in for a request that doesn't set its own.
+fn apply_session_history(request: &mut ChatRequest, session: &sessions::Session) {
+    if request.system_prompt.is_none() {
+        request.system_prompt = session.system_prompt.clone();
+    }
+
+    let mut messages: Vec<ChatMessage> = session
+        .messages
+        .iter()
+        .map(|m| ChatMessage {
+            role: m.role.clone(),
+            content: m.content.clone(),
+            tool_call_id: m.tool_call_id.clone(),
+        })
+        .collect();
+    messages.append(&mut request.messages);
+    request.messages = messages;
+}
+
+/// Store the new user turn and the assistant reply in a session.
+/// Failures are logged rather than failing the completed chat.
+async fn record_session_turn(
+    session: &sessions::Session,
+    turn: Vec<ChatMessage>,
+    assistant: serde_json::Value,
+    model: &str,
+) {
+    let mut messages: Vec<sessions::SessionMessage> = turn
+        .into_iter()
+        .map(|m| sessions::SessionMessage {
+            role: m.role,
+            content: m.content,
+            tool_call_id: m.tool_call_id,
+            tool_calls: None,
+            model: None,
+            timestamp: 0,
+        })
+        .collect();
+
+    messages.push(sessions::SessionMessage {
+        role: "assistant".to_string(),
+        content: assistant.get("content").and_then(|c| c.as_str()).unwrap_or("").to_string(),
+        tool_call_id: None,
+        tool_calls: assistant.get("tool_calls").filter(|t| !t.is_null()).cloned(),
+        model: Some(model.to_string()),
+        timestamp: 0,
+    });
+
+    if let Err(e) = sessions::record_turn(session, messages, model).await {
+        tracing::warn!("Failed to record turn in session {}: {}", session.id, e.message);
+    }
+}
+
 /// Get provider configuration for a specific provider instance or type.
 /// Accepts either an instance ID or a provider type.
 fn get_provider_config(provider: &str) -> Option<ProviderConfig> {
@@ -177,10 +402,29 @@ fn get_provider_config(provider: &str) -> Option<ProviderConfig> {
     })
 }
 
-/// Get provider configuration based on model string (e.g., "openai:gpt-4o").
-fn get_provider_config_for_model(model: &str) -> Option<ProviderConfig> {
-    let provider = model.split(':').next()?;
-    get_provider_config(provider)
+/// Check whether a model string routes to the built-in mock provider.
+fn is_mock_model(model: &str) -> bool {
+    let provider = model.split(':').next().unwrap_or("");
+    resolve_provider_type(provider).as_deref().unwrap_or(provider) == mock::PROVIDER_TYPE
+}
+
+/// Get the fixture path for a mock model from its provider instance or the environment.
+fn mock_fixture_for_model(model: &str) -> Option<String> {
+    let provider = model.split(':').next().unwrap_or("");
+    let instance_fixture = get_config()
+        .and_then(|c| c.resolve_provider(provider).and_then(|i| i.fixture_path.clone()));
+    mock::resolve_fixture_path(instance_fixture)
+}
+
+/// All provider types: any-llm's plus the ones built into the bridge.
+fn supported_provider_types() -> Vec<String> {
+    let mut types = get_supported_providers();
+    for builtin in BUILTIN_PROVIDER_TYPES {
+        if !types.iter().any(|t| t == builtin) {
+            types.push(builtin.to_string());
+        }
+    }
+    types
 }
 
 /// Get the actual provider type from an instance ID or type string.
@@ -200,7 +444,7 @@ fn resolve_provider_type(provider: &str) -> Option<String> {
 // RPC-specific types
 // =============================================================================
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ChatRequest {
     #[serde(default)]
     pub model: Option<String>,
@@ -215,9 +459,21 @@ pub struct ChatRequest {
     /// Tools available for the model to call
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<ToolDefinition>>,
+    /// Stored session to load history from and append this turn to. The
+    /// session's system prompt is used unless `system_prompt` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// How to shrink the history if it overflows the context window
+    /// (defaults to dropping the oldest messages)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_strategy: Option<context::ContextStrategy>,
+    /// Queue priority on instances with a concurrency limit
+    /// (defaults to interactive)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<queue::Priority>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ChatMessage {
     pub role: String,
     pub content: String,
@@ -227,7 +483,7 @@ pub struct ChatMessage {
 }
 
 /// Tool definition for the chat API
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ToolDefinition {
     pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -240,16 +496,181 @@ fn default_input_schema() -> serde_json::Value {
     serde_json::json!({ "type": "object", "properties": {} })
 }
 
+// =============================================================================
+// RPC response types
+// =============================================================================
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct HealthResponse {
+    pub status: String,
+    pub providers: Vec<ProviderHealth>,
+}
+
+/// A model offered by a provider.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ModelInfo {
+    /// Routable model ID (`<instance or type>:<model>`)
+    pub id: String,
+    pub provider: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// Owner as reported by the provider
+    pub owned_by: serde_json::Value,
+    /// Deployment that serves the model (Azure OpenAI only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deployment: Option<String>,
+    /// Attached once every model has been listed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<ModelCapabilities>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ListModelsResponse {
+    pub models: Vec<ModelInfo>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ProviderTypeInfo {
+    #[serde(rename = "type")]
+    pub provider_type: String,
+    pub needs_api_key: bool,
+    pub is_local: bool,
+    pub instance_count: usize,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ListProviderTypesResponse {
+    pub provider_types: Vec<ProviderTypeInfo>,
+}
+
+/// A configured provider instance, or a provider type without one.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ProviderInfo {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub provider_type: String,
+    pub name: String,
+    pub configured: bool,
+    pub needs_api_key: bool,
+    pub is_local: bool,
+    pub is_default: bool,
+    pub is_type_default: bool,
+    pub has_api_key: bool,
+    pub base_url: Option<String>,
+    pub available: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ListProvidersResponse {
+    pub providers: Vec<ProviderInfo>,
+    pub default_provider: Option<String>,
+}
+
+/// A provider instance's settings, without secrets.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ProviderSettings {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub provider_type: String,
+    pub name: String,
+    pub enabled: bool,
+    pub has_api_key: bool,
+    pub base_url: Option<String>,
+    pub fixture_path: Option<String>,
+    /// Header values may carry credentials, so only names are reported
+    pub header_names: Vec<String>,
+    pub organization: Option<String>,
+    pub project: Option<String>,
+    pub timeout_secs: Option<u64>,
+    pub max_concurrency: Option<u32>,
+    pub queue_timeout_secs: Option<u64>,
+    pub deployments: HashMap<String, String>,
+    pub api_version: Option<String>,
+    pub is_type_default: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ConfigResponse {
+    pub version: u32,
+    pub default_model: Option<String>,
+    pub default_provider: Option<String>,
+    pub providers: HashMap<String, ProviderSettings>,
+}
+
+/// Result of `llm.configure_provider` and `llm.add_provider`.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ProviderIdResponse {
+    pub ok: bool,
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DefaultProviderResponse {
+    pub ok: bool,
+    pub default_provider: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DefaultModelResponse {
+    pub ok: bool,
+    pub default_model: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ListConfiguredModelsResponse {
+    pub models: Vec<ModelAlias>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ModelMetadata {
+    pub model_id: String,
+    pub is_local: bool,
+    pub capabilities: ModelCapabilities,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ModelsMetadataResponse {
+    pub metadata: Vec<ModelMetadata>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ModelCapabilitiesResponse {
+    pub ok: bool,
+    pub model_id: String,
+    pub capabilities: ModelCapabilities,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ConfiguredModelResponse {
+    pub ok: bool,
+    pub name: String,
+    pub model_id: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ContextWindowResponse {
+    pub ok: bool,
+    pub name: String,
+    pub context_window: Option<usize>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DefaultConfiguredModelResponse {
+    pub ok: bool,
+    /// Name of the new default model
+    pub default: String,
+}
+
 // =============================================================================
 // Configuration RPC handlers
 // =============================================================================
 
 /// List supported provider types (for UI dropdown).
 pub async fn list_provider_types() -> Result<serde_json::Value, RpcError> {
-    let types = get_supported_providers();
+    let types = supported_provider_types();
     let cfg = get_config();
 
-    let type_info: Vec<serde_json::Value> = types
+    let type_info: Vec<ProviderTypeInfo> = types
         .iter()
         .map(|t| {
             let instance_count = cfg
@@ -257,24 +678,24 @@ pub async fn list_provider_types() -> Result<serde_json::Value, RpcError> {
                 .map(|c| c.get_instances_by_type(t).len())
                 .unwrap_or(0);
 
-            let needs_api_key = matches!(t.as_str(), "openai" | "anthropic" | "mistral" | "groq");
-            let is_local = matches!(t.as_str(), "ollama" | "llamafile" | "lmstudio");
+            let needs_api_key = matches!(t.as_str(), "openai" | "anthropic" | "mistral" | "groq" | "azure_openai");
+            let is_local = matches!(t.as_str(), "ollama" | "llamafile" | "lmstudio" | "mock");
 
-            serde_json::json!({
-                "type": t,
-                "needs_api_key": needs_api_key,
-                "is_local": is_local,
-                "instance_count": instance_count,
-            })
+            ProviderTypeInfo {
+                provider_type: t.clone(),
+                needs_api_key,
+                is_local,
+                instance_count,
+            }
         })
         .collect();
 
-    Ok(serde_json::json!({ "provider_types": type_info }))
+    to_result(ListProviderTypesResponse { provider_types: type_info })
 }
 
 /// List all configured provider instances.
 pub async fn list_providers() -> Result<serde_json::Value, RpcError> {
-    let supported_types = get_supported_providers();
+    let supported_types = supported_provider_types();
     let cfg = get_config().unwrap_or_default();
 
     // Check which local providers are actually running
@@ -286,28 +707,31 @@ pub async fn list_providers() -> Result<serde_json::Value, RpcError> {
         }
     }
 
-    let mut provider_info: Vec<serde_json::Value> = Vec::new();
+    let mut provider_info: Vec<ProviderInfo> = Vec::new();
 
     // Add configured instances
     for instance in cfg.providers.values() {
-        let needs_api_key = matches!(instance.provider_type.as_str(), "openai" | "anthropic" | "mistral" | "groq");
-        let is_local = matches!(instance.provider_type.as_str(), "ollama" | "llamafile" | "lmstudio");
+        let needs_api_key = matches!(instance.provider_type.as_str(), "openai" | "anthropic" | "mistral" | "groq" | "azure_openai");
+        let is_local = matches!(instance.provider_type.as_str(), "ollama" | "llamafile" | "lmstudio" | "mock");
         let is_global_default = cfg.default_provider.as_deref() == Some(&instance.id);
-        let is_available = is_local && local_available.contains(&instance.provider_type);
-
-        provider_info.push(serde_json::json!({
-            "id": instance.id,
-            "type": instance.provider_type,
-            "name": instance.name,
-            "configured": instance.enabled && (is_local || instance.api_key.is_some()),
-            "needs_api_key": needs_api_key,
-            "is_local": is_local,
-            "is_default": is_global_default,
-            "is_type_default": instance.is_type_default,
-            "has_api_key": instance.api_key.is_some(),
-            "base_url": instance.base_url,
-            "available": is_available || (!is_local && instance.api_key.is_some()),
-        }));
+        let is_available = (is_local && local_available.contains(&instance.provider_type))
+            || instance.provider_type == mock::PROVIDER_TYPE;
+        // OpenAI-compatible servers are usable once they have a base URL
+        let is_openai_compat = instance.provider_type == openai_compat::PROVIDER_TYPE && instance.base_url.is_some();
+
+        provider_info.push(ProviderInfo {
+            id: instance.id.clone(),
+            provider_type: instance.provider_type.clone(),
+            name: instance.name.clone(),
+            configured: instance.enabled && (is_local || is_openai_compat || instance.api_key.is_some()),
+            needs_api_key,
+            is_local,
+            is_default: is_global_default,
+            is_type_default: instance.is_type_default,
+            has_api_key: instance.api_key.is_some(),
+            base_url: instance.base_url.clone(),
+            available: is_available || is_openai_compat || (!is_local && instance.api_key.is_some()),
+        });
     }
 
     // Add unconfigured provider types (for backwards compat with old UI)
@@ -315,32 +739,33 @@ pub async fn list_providers() -> Result<serde_json::Value, RpcError> {
     for ptype in &supported_types {
         let has_instance = cfg.get_instances_by_type(ptype).len() > 0;
         if !has_instance {
-            let needs_api_key = matches!(ptype.as_str(), "openai" | "anthropic" | "mistral" | "groq");
-            let is_local = matches!(ptype.as_str(), "ollama" | "llamafile" | "lmstudio");
+            let needs_api_key = matches!(ptype.as_str(), "openai" | "anthropic" | "mistral" | "groq" | "azure_openai");
+            let is_local = matches!(ptype.as_str(), "ollama" | "llamafile" | "lmstudio" | "mock");
             let is_available = local_available.contains(ptype.as_str());
             
             // Auto-detected local providers show as configured
             let auto_configured = is_local && is_available;
 
-            provider_info.push(serde_json::json!({
-                "id": ptype,
-                "type": ptype,
-                "name": if auto_configured { format!("{} (auto-detected)", get_type_display_name(ptype)) } else { get_type_display_name(ptype) },
-                "configured": auto_configured,
-                "needs_api_key": needs_api_key,
-                "is_local": is_local,
-                "is_default": false,
-                "is_type_default": false,
-                "available": is_available,
-                "has_api_key": false,
-            }));
+            provider_info.push(ProviderInfo {
+                id: ptype.clone(),
+                provider_type: ptype.clone(),
+                name: if auto_configured { format!("{} (auto-detected)", get_type_display_name(ptype)) } else { get_type_display_name(ptype) },
+                configured: auto_configured,
+                needs_api_key,
+                is_local,
+                is_default: false,
+                is_type_default: false,
+                has_api_key: false,
+                base_url: None,
+                available: is_available,
+            });
         }
     }
 
-    Ok(serde_json::json!({ 
-        "providers": provider_info,
-        "default_provider": cfg.default_provider,
-    }))
+    to_result(ListProvidersResponse {
+        providers: provider_info,
+        default_provider: cfg.default_provider,
+    })
 }
 
 /// Get display name for a provider type
@@ -353,6 +778,8 @@ fn get_type_display_name(provider_type: &str) -> String {
         "mistral" => "Mistral".to_string(),
         "groq" => "Groq".to_string(),
         "lmstudio" => "LM Studio".to_string(),
+        "openai_compatible" => "OpenAI-Compatible".to_string(),
+        "azure_openai" => "Azure OpenAI".to_string(),
         other => {
             let mut chars = other.chars();
             match chars.next() {
@@ -365,35 +792,366 @@ fn get_type_display_name(provider_type: &str) -> String {
 
 /// Check a specific provider's status.
 /// Accepts either an instance ID or a provider type.
+/// The result also refreshes the health monitor's cache.
 pub async fn check_provider_status(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let provider_param = params
-        .get("provider")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'provider' parameter".to_string(),
-        })?;
+    let params: CheckProviderParams = parse_params(params)?;
+    let provider_param = params.provider.as_str();
+
+    let started = std::time::Instant::now();
+    let status = probe_provider(provider_param).await?;
+
+    // Cache under the instance the parameter resolved to
+    let (provider, provider_type) = match get_config().and_then(|c| c.resolve_provider(provider_param).cloned()) {
+        Some(instance) => (instance.id, instance.provider_type),
+        None => (provider_param.to_string(), provider_param.to_string()),
+    };
+    health::update(&provider, &provider_type, &status, started.elapsed());
 
+    Ok(status)
+}
+
+async fn probe_provider(provider_param: &str) -> Result<serde_json::Value, RpcError> {
     // Resolve to provider type for the check
     let provider_type = resolve_provider_type(provider_param)
         .unwrap_or_else(|| provider_param.to_string());
+
+    if provider_type == mock::PROVIDER_TYPE {
+        let instance_fixture = get_config()
+            .and_then(|c| c.resolve_provider(provider_param).and_then(|i| i.fixture_path.clone()));
+        let fixture_path = mock::resolve_fixture_path(instance_fixture);
+        return Ok(match mock::list_models(fixture_path.as_deref()) {
+            Ok(models) => serde_json::json!({
+                "provider": mock::PROVIDER_TYPE,
+                "available": true,
+                "models": models,
+            }),
+            Err(e) => serde_json::json!({
+                "provider": mock::PROVIDER_TYPE,
+                "available": false,
+                "error": e,
+            }),
+        });
+    }
     
+    // Instances served by the bridge's own OpenAI client are checked through it,
+    // so their headers and timeout apply
+    let instance = get_config().and_then(|c| c.resolve_provider(provider_param).cloned());
+    if BUILTIN_PROVIDER_TYPES.contains(&provider_type.as_str()) && instance.is_none() {
+        return Err(RpcError {
+            code: -32602,
+            message: format!("Provider instance '{}' not found", provider_param),
+            data: None,
+        });
+    }
+    if let Some(instance) = instance {
+        if instance.provider_type == azure_openai::PROVIDER_TYPE {
+            return Ok(match azure_openai::check(&instance).await {
+                Ok(()) => serde_json::json!({
+                    "provider": instance.id,
+                    "available": true,
+                    "models": azure_openai::list_models(&instance),
+                }),
+                Err(e) => serde_json::json!({
+                    "provider": instance.id,
+                    "available": false,
+                    "error": e,
+                }),
+            });
+        }
+
+        let result = match routing::instance_endpoint(&instance) {
+            Ok(Some(endpoint)) => Some(openai_compat::list_models(&endpoint).await),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        };
+        if let Some(result) = result {
+            return Ok(match result {
+                Ok(models) => serde_json::json!({
+                    "provider": instance.id,
+                    "available": true,
+                    "models": models.into_iter().map(|m| m.id).collect::<Vec<_>>(),
+                }),
+                Err(e) => serde_json::json!({
+                    "provider": instance.id,
+                    "available": false,
+                    "error": e,
+                }),
+            });
+        }
+    }
+
     let config = get_provider_config(provider_param);
     let status = check_provider(&provider_type, config).await;
 
     Ok(serde_json::to_value(status).unwrap())
 }
 
+// =============================================================================
+// Provider and Model Configuration Params
+// =============================================================================
+
+fn parse_params<T: serde::de::DeserializeOwned>(params: serde_json::Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::invalid_params(format!("Invalid params: {}", e)))
+}
+
+/// For a field that `null` clears: absent is `None` (with
+/// `#[serde(default)]`) and `null` is `Some(None)`.
+fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Connection and deployment settings accepted by `llm.configure_provider`
+/// and `llm.add_provider`. A JSON `null` (or empty string) clears a setting.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct InstanceSettingsParams {
+    /// Extra headers sent with every request (OpenAI-wire providers only)
+    #[serde(default, deserialize_with = "nullable")]
+    #[schemars(with = "Option<HashMap<String, String>>")]
+    pub headers: Option<Option<HashMap<String, String>>>,
+    /// Sent as `OpenAI-Organization`
+    #[serde(default, deserialize_with = "nullable")]
+    #[schemars(with = "Option<String>")]
+    pub organization: Option<Option<String>>,
+    /// Sent as `OpenAI-Project`
+    #[serde(default, deserialize_with = "nullable")]
+    #[schemars(with = "Option<String>")]
+    pub project: Option<Option<String>>,
+    /// Bound on each request, in seconds
+    #[serde(default, deserialize_with = "nullable")]
+    #[schemars(with = "Option<u64>")]
+    pub timeout_secs: Option<Option<u64>>,
+    /// Requests sent to the instance at once
+    #[serde(default, deserialize_with = "nullable")]
+    #[schemars(with = "Option<u32>")]
+    pub max_concurrency: Option<Option<u64>>,
+    /// How long a request may wait for a free slot, in seconds
+    #[serde(default, deserialize_with = "nullable")]
+    #[schemars(with = "Option<u64>")]
+    pub queue_timeout_secs: Option<Option<u64>>,
+    /// Azure OpenAI deployment names by model
+    #[serde(default, deserialize_with = "nullable")]
+    #[schemars(with = "Option<HashMap<String, String>>")]
+    pub deployments: Option<Option<HashMap<String, String>>>,
+    /// Azure OpenAI API version
+    #[serde(default, deserialize_with = "nullable")]
+    #[schemars(with = "Option<String>")]
+    pub api_version: Option<Option<String>>,
+}
+
+/// Params of `llm.configure_provider`. With `id` the instance is updated;
+/// with only `provider` (a type) its single instance is updated, or a new one
+/// is created.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ConfigureProviderParams {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// `null` clears it
+    #[serde(default, deserialize_with = "nullable")]
+    #[schemars(with = "Option<String>")]
+    pub base_url: Option<Option<String>>,
+    /// Fixture file of a mock instance; `null` clears it
+    #[serde(default, deserialize_with = "nullable")]
+    #[schemars(with = "Option<String>")]
+    pub fixture_path: Option<Option<String>>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub settings: InstanceSettingsParams,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Params of `llm.add_provider`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AddProviderParams {
+    #[serde(rename = "type")]
+    pub provider_type: String,
+    pub name: String,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Required for `openai_compatible` and `azure_openai`
+    #[serde(default)]
+    pub base_url: Option<String>,
+    #[serde(default)]
+    pub fixture_path: Option<String>,
+    #[serde(flatten)]
+    pub settings: InstanceSettingsParams,
+}
+
+/// Params of `llm.check_provider`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CheckProviderParams {
+    /// Instance ID or provider type
+    pub provider: String,
+}
+
+/// Params of `llm.remove_provider`, `llm.set_default_provider` and
+/// `llm.set_type_default`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct InstanceIdParams {
+    pub id: String,
+}
+
+/// Params of `llm.set_default_model`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct DefaultModelParams {
+    pub model: String,
+}
+
+/// Params of `llm.set_model_capabilities`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ModelCapabilitiesParams {
+    pub model_id: String,
+    /// Overrides to store; `null` removes them all
+    #[serde(default)]
+    pub capabilities: Option<CapabilityOverrides>,
+}
+
+/// Params of `llm.add_configured_model`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AddConfiguredModelParams {
+    pub model_id: String,
+    /// Alias for the model (derived from `model_id` by default)
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub context_window: Option<usize>,
+}
+
+/// Params of `llm.remove_configured_model` and
+/// `llm.set_configured_model_default`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ConfiguredModelParams {
+    pub name: String,
+}
+
+/// Params of `llm.set_configured_model_context_window`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ContextWindowParams {
+    pub name: String,
+    /// Positive token count; `null` (or omitted) falls back to the built-in table
+    #[serde(default)]
+    pub context_window: Option<usize>,
+}
+
+/// Validated form of [`InstanceSettingsParams`]. Each field is `None` when the
+/// param is absent and `Some(None)` when it clears the setting.
+#[derive(Debug, Default)]
+struct InstanceSettingsUpdate {
+    headers: Option<HashMap<String, String>>,
+    organization: Option<Option<String>>,
+    project: Option<Option<String>>,
+    timeout_secs: Option<Option<u64>>,
+    max_concurrency: Option<Option<u32>>,
+    queue_timeout_secs: Option<Option<u64>>,
+    deployments: Option<HashMap<String, String>>,
+    api_version: Option<Option<String>>,
+}
+
+impl InstanceSettingsUpdate {
+    fn new(params: InstanceSettingsParams) -> Result<Self, RpcError> {
+        // Empty strings clear a setting, like null
+        let optional_string = |value: Option<Option<String>>| {
+            value.map(|s| s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty()))
+        };
+
+        if let Some(headers) = params.headers.as_ref().and_then(Option::as_ref) {
+            for (name, value) in headers {
+                reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| RpcError::invalid_params(format!("Invalid header name: {}", name)))?;
+                reqwest::header::HeaderValue::from_str(value)
+                    .map_err(|_| RpcError::invalid_params(format!("Invalid value for header '{}'", name)))?;
+            }
+        }
+
+        let positive_int = |key: &str, value: Option<Option<u64>>| -> Result<Option<Option<u64>>, RpcError> {
+            match value {
+                Some(Some(0)) => Err(RpcError::invalid_params(format!("'{}' must be a positive integer", key))),
+                other => Ok(other),
+            }
+        };
+
+        let max_concurrency = positive_int("max_concurrency", params.max_concurrency)?
+            .map(|n| n.map(|n| u32::try_from(n).unwrap_or(u32::MAX)));
+
+        Ok(Self {
+            headers: params.headers.map(Option::unwrap_or_default),
+            organization: optional_string(params.organization),
+            project: optional_string(params.project),
+            timeout_secs: positive_int("timeout_secs", params.timeout_secs)?,
+            max_concurrency,
+            queue_timeout_secs: positive_int("queue_timeout_secs", params.queue_timeout_secs)?,
+            deployments: params.deployments.map(Option::unwrap_or_default),
+            api_version: optional_string(params.api_version),
+        })
+    }
+
+    /// Apply the settings to an instance. Extra headers, organization and
+    /// project are refused for provider types that can't send them.
+    fn apply(&self, instance: &mut ProviderInstance) -> Result<(), RpcError> {
+        let sets_headers = self.headers.as_ref().is_some_and(|h| !h.is_empty())
+            || matches!(self.organization, Some(Some(_)))
+            || matches!(self.project, Some(Some(_)));
+        if sets_headers && !routing::supports_extra_headers(&instance.provider_type) {
+            return Err(RpcError::invalid_params(format!(
+                "'headers', 'organization' and 'project' are not supported for {} providers",
+                instance.provider_type
+            )));
+        }
+
+        if let Some(headers) = &self.headers {
+            instance.headers = headers.clone();
+        }
+        if let Some(organization) = &self.organization {
+            instance.organization = organization.clone();
+        }
+        if let Some(project) = &self.project {
+            instance.project = project.clone();
+        }
+        if let Some(timeout_secs) = self.timeout_secs {
+            instance.timeout_secs = timeout_secs;
+        }
+        if let Some(max_concurrency) = self.max_concurrency {
+            instance.max_concurrency = max_concurrency;
+        }
+        if let Some(queue_timeout_secs) = self.queue_timeout_secs {
+            instance.queue_timeout_secs = queue_timeout_secs;
+        }
+        if let Some(deployments) = &self.deployments {
+            instance.deployments = deployments.clone();
+        }
+        if let Some(api_version) = &self.api_version {
+            instance.api_version = api_version.clone();
+        }
+        Ok(())
+    }
+}
+
 /// Update or create a provider instance.
 /// If 'id' is provided, updates existing instance.
 /// If only 'provider' (type) is provided, creates a new instance or updates existing if only one.
 pub async fn configure_provider(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let instance_id = params.get("id").and_then(|v| v.as_str());
-    let provider_type = params.get("provider").and_then(|v| v.as_str());
-    let name = params.get("name").and_then(|v| v.as_str());
-    let api_key = params.get("api_key").and_then(|v| v.as_str()).map(String::from);
-    let base_url = params.get("base_url").and_then(|v| v.as_str()).map(String::from);
-    let enabled = params.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true);
+    let params: ConfigureProviderParams = parse_params(params)?;
+    let instance_id = params.id.as_deref();
+    let provider_type = params.provider.as_deref();
+    let name = params.name.as_deref();
+    let api_key = params.api_key;
+    let base_url = params.base_url;
+    let fixture_path = params.fixture_path;
+    let enabled = params.enabled;
+    let instance_settings = InstanceSettingsUpdate::new(params.settings)?;
 
     let mut cfg = get_config().unwrap_or_default();
 
@@ -408,15 +1166,20 @@ pub async fn configure_provider(params: serde_json::Value) -> Result<serde_json:
             if api_key.is_some() {
                 instance.api_key = api_key;
             }
-            if base_url.is_some() || params.get("base_url").is_some() {
+            if let Some(base_url) = base_url {
                 instance.base_url = base_url;
             }
+            if let Some(fixture_path) = fixture_path {
+                instance.fixture_path = fixture_path;
+            }
+            instance_settings.apply(instance)?;
             instance.enabled = enabled;
             result_id = id.to_string();
         } else {
             return Err(RpcError {
                 code: -32602,
                 message: format!("Provider instance '{}' not found", id),
+                data: None,
             });
         }
     } else if let Some(ptype) = provider_type {
@@ -433,9 +1196,13 @@ pub async fn configure_provider(params: serde_json::Value) -> Result<serde_json:
                 if api_key.is_some() {
                     instance.api_key = api_key;
                 }
-                if base_url.is_some() || params.get("base_url").is_some() {
+                if let Some(base_url) = base_url {
                     instance.base_url = base_url;
                 }
+                if let Some(fixture_path) = fixture_path {
+                    instance.fixture_path = fixture_path;
+                }
+                instance_settings.apply(instance)?;
                 instance.enabled = enabled;
             }
             result_id = existing_id;
@@ -447,7 +1214,9 @@ pub async fn configure_provider(params: serde_json::Value) -> Result<serde_json:
             
             let mut instance = ProviderInstance::new(ptype, &display_name);
             instance.api_key = api_key;
-            instance.base_url = base_url;
+            instance.base_url = base_url.flatten();
+            instance.fixture_path = fixture_path.flatten();
+            instance_settings.apply(&mut instance)?;
             instance.enabled = enabled;
             
             result_id = cfg.add_instance(instance);
@@ -456,6 +1225,7 @@ pub async fn configure_provider(params: serde_json::Value) -> Result<serde_json:
         return Err(RpcError {
             code: -32602,
             message: "Missing 'provider' or 'id' parameter".to_string(),
+            data: None,
         });
     }
 
@@ -465,39 +1235,36 @@ pub async fn configure_provider(params: serde_json::Value) -> Result<serde_json:
         tracing::warn!("Failed to save config: {}", e);
     }
 
-    Ok(serde_json::json!({
-        "ok": true,
-        "id": result_id,
-    }))
+    to_result(ProviderIdResponse { ok: true, id: result_id })
 }
 
 /// Add a new provider instance.
 pub async fn add_provider(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let provider_type = params
-        .get("type")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'type' parameter".to_string(),
-        })?;
-
-    let name = params
-        .get("name")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'name' parameter".to_string(),
-        })?;
-
-    let api_key = params.get("api_key").and_then(|v| v.as_str()).map(String::from);
-    let base_url = params.get("base_url").and_then(|v| v.as_str()).map(String::from);
+    let params: AddProviderParams = parse_params(params)?;
+    let provider_type = params.provider_type.as_str();
+    let name = params.name.as_str();
+    let api_key = params.api_key;
+    let base_url = params.base_url;
+    let fixture_path = params.fixture_path;
+    let instance_settings = InstanceSettingsUpdate::new(params.settings)?;
 
     // Validate provider type
-    let supported = get_supported_providers();
+    let supported = supported_provider_types();
     if !supported.contains(&provider_type.to_string()) {
         return Err(RpcError {
             code: -32602,
             message: format!("Unsupported provider type: {}", provider_type),
+            data: None,
+        });
+    }
+
+    if (provider_type == openai_compat::PROVIDER_TYPE || provider_type == azure_openai::PROVIDER_TYPE)
+        && base_url.is_none()
+    {
+        return Err(RpcError {
+            code: -32602,
+            message: format!("'base_url' is required for {} providers", provider_type),
+            data: None,
         });
     }
 
@@ -506,6 +1273,8 @@ pub async fn add_provider(params: serde_json::Value) -> Result<serde_json::Value
     let mut instance = ProviderInstance::new(provider_type, name);
     instance.api_key = api_key;
     instance.base_url = base_url;
+    instance.fixture_path = fixture_path;
+    instance_settings.apply(&mut instance)?;
     
     let id = cfg.add_instance(instance);
     set_config(cfg.clone());
@@ -514,21 +1283,13 @@ pub async fn add_provider(params: serde_json::Value) -> Result<serde_json::Value
         tracing::warn!("Failed to save config: {}", e);
     }
 
-    Ok(serde_json::json!({
-        "ok": true,
-        "id": id,
-    }))
+    to_result(ProviderIdResponse { ok: true, id })
 }
 
 /// Remove a provider instance.
 pub async fn remove_provider(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let instance_id = params
-        .get("id")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'id' parameter".to_string(),
-        })?;
+    let params: InstanceIdParams = parse_params(params)?;
+    let instance_id = params.id.as_str();
 
     let mut cfg = get_config().unwrap_or_default();
     
@@ -537,6 +1298,7 @@ pub async fn remove_provider(params: serde_json::Value) -> Result<serde_json::Va
         return Err(RpcError {
             code: -32602,
             message: format!("Provider instance '{}' not found", instance_id),
+            data: None,
         });
     }
 
@@ -546,20 +1308,13 @@ pub async fn remove_provider(params: serde_json::Value) -> Result<serde_json::Va
         tracing::warn!("Failed to save config: {}", e);
     }
 
-    Ok(serde_json::json!({
-        "ok": true,
-    }))
+    to_result(OkResponse { ok: true })
 }
 
 /// Set the global default provider.
 pub async fn set_default_provider(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let instance_id = params
-        .get("id")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'id' parameter".to_string(),
-        })?;
+    let params: InstanceIdParams = parse_params(params)?;
+    let instance_id = params.id.as_str();
 
     let mut cfg = get_config().unwrap_or_default();
     
@@ -567,6 +1322,7 @@ pub async fn set_default_provider(params: serde_json::Value) -> Result<serde_jso
         return Err(RpcError {
             code: -32602,
             message: format!("Provider instance '{}' not found", instance_id),
+            data: None,
         });
     }
 
@@ -576,21 +1332,16 @@ pub async fn set_default_provider(params: serde_json::Value) -> Result<serde_jso
         tracing::warn!("Failed to save config: {}", e);
     }
 
-    Ok(serde_json::json!({
-        "ok": true,
-        "default_provider": instance_id,
-    }))
+    to_result(DefaultProviderResponse {
+        ok: true,
+        default_provider: instance_id.to_string(),
+    })
 }
 
 /// Set the type default for a provider.
 pub async fn set_type_default(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let instance_id = params
-        .get("id")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'id' parameter".to_string(),
-        })?;
+    let params: InstanceIdParams = parse_params(params)?;
+    let instance_id = params.id.as_str();
 
     let mut cfg = get_config().unwrap_or_default();
     
@@ -598,6 +1349,7 @@ pub async fn set_type_default(params: serde_json::Value) -> Result<serde_json::V
         return Err(RpcError {
             code: -32602,
             message: format!("Provider instance '{}' not found", instance_id),
+            data: None,
         });
     }
 
@@ -607,51 +1359,55 @@ pub async fn set_type_default(params: serde_json::Value) -> Result<serde_json::V
         tracing::warn!("Failed to save config: {}", e);
     }
 
-    Ok(serde_json::json!({
-        "ok": true,
-    }))
+    to_result(OkResponse { ok: true })
 }
 
 /// Get current configuration (safe version without API keys).
 pub async fn get_configuration() -> Result<serde_json::Value, RpcError> {
     let cfg = get_config().unwrap_or_default();
 
-    let providers: serde_json::Map<String, serde_json::Value> = cfg
+    let providers: HashMap<String, ProviderSettings> = cfg
         .providers
         .iter()
         .map(|(k, v)| {
+            let mut header_names: Vec<String> = v.headers.keys().cloned().collect();
+            header_names.sort();
             (
                 k.clone(),
-                serde_json::json!({
-                    "id": v.id,
-                    "type": v.provider_type,
-                    "name": v.name,
-                    "enabled": v.enabled,
-                    "has_api_key": v.api_key.is_some(),
-                    "base_url": v.base_url,
-                    "is_type_default": v.is_type_default,
-                }),
+                ProviderSettings {
+                    id: v.id.clone(),
+                    provider_type: v.provider_type.clone(),
+                    name: v.name.clone(),
+                    enabled: v.enabled,
+                    has_api_key: v.api_key.is_some(),
+                    base_url: v.base_url.clone(),
+                    fixture_path: v.fixture_path.clone(),
+                    header_names,
+                    organization: v.organization.clone(),
+                    project: v.project.clone(),
+                    timeout_secs: v.timeout_secs,
+                    max_concurrency: v.max_concurrency,
+                    queue_timeout_secs: v.queue_timeout_secs,
+                    deployments: v.deployments.clone(),
+                    api_version: v.api_version.clone(),
+                    is_type_default: v.is_type_default,
+                },
             )
         })
         .collect();
 
-    Ok(serde_json::json!({
-        "version": cfg.version,
-        "default_model": cfg.default_model,
-        "default_provider": cfg.default_provider,
-        "providers": providers
-    }))
+    to_result(ConfigResponse {
+        version: cfg.version,
+        default_model: cfg.default_model,
+        default_provider: cfg.default_provider,
+        providers,
+    })
 }
 
 /// Set default model (legacy - sets the default_model field).
 pub async fn set_default_model(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let model = params
-        .get("model")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'model' parameter".to_string(),
-        })?;
+    let params: DefaultModelParams = parse_params(params)?;
+    let model = params.model.as_str();
 
     let mut cfg = get_config().unwrap_or_default();
     cfg.default_model = Some(model.to_string());
@@ -661,10 +1417,10 @@ pub async fn set_default_model(params: serde_json::Value) -> Result<serde_json::
         tracing::warn!("Failed to save config: {}", e);
     }
 
-    Ok(serde_json::json!({
-        "ok": true,
-        "default_model": model
-    }))
+    to_result(DefaultModelResponse {
+        ok: true,
+        default_model: model.to_string(),
+    })
 }
 
 // =============================================================================
@@ -675,81 +1431,108 @@ pub async fn set_default_model(params: serde_json::Value) -> Result<serde_json::
 pub async fn list_configured_models() -> Result<serde_json::Value, RpcError> {
     let cfg = get_config().unwrap_or_default();
     
-    let models: Vec<serde_json::Value> = cfg.models.iter().map(|m| {
-        serde_json::json!({
-            "name": m.name,
-            "model_id": m.model_id,
-            "is_default": m.is_default,
-        })
-    }).collect();
-    
-    Ok(serde_json::json!({
-        "models": models
-    }))
+    to_result(ListConfiguredModelsResponse { models: cfg.models })
 }
 
 /// Provider prefixes that are considered local (no API key, run on this machine).
-const LOCAL_PROVIDER_PREFIXES: [&str; 2] = ["ollama:", "llamafile:"];
+const LOCAL_PROVIDER_PREFIXES: [&str; 3] = ["ollama:", "llamafile:", "mock:"];
 
 fn is_local_model(model_id: &str) -> bool {
-    LOCAL_PROVIDER_PREFIXES
+    if LOCAL_PROVIDER_PREFIXES
         .iter()
         .any(|prefix| model_id.starts_with(prefix))
+    {
+        return true;
+    }
+    // Instances served over HTTP are local when they listen on loopback
+    let route = match routing::resolve(model_id) {
+        Ok(route) => route,
+        Err(_) => return false,
+    };
+    if route.instance.as_ref().is_some_and(|i| LOCAL_PROVIDER_TYPES.contains(&i.provider_type.as_str())) {
+        return true;
+    }
+    matches!(route.openai_endpoint(), Ok(Some(endpoint)) if endpoint.is_loopback())
 }
 
 /// Return metadata for each configured model (e.g. local vs remote).
 /// Companion to list_configured_models; use model_id to correlate.
 pub async fn get_configured_models_metadata() -> Result<serde_json::Value, RpcError> {
     let cfg = get_config().unwrap_or_default();
-    let metadata: Vec<serde_json::Value> = cfg
+    let ids: Vec<String> = cfg.models.iter().map(|m| m.model_id.clone()).collect();
+    let caps = capabilities::lookup_all(&ids, Some(&cfg)).await;
+    let metadata: Vec<ModelMetadata> = cfg
         .models
         .iter()
-        .map(|m| {
-            serde_json::json!({
-                "model_id": m.model_id,
-                "is_local": is_local_model(&m.model_id),
-            })
+        .zip(caps)
+        .map(|(m, caps)| ModelMetadata {
+            model_id: m.model_id.clone(),
+            is_local: is_local_model(&m.model_id),
+            capabilities: caps,
         })
         .collect();
-    Ok(serde_json::json!({ "metadata": metadata }))
+    to_result(ModelsMetadataResponse { metadata })
+}
+
+/// Set or clear user capability overrides for a model.
+/// Pass `capabilities: null` to remove all overrides.
+pub async fn set_model_capabilities(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+    let params: ModelCapabilitiesParams = parse_params(params)?;
+    let model_id = params.model_id.as_str();
+
+    let overrides = params.capabilities;
+
+    let mut cfg = get_config().unwrap_or_default();
+    match overrides {
+        Some(o) if o != CapabilityOverrides::default() => {
+            cfg.model_capabilities.insert(model_id.to_string(), o);
+        }
+        _ => {
+            cfg.model_capabilities.remove(model_id);
+        }
+    }
+    set_config(cfg.clone());
+
+    if let Err(e) = cfg.save() {
+        tracing::warn!("Failed to save config: {}", e);
+    }
+
+    to_result(ModelCapabilitiesResponse {
+        ok: true,
+        model_id: model_id.to_string(),
+        capabilities: capabilities::lookup(model_id, Some(&cfg)),
+    })
 }
 
 /// Add a configured model.
 pub async fn add_configured_model(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let model_id = params
-        .get("model_id")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'model_id' parameter".to_string(),
-        })?;
-    
-    let name = params.get("name").and_then(|v| v.as_str());
+    let params: AddConfiguredModelParams = parse_params(params)?;
+    let model_id = params.model_id.as_str();
+    let name = params.name.as_deref();
+    let context_window = params.context_window;
     
     let mut cfg = get_config().unwrap_or_default();
     let final_name = cfg.add_model(model_id, name);
+    if context_window.is_some() {
+        cfg.set_model_context_window(&final_name, context_window);
+    }
     set_config(cfg.clone());
     
     if let Err(e) = cfg.save() {
         tracing::warn!("Failed to save config: {}", e);
     }
     
-    Ok(serde_json::json!({
-        "ok": true,
-        "name": final_name,
-        "model_id": model_id,
-    }))
+    to_result(ConfiguredModelResponse {
+        ok: true,
+        name: final_name,
+        model_id: model_id.to_string(),
+    })
 }
 
 /// Remove a configured model.
 pub async fn remove_configured_model(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let name = params
-        .get("name")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'name' parameter".to_string(),
-        })?;
+    let params: ConfiguredModelParams = parse_params(params)?;
+    let name = params.name.as_str();
     
     let mut cfg = get_config().unwrap_or_default();
     let removed = cfg.remove_model(name);
@@ -758,6 +1541,7 @@ pub async fn remove_configured_model(params: serde_json::Value) -> Result<serde_
         return Err(RpcError {
             code: -32602,
             message: format!("Model '{}' not found", name),
+            data: None,
         });
     }
     
@@ -767,20 +1551,46 @@ pub async fn remove_configured_model(params: serde_json::Value) -> Result<serde_
         tracing::warn!("Failed to save config: {}", e);
     }
     
-    Ok(serde_json::json!({
-        "ok": true,
-    }))
+    to_result(OkResponse { ok: true })
+}
+
+/// Set or clear the context window of a configured model.
+pub async fn set_configured_model_context_window(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+    let params: ContextWindowParams = parse_params(params)?;
+    let name = params.name.as_str();
+
+    // null (or omitted) clears the override and falls back to the built-in table
+    let context_window = params.context_window;
+    if context_window == Some(0) {
+        return Err(RpcError::invalid_params("'context_window' must be a positive integer or null"));
+    }
+
+    let mut cfg = get_config().unwrap_or_default();
+    if !cfg.set_model_context_window(name, context_window) {
+        return Err(RpcError {
+            code: -32602,
+            message: format!("Model '{}' not found", name),
+            data: None,
+        });
+    }
+
+    set_config(cfg.clone());
+
+    if let Err(e) = cfg.save() {
+        tracing::warn!("Failed to save config: {}", e);
+    }
+
+    to_result(ContextWindowResponse {
+        ok: true,
+        name: name.to_string(),
+        context_window,
+    })
 }
 
 /// Set a configured model as default.
 pub async fn set_configured_model_default(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let name = params
-        .get("name")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'name' parameter".to_string(),
-        })?;
+    let params: ConfiguredModelParams = parse_params(params)?;
+    let name = params.name.as_str();
     
     let mut cfg = get_config().unwrap_or_default();
     
@@ -793,6 +1603,7 @@ pub async fn set_configured_model_default(params: serde_json::Value) -> Result<s
         return Err(RpcError {
             code: -32602,
             message: format!("Model '{}' not found", name),
+            data: None,
         });
     }
     
@@ -807,10 +1618,10 @@ pub async fn set_configured_model_default(params: serde_json::Value) -> Result<s
         tracing::warn!("Failed to save config: {}", e);
     }
     
-    Ok(serde_json::json!({
-        "ok": true,
-        "default": name,
-    }))
+    to_result(DefaultConfiguredModelResponse {
+        ok: true,
+        default: name.to_string(),
+    })
 }
 
 // =============================================================================
@@ -818,7 +1629,7 @@ pub async fn set_configured_model_default(params: serde_json::Value) -> Result<s
 // =============================================================================
 
 /// Stream event for chat completion
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct StreamEvent {
     pub id: serde_json::Value,
     #[serde(rename = "type")]
@@ -831,6 +1642,48 @@ pub struct StreamEvent {
     pub model: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<serde_json::Value>,
+    /// Tool-call deltas in the OpenAI `delta.tool_calls` shape (`tool_call` events)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<serde_json::Value>,
+    /// Context-window report, attached to the final event
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<serde_json::Value>,
+    /// Position in the provider's request queue (`queued` events)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_position: Option<usize>,
+}
+
+/// Build a `queued` stream event reporting the request's queue position.
+fn queued_event(request_id: serde_json::Value, model: &str, position: usize) -> StreamEvent {
+    StreamEvent {
+        id: request_id,
+        event_type: "queued".to_string(),
+        token: None,
+        finish_reason: None,
+        model: Some(model.to_string()),
+        error: None,
+        tool_calls: None,
+        context: None,
+        queue_position: Some(position),
+    }
+}
+
+/// Build an error stream event.
+fn stream_error(request_id: serde_json::Value, model: Option<String>, code: i64, message: String) -> StreamEvent {
+    StreamEvent {
+        id: request_id,
+        event_type: "error".to_string(),
+        token: None,
+        finish_reason: None,
+        model,
+        error: Some(serde_json::json!({
+            "code": code,
+            "message": message
+        })),
+        tool_calls: None,
+        context: None,
+        queue_position: None,
+    }
 }
 
 /// Streaming chat completion.
@@ -841,129 +1694,343 @@ pub async fn chat_stream(
     event_tx: mpsc::Sender<StreamEvent>,
 ) {
     // Parse request
-    let request: Result<ChatRequest, _> = serde_json::from_value(params);
-    
-    match request {
-        Ok(request) => {
-            // Use provided model or fall back to default
-            let model = match request.model.or_else(|| {
-                get_config().and_then(|c| c.default_model.clone())
-            }) {
-                Some(m) => m,
-                None => {
-                    let _ = event_tx.send(StreamEvent {
-                        id: request_id,
-                        event_type: "error".to_string(),
-                        token: None,
-                        finish_reason: None,
-                        model: None,
-                        error: Some(serde_json::json!({
-                            "code": -32602,
-                            "message": "No model specified and no default model configured"
-                        })),
-                    }).await;
+    let mut request: ChatRequest = match serde_json::from_value(params) {
+        Ok(r) => r,
+        Err(e) => {
+            let _ = event_tx.send(stream_error(request_id, None, -32602, format!("Invalid params: {}", e))).await;
+            return;
+        }
+    };
+
+    let session = match &request.session_id {
+        Some(id) => match sessions::load(id).await {
+            Ok(s) => Some(s),
+            Err(e) => {
+                let _ = event_tx.send(stream_error(request_id, None, e.code, e.message)).await;
+                return;
+            }
+        },
+        None => None,
+    };
+
+    // Use provided model, then the session's model, then the default
+    let model = match request.model.clone()
+        .or_else(|| session.as_ref().and_then(|s| s.model.clone()))
+        .or_else(|| get_config().and_then(|c| c.default_model.clone()))
+    {
+        Some(m) => m,
+        None => {
+            let _ = event_tx.send(stream_error(
+                request_id,
+                None,
+                -32602,
+                "No model specified and no default model configured".to_string(),
+            )).await;
+            return;
+        }
+    };
+
+    let turn = request.messages.clone();
+    if let Some(session) = &session {
+        apply_session_history(&mut request, session);
+    }
+
+    let report = context::fit(&model, &mut request, get_config().as_ref()).await;
+
+    // Forward events, attaching the context report to the final event and
+    // collecting the reply so a session turn can be stored afterwards.
+    let (inner_tx, mut inner_rx) = mpsc::channel::<StreamEvent>(100);
+    let producer = stream_completion(request_id, model.clone(), request, inner_tx);
+    let forwarder = async {
+        let mut reply = String::new();
+        let mut tool_calls: Vec<serde_json::Value> = Vec::new();
+        let mut completed = false;
+        while let Some(mut event) = inner_rx.recv().await {
+            if let Some(token) = &event.token {
+                reply.push_str(token);
+            }
+            if let Some(deltas) = &event.tool_calls {
+                merge_tool_call_deltas(&mut tool_calls, deltas);
+            }
+            if event.event_type == "done" || event.event_type == "error" {
+                event.context = serde_json::to_value(&report).ok();
+            }
+            completed |= event.event_type == "done";
+            if event_tx.send(event).await.is_err() {
+                break; // Receiver dropped
+            }
+        }
+        (reply, tool_calls, completed)
+    };
+
+    let (_, (reply, tool_calls, completed)) = tokio::join!(producer, forwarder);
+    if let (Some(session), true) = (&session, completed) {
+        let mut assistant = serde_json::json!({ "role": "assistant", "content": reply });
+        if !tool_calls.is_empty() {
+            assistant["tool_calls"] = serde_json::Value::Array(tool_calls);
+        }
+        record_session_turn(session, turn, assistant, &model).await;
+    }
+}
+
+/// Fold streamed `delta.tool_calls` into whole tool calls. Deltas for the
+/// same `index` extend one call: its name and arguments arrive in pieces.
+fn merge_tool_call_deltas(calls: &mut Vec<serde_json::Value>, deltas: &serde_json::Value) {
+    for delta in deltas.as_array().into_iter().flatten() {
+        let index = delta.get("index").and_then(|i| i.as_u64()).unwrap_or(0) as usize;
+        while calls.len() <= index {
+            calls.push(serde_json::json!({
+                "id": "",
+                "type": "function",
+                "function": { "name": "", "arguments": "" },
+            }));
+        }
+        let call = &mut calls[index];
+        if let Some(id) = delta.get("id").and_then(|v| v.as_str()) {
+            call["id"] = serde_json::json!(id);
+        }
+        for field in ["name", "arguments"] {
+            if let Some(piece) = delta.pointer(&format!("/function/{}", field)).and_then(|v| v.as_str()) {
+                let joined = format!("{}{}", call["function"][field].as_str().unwrap_or(""), piece);
+                call["function"][field] = serde_json::json!(joined);
+            }
+        }
+    }
+}
+
+/// Stream a chat completion for an already-resolved model.
+async fn stream_completion(
+    request_id: serde_json::Value,
+    model: String,
+    request: ChatRequest,
+    event_tx: mpsc::Sender<StreamEvent>,
+) {
+    if is_mock_model(&model) {
+        let fixture_path = mock_fixture_for_model(&model);
+        mock::chat_stream(request_id, model, &request, fixture_path, event_tx).await;
+        return;
+    }
+
+    let route = match routing::resolve(&model) {
+        Ok(route) => route,
+        Err(e) => {
+            let _ = event_tx.send(stream_error(request_id, Some(model), e.code, e.message)).await;
+            return;
+        }
+    };
+
+    // Wait for a slot on the instance, reporting queue position meanwhile.
+    // The permit is held until the stream ends.
+    let _permit = match &route.instance {
+        Some(instance) => {
+            let priority = request.priority.unwrap_or_default();
+            let on_position = |position| {
+                let _ = event_tx.try_send(queued_event(request_id.clone(), &model, position));
+            };
+            match queue::acquire(instance, priority, on_position).await {
+                Ok(permit) => permit,
+                Err(e) => {
+                    let _ = event_tx.send(stream_error(request_id, Some(model), e.code, e.message)).await;
                     return;
                 }
-            };
+            }
+        }
+        None => None,
+    };
 
-            // Build completion request
-            let messages: Vec<Message> = request
-                .messages
-                .into_iter()
-                .map(|m| match m.role.as_str() {
-                    "system" => Message::system(m.content),
-                    "user" => Message::user(m.content),
-                    "assistant" => Message::assistant(m.content),
-                    _ => Message::user(m.content),
-                })
-                .collect();
-
-            let provider_config = get_provider_config_for_model(&model);
-
-            let completion_request = CompletionRequest {
-                model: model.clone(),
-                messages,
-                temperature: request.temperature,
-                max_tokens: request.max_tokens,
-                api_key: provider_config.and_then(|c| c.api_key.flatten()),
-                stream: Some(true),
-                ..Default::default()
-            };
+    match route.openai_endpoint() {
+        Ok(Some(endpoint)) => {
+            openai_compat::chat_stream(&endpoint, request_id, model, &route.model_name, &request, event_tx).await;
+            return;
+        }
+        Ok(None) => {}
+        Err(e) => {
+            let _ = event_tx.send(stream_error(request_id, Some(model), e.code, e.message)).await;
+            return;
+        }
+    }
+
+    // Build messages, prepending system prompt if provided
+    let mut messages: Vec<Message> = Vec::new();
+
+    if let Some(system_prompt) = &request.system_prompt {
+        messages.push(Message::system(system_prompt.clone()));
+    }
 
-            // Try to create stream
-            match completion_stream(completion_request).await {
-                Ok(mut stream) => {
-                    while let Some(chunk_result) = stream.next().await {
-                        let event = match chunk_result {
-                            Ok(chunk) => {
-                                let content = chunk.choices.get(0)
-                                    .and_then(|c| c.delta.content.as_ref())
-                                    .cloned();
-                                
-                                let finish_reason = chunk.choices.get(0)
-                                    .and_then(|c| c.finish_reason.as_ref())
-                                    .map(|r| format!("{:?}", r));
-                                
-                                StreamEvent {
-                                    id: request_id.clone(),
-                                    event_type: if finish_reason.is_some() { "done".to_string() } else { "token".to_string() },
-                                    token: content,
-                                    finish_reason,
-                                    model: Some(model.clone()),
-                                    error: None,
-                                }
-                            }
-                            Err(e) => {
-                                StreamEvent {
-                                    id: request_id.clone(),
-                                    event_type: "error".to_string(),
-                                    token: None,
-                                    finish_reason: None,
-                                    model: Some(model.clone()),
-                                    error: Some(serde_json::json!({
-                                        "code": -32001,
-                                        "message": format!("Stream error: {}", e)
-                                    })),
-                                }
-                            }
-                        };
+    messages.extend(request.messages.into_iter().map(|m| match m.role.as_str() {
+        "system" => Message::system(m.content),
+        "user" => Message::user(m.content),
+        "assistant" => Message::assistant(m.content),
+        _ => Message::user(m.content),
+    }));
+
+    let completion_request = CompletionRequest {
+        model: route.any_llm_model(),
+        messages,
+        temperature: request.temperature,
+        max_tokens: request.max_tokens,
+        api_key: route.api_key(),
+        stream: Some(true),
+        ..Default::default()
+    };
+
+    // Try to create stream; the instance timeout covers only the start
+    let started = match route.timeout() {
+        Some(timeout) => match tokio::time::timeout(timeout, completion_stream(completion_request)).await {
+            Ok(result) => result,
+            Err(_) => {
+                let _ = event_tx.send(stream_error(
+                    request_id,
+                    Some(model),
+                    -32001,
+                    format!("Failed to start stream: request timed out after {}s", timeout.as_secs()),
+                )).await;
+                return;
+            }
+        },
+        None => completion_stream(completion_request).await,
+    };
+    match started {
+        Ok(mut stream) => {
+            while let Some(chunk_result) = stream.next().await {
+                let event = match chunk_result {
+                    Ok(chunk) => {
+                        let content = chunk.choices.get(0)
+                            .and_then(|c| c.delta.content.as_ref())
+                            .cloned();
                         
-                        let is_done = event.event_type == "done" || event.event_type == "error";
-                        if event_tx.send(event).await.is_err() {
-                            break; // Receiver dropped
-                        }
-                        if is_done {
-                            break;
+                        let finish_reason = chunk.choices.get(0)
+                            .and_then(|c| c.finish_reason.as_ref())
+                            .map(|r| format!("{:?}", r));
+                        
+                        StreamEvent {
+                            id: request_id.clone(),
+                            event_type: if finish_reason.is_some() { "done".to_string() } else { "token".to_string() },
+                            token: content,
+                            finish_reason,
+                            model: Some(model.clone()),
+                            error: None,
+                            tool_calls: None,
+                            context: None,
+                            queue_position: None,
                         }
                     }
+                    Err(e) => stream_error(
+                        request_id.clone(),
+                        Some(model.clone()),
+                        -32001,
+                        format!("Stream error: {}", e),
+                    ),
+                };
+                
+                let is_done = event.event_type == "done" || event.event_type == "error";
+                if event_tx.send(event).await.is_err() {
+                    break; // Receiver dropped
                 }
-                Err(e) => {
-                    let _ = event_tx.send(StreamEvent {
-                        id: request_id,
-                        event_type: "error".to_string(),
-                        token: None,
-                        finish_reason: None,
-                        model: Some(model),
-                        error: Some(serde_json::json!({
-                            "code": -32001,
-                            "message": format!("Failed to start stream: {}", e)
-                        })),
-                    }).await;
+                if is_done {
+                    break;
                 }
             }
         }
         Err(e) => {
-            let _ = event_tx.send(StreamEvent {
-                id: request_id,
-                event_type: "error".to_string(),
-                token: None,
-                finish_reason: None,
-                model: None,
-                error: Some(serde_json::json!({
-                    "code": -32602,
-                    "message": format!("Invalid params: {}", e)
-                })),
-            }).await;
+            let _ = event_tx.send(stream_error(
+                request_id,
+                Some(model),
+                -32001,
+                format!("Failed to start stream: {}", e),
+            )).await;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(params: serde_json::Value) -> Result<InstanceSettingsUpdate, RpcError> {
+        InstanceSettingsUpdate::new(parse_params(params)?)
+    }
+
+    #[test]
+    fn test_instance_settings_from_params() {
+        let update = settings(serde_json::json!({
+            "headers": { "X-Team": "search" },
+            "organization": "  org-1 ",
+            "project": "",
+            "timeout_secs": null,
+            "max_concurrency": 2,
+        }))
+        .unwrap();
+        assert_eq!(update.headers.unwrap().get("X-Team").map(String::as_str), Some("search"));
+        assert_eq!(update.organization, Some(Some("org-1".to_string())));
+        assert_eq!(update.project, Some(None));
+        assert_eq!(update.timeout_secs, Some(None));
+        assert_eq!(update.max_concurrency, Some(Some(2)));
+        assert_eq!(update.queue_timeout_secs, None);
+
+        for params in [
+            serde_json::json!({ "headers": { "bad header": "x" } }),
+            serde_json::json!({ "headers": { "X-Team": 1 } }),
+            serde_json::json!({ "organization": 5 }),
+            serde_json::json!({ "max_concurrency": 0 }),
+        ] {
+            let err = settings(params.clone()).unwrap_err();
+            assert_eq!(err.code, -32602, "{}", params);
+        }
+    }
+
+    #[test]
+    fn test_instance_settings_apply() {
+        let update = settings(serde_json::json!({
+            "headers": { "X-Team": "search" },
+            "organization": "org-1",
+            "timeout_secs": 30,
+        }))
+        .unwrap();
+
+        let mut openai = ProviderInstance::new("openai", "OpenAI");
+        update.apply(&mut openai).unwrap();
+        assert_eq!(openai.headers.get("X-Team").map(String::as_str), Some("search"));
+        assert_eq!(openai.organization.as_deref(), Some("org-1"));
+        assert_eq!(openai.timeout_secs, Some(30));
+
+        // Anthropic goes through any-llm, which can't send them
+        let mut anthropic = ProviderInstance::new("anthropic", "Anthropic");
+        assert_eq!(update.apply(&mut anthropic).unwrap_err().code, -32602);
+        assert!(anthropic.headers.is_empty());
+
+        // Clearing them is always allowed
+        let clear = settings(serde_json::json!({
+            "headers": null,
+            "organization": null,
+            "timeout_secs": 10,
+        }))
+        .unwrap();
+        clear.apply(&mut anthropic).unwrap();
+        assert_eq!(anthropic.timeout_secs, Some(10));
+        clear.apply(&mut openai).unwrap();
+        assert!(openai.headers.is_empty());
+        assert_eq!(openai.organization, None);
+    }
+
+    #[test]
+    fn test_merge_tool_call_deltas() {
+        let mut calls = Vec::new();
+        merge_tool_call_deltas(&mut calls, &serde_json::json!([
+            { "index": 0, "id": "call_1", "type": "function", "function": { "name": "get_weather", "arguments": "" } }
+        ]));
+        merge_tool_call_deltas(&mut calls, &serde_json::json!([{ "index": 0, "function": { "arguments": "{\"city\":" } }]));
+        merge_tool_call_deltas(&mut calls, &serde_json::json!([{ "index": 0, "function": { "arguments": "\"Paris\"}" } }]));
+        merge_tool_call_deltas(&mut calls, &serde_json::json!([
+            { "index": 1, "id": "call_2", "function": { "name": "get_time", "arguments": "{}" } }
+        ]));
+
+        assert_eq!(
+            serde_json::Value::Array(calls),
+            serde_json::json!([
+                { "id": "call_1", "type": "function", "function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" } },
+                { "id": "call_2", "type": "function", "function": { "name": "get_time", "arguments": "{}" } },
+            ])
+        );
+    }
+}
@@ -0,0 +1,404 @@
+//! Direct access to the Ollama HTTP API.
+//!
+//! Chat and model listing go through any-llm; this module covers the
+//! Ollama-specific endpoints any-llm does not expose, including the
+//! `llm.ollama.*` model management RPCs.
+
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use super::config::LlmConfig;
+use super::get_config;
+use crate::rpc::{to_result, RpcError};
+
+/// Provider type for Ollama instances.
+pub const PROVIDER_TYPE: &str = "ollama";
+
+const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+
+/// Timeout for quick metadata requests (not downloads).
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Resolve the Ollama base URL: the configured `ollama` instance, then the
+/// `OLLAMA_HOST` environment variable, then the default local address.
+pub fn base_url(config: Option<&LlmConfig>) -> String {
+    let url = config
+        .and_then(|c| c.resolve_provider(PROVIDER_TYPE))
+        .and_then(|i| i.base_url.clone())
+        .or_else(|| std::env::var("OLLAMA_HOST").ok().filter(|h| !h.is_empty()))
+        .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+
+    normalize_base_url(&url)
+}
+
+/// Accept the forms users commonly configure ("host:port", ".../v1", trailing
+/// slash) and return a bare `scheme://host:port` style URL.
+pub fn normalize_base_url(url: &str) -> String {
+    let url = url.trim().trim_end_matches('/');
+    let url = url.strip_suffix("/v1").unwrap_or(url);
+    if url.contains("://") {
+        url.to_string()
+    } else {
+        format!("http://{}", url)
+    }
+}
+
+/// Strip the provider prefix from a model ID ("ollama:llama3.2" -> "llama3.2").
+pub fn model_name(model_id: &str) -> &str {
+    model_id.split_once(':').map(|(_, name)| name).unwrap_or(model_id)
+}
+
+/// Resolve the base URL for a specific Ollama instance, or the default one.
+pub fn instance_base_url(config: Option<&LlmConfig>, provider: Option<&str>) -> Result<String, String> {
+    let provider = match provider {
+        Some(p) => p,
+        None => return Ok(base_url(config)),
+    };
+
+    let instance = config
+        .and_then(|c| c.resolve_provider(provider))
+        .ok_or_else(|| format!("Provider '{}' not found", provider))?;
+    if instance.provider_type != PROVIDER_TYPE {
+        return Err(format!("Provider '{}' is not an Ollama instance", provider));
+    }
+    if !instance.enabled {
+        return Err(format!("Provider '{}' is disabled", provider));
+    }
+
+    Ok(instance
+        .base_url
+        .as_deref()
+        .map(normalize_base_url)
+        .unwrap_or_else(|| base_url(None)))
+}
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+}
+
+/// Fetch model details (`POST /api/show`).
+pub async fn show(base_url: &str, model: &str) -> Result<serde_json::Value, String> {
+    let response = client()
+        .post(format!("{}/api/show", base_url))
+        .json(&serde_json::json!({ "model": model }))
+        .send()
+        .await
+        .map_err(|e| format!("Ollama request failed: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("Ollama show failed: {} - {}", status, body));
+    }
+
+    response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse Ollama response: {}", e))
+}
+
+/// Delete a local model (`DELETE /api/delete`).
+pub async fn delete(base_url: &str, model: &str) -> Result<(), String> {
+    let response = client()
+        .delete(format!("{}/api/delete", base_url))
+        .json(&serde_json::json!({ "model": model }))
+        .send()
+        .await
+        .map_err(|e| format!("Ollama request failed: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("Ollama delete failed: {} - {}", status, body));
+    }
+    Ok(())
+}
+
+/// List models currently loaded in memory (`GET /api/ps`).
+pub async fn ps(base_url: &str) -> Result<serde_json::Value, String> {
+    let response = client()
+        .get(format!("{}/api/ps", base_url))
+        .send()
+        .await
+        .map_err(|e| format!("Ollama request failed: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("Ollama ps failed: {} - {}", status, body));
+    }
+
+    response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse Ollama response: {}", e))
+}
+
+// =============================================================================
+// RPC Handlers
+// =============================================================================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ModelParams {
+    pub model: String,
+    /// Ollama instance ID (defaults to the configured `ollama` instance)
+    #[serde(default)]
+    pub provider: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct ProviderParams {
+    #[serde(default)]
+    pub provider: Option<String>,
+}
+
+impl ModelParams {
+    /// Accept both bare names ("llama3.2") and Harbor model IDs
+    /// ("ollama:llama3.2", "<instance-id>:llama3.2").
+    fn model_name(&self) -> &str {
+        let prefixes = [Some(PROVIDER_TYPE), self.provider.as_deref()];
+        prefixes
+            .iter()
+            .flatten()
+            .find_map(|p| self.model.strip_prefix(p).and_then(|rest| rest.strip_prefix(':')))
+            .unwrap_or(&self.model)
+    }
+}
+
+fn parse_params<T: serde::de::DeserializeOwned>(params: serde_json::Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError {
+        code: -32602,
+        message: format!("Invalid params: {}", e),
+        data: None,
+    })
+}
+
+fn resolve_base_url(provider: Option<&str>) -> Result<String, RpcError> {
+    instance_base_url(get_config().as_ref(), provider).map_err(RpcError::invalid_params)
+}
+
+fn ollama_error(message: String) -> RpcError {
+    RpcError {
+        code: -32001,
+        message,
+        data: None,
+    }
+}
+
+/// Show details for a model (modelfile, parameters, capabilities).
+pub async fn rpc_show(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+    let params: ModelParams = parse_params(params)?;
+    let base_url = resolve_base_url(params.provider.as_deref())?;
+    show(&base_url, params.model_name()).await.map_err(ollama_error)
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DeleteResponse {
+    pub ok: bool,
+    pub model: String,
+}
+
+/// Delete a local model.
+pub async fn rpc_delete(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+    let params: ModelParams = parse_params(params)?;
+    let base_url = resolve_base_url(params.provider.as_deref())?;
+    delete(&base_url, params.model_name()).await.map_err(ollama_error)?;
+    to_result(DeleteResponse {
+        ok: true,
+        model: params.model_name().to_string(),
+    })
+}
+
+/// List models currently loaded in memory.
+pub async fn rpc_ps(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+    let params: ProviderParams = if params.is_null() {
+        ProviderParams::default()
+    } else {
+        parse_params(params)?
+    };
+    let base_url = resolve_base_url(params.provider.as_deref())?;
+    ps(&base_url).await.map_err(ollama_error)
+}
+
+// =============================================================================
+// Streaming Pull
+// =============================================================================
+
+/// Progress event for `llm.ollama.pull`.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PullEvent {
+    pub id: serde_json::Value,
+    /// "progress", "done" or "error"
+    #[serde(rename = "type")]
+    pub event_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<serde_json::Value>,
+}
+
+/// One line of Ollama's streamed `/api/pull` response.
+#[derive(Debug, Deserialize)]
+struct PullProgress {
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    digest: Option<String>,
+    #[serde(default)]
+    total: Option<u64>,
+    #[serde(default)]
+    completed: Option<u64>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+impl PullEvent {
+    fn error(id: serde_json::Value, code: i64, message: String) -> Self {
+        Self {
+            id,
+            event_type: "error".to_string(),
+            status: None,
+            digest: None,
+            total: None,
+            completed: None,
+            error: Some(serde_json::json!({
+                "code": code,
+                "message": message,
+            })),
+        }
+    }
+
+    fn from_progress(id: serde_json::Value, progress: PullProgress) -> Self {
+        if let Some(message) = progress.error {
+            return Self::error(id, -32001, message);
+        }
+        let done = progress.status.as_deref() == Some("success");
+        Self {
+            id,
+            event_type: if done { "done" } else { "progress" }.to_string(),
+            status: progress.status,
+            digest: progress.digest,
+            total: progress.total,
+            completed: progress.completed,
+            error: None,
+        }
+    }
+}
+
+/// Pull a model, sending a progress event per status line from Ollama.
+/// Ends with a "done" event on success or an "error" event.
+pub async fn pull_stream(
+    request_id: serde_json::Value,
+    params: serde_json::Value,
+    event_tx: mpsc::Sender<PullEvent>,
+) {
+    let params: ModelParams = match serde_json::from_value(params) {
+        Ok(p) => p,
+        Err(e) => {
+            let _ = event_tx.send(PullEvent::error(request_id, -32602, format!("Invalid params: {}", e))).await;
+            return;
+        }
+    };
+    let base_url = match instance_base_url(get_config().as_ref(), params.provider.as_deref()) {
+        Ok(url) => url,
+        Err(e) => {
+            let _ = event_tx.send(PullEvent::error(request_id, -32602, e)).await;
+            return;
+        }
+    };
+
+    // No overall timeout: large models take minutes to download
+    let client = reqwest::Client::builder()
+        .connect_timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    let response = client
+        .post(format!("{}/api/pull", base_url))
+        .json(&serde_json::json!({ "model": params.model_name(), "stream": true }))
+        .send()
+        .await;
+
+    let mut response = match response {
+        Ok(r) if r.status().is_success() => r,
+        Ok(r) => {
+            let status = r.status();
+            let body = r.text().await.unwrap_or_default();
+            let message = format!("Ollama pull failed: {} - {}", status, body);
+            let _ = event_tx.send(PullEvent::error(request_id, -32001, message)).await;
+            return;
+        }
+        Err(e) => {
+            let message = format!("Ollama request failed: {}", e);
+            let _ = event_tx.send(PullEvent::error(request_id, -32001, message)).await;
+            return;
+        }
+    };
+
+    // The body is newline-delimited JSON; chunks may split lines
+    let mut buffer: Vec<u8> = Vec::new();
+    loop {
+        let chunk = match response.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                let message = format!("Pull stream error: {}", e);
+                let _ = event_tx.send(PullEvent::error(request_id, -32001, message)).await;
+                return;
+            }
+        };
+        buffer.extend_from_slice(&chunk);
+
+        while let Some(newline) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=newline).collect();
+            let progress: PullProgress = match serde_json::from_slice(&line) {
+                Ok(p) => p,
+                Err(_) => continue, // Blank or partial line
+            };
+            let event = PullEvent::from_progress(request_id.clone(), progress);
+            let is_done = event.event_type != "progress";
+            if event_tx.send(event).await.is_err() || is_done {
+                return;
+            }
+        }
+    }
+
+    // Stream ended without a "success" status
+    let _ = event_tx
+        .send(PullEvent::error(request_id, -32001, "Pull ended before completing".to_string()))
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_base_url() {
+        assert_eq!(normalize_base_url("localhost:11434"), "http://localhost:11434");
+        assert_eq!(normalize_base_url("http://gpu-box:11434/v1/"), "http://gpu-box:11434");
+        assert_eq!(normalize_base_url("https://ollama.example.com"), "https://ollama.example.com");
+    }
+
+    #[test]
+    fn test_model_params_strip_prefix() {
+        let params = |model: &str, provider: Option<&str>| ModelParams {
+            model: model.to_string(),
+            provider: provider.map(|p| p.to_string()),
+        };
+        assert_eq!(params("llama3.2:latest", None).model_name(), "llama3.2:latest");
+        assert_eq!(params("ollama:llama3.2:latest", None).model_name(), "llama3.2:latest");
+        assert_eq!(params("ollama-gpu:qwen2.5", Some("ollama-gpu")).model_name(), "qwen2.5");
+    }
+}
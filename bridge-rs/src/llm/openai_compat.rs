@@ -0,0 +1,525 @@
+//! Client for OpenAI-compatible chat servers.
+//!
+//! Used for `openai_compatible` provider instances (LM Studio, vLLM,
+//! llama.cpp server, ...), and for instances of OpenAI-style providers with
+//! settings any-llm cannot pass. Each instance has its own base URL
+//! (including the `/v1` suffix), optional API key and extra headers, so
+//! several servers can be configured side by side.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use super::config::ProviderInstance;
+use super::{ChatRequest, StreamEvent};
+use crate::rpc::RpcError;
+
+/// Provider type for generic OpenAI-compatible instances.
+pub const PROVIDER_TYPE: &str = "openai_compatible";
+
+/// Connection details for one OpenAI-compatible server.
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    pub base_url: String,
+    pub api_key: Option<String>,
+    /// Headers sent with every request (extra headers plus organization/project)
+    pub headers: Vec<(String, String)>,
+    /// Query parameters added to every request URL
+    pub query: Vec<(String, String)>,
+    /// Header carrying the API key; bearer auth when `None`
+    pub api_key_header: Option<&'static str>,
+    pub timeout: Option<Duration>,
+    /// The instance's client, shared by all its endpoints for pooling
+    client: reqwest::Client,
+}
+
+/// Clients by instance id, with the timeout each was built for.
+type ClientCache = Mutex<HashMap<String, (Option<Duration>, reqwest::Client)>>;
+
+/// Client for an instance, built once and kept for its timeout. The
+/// timeout bounds connecting here; `Endpoint::send` applies it to whole
+/// exchanges, which streams can outlast.
+fn client_for(instance: &ProviderInstance) -> reqwest::Client {
+    static CLIENTS: OnceLock<ClientCache> = OnceLock::new();
+    let timeout = instance.timeout();
+    let mut clients = CLIENTS.get_or_init(Default::default).lock().unwrap();
+    if let Some((cached_timeout, client)) = clients.get(&instance.id) {
+        if *cached_timeout == timeout {
+            return client.clone();
+        }
+    }
+    let mut builder = reqwest::Client::builder();
+    if let Some(timeout) = timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    let client = builder.build().unwrap_or_default();
+    clients.insert(instance.id.clone(), (timeout, client.clone()));
+    client
+}
+
+impl Endpoint {
+    /// Build an endpoint for an instance served at `base_url`.
+    pub fn new(instance: &ProviderInstance, base_url: &str) -> Self {
+        let mut headers: Vec<(String, String)> = instance
+            .headers
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        if let Some(organization) = &instance.organization {
+            headers.push(("OpenAI-Organization".to_string(), organization.clone()));
+        }
+        if let Some(project) = &instance.project {
+            headers.push(("OpenAI-Project".to_string(), project.clone()));
+        }
+
+        Self {
+            base_url: base_url.trim().trim_end_matches('/').to_string(),
+            api_key: instance.api_key.clone().filter(|k| !k.is_empty()),
+            headers,
+            query: Vec::new(),
+            api_key_header: None,
+            timeout: instance.timeout(),
+            client: client_for(instance),
+        }
+    }
+
+    /// Build an endpoint from an instance's own base URL, which is required.
+    pub fn from_instance(instance: &ProviderInstance) -> Result<Self, String> {
+        let base_url = instance
+            .base_url
+            .as_deref()
+            .filter(|u| !u.trim().is_empty())
+            .ok_or_else(|| format!("Provider '{}' has no base_url", instance.id))?;
+        Ok(Self::new(instance, base_url))
+    }
+
+    pub(super) fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let mut builder = self.client.request(method, format!("{}{}", self.base_url, path));
+        if !self.query.is_empty() {
+            builder = builder.query(&self.query);
+        }
+        if let Some(key) = &self.api_key {
+            builder = match self.api_key_header {
+                Some(header) => builder.header(header, key.as_str()),
+                None => builder.bearer_auth(key),
+            };
+        }
+        for (name, value) in &self.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        builder
+    }
+
+    /// Send a request, applying the timeout to the whole exchange.
+    pub(super) async fn send(&self, builder: reqwest::RequestBuilder) -> Result<reqwest::Response, String> {
+        let builder = match self.timeout {
+            Some(timeout) => builder.timeout(timeout),
+            None => builder,
+        };
+        builder.send().await.map_err(|e| format!("Request failed: {}", e))
+    }
+
+    /// Send a streaming request. The timeout covers only the wait for the
+    /// response headers, so long generations are not cut off.
+    async fn send_streaming(&self, builder: reqwest::RequestBuilder) -> Result<reqwest::Response, String> {
+        let send = builder.send();
+        let result = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, send)
+                .await
+                .map_err(|_| format!("Request timed out after {}s", timeout.as_secs()))?,
+            None => send.await,
+        };
+        result.map_err(|e| format!("Request failed: {}", e))
+    }
+
+    /// Whether the server runs on this machine.
+    pub fn is_loopback(&self) -> bool {
+        url::Url::parse(&self.base_url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+            .map(|h| h == "localhost" || h == "127.0.0.1" || h == "[::1]")
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelList {
+    #[serde(default)]
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModelEntry {
+    pub id: String,
+    #[serde(default)]
+    pub owned_by: Option<String>,
+}
+
+pub(super) async fn error_from_response(response: reqwest::Response, action: &str) -> String {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    format!("{} failed: {} - {}", action, status, body)
+}
+
+/// List models served by the endpoint (`GET /models`).
+pub async fn list_models(endpoint: &Endpoint) -> Result<Vec<ModelEntry>, String> {
+    let response = endpoint
+        .send(endpoint.request(reqwest::Method::GET, "/models"))
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(error_from_response(response, "List models").await);
+    }
+
+    let list: ModelList = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse model list: {}", e))?;
+    Ok(list.data)
+}
+
+/// Build an OpenAI chat completions request body.
+pub fn chat_body(model_name: &str, request: &ChatRequest, stream: bool) -> serde_json::Value {
+    let mut messages: Vec<serde_json::Value> = Vec::new();
+    if let Some(system_prompt) = &request.system_prompt {
+        messages.push(serde_json::json!({ "role": "system", "content": system_prompt }));
+    }
+    for m in &request.messages {
+        let mut message = serde_json::json!({ "role": m.role, "content": m.content });
+        if let Some(id) = &m.tool_call_id {
+            message["tool_call_id"] = serde_json::json!(id);
+        }
+        messages.push(message);
+    }
+
+    let mut body = serde_json::json!({
+        "model": model_name,
+        "messages": messages,
+        "stream": stream,
+    });
+    if let Some(temperature) = request.temperature {
+        body["temperature"] = serde_json::json!(temperature);
+    }
+    if let Some(max_tokens) = request.max_tokens {
+        body["max_tokens"] = serde_json::json!(max_tokens);
+    }
+    if let Some(tools) = request.tools.as_ref().filter(|t| !t.is_empty()) {
+        body["tools"] = tools
+            .iter()
+            .map(|t| {
+                serde_json::json!({
+                    "type": "function",
+                    "function": {
+                        "name": t.name,
+                        "description": t.description,
+                        "parameters": t.input_schema,
+                    }
+                })
+            })
+            .collect();
+    }
+    body
+}
+
+/// Non-streaming chat completion. The server's OpenAI-format response is
+/// returned as-is.
+pub async fn chat(
+    endpoint: &Endpoint,
+    model_name: &str,
+    request: &ChatRequest,
+) -> Result<serde_json::Value, RpcError> {
+    let llm_error = |message: String| RpcError {
+        code: -32001,
+        message: format!("LLM error: {}", message),
+        data: None,
+    };
+
+    let builder = endpoint
+        .request(reqwest::Method::POST, "/chat/completions")
+        .json(&chat_body(model_name, request, false));
+    let response = endpoint.send(builder).await.map_err(llm_error)?;
+
+    if !response.status().is_success() {
+        return Err(llm_error(error_from_response(response, "Chat completion").await));
+    }
+
+    response
+        .json()
+        .await
+        .map_err(|e| llm_error(format!("Failed to parse response: {}", e)))
+}
+
+fn stream_event(
+    request_id: &serde_json::Value,
+    model_id: &str,
+    event_type: &str,
+    token: Option<String>,
+    finish_reason: Option<String>,
+    error: Option<serde_json::Value>,
+) -> StreamEvent {
+    StreamEvent {
+        id: request_id.clone(),
+        event_type: event_type.to_string(),
+        token,
+        finish_reason,
+        model: Some(model_id.to_string()),
+        error,
+        tool_calls: None,
+        context: None,
+        queue_position: None,
+    }
+}
+
+fn stream_error(request_id: &serde_json::Value, model_id: &str, message: String) -> StreamEvent {
+    let error = serde_json::json!({ "code": -32001, "message": message });
+    stream_event(request_id, model_id, "error", None, None, Some(error))
+}
+
+/// Tool calls streamed as OpenAI deltas, merged by `index` until the stream
+/// finishes: a call's first delta carries its id and name, and the
+/// arguments arrive in pieces.
+#[derive(Debug, Default)]
+struct ToolCallDeltas {
+    calls: BTreeMap<u64, serde_json::Value>,
+}
+
+impl ToolCallDeltas {
+    fn merge(&mut self, deltas: &[serde_json::Value]) {
+        for delta in deltas {
+            let index = delta.get("index").and_then(|i| i.as_u64()).unwrap_or(0);
+            let call = self.calls.entry(index).or_insert_with(|| {
+                serde_json::json!({
+                    "id": null,
+                    "type": "function",
+                    "function": { "name": "", "arguments": "" },
+                    "index": index,
+                })
+            });
+            for field in ["id", "type"] {
+                if let Some(value) = delta.get(field).and_then(|v| v.as_str()) {
+                    call[field] = serde_json::json!(value);
+                }
+            }
+            for field in ["name", "arguments"] {
+                if let Some(piece) = delta.get("function").and_then(|f| f.get(field)).and_then(|v| v.as_str()) {
+                    let merged = format!("{}{}", call["function"][field].as_str().unwrap_or_default(), piece);
+                    call["function"][field] = serde_json::json!(merged);
+                }
+            }
+        }
+    }
+
+    /// One `tool_call` event per call, each holding the whole call as a
+    /// single delta, as the mock provider sends them.
+    fn take_events(&mut self, request_id: &serde_json::Value, model_id: &str) -> Vec<StreamEvent> {
+        std::mem::take(&mut self.calls)
+            .into_values()
+            .map(|call| StreamEvent {
+                tool_calls: Some(serde_json::json!([call])),
+                ..stream_event(request_id, model_id, "tool_call", None, None, None)
+            })
+            .collect()
+    }
+}
+
+/// Turn one server-sent `data:` payload into a stream event, collecting any
+/// tool call deltas it carries. Returns `None` for chunks that carry
+/// neither text nor a finish reason.
+fn parse_sse_data(
+    request_id: &serde_json::Value,
+    model_id: &str,
+    data: &str,
+    tool_calls: &mut ToolCallDeltas,
+) -> Option<StreamEvent> {
+    if data == "[DONE]" {
+        return Some(stream_event(request_id, model_id, "done", None, Some("stop".to_string()), None));
+    }
+
+    let chunk: serde_json::Value = serde_json::from_str(data).ok()?;
+    if let Some(error) = chunk.get("error") {
+        let message = error
+            .get("message")
+            .and_then(|m| m.as_str())
+            .map(|m| m.to_string())
+            .unwrap_or_else(|| error.to_string());
+        return Some(stream_error(request_id, model_id, message));
+    }
+
+    let choice = chunk.get("choices")?.get(0)?;
+    if let Some(deltas) = choice.pointer("/delta/tool_calls").and_then(|d| d.as_array()) {
+        tool_calls.merge(deltas);
+    }
+    let token = choice
+        .pointer("/delta/content")
+        .and_then(|c| c.as_str())
+        .filter(|c| !c.is_empty())
+        .map(|c| c.to_string());
+    let finish_reason = choice
+        .get("finish_reason")
+        .and_then(|r| r.as_str())
+        .map(|r| r.to_string());
+
+    match (token, finish_reason) {
+        (token, Some(reason)) => Some(stream_event(request_id, model_id, "done", token, Some(reason), None)),
+        (Some(token), None) => Some(stream_event(request_id, model_id, "token", Some(token), None, None)),
+        (None, None) => None,
+    }
+}
+
+/// Streaming chat completion over server-sent events.
+pub async fn chat_stream(
+    endpoint: &Endpoint,
+    request_id: serde_json::Value,
+    model_id: String,
+    model_name: &str,
+    request: &ChatRequest,
+    event_tx: mpsc::Sender<StreamEvent>,
+) {
+    let builder = endpoint
+        .request(reqwest::Method::POST, "/chat/completions")
+        .json(&chat_body(model_name, request, true));
+    let response = endpoint.send_streaming(builder).await;
+
+    let mut response = match response {
+        Ok(r) if r.status().is_success() => r,
+        Ok(r) => {
+            let message = error_from_response(r, "Chat completion").await;
+            let _ = event_tx.send(stream_error(&request_id, &model_id, message)).await;
+            return;
+        }
+        Err(e) => {
+            let message = format!("Failed to start stream: {}", e);
+            let _ = event_tx.send(stream_error(&request_id, &model_id, message)).await;
+            return;
+        }
+    };
+
+    // SSE lines may be split across chunks
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut tool_calls = ToolCallDeltas::default();
+    loop {
+        let chunk = match response.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                let message = format!("Stream error: {}", e);
+                let _ = event_tx.send(stream_error(&request_id, &model_id, message)).await;
+                return;
+            }
+        };
+        buffer.extend_from_slice(&chunk);
+
+        while let Some(newline) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=newline).collect();
+            let line = String::from_utf8_lossy(&line);
+            let data = match line.trim().strip_prefix("data:") {
+                Some(data) => data.trim().to_string(),
+                None => continue, // Comments, event names, blank separators
+            };
+
+            if let Some(event) = parse_sse_data(&request_id, &model_id, &data, &mut tool_calls) {
+                // Collected tool calls go out before the done event
+                if event.event_type == "done" {
+                    for call in tool_calls.take_events(&request_id, &model_id) {
+                        if event_tx.send(call).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                let is_done = event.event_type == "done" || event.event_type == "error";
+                if event_tx.send(event).await.is_err() || is_done {
+                    return;
+                }
+            }
+        }
+    }
+
+    // Server closed the stream without [DONE]
+    for call in tool_calls.take_events(&request_id, &model_id) {
+        if event_tx.send(call).await.is_err() {
+            return;
+        }
+    }
+    let done = stream_event(&request_id, &model_id, "done", None, Some("stop".to_string()), None);
+    let _ = event_tx.send(done).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sse_data() {
+        let id = serde_json::json!(1);
+        let parse_sse_data = |id, model_id, data| parse_sse_data(id, model_id, data, &mut ToolCallDeltas::default());
+        let token = parse_sse_data(&id, "lmstudio:qwen", r#"{"choices":[{"delta":{"content":"Hi"}}]}"#).unwrap();
+        assert_eq!(token.event_type, "token");
+        assert_eq!(token.token.as_deref(), Some("Hi"));
+
+        let done = parse_sse_data(&id, "lmstudio:qwen", r#"{"choices":[{"delta":{},"finish_reason":"stop"}]}"#).unwrap();
+        assert_eq!(done.event_type, "done");
+        assert_eq!(done.finish_reason.as_deref(), Some("stop"));
+
+        assert!(parse_sse_data(&id, "lmstudio:qwen", r#"{"choices":[{"delta":{"role":"assistant"}}]}"#).is_none());
+        assert_eq!(parse_sse_data(&id, "lmstudio:qwen", "[DONE]").unwrap().event_type, "done");
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_tool_calls() {
+        // Two tool calls, the first with its arguments split across chunks
+        let chunks = [
+            r#"{"choices":[{"delta":{"role":"assistant","tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"get_weather","arguments":""}}]}}]}"#,
+            r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"city\":"}}]}}]}"#,
+            r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"Paris\"}"}}]}}]}"#,
+            r#"{"choices":[{"delta":{"tool_calls":[{"index":1,"id":"call_2","type":"function","function":{"name":"get_time","arguments":"{}"}}]}}]}"#,
+            r#"{"choices":[{"delta":{},"finish_reason":"tool_calls"}]}"#,
+            "[DONE]",
+        ];
+        let body: String = chunks.iter().map(|chunk| format!("data: {}\n\n", chunk)).collect();
+        let app = axum::Router::new().route(
+            "/v1/chat/completions",
+            axum::routing::post(move || async move { body }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut instance = ProviderInstance::with_id("local", PROVIDER_TYPE, "Local");
+        instance.base_url = Some(format!("http://{}/v1", addr));
+        let endpoint = Endpoint::from_instance(&instance).unwrap();
+        let request = ChatRequest {
+            model: Some("local:qwen".to_string()),
+            messages: Vec::new(),
+            temperature: None,
+            max_tokens: None,
+            system_prompt: None,
+            tools: None,
+            session_id: None,
+            context_strategy: None,
+            priority: None,
+        };
+        let (event_tx, mut event_rx) = mpsc::channel(16);
+        chat_stream(&endpoint, serde_json::json!(1), "local:qwen".to_string(), "qwen", &request, event_tx).await;
+
+        let mut events = Vec::new();
+        while let Some(event) = event_rx.recv().await {
+            events.push(event);
+        }
+        let types: Vec<&str> = events.iter().map(|e| e.event_type.as_str()).collect();
+        assert_eq!(types, vec!["tool_call", "tool_call", "done"]);
+        assert_eq!(
+            events[0].tool_calls,
+            Some(serde_json::json!([{
+                "id": "call_1",
+                "type": "function",
+                "function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" },
+                "index": 0,
+            }]))
+        );
+        assert_eq!(events[1].tool_calls.as_ref().unwrap()[0]["function"]["name"], "get_time");
+        assert_eq!(events[2].finish_reason.as_deref(), Some("tool_calls"));
+    }
+}
@@ -0,0 +1,267 @@
+//! Per-instance concurrency limits for chat requests.
+//!
+//! Provider instances with `max_concurrency` set serve at most that many
+//! requests at once; further requests wait in a FIFO queue, with
+//! interactive requests ahead of background ones. Waiting requests give up
+//! after the instance's queue timeout.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{oneshot, watch};
+
+use super::config::ProviderInstance;
+use crate::rpc::RpcError;
+
+/// How long a request waits for a slot when the instance doesn't set
+/// `queue_timeout_secs`.
+pub const DEFAULT_QUEUE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Scheduling priority of a chat request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// A user is waiting on the response
+    #[default]
+    Interactive,
+    /// Summaries, agents and other work nobody is watching
+    Background,
+}
+
+struct Waiter {
+    ticket: u64,
+    priority: Priority,
+    grant: oneshot::Sender<()>,
+    position: watch::Sender<usize>,
+}
+
+#[derive(Default)]
+struct InstanceQueue {
+    limit: usize,
+    active: usize,
+    waiting: VecDeque<Waiter>,
+}
+
+impl InstanceQueue {
+    /// Index of the next waiter to serve: the oldest interactive request,
+    /// else the oldest background one.
+    fn next_index(&self) -> Option<usize> {
+        self.waiting
+            .iter()
+            .position(|w| w.priority == Priority::Interactive)
+            .or_else(|| (!self.waiting.is_empty()).then_some(0))
+    }
+
+    /// Hand free slots to waiters, then tell the rest where they stand.
+    fn grant_next(&mut self) {
+        while self.active < self.limit {
+            let waiter = match self.next_index().and_then(|i| self.waiting.remove(i)) {
+                Some(waiter) => waiter,
+                None => break,
+            };
+            // A closed receiver means the waiter gave up; skip it
+            if waiter.grant.send(()).is_ok() {
+                self.active += 1;
+            }
+        }
+
+        let order = self
+            .waiting
+            .iter()
+            .filter(|w| w.priority == Priority::Interactive)
+            .chain(self.waiting.iter().filter(|w| w.priority == Priority::Background));
+        for (i, waiter) in order.enumerate() {
+            waiter.position.send_if_modified(|p| std::mem::replace(p, i + 1) != i + 1);
+        }
+    }
+}
+
+fn queues() -> &'static Mutex<HashMap<String, InstanceQueue>> {
+    static QUEUES: OnceLock<Mutex<HashMap<String, InstanceQueue>>> = OnceLock::new();
+    QUEUES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn next_ticket() -> u64 {
+    static NEXT: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+    NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
+
+/// A held slot on an instance, released when dropped.
+pub struct Permit {
+    instance_id: String,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        release(&self.instance_id);
+    }
+}
+
+fn release(instance_id: &str) {
+    let mut queues = queues().lock().unwrap();
+    if let Some(queue) = queues.get_mut(instance_id) {
+        queue.active = queue.active.saturating_sub(1);
+        queue.grant_next();
+    }
+}
+
+/// A queued request. Dropping it (timeout, or the caller went away) leaves
+/// the queue, or gives the slot back if it was granted in the meantime.
+struct Ticket {
+    instance_id: String,
+    ticket: u64,
+    grant: oneshot::Receiver<()>,
+    done: bool,
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let mut queues = queues().lock().unwrap();
+        let queue = match queues.get_mut(&self.instance_id) {
+            Some(queue) => queue,
+            None => return,
+        };
+        if let Some(i) = queue.waiting.iter().position(|w| w.ticket == self.ticket) {
+            queue.waiting.remove(i);
+            queue.grant_next();
+        } else if self.grant.try_recv().is_ok() {
+            queue.active = queue.active.saturating_sub(1);
+            queue.grant_next();
+        }
+    }
+}
+
+/// Wait for a slot on an instance. Returns `None` for instances without a
+/// concurrency limit. `on_position` is called with the 1-based queue
+/// position whenever it changes while waiting.
+pub async fn acquire(
+    instance: &ProviderInstance,
+    priority: Priority,
+    mut on_position: impl FnMut(usize),
+) -> Result<Option<Permit>, RpcError> {
+    let limit = match instance.max_concurrency {
+        Some(limit) if limit > 0 => limit as usize,
+        _ => return Ok(None),
+    };
+    let permit = || Permit {
+        instance_id: instance.id.clone(),
+    };
+
+    let (grant_tx, grant_rx) = oneshot::channel();
+    let (position_tx, mut position_rx) = watch::channel(0);
+    let mut ticket = {
+        let mut queues = queues().lock().unwrap();
+        let queue = queues.entry(instance.id.clone()).or_default();
+        queue.limit = limit;
+        if queue.active < limit && queue.waiting.is_empty() {
+            queue.active += 1;
+            return Ok(Some(permit()));
+        }
+
+        let ticket = next_ticket();
+        queue.waiting.push_back(Waiter {
+            ticket,
+            priority,
+            grant: grant_tx,
+            position: position_tx,
+        });
+        queue.grant_next();
+        Ticket {
+            instance_id: instance.id.clone(),
+            ticket,
+            grant: grant_rx,
+            done: false,
+        }
+    };
+
+    let timeout = instance
+        .queue_timeout_secs
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_QUEUE_TIMEOUT);
+    let deadline = tokio::time::sleep(timeout);
+    tokio::pin!(deadline);
+    // The position sender goes away once the waiter leaves the queue
+    let mut waiting = true;
+
+    loop {
+        tokio::select! {
+            granted = &mut ticket.grant => {
+                if granted.is_ok() {
+                    ticket.done = true;
+                    return Ok(Some(permit()));
+                }
+                // Sender dropped without granting; shouldn't happen
+                return Err(RpcError::internal("Request queue closed"));
+            }
+            changed = position_rx.changed(), if waiting => {
+                match changed {
+                    Ok(()) => on_position(*position_rx.borrow()),
+                    Err(_) => waiting = false,
+                }
+            }
+            _ = &mut deadline => {
+                return Err(RpcError {
+                    code: -32001,
+                    message: format!(
+                        "LLM error: timed out after {}s waiting for provider '{}' (max {} concurrent requests)",
+                        timeout.as_secs(),
+                        instance.id,
+                        limit
+                    ),
+                    data: None,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_queue_order_and_timeout() {
+        let mut instance = ProviderInstance::with_id("test-queue-ollama", "ollama", "Ollama");
+        instance.max_concurrency = Some(1);
+        instance.queue_timeout_secs = Some(5);
+
+        let first = acquire(&instance, Priority::Interactive, |_| {}).await.unwrap();
+        assert!(first.is_some());
+
+        // Background request queued first, interactive one overtakes it
+        let (order_tx, mut order_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut waiters = Vec::new();
+        for (name, priority) in [("background", Priority::Background), ("interactive", Priority::Interactive)] {
+            let instance = instance.clone();
+            let order_tx = order_tx.clone();
+            waiters.push(tokio::spawn(async move {
+                let permit = acquire(&instance, priority, |_| {}).await.unwrap();
+                order_tx.send(name).unwrap();
+                drop(permit);
+            }));
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        drop(first);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        assert_eq!(order_rx.recv().await, Some("interactive"));
+        assert_eq!(order_rx.recv().await, Some("background"));
+
+        // A full instance makes later requests time out
+        instance.queue_timeout_secs = Some(0);
+        let _held = acquire(&instance, Priority::Interactive, |_| {}).await.unwrap();
+        let err = acquire(&instance, Priority::Interactive, |_| {})
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code, -32001);
+    }
+}
@@ -0,0 +1,197 @@
+//! Resolution of model IDs to provider instances.
+//!
+//! Model IDs have the form `<provider>:<model>`, where `<provider>` is either
+//! a provider instance ID (e.g. "openai-work") or a provider type (which
+//! resolves to that type's default instance). IDs without a prefix go to the
+//! default provider instance. A route carries everything needed to send a
+//! request to that instance.
+
+use std::time::Duration;
+
+use super::config::{LlmConfig, ProviderInstance};
+use super::{azure_openai, get_config, ollama, openai_compat, supported_provider_types};
+use crate::rpc::RpcError;
+
+/// Provider types that speak the OpenAI chat completions wire format, with
+/// their default API base URLs. Instances of these types with settings
+/// any-llm cannot pass (a custom base URL, extra headers, organization or
+/// project) are sent through the bridge's own OpenAI-compatible client.
+const OPENAI_WIRE_TYPES: [(&str, &str); 5] = [
+    ("openai", "https://api.openai.com/v1"),
+    ("groq", "https://api.groq.com/openai/v1"),
+    ("mistral", "https://api.mistral.ai/v1"),
+    ("lmstudio", "http://localhost:1234/v1"),
+    ("llamafile", "http://localhost:8080/v1"),
+];
+
+/// Whether instances of a provider type can send extra headers, an
+/// organization or a project. Only types the bridge's OpenAI-compatible
+/// client can talk to can; any-llm has no way to pass them.
+pub fn supports_extra_headers(provider_type: &str) -> bool {
+    provider_type == openai_compat::PROVIDER_TYPE
+        || provider_type == azure_openai::PROVIDER_TYPE
+        || provider_type == ollama::PROVIDER_TYPE
+        || OPENAI_WIRE_TYPES.iter().any(|(t, _)| *t == provider_type)
+}
+
+/// Endpoint for the bridge's OpenAI-compatible client, when requests to this
+/// instance must bypass any-llm.
+pub fn instance_endpoint(instance: &ProviderInstance) -> Result<Option<openai_compat::Endpoint>, String> {
+    if instance.provider_type == openai_compat::PROVIDER_TYPE {
+        return openai_compat::Endpoint::from_instance(instance).map(Some);
+    }
+    // Azure endpoints are per deployment, see `ModelRoute::openai_endpoint`
+    if instance.provider_type == azure_openai::PROVIDER_TYPE {
+        return Ok(None);
+    }
+
+    // Timeouts are applied on either path, so only these need the bridge client
+    let needs_endpoint = instance.base_url.is_some() || instance.has_extra_headers();
+    if !needs_endpoint {
+        return Ok(None);
+    }
+
+    // Ollama serves the OpenAI API under /v1 of its native base URL
+    if instance.provider_type == ollama::PROVIDER_TYPE {
+        let base_url = match &instance.base_url {
+            Some(url) => ollama::normalize_base_url(url),
+            None => ollama::base_url(None),
+        };
+        return Ok(Some(openai_compat::Endpoint::new(instance, &format!("{}/v1", base_url))));
+    }
+
+    if let Some((_, default_url)) = OPENAI_WIRE_TYPES
+        .iter()
+        .find(|(t, _)| *t == instance.provider_type)
+    {
+        let base_url = instance.base_url.as_deref().unwrap_or(default_url);
+        return Ok(Some(openai_compat::Endpoint::new(instance, base_url)));
+    }
+
+    tracing::warn!(
+        "Provider '{}' ({}) has a base_url or headers the bridge cannot apply; using the provider defaults",
+        instance.id,
+        instance.provider_type
+    );
+    Ok(None)
+}
+
+/// Where a chat request for a model ID is sent.
+#[derive(Debug, Clone)]
+pub struct ModelRoute {
+    /// The provider instance the ID resolved to, if one is configured
+    pub instance: Option<ProviderInstance>,
+    /// Provider type (e.g. "openai"), or empty for unqualified model IDs
+    /// when no default instance is configured
+    pub provider_type: String,
+    /// Model name as the provider knows it (e.g. "gpt-4o")
+    pub model_name: String,
+}
+
+impl ModelRoute {
+    /// Model string in the `<type>:<model>` form any-llm expects.
+    pub fn any_llm_model(&self) -> String {
+        if self.provider_type.is_empty() {
+            self.model_name.clone()
+        } else {
+            format!("{}:{}", self.provider_type, self.model_name)
+        }
+    }
+
+    /// API key of the resolved instance.
+    pub fn api_key(&self) -> Option<String> {
+        self.instance.as_ref().and_then(|i| i.api_key.clone())
+    }
+
+    /// Request timeout of the resolved instance.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.instance.as_ref().and_then(|i| i.timeout())
+    }
+
+    /// Endpoint for the bridge's OpenAI-compatible client, when this route
+    /// must bypass any-llm.
+    pub fn openai_endpoint(&self) -> Result<Option<openai_compat::Endpoint>, RpcError> {
+        let instance = match &self.instance {
+            Some(instance) => instance,
+            None => return Ok(None),
+        };
+        let endpoint = if instance.provider_type == azure_openai::PROVIDER_TYPE {
+            azure_openai::endpoint(instance, &self.model_name).map(Some)
+        } else {
+            instance_endpoint(instance)
+        };
+        endpoint.map_err(|message| RpcError {
+            code: -32001,
+            message,
+            data: None,
+        })
+    }
+}
+
+/// Resolve a model ID to the provider instance it should be sent to.
+pub fn resolve(model: &str) -> Result<ModelRoute, RpcError> {
+    resolve_in(get_config().as_ref(), model)
+}
+
+/// Resolve a model ID against a configuration. IDs without a provider
+/// prefix (including tagged ones such as "llama3.2:latest") go to the
+/// default provider instance, so its settings and queue apply to them too.
+fn resolve_in(config: Option<&LlmConfig>, model: &str) -> Result<ModelRoute, RpcError> {
+    let prefixed = model.split_once(':').filter(|(provider, _)| {
+        config.is_some_and(|c| c.resolve_provider(provider).is_some())
+            || supported_provider_types().iter().any(|t| t == provider)
+    });
+
+    let (instance, provider, model_name) = match prefixed {
+        Some((provider, model_name)) => {
+            (config.and_then(|c| c.resolve_provider(provider).cloned()), provider, model_name)
+        }
+        None => (config.and_then(|c| c.get_global_default().cloned()), "", model),
+    };
+    if let Some(instance) = &instance {
+        if !instance.enabled {
+            return Err(RpcError {
+                code: -32001,
+                message: format!("Provider '{}' is disabled", instance.id),
+                data: None,
+            });
+        }
+    }
+
+    Ok(ModelRoute {
+        provider_type: instance
+            .as_ref()
+            .map(|i| i.provider_type.clone())
+            .unwrap_or_else(|| provider.to_string()),
+        instance,
+        model_name: model_name.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unprefixed_models_use_default_instance() {
+        let mut config = LlmConfig::default();
+        let mut ollama = ProviderInstance::new(ollama::PROVIDER_TYPE, "Ollama");
+        ollama.max_concurrency = Some(1);
+        let id = config.add_instance(ollama);
+
+        for model in ["llama3.2", "llama3.2:latest"] {
+            let route = resolve_in(Some(&config), model).unwrap();
+            assert_eq!(route.instance.as_ref().map(|i| i.id.as_str()), Some(id.as_str()), "{}", model);
+            assert_eq!(route.any_llm_model(), format!("ollama:{}", model));
+        }
+
+        let route = resolve_in(Some(&config), "ollama:llama3.2:latest").unwrap();
+        assert_eq!(route.instance.map(|i| i.id), Some(id));
+        assert_eq!(route.model_name, "llama3.2:latest");
+
+        // With nothing configured, any-llm gets the ID unchanged
+        let route = resolve_in(None, "llama3.2").unwrap();
+        assert!(route.instance.is_none());
+        assert_eq!(route.any_llm_model(), "llama3.2");
+    }
+}
@@ -6,6 +6,7 @@ mod mcp;
 mod native_messaging;
 mod oauth;
 mod rpc;
+mod sessions;
 
 use std::env;
 
@@ -57,6 +58,9 @@ async fn main() {
   // Initialize OAuth module (loads credentials and stored tokens)
   oauth::init().await;
 
+  // Check provider health in the background; llm.health serves the cache
+  llm::health::start_monitor();
+
   if http_mode {
     // HTTP server mode for Safari
     tracing::info!("Harbor bridge starting in HTTP server mode on port {}", http_port);
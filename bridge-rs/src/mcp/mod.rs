@@ -5,15 +5,16 @@
 //! This module maintains a registry of tools that Harbor syncs to the bridge,
 //! allowing Web Agents to query available tools.
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::OnceLock;
 use tokio::sync::RwLock;
 
-use crate::rpc::RpcError;
+use crate::rpc::{to_result, OkResponse, RpcError};
 
 /// A registered MCP tool
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct RegisteredTool {
     #[serde(rename = "serverId", alias = "server_id")]
     pub server_id: String,
@@ -31,13 +32,13 @@ fn tool_registry() -> &'static RwLock<HashMap<String, RegisteredTool>> {
 }
 
 /// Register tools from a server
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct RegisterToolsParams {
     pub server_id: String,
     pub tools: Vec<ToolInfo>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct ToolInfo {
     pub name: String,
     #[serde(default)]
@@ -50,6 +51,7 @@ pub async fn register_tools(params: serde_json::Value) -> Result<serde_json::Val
     let params: RegisterToolsParams = serde_json::from_value(params).map_err(|e| RpcError {
         code: -32602,
         message: format!("Invalid params: {}", e),
+        data: None,
     })?;
     
     let mut registry = tool_registry().write().await;
@@ -64,11 +66,11 @@ pub async fn register_tools(params: serde_json::Value) -> Result<serde_json::Val
         });
     }
     
-    Ok(serde_json::json!({ "ok": true }))
+    to_result(OkResponse { ok: true })
 }
 
 /// Unregister tools from a server
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct UnregisterToolsParams {
     pub server_id: String,
 }
@@ -77,6 +79,7 @@ pub async fn unregister_tools(params: serde_json::Value) -> Result<serde_json::V
     let params: UnregisterToolsParams = serde_json::from_value(params).map_err(|e| RpcError {
         code: -32602,
         message: format!("Invalid params: {}", e),
+        data: None,
     })?;
     
     let mut registry = tool_registry().write().await;
@@ -84,7 +87,12 @@ pub async fn unregister_tools(params: serde_json::Value) -> Result<serde_json::V
     // Remove all tools from this server
     registry.retain(|_, tool| tool.server_id != params.server_id);
     
-    Ok(serde_json::json!({ "ok": true }))
+    to_result(OkResponse { ok: true })
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ListToolsResponse {
+    pub tools: Vec<RegisteredTool>,
 }
 
 /// List all registered tools
@@ -93,7 +101,7 @@ pub async fn list_tools() -> Result<serde_json::Value, RpcError> {
     
     let tools: Vec<RegisteredTool> = registry.values().cloned().collect();
     
-    Ok(serde_json::json!({ "tools": tools }))
+    to_result(ListToolsResponse { tools })
 }
 
 // ============================================================================
@@ -105,7 +113,7 @@ use std::time::{Duration, Instant};
 
 static CALL_COUNTER: AtomicU64 = AtomicU64::new(1);
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct PendingToolCall {
     pub call_id: String,
     #[serde(rename = "serverId")]
@@ -136,7 +144,7 @@ fn call_results() -> &'static RwLock<HashMap<String, ToolCallResult>> {
 }
 
 /// Call a tool - queues for WASM servers, calls directly for JS servers
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct CallToolParams {
     #[serde(rename = "serverId")]
     pub server_id: String,
@@ -146,10 +154,17 @@ pub struct CallToolParams {
     pub args: serde_json::Value,
 }
 
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CallToolResponse {
+    /// The MCP `tools/call` result (`content` plus any extra keys)
+    pub result: serde_json::Value,
+}
+
 pub async fn call_tool(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
     let params: CallToolParams = serde_json::from_value(params).map_err(|e| RpcError {
         code: -32602,
         message: format!("Invalid params: {}", e),
+        data: None,
     })?;
     
     // First, try calling via JS runtime (works for JS servers)
@@ -168,9 +183,9 @@ pub async fn call_tool(params: serde_json::Value) -> Result<serde_json::Value, R
         Ok(result) => {
             // JS server call succeeded — pass through the full MCP result (content + any extra keys e.g. searchResult)
             if let Some(mcp_result) = result.get("result") {
-                return Ok(serde_json::json!({ "result": mcp_result }));
+                return to_result(CallToolResponse { result: mcp_result.clone() });
             }
-            Ok(serde_json::json!({ "result": result }))
+            to_result(CallToolResponse { result })
         }
         Err(_) => {
             // JS call failed - queue for Harbor to handle (WASM servers)
@@ -201,9 +216,12 @@ pub async fn call_tool(params: serde_json::Value) -> Result<serde_json::Value, R
                         return Err(RpcError {
                             code: -32000,
                             message: err,
+                            data: None,
                         });
                     }
-                    return Ok(serde_json::json!({ "result": result.result }));
+                    return to_result(CallToolResponse {
+                        result: result.result.unwrap_or_default(),
+                    });
                 }
                 
                 if start.elapsed() > timeout {
@@ -211,6 +229,7 @@ pub async fn call_tool(params: serde_json::Value) -> Result<serde_json::Value, R
                     return Err(RpcError {
                         code: -32000,
                         message: "Tool call timed out waiting for Harbor".to_string(),
+                        data: None,
                     });
                 }
             }
@@ -218,15 +237,20 @@ pub async fn call_tool(params: serde_json::Value) -> Result<serde_json::Value, R
     }
 }
 
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PollPendingCallsResponse {
+    pub calls: Vec<PendingToolCall>,
+}
+
 /// Get pending tool calls (called by Harbor to execute WASM tools)
 pub async fn poll_pending_calls() -> Result<serde_json::Value, RpcError> {
     let pending = pending_calls().read().await;
-    let calls: Vec<&PendingToolCall> = pending.values().collect();
-    Ok(serde_json::json!({ "calls": calls }))
+    let calls: Vec<PendingToolCall> = pending.values().cloned().collect();
+    to_result(PollPendingCallsResponse { calls })
 }
 
 /// Submit a tool call result (called by Harbor after executing WASM tool)
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct SubmitResultParams {
     pub call_id: String,
     pub result: Option<serde_json::Value>,
@@ -237,6 +261,7 @@ pub async fn submit_call_result(params: serde_json::Value) -> Result<serde_json:
     let params: SubmitResultParams = serde_json::from_value(params).map_err(|e| RpcError {
         code: -32602,
         message: format!("Invalid params: {}", e),
+        data: None,
     })?;
     
     let result = ToolCallResult {
@@ -247,5 +272,5 @@ pub async fn submit_call_result(params: serde_json::Value) -> Result<serde_json:
     
     call_results().write().await.insert(params.call_id, result);
     
-    Ok(serde_json::json!({ "ok": true }))
+    to_result(OkResponse { ok: true })
 }
@@ -8,14 +8,20 @@
 //! - `rpc_stream`: Streaming RPC request, sends multiple `stream` messages
 //! - `ping`: Health check, responds with `status`
 //! - `shutdown`: Graceful shutdown request
+//!
+//! The bridge also pushes `console` (JS server logs), `host_request`,
+//! `server_message` (a JS server's notifications) and `provider_status`
+//! (a provider went up or down) messages unprompted.
 
 use std::collections::HashMap;
 use std::io::{self, Read, Write};
 use std::sync::Arc;
 use tokio::sync::{broadcast, mpsc, RwLock};
 
+use crate::js;
 use crate::llm;
-use crate::rpc::{self, RpcRequest};
+use crate::rpc::audit::{AuditEntry, Transport};
+use crate::rpc::{self, RpcRequest, Source};
 
 /// Message from the browser extension
 #[derive(Debug, serde::Deserialize)]
@@ -213,6 +219,38 @@ pub async fn run_native_messaging() {
         }
     });
 
+    // Spawn provider status forwarder (health monitor up/down transitions)
+    let status_writer = writer.clone();
+    let mut status_rx = llm::health::subscribe();
+    tokio::spawn(async move {
+        loop {
+            match status_rx.recv().await {
+                Ok(event) => {
+                    let payload = serde_json::to_value(&event).unwrap_or_default();
+                    status_writer.send("provider_status", payload).await;
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+
+    // Spawn JS server message forwarder (notifications that answer no call)
+    let message_writer = writer.clone();
+    let mut message_rx = js::subscribe();
+    tokio::spawn(async move {
+        loop {
+            match message_rx.recv().await {
+                Ok(message) => {
+                    let payload = serde_json::to_value(&message).unwrap_or_default();
+                    message_writer.send("server_message", payload).await;
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+
     // Create channel for incoming messages
     let (msg_tx, mut msg_rx) = mpsc::channel::<IncomingMessage>(32);
     // Channel for host requests (JS server asks bridge to send host_request and wait for host_response)
@@ -328,23 +366,22 @@ async fn handle_rpc(
     writer: Arc<MessageWriter>,
     host_request_tx: HostRequestSender,
 ) {
-    let response = if method == "js.call" {
-        match crate::js::call_server_with_host(params, host_request_tx).await {
-            Ok(value) => rpc::RpcResponse::success(id.clone(), value),
-            Err(e) => rpc::RpcResponse::error(id.clone(), e),
-        }
+    let is_js_call = method == "js.call";
+    let request = RpcRequest { id: id.clone(), method, params };
+    let source = Source::new(Transport::NativeMessaging, None);
+    let response = if is_js_call {
+        rpc::run(&source, request, |params| {
+            crate::js::call_server_with_host(params, host_request_tx)
+        })
+        .await
     } else {
-        let request = RpcRequest { id: id.clone(), method, params };
-        rpc::handle(request).await
+        rpc::handle_from(&source, request).await
     };
 
     writer.send_rpc_response(
         response.id,
         response.result,
-        response.error.map(|e| serde_json::json!({
-            "code": e.code,
-            "message": e.message,
-        })),
+        response.error.map(|e| serde_json::json!(e)),
     ).await;
 }
 
@@ -355,19 +392,45 @@ async fn handle_streaming_rpc(
     params: serde_json::Value,
     writer: Arc<MessageWriter>,
 ) {
+    let started = std::time::Instant::now();
+    let source = Source::new(Transport::NativeMessaging, None);
+    let entry = AuditEntry::start(&source, &method, &params);
+
+    let caller = match rpc::authorize(&source, &method, &params) {
+        Ok(caller) => caller,
+        Err(error) => {
+            rpc::audit::record(entry.finish(started.elapsed(), Some(error.code)));
+            writer.send_stream_event(id.clone(), serde_json::json!({
+                "id": id,
+                "type": "error",
+                "error": error,
+            })).await;
+            return;
+        }
+    };
+
+    // Code of the error event that ended the stream, for the audit log
+    let mut error_code: Option<i64> = None;
+    let stream_error_code = |event: &serde_json::Value| {
+        event.pointer("/error/code").and_then(|c| c.as_i64()).or(Some(-32000))
+    };
+
     match method.as_str() {
         "llm.chat_stream" => {
             let (event_tx, mut event_rx) = tokio::sync::mpsc::channel(32);
             
             // Spawn the streaming task
             let stream_id = id.clone();
-            tokio::spawn(async move {
+            tokio::spawn(rpc::as_caller(caller, async move {
                 llm::chat_stream(stream_id, params, event_tx).await;
-            });
+            }));
             
             // Forward events to the extension
             while let Some(event) = event_rx.recv().await {
                 let event_json = serde_json::to_value(&event).unwrap_or_default();
+                if event.event_type == "error" {
+                    error_code = stream_error_code(&event_json);
+                }
                 writer.send_stream_event(id.clone(), event_json).await;
                 
                 if event.event_type == "done" || event.event_type == "error" {
@@ -375,7 +438,29 @@ async fn handle_streaming_rpc(
                 }
             }
         }
+        "llm.ollama.pull" => {
+            let (event_tx, mut event_rx) = tokio::sync::mpsc::channel(32);
+
+            let stream_id = id.clone();
+            tokio::spawn(async move {
+                llm::ollama::pull_stream(stream_id, params, event_tx).await;
+            });
+
+            // Forward progress events to the extension
+            while let Some(event) = event_rx.recv().await {
+                let event_json = serde_json::to_value(&event).unwrap_or_default();
+                if event.event_type == "error" {
+                    error_code = stream_error_code(&event_json);
+                }
+                writer.send_stream_event(id.clone(), event_json).await;
+
+                if event.event_type == "done" || event.event_type == "error" {
+                    break;
+                }
+            }
+        }
         _ => {
+            error_code = Some(-32601);
             writer.send_rpc_response(
                 id,
                 None,
@@ -386,4 +471,6 @@ async fn handle_streaming_rpc(
             ).await;
         }
     }
+
+    rpc::audit::record(entry.finish(started.elapsed(), error_code));
 }
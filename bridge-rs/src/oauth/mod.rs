@@ -8,6 +8,7 @@ pub mod providers;
 pub mod server;
 pub mod storage;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -282,36 +283,78 @@ pub async fn get_token_store_mut() -> tokio::sync::RwLockWriteGuard<'static, Opt
     TOKEN_STORE.write().await
 }
 
+/// Current access token a server got from `provider`, refreshed if it is
+/// about to expire. Only a refresh takes the store's write lock.
+pub async fn access_token(server_id: &str, provider: &str) -> Result<String, String> {
+    if let Some(store) = get_token_store().await.as_ref() {
+        let current = store
+            .get_tokens(server_id)
+            .filter(|stored| stored.provider == provider && !store.is_expired(server_id));
+        if let Some(stored) = current {
+            return Ok(stored.tokens.access_token.clone());
+        }
+    }
+    // Another caller may have refreshed the tokens in between, which
+    // get_access_token checks again under the write lock
+    let mut store = get_token_store_mut().await;
+    let store = store.as_mut().ok_or("Token store is not loaded")?;
+    match store.get_tokens(server_id) {
+        Some(stored) if stored.provider == provider => store.get_access_token(server_id).await,
+        Some(stored) => Err(format!(
+            "Server {} is authorized with {}, not {}",
+            server_id, stored.provider, provider
+        )),
+        None => Err(format!("Server {} has not been authorized with {}", server_id, provider)),
+    }
+}
+
 // ============================================================================
 // RPC Handlers
 // ============================================================================
 
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct StartFlowParams {
+    /// OAuth provider ("google" or "github")
+    pub provider: String,
+    pub server_id: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ServerIdParams {
+    pub server_id: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ProviderParams {
+    pub provider: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetCredentialsParams {
+    pub provider: String,
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+fn parse_params<T: serde::de::DeserializeOwned>(params: serde_json::Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::invalid_params(format!("Invalid params: {}", e)))
+}
+
 /// Start an OAuth flow for a server.
 /// Returns the authorization URL to open in browser.
 pub async fn rpc_start_flow(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let provider_id = params.get("provider")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'provider' parameter".to_string(),
-        })?;
-    
-    let server_id = params.get("server_id")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'server_id' parameter".to_string(),
-        })?;
-    
-    let scopes: Vec<String> = params.get("scopes")
-        .and_then(|v| v.as_array())
-        .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
-        .unwrap_or_default();
+    let params: StartFlowParams = parse_params(params)?;
+    let provider_id = params.provider.as_str();
+    let server_id = params.server_id.as_str();
+    let scopes = params.scopes;
     
     if scopes.is_empty() {
         return Err(RpcError {
             code: -32602,
             message: "Missing or empty 'scopes' parameter".to_string(),
+            data: None,
         });
     }
     
@@ -319,6 +362,7 @@ pub async fn rpc_start_flow(params: serde_json::Value) -> Result<serde_json::Val
     let credentials = get_credentials(provider_id).await.ok_or_else(|| RpcError {
         code: -32000,
         message: format!("OAuth provider '{}' is not configured", provider_id),
+        data: None,
     })?;
     
     // Start the flow
@@ -326,6 +370,7 @@ pub async fn rpc_start_flow(params: serde_json::Value) -> Result<serde_json::Val
         .map_err(|e| RpcError {
             code: -32000,
             message: format!("Failed to start OAuth flow: {}", e),
+            data: None,
         })?;
     
     // Store the pending flow
@@ -336,6 +381,7 @@ pub async fn rpc_start_flow(params: serde_json::Value) -> Result<serde_json::Val
     server::ensure_server_running().await.map_err(|e| RpcError {
         code: -32000,
         message: format!("Failed to start OAuth callback server: {}", e),
+        data: None,
     })?;
     
     Ok(serde_json::json!({
@@ -346,12 +392,8 @@ pub async fn rpc_start_flow(params: serde_json::Value) -> Result<serde_json::Val
 
 /// Get tokens for a server (with automatic refresh if expired).
 pub async fn rpc_get_tokens(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let server_id = params.get("server_id")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'server_id' parameter".to_string(),
-        })?;
+    let params: ServerIdParams = parse_params(params)?;
+    let server_id = params.server_id.as_str();
     
     // Use get_access_token which handles refresh automatically
     let mut store = get_token_store_mut().await;
@@ -383,6 +425,7 @@ pub async fn rpc_get_tokens(params: serde_json::Value) -> Result<serde_json::Val
                     Err(RpcError {
                         code: -32000,
                         message: format!("Failed to get access token: {}", e),
+                        data: None,
                     })
                 }
             }
@@ -395,12 +438,8 @@ pub async fn rpc_get_tokens(params: serde_json::Value) -> Result<serde_json::Val
 
 /// Check OAuth status for a server.
 pub async fn rpc_status(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let server_id = params.get("server_id")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'server_id' parameter".to_string(),
-        })?;
+    let params: ServerIdParams = parse_params(params)?;
+    let server_id = params.server_id.as_str();
     
     let store = get_token_store().await;
     let stored = store.as_ref().and_then(|s| s.get_tokens(server_id));
@@ -429,12 +468,8 @@ pub async fn rpc_status(params: serde_json::Value) -> Result<serde_json::Value,
 
 /// Revoke OAuth tokens for a server.
 pub async fn rpc_revoke(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let server_id = params.get("server_id")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'server_id' parameter".to_string(),
-        })?;
+    let params: ServerIdParams = parse_params(params)?;
+    let server_id = params.server_id.as_str();
     
     let mut store = get_token_store_mut().await;
     if let Some(ref mut s) = *store {
@@ -517,32 +552,17 @@ pub async fn rpc_get_credentials_status(_params: serde_json::Value) -> Result<se
 
 /// Set OAuth credentials for a provider.
 pub async fn rpc_set_credentials(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let provider_id = params.get("provider")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'provider' parameter".to_string(),
-        })?;
-    
-    let client_id = params.get("client_id")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'client_id' parameter".to_string(),
-        })?;
-    
-    let client_secret = params.get("client_secret")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'client_secret' parameter".to_string(),
-        })?;
+    let params: SetCredentialsParams = parse_params(params)?;
+    let provider_id = params.provider.as_str();
+    let client_id = params.client_id.as_str();
+    let client_secret = params.client_secret.as_str();
     
     // Validate provider
     if provider_id != "google" && provider_id != "github" {
         return Err(RpcError {
             code: -32602,
             message: format!("Unknown provider: {}", provider_id),
+            data: None,
         });
     }
     
@@ -551,12 +571,14 @@ pub async fn rpc_set_credentials(params: serde_json::Value) -> Result<serde_json
         return Err(RpcError {
             code: -32602,
             message: "client_id cannot be empty".to_string(),
+            data: None,
         });
     }
     if client_secret.trim().is_empty() {
         return Err(RpcError {
             code: -32602,
             message: "client_secret cannot be empty".to_string(),
+            data: None,
         });
     }
     
@@ -566,6 +588,7 @@ pub async fn rpc_set_credentials(params: serde_json::Value) -> Result<serde_json
         .map_err(|e| RpcError {
             code: -32000,
             message: format!("Failed to save credentials: {}", e),
+            data: None,
         })?;
     
     tracing::info!("Configured OAuth credentials for {}", provider_id);
@@ -578,18 +601,15 @@ pub async fn rpc_set_credentials(params: serde_json::Value) -> Result<serde_json
 
 /// Remove OAuth credentials for a provider.
 pub async fn rpc_remove_credentials(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
-    let provider_id = params.get("provider")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| RpcError {
-            code: -32602,
-            message: "Missing 'provider' parameter".to_string(),
-        })?;
+    let params: ProviderParams = parse_params(params)?;
+    let provider_id = params.provider.as_str();
     
     remove_credentials(provider_id)
         .await
         .map_err(|e| RpcError {
             code: -32000,
             message: format!("Failed to remove credentials: {}", e),
+            data: None,
         })?;
     
     tracing::info!("Removed OAuth credentials for {}", provider_id);
@@ -136,7 +136,6 @@ impl TokenStore {
     }
     
     /// Get access token for a server, refreshing if needed.
-    #[allow(dead_code)]
     pub async fn get_access_token(
         &mut self,
         server_id: &str,
@@ -0,0 +1,521 @@
+//! Audit log of RPC calls.
+//!
+//! Every call handled through `rpc::handle_from` (and every streaming call),
+//! including calls to unknown methods, is appended to
+//! `~/.harbor/audit/audit.jsonl` as one JSON line with the transport, method,
+//! calling origin or server, duration, outcome and a redacted summary of the
+//! params. The file rotates when it grows past
+//! `max_file_bytes`, keeping `max_files` old files (`audit.1.jsonl`, ...).
+//!
+//! Settings are read from `~/.harbor/audit.json`:
+//!
+//! ```json
+//! { "enabled": true, "redact_bodies": true, "max_file_bytes": 5242880, "max_files": 5 }
+//! ```
+//!
+//! Secrets (API keys, tokens, passwords, headers, server environments) are
+//! always redacted.
+//! Message bodies, prompts and code are reduced to their size unless
+//! `redact_bodies` is false.
+
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::policy::Caller;
+use super::{RpcError, Source};
+
+/// Transport an RPC call arrived on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+  NativeMessaging,
+  Http,
+  WebSocket,
+  /// Called from inside the bridge
+  Internal,
+}
+
+/// One audit log line.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AuditEntry {
+  /// RFC 3339 timestamp of when the call started
+  pub timestamp: String,
+  pub transport: Transport,
+  pub method: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub origin: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub server_id: Option<String>,
+  pub duration_ms: u64,
+  pub ok: bool,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub error_code: Option<i64>,
+  /// Redacted params summary
+  pub params: serde_json::Value,
+}
+
+impl AuditEntry {
+  /// Start an entry for a call; finish it with `finish`.
+  pub fn start(source: &Source, method: &str, params: &serde_json::Value) -> Self {
+    let redact_bodies = audit_log().lock().unwrap().config.redact_bodies;
+    let (origin, caller_server) = match Caller::identify(source, params) {
+      Caller::Origin(origin) => (Some(origin), None),
+      Caller::Server(server_id) => (None, Some(server_id)),
+      _ => (None, None),
+    };
+    Self {
+      timestamp: chrono::Utc::now().to_rfc3339(),
+      transport: source.transport,
+      method: method.to_string(),
+      origin,
+      server_id: caller_server.or_else(|| target_server_id(params).map(|s| s.to_string())),
+      duration_ms: 0,
+      ok: true,
+      error_code: None,
+      params: redact(params, redact_bodies, 0),
+    }
+  }
+
+  /// Complete the entry with the call's duration and error code, if any.
+  pub fn finish(mut self, duration: Duration, error_code: Option<i64>) -> Self {
+    self.duration_ms = duration.as_millis() as u64;
+    self.ok = error_code.is_none();
+    self.error_code = error_code;
+    self
+  }
+}
+
+/// JS server a call targets.
+fn target_server_id(params: &serde_json::Value) -> Option<&str> {
+  params.get("server_id").and_then(|s| s.as_str())
+}
+
+// =============================================================================
+// Redaction
+// =============================================================================
+
+/// Keys whose values are never logged.
+const SECRET_KEYS: &[&str] = &[
+  "api_key",
+  "apikey",
+  "key",
+  "token",
+  "access_token",
+  "refresh_token",
+  "secret",
+  "client_secret",
+  "password",
+  "authorization",
+  "headers",
+  "credentials",
+  // Environment of spawned servers, which commonly carries API keys
+  "env",
+];
+
+/// Keys holding content (messages, prompts, code) that is logged by size only.
+const BODY_KEYS: &[&str] = &[
+  "messages",
+  "content",
+  "prompt",
+  "system_prompt",
+  "code",
+  "source",
+  "text",
+  "input",
+  "args",
+  "arguments",
+  "body",
+  "data",
+];
+
+const MAX_DEPTH: usize = 3;
+const MAX_STRING: usize = 200;
+
+fn is_secret_key(key: &str) -> bool {
+  let key = key.to_lowercase();
+  SECRET_KEYS.contains(&key.as_str()) || key.ends_with("_key") || key.ends_with("_token") || key.ends_with("secret")
+}
+
+fn size_summary(value: &serde_json::Value) -> serde_json::Value {
+  match value {
+    serde_json::Value::String(s) => serde_json::json!(format!("[redacted: {} chars]", s.chars().count())),
+    serde_json::Value::Array(a) => serde_json::json!(format!("[redacted: {} items]", a.len())),
+    serde_json::Value::Null => serde_json::Value::Null,
+    _ => serde_json::json!("[redacted]"),
+  }
+}
+
+/// Summarize params for the log: secrets removed, bodies reduced to their
+/// size (when `redact_bodies`), long strings truncated, nesting limited.
+pub fn redact(value: &serde_json::Value, redact_bodies: bool, depth: usize) -> serde_json::Value {
+  match value {
+    serde_json::Value::Object(map) => {
+      if depth >= MAX_DEPTH {
+        return serde_json::json!(format!("[{} fields]", map.len()));
+      }
+      let redacted = map
+        .iter()
+        .map(|(key, v)| {
+          let v = if is_secret_key(key) {
+            serde_json::json!("[redacted]")
+          } else if redact_bodies && BODY_KEYS.contains(&key.as_str()) {
+            size_summary(v)
+          } else {
+            redact(v, redact_bodies, depth + 1)
+          };
+          (key.clone(), v)
+        })
+        .collect();
+      serde_json::Value::Object(redacted)
+    }
+    serde_json::Value::Array(items) => {
+      if depth >= MAX_DEPTH {
+        return serde_json::json!(format!("[{} items]", items.len()));
+      }
+      serde_json::Value::Array(items.iter().map(|v| redact(v, redact_bodies, depth + 1)).collect())
+    }
+    serde_json::Value::String(s) if s.chars().count() > MAX_STRING => {
+      let truncated: String = s.chars().take(MAX_STRING).collect();
+      serde_json::json!(format!("{}…", truncated))
+    }
+    other => other.clone(),
+  }
+}
+
+// =============================================================================
+// Log Files
+// =============================================================================
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+  pub enabled: bool,
+  pub redact_bodies: bool,
+  pub max_file_bytes: u64,
+  pub max_files: usize,
+}
+
+impl Default for AuditConfig {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      redact_bodies: true,
+      max_file_bytes: 5 * 1024 * 1024,
+      max_files: 5,
+    }
+  }
+}
+
+impl AuditConfig {
+  /// Load settings from `~/.harbor/audit.json`, falling back to defaults.
+  pub fn load() -> Self {
+    let path = harbor_dir().join("audit.json");
+    match fs::read_to_string(&path) {
+      Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+        tracing::warn!("Ignoring invalid {}: {}", path.display(), e);
+        Self::default()
+      }),
+      Err(_) => Self::default(),
+    }
+  }
+}
+
+fn harbor_dir() -> PathBuf {
+  dirs::home_dir()
+    .unwrap_or_else(|| PathBuf::from("."))
+    .join(".harbor")
+}
+
+/// Rotating JSON-lines audit log in a directory.
+pub struct AuditLog {
+  dir: PathBuf,
+  config: AuditConfig,
+}
+
+impl AuditLog {
+  pub fn new(dir: PathBuf, config: AuditConfig) -> Self {
+    Self { dir, config }
+  }
+
+  /// Path of the current file (`index` 0) or a rotated one.
+  fn file_path(&self, index: usize) -> PathBuf {
+    if index == 0 {
+      self.dir.join("audit.jsonl")
+    } else {
+      self.dir.join(format!("audit.{}.jsonl", index))
+    }
+  }
+
+  fn rotate(&self) -> std::io::Result<()> {
+    let oldest = self.file_path(self.config.max_files);
+    if oldest.exists() {
+      fs::remove_file(oldest)?;
+    }
+    for index in (0..self.config.max_files).rev() {
+      let from = self.file_path(index);
+      if from.exists() {
+        fs::rename(&from, self.file_path(index + 1))?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Append an entry, rotating first if the current file is full.
+  pub fn append(&self, entry: &AuditEntry) -> Result<(), String> {
+    if !self.config.enabled {
+      return Ok(());
+    }
+    fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create audit directory: {}", e))?;
+
+    let path = self.file_path(0);
+    let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    if size >= self.config.max_file_bytes {
+      self.rotate().map_err(|e| format!("Failed to rotate audit log: {}", e))?;
+    }
+
+    let mut line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+    line.push('\n');
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    // Entries name origins and summarize params; keep them owner-only
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::OpenOptionsExt;
+      options.mode(0o600);
+    }
+    options
+      .open(&path)
+      .and_then(|mut f| f.write_all(line.as_bytes()))
+      .map_err(|e| format!("Failed to write audit log: {}", e))
+  }
+
+  /// Entries matching a query, newest first.
+  pub fn query(&self, query: &AuditQuery) -> Vec<AuditEntry> {
+    let limit = query.limit.unwrap_or(100).min(1000);
+    let mut results = Vec::new();
+
+    // Current file first, then older rotations
+    for index in 0..=self.config.max_files {
+      let file = match fs::File::open(self.file_path(index)) {
+        Ok(file) => file,
+        Err(_) => continue,
+      };
+      let mut entries: Vec<AuditEntry> = BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .filter(|entry| query.matches(entry))
+        .collect();
+      entries.reverse();
+      results.extend(entries);
+      if results.len() >= limit {
+        break;
+      }
+    }
+
+    results.truncate(limit);
+    results
+  }
+}
+
+fn audit_log() -> &'static Mutex<AuditLog> {
+  static LOG: OnceLock<Mutex<AuditLog>> = OnceLock::new();
+  LOG.get_or_init(|| {
+    // Tests log to a temporary directory, leaving the user's ~/.harbor alone
+    let log = if cfg!(test) {
+      let dir = std::env::temp_dir().join(format!("harbor-audit-{}", std::process::id()));
+      AuditLog::new(dir, AuditConfig::default())
+    } else {
+      AuditLog::new(harbor_dir().join("audit"), AuditConfig::load())
+    };
+    Mutex::new(log)
+  })
+}
+
+/// Entries of the global audit log matching a query, newest first.
+pub fn query(query: &AuditQuery) -> Vec<AuditEntry> {
+  audit_log().lock().unwrap().query(query)
+}
+
+/// Append an entry to the global audit log.
+pub fn record(entry: AuditEntry) {
+  if let Err(e) = audit_log().lock().unwrap().append(&entry) {
+    tracing::warn!("{}", e);
+  }
+}
+
+// =============================================================================
+// Query
+// =============================================================================
+
+/// Filters for `system.query_audit_log`. All are optional.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct AuditQuery {
+  /// Exact method, or a prefix ending in `*` (e.g. "llm.*")
+  #[serde(default)]
+  pub method: Option<String>,
+  #[serde(default)]
+  pub origin: Option<String>,
+  #[serde(default)]
+  pub server_id: Option<String>,
+  #[serde(default)]
+  pub transport: Option<Transport>,
+  /// Only failed calls
+  #[serde(default)]
+  pub errors_only: bool,
+  /// Only calls that returned this error code
+  #[serde(default)]
+  pub error_code: Option<i64>,
+  /// RFC 3339 lower bound on the timestamp
+  #[serde(default)]
+  pub since: Option<String>,
+  /// RFC 3339 upper bound on the timestamp
+  #[serde(default)]
+  pub until: Option<String>,
+  /// Maximum entries to return (default 100, at most 1000)
+  #[serde(default)]
+  pub limit: Option<usize>,
+}
+
+fn parse_time(value: &str) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+  chrono::DateTime::parse_from_rfc3339(value).ok()
+}
+
+impl AuditQuery {
+  fn matches(&self, entry: &AuditEntry) -> bool {
+    if let Some(method) = &self.method {
+      let matched = match method.strip_suffix('*') {
+        Some(prefix) => entry.method.starts_with(prefix),
+        None => entry.method == *method,
+      };
+      if !matched {
+        return false;
+      }
+    }
+    if self.origin.is_some() && entry.origin != self.origin {
+      return false;
+    }
+    if self.server_id.is_some() && entry.server_id != self.server_id {
+      return false;
+    }
+    if self.transport.is_some_and(|t| t != entry.transport) {
+      return false;
+    }
+    if self.errors_only && entry.ok {
+      return false;
+    }
+    if self.error_code.is_some() && entry.error_code != self.error_code {
+      return false;
+    }
+
+    let timestamp = parse_time(&entry.timestamp);
+    if let (Some(since), Some(timestamp)) = (self.since.as_deref().and_then(parse_time), timestamp) {
+      if timestamp < since {
+        return false;
+      }
+    }
+    if let (Some(until), Some(timestamp)) = (self.until.as_deref().and_then(parse_time), timestamp) {
+      if timestamp > until {
+        return false;
+      }
+    }
+    true
+  }
+}
+
+/// `system.query_audit_log`: entries matching the filters, newest first.
+pub async fn rpc_query(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+  let query: AuditQuery = if params.is_null() {
+    AuditQuery::default()
+  } else {
+    serde_json::from_value(params).map_err(|e| RpcError::invalid_params(format!("Invalid params: {}", e)))?
+  };
+  for bound in [&query.since, &query.until].into_iter().flatten() {
+    if parse_time(bound).is_none() {
+      return Err(RpcError::invalid_params(format!("Invalid RFC 3339 timestamp: {}", bound)));
+    }
+  }
+
+  let entries = self::query(&query);
+  Ok(serde_json::json!({ "entries": entries }))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_redact() {
+    let params = serde_json::json!({
+      "model": "openai:gpt-4o",
+      "api_key": "sk-secret",
+      "messages": [{ "role": "user", "content": "hello" }],
+      "context": { "origin": "https://example.com", "refresh_token": "abc" },
+      "env": { "GITHUB_PAT": "ghp_abc" },
+      "headers": { "X-Api": "abc" },
+    });
+    let redacted = redact(&params, true, 0);
+    assert_eq!(redacted["model"], "openai:gpt-4o");
+    assert_eq!(redacted["api_key"], "[redacted]");
+    assert_eq!(redacted["messages"], "[redacted: 1 items]");
+    assert_eq!(redacted["context"]["origin"], "https://example.com");
+    assert_eq!(redacted["context"]["refresh_token"], "[redacted]");
+    assert_eq!(redacted["env"], "[redacted]");
+    assert_eq!(redacted["headers"], "[redacted]");
+
+    // Bodies are kept when asked, secrets never are
+    let unredacted = redact(&params, false, 0);
+    assert_eq!(unredacted["messages"][0]["content"], "hello");
+    assert_eq!(unredacted["api_key"], "[redacted]");
+  }
+
+  #[test]
+  fn test_append_rotate_query() {
+    let dir = std::env::temp_dir().join(format!("harbor-audit-test-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let config = AuditConfig {
+      max_file_bytes: 1,
+      max_files: 2,
+      ..Default::default()
+    };
+    let log = AuditLog::new(dir.clone(), config);
+
+    for (i, method) in ["llm.chat", "fs.read", "llm.list_models", "llm.chat"].iter().enumerate() {
+      let source = Source::new(Transport::Http, Some("https://example.com".to_string()));
+      let error_code = if i == 3 { Some(-32029) } else { None };
+      let entry = AuditEntry::start(&source, method, &serde_json::json!({})).finish(Duration::from_millis(3), error_code);
+      log.append(&entry).unwrap();
+    }
+
+    // Every append rotated; only the newest three files remain
+    let all = log.query(&AuditQuery::default());
+    assert_eq!(all.len(), 3);
+    assert_eq!(all[0].error_code, Some(-32029));
+
+    let llm = log.query(&AuditQuery {
+      method: Some("llm.*".to_string()),
+      ..Default::default()
+    });
+    assert_eq!(llm.len(), 2);
+
+    let errors = log.query(&AuditQuery {
+      errors_only: true,
+      ..Default::default()
+    });
+    assert_eq!(errors.len(), 1);
+
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      let mode = fs::metadata(log.file_path(0)).unwrap().permissions().mode();
+      assert_eq!(mode & 0o777, 0o600);
+    }
+    let _ = fs::remove_dir_all(&dir);
+  }
+}
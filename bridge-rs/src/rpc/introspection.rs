@@ -0,0 +1,338 @@
+//! RPC introspection: `system.list_methods` and `system.describe`.
+//!
+//! Describes every method the bridge serves, including the streaming ones
+//! handled by the transports, with JSON Schemas for its params and result.
+//! Schemas come from the typed param and response structs the handlers use;
+//! methods without one (mostly pass-throughs of provider or server responses)
+//! accept and return a plain object. Clients use this to generate bindings
+//! and to validate requests before sending them.
+
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+use super::{audit, is_streaming_method, list_methods, to_result, OkResponse, RpcError};
+use crate::{fs, js, llm, mcp, oauth, sessions};
+
+/// Description of one RPC method.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct MethodInfo {
+  pub name: &'static str,
+  pub description: &'static str,
+  /// Results arrive as a series of events rather than one response
+  pub streaming: bool,
+  /// Version of the params/result contract, bumped when either changes
+  /// incompatibly
+  pub version: u32,
+  /// JSON Schema for `params`
+  pub params: serde_json::Value,
+  /// JSON Schema for the result, or for each event of a streaming method
+  pub result: serde_json::Value,
+}
+
+fn schema<T: JsonSchema>() -> serde_json::Value {
+  serde_json::to_value(schema_for!(T)).unwrap_or_default()
+}
+
+fn any_object() -> serde_json::Value {
+  serde_json::json!({ "type": "object" })
+}
+
+fn description(method: &str) -> &'static str {
+  match method {
+    "system.health" => "Check that the bridge is running",
+    "system.list_methods" => "List every RPC method with its schemas",
+    "system.describe" => "Describe one RPC method",
+    "system.query_audit_log" => "Query the RPC audit log",
+
+    "llm.health" => "Cached availability of each LLM provider",
+    "llm.list_models" => "List models from every configured provider",
+    "llm.chat" => "Run a chat completion",
+    "llm.chat_stream" => "Run a chat completion, streaming tokens as events",
+    "llm.list_providers" => "List configured provider instances",
+    "llm.list_provider_types" => "List supported provider types",
+    "llm.check_provider" => "Probe a provider and update its cached health",
+    "llm.configure_provider" => "Update a provider instance's settings",
+    "llm.add_provider" => "Add a provider instance",
+    "llm.remove_provider" => "Remove a provider instance",
+    "llm.set_default_provider" => "Set the default provider instance",
+    "llm.set_type_default" => "Set the default instance for a provider type",
+    "llm.get_config" => "Get the LLM configuration (without secrets)",
+    "llm.set_default_model" => "Set the default model",
+    "llm.list_configured_models" => "List configured model aliases",
+    "llm.get_configured_models_metadata" => "List configured models with their metadata",
+    "llm.add_configured_model" => "Add a configured model alias",
+    "llm.remove_configured_model" => "Remove a configured model alias",
+    "llm.set_configured_model_default" => "Make a configured model the default",
+    "llm.set_configured_model_context_window" => "Override a configured model's context window",
+    "llm.set_model_capabilities" => "Override a model's capabilities",
+    "llm.ollama.show" => "Show an Ollama model's details",
+    "llm.ollama.delete" => "Delete an Ollama model",
+    "llm.ollama.ps" => "List models loaded in Ollama",
+    "llm.ollama.pull" => "Pull an Ollama model, streaming progress as events",
+
+    "fs.read" => "Read a file",
+    "fs.write" => "Write a file",
+    "fs.list" => "List a directory",
+
+    "js.start_server" => "Start a JavaScript MCP server",
+    "js.stop_server" => "Stop a JavaScript MCP server",
+    "js.call" => "Send a JSON-RPC request to a JavaScript MCP server",
+    "js.list_servers" => "List running JavaScript MCP servers",
+    "js.get_storage" => "Get a JavaScript MCP server's stored keys and values",
+    "js.clear_storage" => "Clear a JavaScript MCP server's storage",
+
+    "oauth.start_flow" => "Start an OAuth flow for a server",
+    "oauth.get_tokens" => "Get a server's OAuth tokens, refreshing if needed",
+    "oauth.status" => "Get a server's OAuth status",
+    "oauth.revoke" => "Revoke a server's OAuth tokens",
+    "oauth.list_providers" => "List OAuth providers",
+    "oauth.get_credentials_status" => "Show which OAuth providers have client credentials",
+    "oauth.set_credentials" => "Set an OAuth provider's client credentials",
+    "oauth.remove_credentials" => "Remove an OAuth provider's client credentials",
+
+    "mcp.register_tools" => "Register a server's tools",
+    "mcp.unregister_tools" => "Unregister a server's tools",
+    "mcp.list_tools" => "List registered tools",
+    "mcp.call_tool" => "Call a registered tool",
+    "mcp.poll_pending_calls" => "Take tool calls waiting for the extension to run",
+    "mcp.submit_call_result" => "Submit the result of a pending tool call",
+
+    "sessions.create" => "Create a chat session",
+    "sessions.append" => "Append messages to a chat session",
+    "sessions.list" => "List chat sessions",
+    "sessions.get" => "Get a chat session with its messages",
+    "sessions.delete" => "Delete a chat session",
+    "sessions.search" => "Search chat sessions",
+
+    _ => "",
+  }
+}
+
+/// Contract version of a method. Every method starts at 1; add an arm here
+/// when one changes incompatibly.
+fn version(method: &str) -> u32 {
+  match method {
+    // v2: tool calls are streamed as `tool_call` events
+    "llm.chat_stream" => 2,
+    _ => 1,
+  }
+}
+
+fn params_schema(method: &str) -> serde_json::Value {
+  match method {
+    "system.describe" => schema::<DescribeParams>(),
+    "system.query_audit_log" => schema::<audit::AuditQuery>(),
+    "llm.chat" | "llm.chat_stream" => schema::<llm::ChatRequest>(),
+    "llm.check_provider" => schema::<llm::CheckProviderParams>(),
+    "llm.configure_provider" => schema::<llm::ConfigureProviderParams>(),
+    "llm.add_provider" => schema::<llm::AddProviderParams>(),
+    "llm.remove_provider" | "llm.set_default_provider" | "llm.set_type_default" => {
+      schema::<llm::InstanceIdParams>()
+    }
+    "llm.set_default_model" => schema::<llm::DefaultModelParams>(),
+    "llm.add_configured_model" => schema::<llm::AddConfiguredModelParams>(),
+    "llm.remove_configured_model" | "llm.set_configured_model_default" => {
+      schema::<llm::ConfiguredModelParams>()
+    }
+    "llm.set_configured_model_context_window" => schema::<llm::ContextWindowParams>(),
+    "llm.set_model_capabilities" => schema::<llm::ModelCapabilitiesParams>(),
+    "llm.ollama.show" | "llm.ollama.delete" | "llm.ollama.pull" => schema::<llm::ollama::ModelParams>(),
+    "llm.ollama.ps" => schema::<llm::ollama::ProviderParams>(),
+    "fs.read" | "fs.list" => schema::<fs::PathParams>(),
+    "fs.write" => schema::<fs::WriteParams>(),
+    "js.start_server" => schema::<js::StartServerParams>(),
+    "js.stop_server" => schema::<js::StopServerParams>(),
+    "js.call" => schema::<js::CallServerParams>(),
+    "js.get_storage" | "js.clear_storage" => schema::<js::StorageParams>(),
+    "mcp.register_tools" => schema::<mcp::RegisterToolsParams>(),
+    "oauth.start_flow" => schema::<oauth::StartFlowParams>(),
+    "oauth.get_tokens" | "oauth.status" | "oauth.revoke" => schema::<oauth::ServerIdParams>(),
+    "oauth.set_credentials" => schema::<oauth::SetCredentialsParams>(),
+    "oauth.remove_credentials" => schema::<oauth::ProviderParams>(),
+    "mcp.unregister_tools" => schema::<mcp::UnregisterToolsParams>(),
+    "mcp.call_tool" => schema::<mcp::CallToolParams>(),
+    "mcp.submit_call_result" => schema::<mcp::SubmitResultParams>(),
+    "sessions.create" => schema::<sessions::CreateSessionParams>(),
+    "sessions.append" => schema::<sessions::AppendParams>(),
+    "sessions.list" => schema::<sessions::ListSessionsParams>(),
+    "sessions.get" | "sessions.delete" => schema::<sessions::SessionIdParams>(),
+    "sessions.search" => schema::<sessions::SearchParams>(),
+    _ => any_object(),
+  }
+}
+
+fn result_schema(method: &str) -> serde_json::Value {
+  match method {
+    "system.list_methods" => schema::<ListMethodsResponse>(),
+    "system.describe" => schema::<MethodInfo>(),
+
+    "llm.health" => schema::<llm::HealthResponse>(),
+    "llm.list_models" => schema::<llm::ListModelsResponse>(),
+    "llm.chat_stream" => schema::<llm::StreamEvent>(),
+    "llm.list_providers" => schema::<llm::ListProvidersResponse>(),
+    "llm.list_provider_types" => schema::<llm::ListProviderTypesResponse>(),
+    "llm.configure_provider" | "llm.add_provider" => schema::<llm::ProviderIdResponse>(),
+    "llm.set_default_provider" => schema::<llm::DefaultProviderResponse>(),
+    "llm.get_config" => schema::<llm::ConfigResponse>(),
+    "llm.set_default_model" => schema::<llm::DefaultModelResponse>(),
+    "llm.list_configured_models" => schema::<llm::ListConfiguredModelsResponse>(),
+    "llm.get_configured_models_metadata" => schema::<llm::ModelsMetadataResponse>(),
+    "llm.add_configured_model" => schema::<llm::ConfiguredModelResponse>(),
+    "llm.set_configured_model_default" => schema::<llm::DefaultConfiguredModelResponse>(),
+    "llm.set_configured_model_context_window" => schema::<llm::ContextWindowResponse>(),
+    "llm.set_model_capabilities" => schema::<llm::ModelCapabilitiesResponse>(),
+    "llm.ollama.delete" => schema::<llm::ollama::DeleteResponse>(),
+    "llm.ollama.pull" => schema::<llm::ollama::PullEvent>(),
+
+    "js.start_server" | "js.stop_server" => schema::<js::ServerStatusResponse>(),
+    "js.list_servers" => schema::<js::ListServersResponse>(),
+    "js.get_storage" => schema::<js::StorageResponse>(),
+    "js.clear_storage" => schema::<js::ClearStorageResponse>(),
+
+    "mcp.list_tools" => schema::<mcp::ListToolsResponse>(),
+    "mcp.call_tool" => schema::<mcp::CallToolResponse>(),
+    "mcp.poll_pending_calls" => schema::<mcp::PollPendingCallsResponse>(),
+
+    "sessions.create" | "sessions.get" => schema::<sessions::SessionResponse>(),
+    "sessions.append" => schema::<sessions::AppendResponse>(),
+    "sessions.list" => schema::<sessions::ListSessionsResponse>(),
+    "sessions.search" => schema::<sessions::SearchResponse>(),
+
+    "llm.remove_provider"
+    | "llm.set_type_default"
+    | "llm.remove_configured_model"
+    | "mcp.register_tools"
+    | "mcp.unregister_tools"
+    | "mcp.submit_call_result"
+    | "sessions.delete" => schema::<OkResponse>(),
+
+    _ => any_object(),
+  }
+}
+
+/// Describe a method, or `None` if the bridge doesn't serve it.
+pub fn describe_method(method: &str) -> Option<MethodInfo> {
+  let name = list_methods().into_iter().find(|m| *m == method)?;
+  Some(MethodInfo {
+    name,
+    description: description(name),
+    streaming: is_streaming_method(name),
+    version: version(name),
+    params: params_schema(name),
+    result: result_schema(name),
+  })
+}
+
+// =============================================================================
+// RPC Handlers
+// =============================================================================
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct DescribeParams {
+  pub method: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ListMethodsResponse {
+  pub bridge_version: &'static str,
+  pub methods: Vec<MethodInfo>,
+}
+
+/// `system.list_methods`: every method with its schemas.
+pub async fn rpc_list_methods(_params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+  let methods: Vec<MethodInfo> = list_methods()
+    .into_iter()
+    .filter_map(describe_method)
+    .collect();
+  to_result(ListMethodsResponse {
+    bridge_version: env!("CARGO_PKG_VERSION"),
+    methods,
+  })
+}
+
+/// `system.describe`: one method's schemas.
+pub async fn rpc_describe(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+  let params: DescribeParams = serde_json::from_value(params)
+    .map_err(|e| RpcError::invalid_params(format!("Invalid params: {}", e)))?;
+  let info = describe_method(&params.method).ok_or_else(|| RpcError::method_not_found(&params.method))?;
+  serde_json::to_value(info).map_err(|e| RpcError::internal(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_every_method_is_described() {
+    for method in list_methods() {
+      assert!(!description(method).is_empty(), "{} has no description", method);
+    }
+  }
+
+  #[test]
+  fn test_response_families_are_typed() {
+    // Everything in these families except pass-throughs of provider and
+    // server responses has a typed result
+    let untyped = ["llm.chat", "llm.check_provider", "llm.ollama.show", "llm.ollama.ps", "js.call"];
+    for method in list_methods() {
+      let family = method.split('.').next().unwrap();
+      if ["llm", "js", "mcp", "sessions"].contains(&family) && !untyped.contains(&method) {
+        assert_ne!(result_schema(method), any_object(), "{} has no result schema", method);
+      }
+    }
+  }
+
+  #[test]
+  fn test_params_are_typed() {
+    // Every method that takes params has a schema for them
+    let no_params = [
+      "system.health",
+      "system.list_methods",
+      "llm.health",
+      "llm.list_models",
+      "llm.list_providers",
+      "llm.list_provider_types",
+      "llm.get_config",
+      "llm.list_configured_models",
+      "llm.get_configured_models_metadata",
+      "js.list_servers",
+      "mcp.list_tools",
+      "mcp.poll_pending_calls",
+      "oauth.list_providers",
+      "oauth.get_credentials_status",
+    ];
+    for method in list_methods() {
+      if !no_params.contains(&method) {
+        assert_ne!(params_schema(method), any_object(), "{} has no params schema", method);
+      }
+    }
+
+    let configure = describe_method("llm.configure_provider").unwrap();
+    for field in ["id", "provider", "base_url", "headers", "timeout_secs"] {
+      assert!(configure.params["properties"].get(field).is_some(), "configure_provider lacks {}", field);
+    }
+  }
+
+  #[test]
+  fn test_describe_method() {
+    let chat = describe_method("llm.chat").unwrap();
+    assert!(!chat.streaming);
+    assert_eq!(chat.params["required"], serde_json::json!(["messages"]));
+
+    let stream = describe_method("llm.chat_stream").unwrap();
+    assert!(stream.streaming);
+    assert!(stream.result["properties"].get("type").is_some());
+
+    assert_eq!(stream.version, 2);
+    assert_eq!(chat.version, 1);
+
+    let call = describe_method("mcp.call_tool").unwrap();
+    assert!(call.params["properties"].get("serverId").is_some());
+    assert_eq!(call.result["required"], serde_json::json!(["result"]));
+
+    let search = describe_method("sessions.search").unwrap();
+    assert!(search.result["properties"].get("results").is_some());
+
+    assert!(describe_method("unknown.method").is_none());
+  }
+}
@@ -8,10 +8,17 @@ use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::OnceLock;
+use std::time::Instant;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::{fs, js, llm, mcp, oauth};
+pub mod audit;
+pub mod introspection;
+pub mod policy;
+pub mod rate_limit;
+
+use crate::{fs, js, llm, mcp, oauth, sessions};
 
 // =============================================================================
 // Types
@@ -36,6 +43,9 @@ pub struct RpcResponse {
 pub struct RpcError {
   pub code: i64,
   pub message: String,
+  /// Structured details for clients (e.g. `retry_after_ms`)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub data: Option<serde_json::Value>,
 }
 
 impl RpcError {
@@ -43,27 +53,43 @@ impl RpcError {
     RpcError {
       code,
       message: message.into(),
+      data: None,
     }
   }
 
+  /// Attach structured details to the error.
+  pub fn with_data(mut self, data: serde_json::Value) -> Self {
+    self.data = Some(data);
+    self
+  }
+
   /// Standard JSON-RPC error: Method not found
   pub fn method_not_found(method: &str) -> Self {
     RpcError::new(-32601, format!("Unknown method: {}", method))
   }
 
   /// Standard JSON-RPC error: Invalid params
-  #[allow(dead_code)]
   pub fn invalid_params(message: impl Into<String>) -> Self {
     RpcError::new(-32602, message)
   }
 
   /// Standard JSON-RPC error: Internal error
-  #[allow(dead_code)]
   pub fn internal(message: impl Into<String>) -> Self {
     RpcError::new(-32603, message)
   }
 }
 
+/// Result of a call that only reports success.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct OkResponse {
+  pub ok: bool,
+}
+
+/// Serialize a handler's typed response into its JSON result.
+pub fn to_result<T: Serialize>(response: T) -> Result<serde_json::Value, RpcError> {
+  serde_json::to_value(response).map_err(|e| RpcError::internal(e.to_string()))
+}
+
 impl RpcResponse {
   pub fn success(id: serde_json::Value, result: serde_json::Value) -> Self {
     RpcResponse {
@@ -104,6 +130,9 @@ fn get_handlers() -> &'static HashMap<&'static str, RpcHandler> {
     handlers.insert("system.health", |_| {
       Box::pin(async { Ok(serde_json::json!({ "status": "ok" })) })
     });
+    handlers.insert("system.query_audit_log", |p| Box::pin(audit::rpc_query(p)));
+    handlers.insert("system.list_methods", |p| Box::pin(introspection::rpc_list_methods(p)));
+    handlers.insert("system.describe", |p| Box::pin(introspection::rpc_describe(p)));
 
     // LLM handlers
     register_llm_handlers(&mut handlers);
@@ -120,6 +149,9 @@ fn get_handlers() -> &'static HashMap<&'static str, RpcHandler> {
     // MCP tool registry handlers
     register_mcp_handlers(&mut handlers);
 
+    // Chat session persistence handlers
+    register_session_handlers(&mut handlers);
+
     handlers
   })
 }
@@ -149,6 +181,13 @@ fn register_llm_handlers(handlers: &mut HashMap<&'static str, RpcHandler>) {
   handlers.insert("llm.set_configured_model_default", |p| {
     Box::pin(llm::set_configured_model_default(p))
   });
+  handlers.insert("llm.set_configured_model_context_window", |p| {
+    Box::pin(llm::set_configured_model_context_window(p))
+  });
+  handlers.insert("llm.set_model_capabilities", |p| Box::pin(llm::set_model_capabilities(p)));
+  handlers.insert("llm.ollama.show", |p| Box::pin(llm::ollama::rpc_show(p)));
+  handlers.insert("llm.ollama.delete", |p| Box::pin(llm::ollama::rpc_delete(p)));
+  handlers.insert("llm.ollama.ps", |p| Box::pin(llm::ollama::rpc_ps(p)));
 }
 
 fn register_fs_handlers(handlers: &mut HashMap<&'static str, RpcHandler>) {
@@ -162,6 +201,8 @@ fn register_js_handlers(handlers: &mut HashMap<&'static str, RpcHandler>) {
   handlers.insert("js.stop_server", |p| Box::pin(js::stop_server(p)));
   handlers.insert("js.call", |p| Box::pin(js::call_server(p)));
   handlers.insert("js.list_servers", |_| Box::pin(js::list_servers()));
+  handlers.insert("js.get_storage", |p| Box::pin(js::get_storage(p)));
+  handlers.insert("js.clear_storage", |p| Box::pin(js::clear_storage(p)));
 }
 
 fn register_oauth_handlers(handlers: &mut HashMap<&'static str, RpcHandler>) {
@@ -186,37 +227,112 @@ fn register_mcp_handlers(handlers: &mut HashMap<&'static str, RpcHandler>) {
   handlers.insert("mcp.submit_call_result", |p| Box::pin(mcp::submit_call_result(p)));
 }
 
+fn register_session_handlers(handlers: &mut HashMap<&'static str, RpcHandler>) {
+  handlers.insert("sessions.create", |p| Box::pin(sessions::create(p)));
+  handlers.insert("sessions.append", |p| Box::pin(sessions::append(p)));
+  handlers.insert("sessions.list", |p| Box::pin(sessions::list(p)));
+  handlers.insert("sessions.get", |p| Box::pin(sessions::get(p)));
+  handlers.insert("sessions.delete", |p| Box::pin(sessions::delete(p)));
+  handlers.insert("sessions.search", |p| Box::pin(sessions::search(p)));
+}
+
 // =============================================================================
 // Request Handling
 // =============================================================================
 
-/// Handle an RPC request and return a response.
-pub async fn handle(request: RpcRequest) -> RpcResponse {
-  let handlers = get_handlers();
-
-  match handlers.get(request.method.as_str()) {
-    Some(handler) => {
-      let result = handler(request.params).await;
-      match result {
-        Ok(value) => RpcResponse::success(request.id, value),
-        Err(error) => RpcResponse::error(request.id, error),
-      }
+/// Where a call came from.
+#[derive(Debug, Clone)]
+pub struct Source {
+  pub transport: audit::Transport,
+  /// `Origin` header of an HTTP or WebSocket request
+  pub origin: Option<String>,
+}
+
+impl Source {
+  pub fn new(transport: audit::Transport, origin: Option<String>) -> Self {
+    Self { transport, origin }
+  }
+}
+
+/// Handle an RPC request that arrived on a transport and return a response.
+pub async fn handle_from(source: &Source, request: RpcRequest) -> RpcResponse {
+  // Unknown methods go through `run` as well, so they are audited too
+  let handler = get_handlers().get(request.method.as_str()).copied();
+  let method = request.method.clone();
+  run(source, request, move |params| async move {
+    match handler {
+      Some(handler) => handler(params).await,
+      None => Err(RpcError::method_not_found(&method)),
     }
-    None => RpcResponse::error(request.id, RpcError::method_not_found(&request.method)),
+  })
+  .await
+}
+
+/// Checks every call passes before its handler runs: identifying the
+/// caller, the permission policy, then the rate limiter. Returns the caller.
+pub fn authorize(source: &Source, method: &str, params: &serde_json::Value) -> Result<policy::Caller, RpcError> {
+  let caller = policy::Caller::identify(source, params);
+  policy::check(&caller, method, params)?;
+  rate_limit::check(&caller, method)?;
+  Ok(caller)
+}
+
+tokio::task_local! {
+  static CALLER: policy::Caller;
+}
+
+/// Run a handler's future on behalf of `caller`, for `caller()` to report.
+pub async fn as_caller<F: Future>(caller: policy::Caller, future: F) -> F::Output {
+  CALLER.scope(caller, future).await
+}
+
+/// Caller of the call being handled. Work the bridge does on its own counts
+/// as the extension's.
+pub fn caller() -> policy::Caller {
+  CALLER.try_with(Clone::clone).unwrap_or(policy::Caller::Extension)
+}
+
+/// Run a call through authorization and the audit log.
+/// Used directly for methods a transport handles itself (e.g. `js.call`).
+pub async fn run<F, Fut>(source: &Source, request: RpcRequest, handler: F) -> RpcResponse
+where
+  F: FnOnce(serde_json::Value) -> Fut,
+  Fut: Future<Output = Result<serde_json::Value, RpcError>>,
+{
+  let started = Instant::now();
+  let entry = audit::AuditEntry::start(source, &request.method, &request.params);
+
+  let result = match authorize(source, &request.method, &request.params) {
+    Ok(caller) => as_caller(caller, handler(request.params)).await,
+    Err(error) => Err(error),
+  };
+
+  let error_code = result.as_ref().err().map(|e| e.code);
+  audit::record(entry.finish(started.elapsed(), error_code));
+
+  match result {
+    Ok(value) => RpcResponse::success(request.id, value),
+    Err(error) => RpcResponse::error(request.id, error),
   }
 }
 
+/// Methods the transports handle themselves because they send multiple
+/// messages; they have no entry in the handler registry.
+pub const STREAMING_METHODS: &[&str] = &["llm.chat_stream", "llm.ollama.pull"];
+
 /// Check if a method is a streaming method.
 /// Streaming methods are handled differently (they send multiple messages).
 pub fn is_streaming_method(method: &str) -> bool {
-  matches!(method, "llm.chat_stream")
+  STREAMING_METHODS.contains(&method)
 }
 
-/// List all registered RPC methods.
-/// Useful for debugging and introspection.
-#[allow(dead_code)]
+/// List all RPC methods, including streaming ones, sorted by name.
 pub fn list_methods() -> Vec<&'static str> {
-  let mut methods: Vec<&'static str> = get_handlers().keys().copied().collect();
+  let mut methods: Vec<&'static str> = get_handlers()
+    .keys()
+    .chain(STREAMING_METHODS)
+    .copied()
+    .collect();
   methods.sort();
   methods
 }
@@ -237,7 +353,7 @@ mod tests {
       params: serde_json::json!({}),
     };
 
-    let response = handle(request).await;
+    let response = handle_from(&Source::new(audit::Transport::Internal, None), request).await;
     assert!(response.error.is_none());
     assert!(response.result.is_some());
   }
@@ -250,9 +366,34 @@ mod tests {
       params: serde_json::json!({}),
     };
 
-    let response = handle(request).await;
+    let response = handle_from(&Source::new(audit::Transport::Internal, None), request).await;
     assert!(response.error.is_some());
     assert_eq!(response.error.unwrap().code, -32601);
+
+    // The refused call is still audited
+    let entries = audit::query(&audit::AuditQuery {
+      method: Some("unknown.method".to_string()),
+      ..Default::default()
+    });
+    assert!(entries.iter().any(|entry| entry.error_code == Some(-32601)));
+  }
+
+  #[tokio::test]
+  async fn test_http_call_without_origin_is_local() {
+    let request = |params| RpcRequest {
+      id: serde_json::json!(1),
+      method: "system.health".to_string(),
+      params,
+    };
+
+    // A claimed origin or server in the params doesn't count over HTTP: the
+    // call is a local client's, allowed unless a policy leaves it out
+    let claimed = serde_json::json!({ "origin": "https://example.com", "context": { "server_id": "files" } });
+    let expected = policy::check(&policy::Caller::Local, "system.health", &claimed).err().map(|e| e.code);
+    for transport in [audit::Transport::Http, audit::Transport::WebSocket] {
+      let response = handle_from(&Source::new(transport, None), request(claimed.clone())).await;
+      assert_eq!(response.error.map(|e| e.code), expected);
+    }
   }
 
   #[test]
@@ -261,5 +402,6 @@ mod tests {
     assert!(methods.contains(&"system.health"));
     assert!(methods.contains(&"llm.chat"));
     assert!(methods.contains(&"fs.read"));
+    assert!(methods.contains(&"llm.chat_stream"));
   }
 }
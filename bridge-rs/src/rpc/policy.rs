@@ -0,0 +1,304 @@
+//! Bridge-side permission policy.
+//!
+//! Each request is attributed to a caller. Over native messaging the
+//! extension names it: a JS server (`params.context.server_id`), a web origin
+//! (`params.origin` or `params.context.origin`), or otherwise the extension
+//! itself. HTTP and WebSocket requests are attributed to the origin in their
+//! `Origin` header, whatever the params say, or to a local client (curl,
+//! scripts) when they have none. The policy in `~/.harbor/policy.json` maps
+//! callers to
+//! the methods they may call and, optionally, the tools and models they may
+//! use:
+//!
+//! ```json
+//! {
+//!   "extension": { "methods": ["*"] },
+//!   "origins": {
+//!     "https://example.com": { "methods": ["llm.chat*", "mcp.call_tool"], "tools": ["weather/*"], "models": ["ollama:*"] }
+//!   },
+//!   "servers": { "*": { "methods": ["llm.chat"] } },
+//!   "local": { "methods": ["llm.*"] }
+//! }
+//! ```
+//!
+//! Patterns use `*` as a wildcard. Origin and server keys are patterns too;
+//! the longest matching key wins. Tools are matched as `<server>/<tool>` or
+//! by bare tool name. Without a policy file every call is allowed; with one,
+//! the extension may call anything unless `extension` is set, and origins,
+//! servers and local clients with no matching entry may call nothing.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use super::audit::Transport;
+use super::{rate_limit, RpcError, Source};
+
+/// Error code returned for calls the policy does not allow.
+pub const PERMISSION_DENIED: i64 = -32003;
+
+/// Who made a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Caller {
+  Extension,
+  Origin(String),
+  Server(String),
+  /// An HTTP or WebSocket client that sent no `Origin` header
+  Local,
+}
+
+impl Caller {
+  /// Identify the caller of a request. Only native messaging (and the bridge
+  /// itself) may name a caller in the params.
+  pub fn identify(source: &Source, params: &serde_json::Value) -> Self {
+    match source.transport {
+      Transport::NativeMessaging | Transport::Internal => Self::from_params(params),
+      Transport::Http | Transport::WebSocket => match source.origin.as_deref().filter(|o| !o.is_empty()) {
+        Some(origin) => Caller::Origin(origin.to_string()),
+        None => Caller::Local,
+      },
+    }
+  }
+
+  fn from_params(params: &serde_json::Value) -> Self {
+    if let Some(server_id) = params.pointer("/context/server_id").and_then(|s| s.as_str()) {
+      return Caller::Server(server_id.to_string());
+    }
+    match rate_limit::origin(params) {
+      Some(origin) => Caller::Origin(origin.to_string()),
+      None => Caller::Extension,
+    }
+  }
+}
+
+impl fmt::Display for Caller {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Caller::Extension => write!(f, "the extension"),
+      Caller::Origin(origin) => write!(f, "origin {}", origin),
+      Caller::Server(server_id) => write!(f, "server {}", server_id),
+      Caller::Local => write!(f, "a local client"),
+    }
+  }
+}
+
+/// What one caller may do. `None` for tools or models means any.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Rule {
+  #[serde(default)]
+  pub methods: Vec<String>,
+  #[serde(default)]
+  pub tools: Option<Vec<String>>,
+  #[serde(default)]
+  pub models: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Policy {
+  #[serde(default)]
+  pub extension: Option<Rule>,
+  #[serde(default)]
+  pub origins: HashMap<String, Rule>,
+  #[serde(default)]
+  pub servers: HashMap<String, Rule>,
+  /// HTTP and WebSocket clients without an `Origin` header
+  #[serde(default)]
+  pub local: Option<Rule>,
+}
+
+/// Match `value` against a pattern where `*` matches any run of characters.
+pub fn glob_match(pattern: &str, value: &str) -> bool {
+  let mut parts = pattern.split('*');
+  let first = parts.next().unwrap_or("");
+  let mut rest = match value.strip_prefix(first) {
+    Some(rest) => rest,
+    None => return false,
+  };
+  let parts: Vec<&str> = parts.collect();
+  let last = match parts.split_last() {
+    Some((last, middle)) => {
+      for part in middle {
+        match rest.find(part) {
+          Some(i) => rest = &rest[i + part.len()..],
+          None => return false,
+        }
+      }
+      last
+    }
+    // No wildcard: the prefix must be the whole value
+    None => return rest.is_empty(),
+  };
+  rest.ends_with(last)
+}
+
+fn any_match(patterns: &[String], value: &str) -> bool {
+  patterns.iter().any(|p| glob_match(p, value))
+}
+
+/// Rule for the most specific (longest) key matching `name`.
+fn best_rule<'a>(rules: &'a HashMap<String, Rule>, name: &str) -> Option<&'a Rule> {
+  rules
+    .iter()
+    .filter(|(pattern, _)| glob_match(pattern, name))
+    .max_by_key(|(pattern, _)| pattern.len())
+    .map(|(_, rule)| rule)
+}
+
+fn denied(message: String) -> RpcError {
+  RpcError::new(PERMISSION_DENIED, format!("Permission denied: {}", message))
+}
+
+impl Policy {
+  fn rule_for(&self, caller: &Caller) -> Option<&Rule> {
+    match caller {
+      Caller::Extension => self.extension.as_ref(),
+      Caller::Origin(origin) => best_rule(&self.origins, origin),
+      Caller::Server(server_id) => best_rule(&self.servers, server_id),
+      Caller::Local => self.local.as_ref(),
+    }
+  }
+
+  /// Check a call against the policy.
+  pub fn check(&self, caller: &Caller, method: &str, params: &serde_json::Value) -> Result<(), RpcError> {
+    let rule = match (self.rule_for(caller), caller) {
+      (Some(rule), _) => rule,
+      (None, Caller::Extension) => return Ok(()),
+      (None, _) => return Err(denied(format!("{} has no policy entry", caller))),
+    };
+
+    if !any_match(&rule.methods, method) {
+      return Err(denied(format!("{} may not call {}", caller, method)));
+    }
+
+    if let Some(tools) = &rule.tools {
+      let server_id = params.get("serverId").and_then(|v| v.as_str());
+      if let Some(tool) = params.get("toolName").and_then(|v| v.as_str()) {
+        let qualified = server_id.map(|s| format!("{}/{}", s, tool));
+        let allowed = any_match(tools, tool) || qualified.as_deref().is_some_and(|q| any_match(tools, q));
+        if !allowed {
+          return Err(denied(format!("{} may not use tool {}", caller, qualified.as_deref().unwrap_or(tool))));
+        }
+      }
+    }
+
+    if let Some(models) = &rule.models {
+      if method.starts_with("llm.chat") {
+        match params.get("model").and_then(|v| v.as_str()) {
+          Some(model) if any_match(models, model) => {}
+          Some(model) => return Err(denied(format!("{} may not use model {}", caller, model))),
+          None => return Err(denied(format!("{} must name an allowed model", caller))),
+        }
+      }
+    }
+
+    Ok(())
+  }
+}
+
+fn policy_path() -> PathBuf {
+  dirs::home_dir()
+    .unwrap_or_else(|| PathBuf::from("."))
+    .join(".harbor")
+    .join("policy.json")
+}
+
+/// Policy loaded at startup; `None` when there is no policy file.
+/// An unreadable policy file denies every call rather than allowing all.
+fn policy() -> &'static Option<Policy> {
+  static POLICY: OnceLock<Option<Policy>> = OnceLock::new();
+  POLICY.get_or_init(|| {
+    let path = policy_path();
+    let content = std::fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&content) {
+      Ok(policy) => {
+        tracing::info!("Loaded policy from {}", path.display());
+        Some(policy)
+      }
+      Err(e) => {
+        tracing::error!("Invalid policy file {}, denying all calls: {}", path.display(), e);
+        Some(Policy {
+          extension: Some(Rule::default()),
+          ..Default::default()
+        })
+      }
+    }
+  })
+}
+
+/// Check whether a caller may make a request.
+pub fn check(caller: &Caller, method: &str, params: &serde_json::Value) -> Result<(), RpcError> {
+  match policy() {
+    Some(policy) => policy.check(caller, method, params),
+    None => Ok(()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_glob_match() {
+    assert!(glob_match("*", "llm.chat"));
+    assert!(glob_match("llm.*", "llm.chat_stream"));
+    assert!(glob_match("https://*.example.com", "https://app.example.com"));
+    assert!(glob_match("*/search", "web/search"));
+    assert!(!glob_match("llm.chat", "llm.chat_stream"));
+    assert!(!glob_match("llm.*", "fs.read"));
+  }
+
+  #[test]
+  fn test_policy_check() {
+    let policy: Policy = serde_json::from_value(serde_json::json!({
+      "origins": {
+        "https://example.com": {
+          "methods": ["llm.chat*", "mcp.call_tool"],
+          "tools": ["weather/*"],
+          "models": ["ollama:*"]
+        }
+      }
+    }))
+    .unwrap();
+
+    let origin = Caller::Origin("https://example.com".to_string());
+    let chat = serde_json::json!({ "model": "ollama:llama3.2" });
+    assert!(policy.check(&origin, "llm.chat", &chat).is_ok());
+
+    let err = policy.check(&origin, "llm.chat", &serde_json::json!({ "model": "openai:gpt-4o" })).unwrap_err();
+    assert_eq!(err.code, PERMISSION_DENIED);
+    assert!(policy.check(&origin, "fs.read", &serde_json::json!({})).is_err());
+
+    let tool = serde_json::json!({ "serverId": "weather", "toolName": "forecast" });
+    assert!(policy.check(&origin, "mcp.call_tool", &tool).is_ok());
+    let tool = serde_json::json!({ "serverId": "files", "toolName": "delete" });
+    assert!(policy.check(&origin, "mcp.call_tool", &tool).is_err());
+
+    // Unknown origins and local clients get nothing; the extension keeps
+    // full access
+    let other = Caller::Origin("https://other.com".to_string());
+    assert!(policy.check(&other, "llm.chat", &chat).is_err());
+    assert!(policy.check(&Caller::Local, "llm.chat", &chat).is_err());
+    assert!(policy.check(&Caller::Extension, "fs.read", &serde_json::json!({})).is_ok());
+  }
+
+  #[test]
+  fn test_identify_caller() {
+    let params = serde_json::json!({ "origin": "https://claimed.com", "context": { "server_id": "files" } });
+
+    // The extension may say who it is calling for
+    let native = Source::new(Transport::NativeMessaging, None);
+    assert_eq!(Caller::identify(&native, &params), Caller::Server("files".to_string()));
+    assert_eq!(Caller::identify(&native, &serde_json::json!({})), Caller::Extension);
+
+    // HTTP and WebSocket callers are their Origin header, or a local client
+    // without one, never the extension
+    let http = Source::new(Transport::Http, Some("https://page.com".to_string()));
+    assert_eq!(Caller::identify(&http, &params), Caller::Origin("https://page.com".to_string()));
+    for transport in [Transport::Http, Transport::WebSocket] {
+      assert_eq!(Caller::identify(&Source::new(transport, None), &params), Caller::Local);
+    }
+  }
+}
@@ -0,0 +1,293 @@
+//! Per-caller rate limiting for RPC calls.
+//!
+//! Each caller gets a token bucket per method family (the part of the method
+//! name before the first dot, e.g. `llm` or `mcp`). Callers are identified as
+//! for the permission policy. Web origins, JS servers and local clients are
+//! limited; the extension itself (native messaging calls that name neither)
+//! is not, since its own calls would otherwise throttle it.
+//!
+//! Limits are read from `rate_limits` in the harbor config (`llm.json`) when
+//! the bridge starts:
+//!
+//! ```json
+//! { "rate_limits": { "enabled": true, "families": { "llm": { "burst": 10, "per_minute": 30 } } } }
+//! ```
+//!
+//! Families in the config replace the defaults for that family; families
+//! with no limit (configured or default) are not limited.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::policy::Caller;
+use super::RpcError;
+
+/// Error code returned when a call is rate limited.
+pub const RATE_LIMITED: i64 = -32029;
+
+/// How often buckets that have refilled are dropped.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Bucket size and refill rate for one method family.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Limit {
+  /// Calls allowed in a burst
+  pub burst: u32,
+  /// Sustained calls per minute
+  pub per_minute: u32,
+}
+
+/// `rate_limits` section of the harbor config.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitSettings {
+  #[serde(default = "default_true")]
+  pub enabled: bool,
+  #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+  pub families: HashMap<String, Limit>,
+}
+
+fn default_true() -> bool {
+  true
+}
+
+/// Effective limits.
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+  pub enabled: bool,
+  pub families: HashMap<String, Limit>,
+}
+
+impl Default for RateLimitConfig {
+  fn default() -> Self {
+    let families = [
+      ("llm", Limit { burst: 10, per_minute: 30 }),
+      ("mcp", Limit { burst: 20, per_minute: 120 }),
+      ("js", Limit { burst: 20, per_minute: 120 }),
+    ];
+    Self {
+      enabled: true,
+      families: families.iter().map(|(f, l)| (f.to_string(), *l)).collect(),
+    }
+  }
+}
+
+impl RateLimitConfig {
+  /// The defaults with any configured settings applied.
+  pub fn from_settings(settings: Option<&RateLimitSettings>) -> Self {
+    let mut config = Self::default();
+    if let Some(settings) = settings {
+      config.enabled = settings.enabled;
+      config.families.extend(settings.families.clone());
+    }
+    config
+  }
+}
+
+/// Token bucket for one (origin, family) pair.
+#[derive(Debug)]
+struct TokenBucket {
+  tokens: f64,
+  last_refill: Instant,
+}
+
+impl TokenBucket {
+  fn new(limit: &Limit, now: Instant) -> Self {
+    Self {
+      tokens: limit.burst as f64,
+      last_refill: now,
+    }
+  }
+
+  /// Take a token, or return how long until one is available.
+  fn take(&mut self, limit: &Limit, now: Instant) -> Result<(), Duration> {
+    let per_sec = limit.per_minute as f64 / 60.0;
+    let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+    self.tokens = (self.tokens + elapsed * per_sec).min(limit.burst as f64);
+    self.last_refill = now;
+
+    if self.tokens >= 1.0 {
+      self.tokens -= 1.0;
+      return Ok(());
+    }
+    if per_sec <= 0.0 {
+      return Err(Duration::from_secs(60));
+    }
+    Err(Duration::from_secs_f64((1.0 - self.tokens) / per_sec))
+  }
+
+  /// Whether the bucket has refilled, so a new one would be the same.
+  fn is_full(&self, limit: &Limit, now: Instant) -> bool {
+    let per_sec = limit.per_minute as f64 / 60.0;
+    let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+    self.tokens + elapsed * per_sec >= limit.burst as f64
+  }
+}
+
+struct Limiter {
+  config: RateLimitConfig,
+  buckets: HashMap<(String, String), TokenBucket>,
+  last_prune: Instant,
+}
+
+impl Limiter {
+  fn new(config: RateLimitConfig) -> Self {
+    Self {
+      config,
+      buckets: HashMap::new(),
+      last_prune: Instant::now(),
+    }
+  }
+
+  /// Take a token from the caller's bucket for a method's family.
+  fn take(&mut self, caller: &Caller, method: &str, now: Instant) -> Result<(), RpcError> {
+    if !self.config.enabled || *caller == Caller::Extension {
+      return Ok(());
+    }
+    if now.saturating_duration_since(self.last_prune) >= PRUNE_INTERVAL {
+      self.prune(now);
+    }
+    let family = family(method);
+    let limit = match self.config.families.get(family) {
+      Some(limit) => *limit,
+      None => return Ok(()),
+    };
+
+    self
+      .buckets
+      .entry((caller.to_string(), family.to_string()))
+      .or_insert_with(|| TokenBucket::new(&limit, now))
+      .take(&limit, now)
+      .map_err(|retry_after| rate_limited(caller, family, retry_after))
+  }
+
+  /// Drop buckets that have refilled, so callers that went away (and every
+  /// origin ever seen) don't stay in the map.
+  fn prune(&mut self, now: Instant) {
+    let families = &self.config.families;
+    self.buckets.retain(|(_, family), bucket| {
+      families
+        .get(family)
+        .is_some_and(|limit| !bucket.is_full(limit, now))
+    });
+    self.last_prune = now;
+  }
+}
+
+fn limiter() -> &'static Mutex<Limiter> {
+  static LIMITER: OnceLock<Mutex<Limiter>> = OnceLock::new();
+  LIMITER.get_or_init(|| {
+    let config = crate::llm::get_config();
+    let settings = config.as_ref().and_then(|config| config.rate_limits.as_ref());
+    Mutex::new(Limiter::new(RateLimitConfig::from_settings(settings)))
+  })
+}
+
+/// Calling origin from `params.origin` or `params.context.origin`.
+pub fn origin(params: &serde_json::Value) -> Option<&str> {
+  params
+    .get("origin")
+    .or_else(|| params.pointer("/context/origin"))
+    .and_then(|o| o.as_str())
+    .filter(|o| !o.is_empty())
+}
+
+/// Method family used for limiting (`llm.chat` → `llm`).
+pub fn family(method: &str) -> &str {
+  method.split('.').next().unwrap_or(method)
+}
+
+fn rate_limited(caller: &Caller, family: &str, retry_after: Duration) -> RpcError {
+  // Round up so clients that wait the hinted time get a token
+  let retry_after_ms = retry_after.as_millis().max(1) as u64;
+  let secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+  RpcError::new(
+    RATE_LIMITED,
+    format!(
+      "Rate limited: too many {} requests from {}; retry after {}s",
+      family, caller, secs
+    ),
+  )
+  .with_data(serde_json::json!({ "retry_after_ms": retry_after_ms }))
+}
+
+/// Take a token for a call, or fail with a `RATE_LIMITED` error.
+pub fn check(caller: &Caller, method: &str) -> Result<(), RpcError> {
+  limiter().lock().unwrap().take(caller, method, Instant::now())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_token_bucket() {
+    let limit = Limit { burst: 2, per_minute: 60 };
+    let start = Instant::now();
+    let mut bucket = TokenBucket::new(&limit, start);
+
+    assert!(bucket.take(&limit, start).is_ok());
+    assert!(bucket.take(&limit, start).is_ok());
+    let retry_after = bucket.take(&limit, start).unwrap_err();
+    assert!(retry_after <= Duration::from_secs(1));
+
+    // One token per second refills
+    assert!(bucket.take(&limit, start + Duration::from_secs(1)).is_ok());
+    assert!(bucket.take(&limit, start + Duration::from_secs(1)).is_err());
+  }
+
+  #[test]
+  fn test_origin_and_family() {
+    let params = serde_json::json!({ "context": { "origin": "https://example.com" } });
+    assert_eq!(origin(&params), Some("https://example.com"));
+    assert_eq!(origin(&serde_json::json!({})), None);
+    assert_eq!(family("llm.chat_stream"), "llm");
+
+    let err = rate_limited(&Caller::Origin("https://example.com".to_string()), "llm", Duration::from_millis(1500));
+    assert_eq!(err.code, RATE_LIMITED);
+    assert!(err.message.ends_with("retry after 2s"));
+    assert_eq!(err.data, Some(serde_json::json!({ "retry_after_ms": 1500 })));
+  }
+
+  #[test]
+  fn test_limiter_keys_on_caller() {
+    let mut limiter = Limiter::new(RateLimitConfig {
+      enabled: true,
+      families: HashMap::from([("llm".to_string(), Limit { burst: 1, per_minute: 60 })]),
+    });
+    let now = Instant::now();
+
+    // The extension isn't limited
+    for _ in 0..5 {
+      assert!(limiter.take(&Caller::Extension, "llm.chat", now).is_ok());
+    }
+
+    // Origins and servers have their own buckets
+    let origin = Caller::Origin("https://example.com".to_string());
+    assert!(limiter.take(&origin, "llm.chat", now).is_ok());
+    let err = limiter.take(&origin, "llm.chat", now).unwrap_err();
+    assert_eq!(err.code, RATE_LIMITED);
+    assert!(err.data.unwrap()["retry_after_ms"].as_u64().unwrap() <= 1000);
+    assert!(limiter.take(&Caller::Server("files".to_string()), "llm.chat", now).is_ok());
+    assert!(limiter.take(&origin, "fs.read", now).is_ok());
+    assert_eq!(limiter.buckets.len(), 2);
+
+    // Buckets that have refilled are dropped
+    let later = now + PRUNE_INTERVAL;
+    let other = Caller::Origin("https://other.example".to_string());
+    assert!(limiter.take(&other, "llm.chat", later).is_ok());
+    assert_eq!(limiter.buckets.len(), 1);
+  }
+
+  #[test]
+  fn test_settings() {
+    let settings: RateLimitSettings =
+      serde_json::from_value(serde_json::json!({ "families": { "llm": { "burst": 5, "per_minute": 10 } } })).unwrap();
+    let config = RateLimitConfig::from_settings(Some(&settings));
+    assert!(config.enabled);
+    assert_eq!(config.families["llm"], Limit { burst: 5, per_minute: 10 });
+    assert_eq!(config.families["mcp"], RateLimitConfig::default().families["mcp"]);
+  }
+}
@@ -0,0 +1,349 @@
+//! Conversation persistence for the bridge.
+//!
+//! Stores chat sessions (messages, model, tool calls, timestamps, origin) so
+//! callers can pass a `session_id` to `llm.chat` instead of resending the full
+//! history on every turn. Each caller sees only the sessions it created; the
+//! extension sees all of them.
+
+mod store;
+
+pub use store::{Session, SessionMessage, SessionStore, SessionSummary, StoreError};
+
+use std::sync::{Mutex, OnceLock};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::rpc::policy::Caller;
+use crate::rpc::{self, to_result, OkResponse, RpcError};
+
+/// Global session store. File access is serialized through the mutex.
+fn session_store() -> &'static Mutex<SessionStore> {
+    static STORE: OnceLock<Mutex<SessionStore>> = OnceLock::new();
+    STORE.get_or_init(|| Mutex::new(SessionStore::new(SessionStore::default_dir())))
+}
+
+/// Run `f` on the session store. The store reads and writes session files
+/// under its lock, so this happens on the blocking pool.
+async fn with_store<T, F>(f: F) -> Result<T, RpcError>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut SessionStore) -> Result<T, RpcError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || f(&mut session_store().lock().unwrap()))
+        .await
+        .map_err(|e| RpcError::internal(e.to_string()))?
+}
+
+/// Whether `caller` may use a session created by `owner`. Sessions stored
+/// before owners were recorded are left to the extension.
+fn visible_to(owner: Option<&str>, caller: &Caller) -> bool {
+    *caller == Caller::Extension || owner == Some(caller.to_string().as_str())
+}
+
+/// Check `caller` may use a session. Other callers' sessions are reported
+/// as not found, so their ids can't be probed.
+fn check_access(store: &mut SessionStore, id: &str, caller: &Caller) -> Result<(), StoreError> {
+    let summary = store.summary(id)?;
+    if !visible_to(summary.owner.as_deref(), caller) {
+        return Err(StoreError::NotFound(format!("Session '{}' not found", id)));
+    }
+    Ok(())
+}
+
+fn not_found(message: String) -> RpcError {
+    RpcError {
+        code: -32000,
+        message,
+        data: None,
+    }
+}
+
+/// A missing session is "not found"; anything else is an internal error.
+fn store_error(e: StoreError) -> RpcError {
+    match e {
+        StoreError::NotFound(message) => not_found(message),
+        StoreError::Failed(message) => RpcError::internal(message),
+    }
+}
+
+/// Load a session of the current caller for use by another module (e.g.
+/// `llm.chat`).
+pub async fn load(session_id: &str) -> Result<Session, RpcError> {
+    let (id, caller) = (session_id.to_string(), rpc::caller());
+    with_store(move |store| {
+        check_access(store, &id, &caller)
+            .and_then(|()| store.get(&id))
+            .map_err(store_error)
+    })
+    .await
+}
+
+/// Append a completed turn to a loaded session, recording the model that
+/// answered.
+pub async fn record_turn(session: &Session, messages: Vec<SessionMessage>, model: &str) -> Result<(), RpcError> {
+    let id = session.id.clone();
+    let model = Some(model.to_string()).filter(|model| session.model.as_ref() != Some(model));
+    with_store(move |store| {
+        store.append(&id, messages).map_err(store_error)?;
+        if model.is_some() {
+            store.update(&id, model, None).map_err(store_error)?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+// =============================================================================
+// RPC Handlers
+// =============================================================================
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct CreateSessionParams {
+    #[serde(default)]
+    pub origin: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AppendParams {
+    pub session_id: String,
+    pub messages: Vec<SessionMessage>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SessionIdParams {
+    pub session_id: String,
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct ListSessionsParams {
+    #[serde(default)]
+    pub origin: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SearchParams {
+    pub query: String,
+    #[serde(default)]
+    pub origin: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Result of `sessions.create` and `sessions.get`.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SessionResponse {
+    pub session: Session,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct AppendResponse {
+    pub ok: bool,
+    /// Number of messages appended
+    pub appended: usize,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ListSessionsResponse {
+    pub sessions: Vec<SessionSummary>,
+}
+
+/// A session title or message matching a search.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SearchResult {
+    pub session_id: String,
+    pub title: Option<String>,
+    /// Index of the matching message, or `null` for a title match
+    pub message_index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    pub snippet: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SearchResponse {
+    pub results: Vec<SearchResult>,
+}
+
+fn parse_params<T: serde::de::DeserializeOwned>(params: serde_json::Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError {
+        code: -32602,
+        message: format!("Invalid params: {}", e),
+        data: None,
+    })
+}
+
+/// Create a new session.
+pub async fn create(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+    let params: CreateSessionParams = if params.is_null() {
+        CreateSessionParams::default()
+    } else {
+        parse_params(params)?
+    };
+
+    let owner = rpc::caller().to_string();
+    let session = with_store(move |store| {
+        store
+            .create(Some(owner), params.origin, params.model, params.title, params.system_prompt)
+            .map_err(RpcError::internal)
+    })
+    .await?;
+
+    to_result(SessionResponse { session })
+}
+
+/// Append messages to a session.
+pub async fn append(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+    let params: AppendParams = parse_params(params)?;
+    let count = params.messages.len();
+    let caller = rpc::caller();
+
+    with_store(move |store| {
+        check_access(store, &params.session_id, &caller)
+            .and_then(|()| store.append(&params.session_id, params.messages))
+            .map_err(store_error)
+    })
+    .await?;
+
+    to_result(AppendResponse { ok: true, appended: count })
+}
+
+/// List sessions (summaries only), most recent first.
+pub async fn list(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+    let params: ListSessionsParams = if params.is_null() {
+        ListSessionsParams::default()
+    } else {
+        parse_params(params)?
+    };
+
+    let caller = rpc::caller();
+    let sessions = with_store(|store| Ok(store.list())).await?;
+    let summaries: Vec<SessionSummary> = sessions
+        .into_iter()
+        .filter(|s| visible_to(s.owner.as_deref(), &caller))
+        .filter(|s| params.origin.is_none() || s.origin == params.origin)
+        .take(params.limit.unwrap_or(usize::MAX))
+        .collect();
+
+    to_result(ListSessionsResponse { sessions: summaries })
+}
+
+/// Get a session with its full message history.
+pub async fn get(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+    let params: SessionIdParams = parse_params(params)?;
+    let session = load(&params.session_id).await?;
+    to_result(SessionResponse { session })
+}
+
+/// Delete a session.
+pub async fn delete(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+    let params: SessionIdParams = parse_params(params)?;
+    let caller = rpc::caller();
+
+    with_store(move |store| {
+        check_access(store, &params.session_id, &caller).map_err(store_error)?;
+        match store.delete(&params.session_id).map_err(RpcError::internal)? {
+            true => Ok(()),
+            false => Err(not_found(format!("Session '{}' not found", params.session_id))),
+        }
+    })
+    .await?;
+
+    to_result(OkResponse { ok: true })
+}
+
+/// Search session titles and message contents (case-insensitive substring match).
+pub async fn search(params: serde_json::Value) -> Result<serde_json::Value, RpcError> {
+    let params: SearchParams = parse_params(params)?;
+    let query = params.query.to_lowercase();
+    if query.trim().is_empty() {
+        return Err(RpcError::invalid_params("'query' cannot be empty"));
+    }
+
+    let caller = rpc::caller();
+    let limit = params.limit.unwrap_or(50);
+    let results = with_store(move |store| Ok(search_sessions(store, &query, params.origin, &caller, limit))).await?;
+
+    to_result(SearchResponse { results })
+}
+
+/// Search the sessions `caller` may see, most recent first. Only those
+/// sessions' files are read.
+fn search_sessions(
+    store: &mut SessionStore,
+    query: &str,
+    origin: Option<String>,
+    caller: &Caller,
+    limit: usize,
+) -> Vec<SearchResult> {
+    let mut results: Vec<SearchResult> = Vec::new();
+
+    let candidates = store
+        .list()
+        .into_iter()
+        .filter(|s| visible_to(s.owner.as_deref(), caller))
+        .filter(|s| origin.is_none() || s.origin == origin);
+    for summary in candidates {
+        let session = match store.get(&summary.id) {
+            Ok(session) => session,
+            Err(_) => continue,
+        };
+        if let Some(title) = session.title.as_ref().filter(|t| t.to_lowercase().contains(query)) {
+            results.push(SearchResult {
+                session_id: session.id.clone(),
+                title: session.title.clone(),
+                message_index: None,
+                role: None,
+                snippet: title.clone(),
+            });
+        }
+        for (index, message) in session.messages.iter().enumerate() {
+            if let Some(pos) = message.content.to_lowercase().find(query) {
+                results.push(SearchResult {
+                    session_id: session.id.clone(),
+                    title: session.title.clone(),
+                    message_index: Some(index),
+                    role: Some(message.role.clone()),
+                    snippet: snippet(&message.content, pos, query.len()),
+                });
+            }
+        }
+        if results.len() >= limit {
+            break;
+        }
+    }
+    results.truncate(limit);
+    results
+}
+
+/// Extract roughly 40 characters of context either side of a match.
+fn snippet(content: &str, byte_pos: usize, byte_len: usize) -> String {
+    const CONTEXT: usize = 40;
+    // Positions come from the lowercased text, which can differ in length
+    let byte_pos = byte_pos.min(content.len());
+    let mut start = byte_pos.saturating_sub(CONTEXT);
+    while !content.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (byte_pos + byte_len + CONTEXT).min(content.len());
+    while !content.is_char_boundary(end) {
+        end += 1;
+    }
+    let mut s = String::new();
+    if start > 0 {
+        s.push_str("...");
+    }
+    s.push_str(&content[start..end]);
+    if end < content.len() {
+        s.push_str("...");
+    }
+    s
+}
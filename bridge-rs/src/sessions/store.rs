@@ -0,0 +1,490 @@
+//! Append-only session storage.
+//!
+//! Each session is a JSON-lines file in `~/.harbor/sessions/<id>.jsonl`.
+//! The first record describes the session; every later record is either a
+//! message or a metadata update. Records are only ever appended, so a crash
+//! mid-write loses at most the last line. Listing works from an in-memory
+//! index of session metadata, built from the files on first use.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A single stored chat message.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SessionMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: String,
+    /// For tool responses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// Tool calls made by the assistant (OpenAI format)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<serde_json::Value>,
+    /// Model that produced this message (assistant messages)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// When the message was stored (Unix timestamp ms)
+    #[serde(default)]
+    pub timestamp: i64,
+}
+
+/// One line of a session file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SessionRecord {
+    Created {
+        id: String,
+        /// Caller that created the session
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        owner: Option<String>,
+        #[serde(default)]
+        origin: Option<String>,
+        #[serde(default)]
+        model: Option<String>,
+        #[serde(default)]
+        title: Option<String>,
+        #[serde(default)]
+        system_prompt: Option<String>,
+        created_at: i64,
+    },
+    Message(SessionMessage),
+    Updated {
+        #[serde(default)]
+        model: Option<String>,
+        #[serde(default)]
+        title: Option<String>,
+        updated_at: i64,
+    },
+}
+
+/// A fully loaded session.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Session {
+    pub id: String,
+    /// Caller that created the session; `None` for sessions stored before
+    /// owners were recorded
+    #[serde(skip)]
+    pub owner: Option<String>,
+    pub origin: Option<String>,
+    pub model: Option<String>,
+    pub title: Option<String>,
+    pub system_prompt: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub messages: Vec<SessionMessage>,
+}
+
+/// Session listing entry (without messages).
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SessionSummary {
+    pub id: String,
+    #[serde(skip)]
+    pub owner: Option<String>,
+    pub origin: Option<String>,
+    pub model: Option<String>,
+    pub title: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub message_count: usize,
+}
+
+impl Session {
+    pub fn summary(&self) -> SessionSummary {
+        SessionSummary {
+            id: self.id.clone(),
+            owner: self.owner.clone(),
+            origin: self.origin.clone(),
+            model: self.model.clone(),
+            title: self.title.clone(),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            message_count: self.messages.len(),
+        }
+    }
+}
+
+/// Why a session couldn't be read or written.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoreError {
+    /// No session has that id (or the id isn't a valid one)
+    NotFound(String),
+    /// Reading or writing the session file failed
+    Failed(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::NotFound(message) | StoreError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Session store - reads and appends session files in a directory.
+#[derive(Debug)]
+pub struct SessionStore {
+    dir: PathBuf,
+    /// Metadata of every session by id, loaded on first use
+    index: Option<HashMap<String, SessionSummary>>,
+}
+
+impl SessionStore {
+    /// Create a store rooted at a directory.
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir, index: None }
+    }
+
+    /// Default session directory (`~/.harbor/sessions`).
+    pub fn default_dir() -> PathBuf {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".harbor")
+            .join("sessions")
+    }
+
+    fn session_path(&self, id: &str) -> Result<PathBuf, String> {
+        // Session IDs become file names, so keep them to a safe alphabet
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(format!("Invalid session id: {}", id));
+        }
+        Ok(self.dir.join(format!("{}.jsonl", id)))
+    }
+
+    fn append_records(&self, id: &str, records: &[SessionRecord]) -> Result<(), String> {
+        let path = self.session_path(id)?;
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create sessions directory: {}", e))?;
+
+        let mut contents = String::new();
+        for record in records {
+            let line = serde_json::to_string(record)
+                .map_err(|e| format!("Failed to serialize session record: {}", e))?;
+            contents.push_str(&line);
+            contents.push('\n');
+        }
+
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        // Conversations are private; the file is owner-only from the start
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(&path)
+            .map_err(|e| format!("Failed to open session file: {}", e))?;
+        file.write_all(contents.as_bytes())
+            .map_err(|e| format!("Failed to write session file: {}", e))
+    }
+
+    /// The metadata index, built by reading every session file the first
+    /// time it is needed and kept up to date by the store's writes.
+    fn index(&mut self) -> &mut HashMap<String, SessionSummary> {
+        if self.index.is_none() {
+            let index = self.scan().into_iter().map(|s| (s.id.clone(), s)).collect();
+            self.index = Some(index);
+        }
+        self.index.get_or_insert_with(HashMap::new)
+    }
+
+    /// Summaries of the sessions in the directory.
+    fn scan(&self) -> Vec<SessionSummary> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(e) => e,
+            Err(_) => return Vec::new(),
+        };
+
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("jsonl") {
+                    return None;
+                }
+                let id = path.file_stem()?.to_str()?.to_string();
+                self.get(&id).ok().map(|s| s.summary())
+            })
+            .collect()
+    }
+
+    /// Create a new session on behalf of `owner`.
+    pub fn create(
+        &mut self,
+        owner: Option<String>,
+        origin: Option<String>,
+        model: Option<String>,
+        title: Option<String>,
+        system_prompt: Option<String>,
+    ) -> Result<Session, String> {
+        let id = generate_session_id();
+        let now = chrono::Utc::now().timestamp_millis();
+        self.append_records(
+            &id,
+            &[SessionRecord::Created {
+                id: id.clone(),
+                owner: owner.clone(),
+                origin: origin.clone(),
+                model: model.clone(),
+                title: title.clone(),
+                system_prompt: system_prompt.clone(),
+                created_at: now,
+            }],
+        )?;
+
+        let session = Session {
+            id,
+            owner,
+            origin,
+            model,
+            title,
+            system_prompt,
+            created_at: now,
+            updated_at: now,
+            messages: Vec::new(),
+        };
+        self.index().insert(session.id.clone(), session.summary());
+        Ok(session)
+    }
+
+    /// Summary of a session from the index.
+    pub fn summary(&mut self, id: &str) -> Result<SessionSummary, StoreError> {
+        self.index()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| StoreError::NotFound(format!("Session '{}' not found", id)))
+    }
+
+    /// Append messages to an existing session.
+    pub fn append(&mut self, id: &str, messages: Vec<SessionMessage>) -> Result<(), StoreError> {
+        self.summary(id)?;
+        let now = chrono::Utc::now().timestamp_millis();
+        let records: Vec<SessionRecord> = messages
+            .into_iter()
+            .map(|mut m| {
+                if m.timestamp == 0 {
+                    m.timestamp = now;
+                }
+                SessionRecord::Message(m)
+            })
+            .collect();
+        self.append_records(id, &records).map_err(StoreError::Failed)?;
+
+        if let Some(summary) = self.index().get_mut(id) {
+            for record in &records {
+                if let SessionRecord::Message(m) = record {
+                    summary.updated_at = summary.updated_at.max(m.timestamp);
+                    summary.message_count += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Update session metadata (model and/or title).
+    pub fn update(&mut self, id: &str, model: Option<String>, title: Option<String>) -> Result<(), StoreError> {
+        self.summary(id)?;
+        let updated_at = chrono::Utc::now().timestamp_millis();
+        self.append_records(
+            id,
+            &[SessionRecord::Updated {
+                model: model.clone(),
+                title: title.clone(),
+                updated_at,
+            }],
+        )
+        .map_err(StoreError::Failed)?;
+
+        if let Some(summary) = self.index().get_mut(id) {
+            if model.is_some() {
+                summary.model = model;
+            }
+            if title.is_some() {
+                summary.title = title;
+            }
+            summary.updated_at = summary.updated_at.max(updated_at);
+        }
+        Ok(())
+    }
+
+    /// Load a session by replaying its records.
+    pub fn get(&self, id: &str) -> Result<Session, StoreError> {
+        let path = self.session_path(id).map_err(StoreError::NotFound)?;
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(StoreError::NotFound(format!("Session '{}' not found", id)));
+            }
+            Err(e) => return Err(StoreError::Failed(format!("Failed to read session '{}': {}", id, e))),
+        };
+
+        let mut session: Option<Session> = None;
+        for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+            let record: SessionRecord = match serde_json::from_str(line) {
+                Ok(r) => r,
+                Err(e) => {
+                    // A torn final line from an interrupted write is skipped
+                    tracing::warn!("Skipping unreadable record in session {}: {}", id, e);
+                    continue;
+                }
+            };
+            match record {
+                SessionRecord::Created { id, owner, origin, model, title, system_prompt, created_at } => {
+                    session = Some(Session {
+                        id,
+                        owner,
+                        origin,
+                        model,
+                        title,
+                        system_prompt,
+                        created_at,
+                        updated_at: created_at,
+                        messages: Vec::new(),
+                    });
+                }
+                SessionRecord::Message(message) => {
+                    if let Some(s) = session.as_mut() {
+                        s.updated_at = s.updated_at.max(message.timestamp);
+                        s.messages.push(message);
+                    }
+                }
+                SessionRecord::Updated { model, title, updated_at } => {
+                    if let Some(s) = session.as_mut() {
+                        if model.is_some() {
+                            s.model = model;
+                        }
+                        if title.is_some() {
+                            s.title = title;
+                        }
+                        s.updated_at = s.updated_at.max(updated_at);
+                    }
+                }
+            }
+        }
+
+        session.ok_or_else(|| StoreError::Failed(format!("Session '{}' is corrupt", id)))
+    }
+
+    /// Summaries of all sessions, most recently updated first.
+    pub fn list(&mut self) -> Vec<SessionSummary> {
+        let mut sessions: Vec<SessionSummary> = self.index().values().cloned().collect();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.updated_at));
+        sessions
+    }
+
+    /// Delete a session. Returns false if it did not exist.
+    pub fn delete(&mut self, id: &str) -> Result<bool, String> {
+        let path = self.session_path(id)?;
+        self.index().remove(id);
+        if !path.exists() {
+            return Ok(false);
+        }
+        fs::remove_file(&path).map_err(|e| format!("Failed to delete session: {}", e))?;
+        Ok(true)
+    }
+}
+
+/// Generate a random session ID.
+fn generate_session_id() -> String {
+    use rand::Rng;
+    let bytes: [u8; 8] = rand::thread_rng().gen();
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sess-{}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store() -> SessionStore {
+        let dir = std::env::temp_dir().join(format!("harbor-sessions-test-{}", generate_session_id()));
+        SessionStore::new(dir)
+    }
+
+    fn message(role: &str, content: &str) -> SessionMessage {
+        SessionMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+            tool_call_id: None,
+            tool_calls: None,
+            model: None,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_create_append_get() {
+        let mut store = temp_store();
+        let session = store
+            .create(None, Some("https://example.com".to_string()), Some("mock:mock-model".to_string()), None, None)
+            .unwrap();
+
+        store.append(&session.id, vec![message("user", "hi"), message("assistant", "hello")]).unwrap();
+        store.update(&session.id, None, Some("Greeting".to_string())).unwrap();
+
+        let loaded = store.get(&session.id).unwrap();
+        assert_eq!(loaded.messages.len(), 2);
+        assert_eq!(loaded.messages[1].content, "hello");
+        assert_eq!(loaded.title.as_deref(), Some("Greeting"));
+        assert_eq!(loaded.model.as_deref(), Some("mock:mock-model"));
+        assert_eq!(store.list().len(), 1);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(store.session_path(&session.id).unwrap()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        assert!(store.delete(&session.id).unwrap());
+        assert!(matches!(store.get(&session.id), Err(StoreError::NotFound(_))));
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn test_index() {
+        let mut store = temp_store();
+        let owner = Some("origin https://example.com".to_string());
+        let session = store.create(owner.clone(), None, None, Some("First".to_string()), None).unwrap();
+        store.append(&session.id, vec![message("user", "hi")]).unwrap();
+        store.update(&session.id, Some("mock:mock-model".to_string()), None).unwrap();
+
+        // The index kept up with the writes, and agrees with a fresh scan
+        let indexed = store.summary(&session.id).unwrap();
+        assert_eq!(indexed.message_count, 1);
+        assert_eq!(indexed.owner, owner);
+        assert_eq!(indexed.model.as_deref(), Some("mock:mock-model"));
+        let mut reopened = SessionStore::new(store.dir.clone());
+        let scanned = reopened.summary(&session.id).unwrap();
+        assert_eq!(serde_json::to_value(&scanned).unwrap(), serde_json::to_value(&indexed).unwrap());
+        assert_eq!(scanned.owner, owner);
+
+        assert!(store.delete(&session.id).unwrap());
+        assert!(store.list().is_empty());
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn test_read_failure_is_not_not_found() {
+        let store = temp_store();
+        // A directory in place of the session file can't be read
+        fs::create_dir_all(store.dir.join("sess-broken.jsonl")).unwrap();
+        assert!(matches!(store.get("sess-broken"), Err(StoreError::Failed(_))));
+        assert!(matches!(store.get("sess-missing"), Err(StoreError::NotFound(_))));
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn test_rejects_path_ids() {
+        let mut store = temp_store();
+        assert!(store.get("../etc/passwd").is_err());
+        assert!(store.append("a/b", vec![message("user", "x")]).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+#!/usr/bin/env node
+/**
+ * Mock LLM Test
+ *
+ * Tests llm.chat and llm.chat_stream end to end against the bridge's mock
+ * provider, scripted by fixtures/mock-llm.json. Needs no model or network:
+ * 1. Plain chat returns the scripted content
+ * 2. A matching prompt returns the scripted tool call
+ * 3. A matching prompt returns the injected error
+ * 4. Streaming sends the scripted tokens, then done
+ * 5. Streaming sends the scripted tool call as a tool_call event
+ *
+ * The bridge runs with HOME set to a temporary directory so it doesn't read
+ * or write the user's ~/.harbor.
+ *
+ * Usage:
+ *   node test-mock-llm.mjs
+ */
+
+import { spawn } from 'child_process';
+import fs from 'fs';
+import path from 'path';
+import { fileURLToPath } from 'url';
+import os from 'os';
+
+const __dirname = path.dirname(fileURLToPath(import.meta.url));
+
+const FIXTURE = path.resolve(__dirname, 'fixtures/mock-llm.json');
+const MODEL = 'mock:mock-model';
+
+function findBridgeBinary() {
+  const possiblePaths = [
+    path.resolve(__dirname, '../../bridge-rs/target/release/harbor-bridge'),
+    path.resolve(__dirname, '../../bridge-rs/target/debug/harbor-bridge'),
+  ];
+  for (const p of possiblePaths) {
+    if (fs.existsSync(p)) return p;
+  }
+  return null;
+}
+
+function encodeMessage(message) {
+  const json = JSON.stringify(message);
+  const jsonBuffer = Buffer.from(json, 'utf-8');
+  const lengthBuffer = Buffer.alloc(4);
+  lengthBuffer.writeUInt32LE(jsonBuffer.length, 0);
+  return Buffer.concat([lengthBuffer, jsonBuffer]);
+}
+
+function decodeMessage(buffer) {
+  if (buffer.length < 4) return null;
+  const length = buffer.readUInt32LE(0);
+  if (buffer.length < 4 + length) return null;
+  const json = buffer.slice(4, 4 + length).toString('utf-8');
+  return { message: JSON.parse(json), bytesConsumed: 4 + length };
+}
+
+// One bridge process shared by all tests, with replies routed by request id
+function startBridge(binaryPath, home) {
+  const bridge = spawn(binaryPath, ['--native-messaging'], {
+    stdio: ['pipe', 'pipe', 'pipe'],
+    env: { ...process.env, HOME: home, HARBOR_LLM_MOCK_FIXTURE: FIXTURE },
+  });
+  const handlers = new Map();
+  let outputBuffer = Buffer.alloc(0);
+  let nextId = 1;
+
+  bridge.stdout.on('data', (data) => {
+    outputBuffer = Buffer.concat([outputBuffer, data]);
+    while (outputBuffer.length >= 4) {
+      const result = decodeMessage(outputBuffer);
+      if (!result) break;
+      outputBuffer = outputBuffer.slice(result.bytesConsumed);
+      const handler = handlers.get(result.message.id);
+      if (handler) handler(result.message);
+    }
+  });
+  bridge.stderr.on('data', () => {});
+
+  // Send an RPC and collect replies until `done` returns true
+  function send(method, params, done, timeoutMs = 10000) {
+    const id = `mock-${nextId++}`;
+    return new Promise((resolve, reject) => {
+      const messages = [];
+      const timeout = setTimeout(() => {
+        handlers.delete(id);
+        reject(new Error(`Timeout waiting for ${method}`));
+      }, timeoutMs);
+      handlers.set(id, (msg) => {
+        messages.push(msg);
+        if (done(msg)) {
+          clearTimeout(timeout);
+          handlers.delete(id);
+          resolve(messages);
+        }
+      });
+      bridge.stdin.write(encodeMessage({ type: 'rpc', id, method, params }));
+    });
+  }
+
+  return {
+    rpc: async (method, params) =>
+      (await send(method, params, (msg) => msg.type === 'rpc_response'))[0],
+    stream: async (method, params) =>
+      (await send(method, params, (msg) =>
+        msg.type === 'rpc_response' || ['done', 'error'].includes(msg.event?.type)
+      )).map((msg) => msg.event ?? msg),
+    stop: () => bridge.kill(),
+  };
+}
+
+function chatParams(prompt) {
+  return { model: MODEL, messages: [{ role: 'user', content: prompt }] };
+}
+
+function assertEqual(actual, expected, what) {
+  if (actual !== expected) {
+    throw new Error(`${what}: expected ${JSON.stringify(expected)}, got ${JSON.stringify(actual)}`);
+  }
+}
+
+async function runTest(name, fn) {
+  process.stdout.write(`  ${name}... `);
+  try {
+    await fn();
+    console.log('✓');
+    return true;
+  } catch (err) {
+    console.log('✗');
+    console.log(`    Error: ${err.message}`);
+    return false;
+  }
+}
+
+async function main() {
+  console.log('🚢 Harbor Mock LLM Tests\n');
+
+  const binaryPath = findBridgeBinary();
+  if (!binaryPath) {
+    console.log('❌ Binary not found. Run: cd bridge-rs && cargo build --release');
+    process.exit(1);
+  }
+
+  const home = fs.mkdtempSync(path.join(os.tmpdir(), 'harbor-mock-llm-'));
+  const bridge = startBridge(binaryPath, home);
+  // Give the bridge a moment to initialize
+  await new Promise((resolve) => setTimeout(resolve, 300));
+
+  const results = [];
+
+  results.push(await runTest('llm.chat returns scripted content', async () => {
+    const msg = await bridge.rpc('llm.chat', chatParams('Hi there'));
+    if (msg.error) throw new Error(`RPC error: ${msg.error.message}`);
+    const choice = msg.result.choices[0];
+    assertEqual(choice.message.content, 'Hello from the Harbor mock provider!', 'content');
+    assertEqual(choice.finish_reason, 'stop', 'finish_reason');
+  }));
+
+  results.push(await runTest('llm.chat returns scripted tool calls', async () => {
+    const msg = await bridge.rpc('llm.chat', chatParams('What time is it?'));
+    if (msg.error) throw new Error(`RPC error: ${msg.error.message}`);
+    const choice = msg.result.choices[0];
+    assertEqual(choice.finish_reason, 'tool_calls', 'finish_reason');
+    assertEqual(choice.message.tool_calls?.[0]?.id, 'call_time', 'tool call id');
+    assertEqual(choice.message.tool_calls?.[0]?.function?.name, 'time__get_current_time', 'tool call name');
+  }));
+
+  results.push(await runTest('llm.chat returns injected errors', async () => {
+    const msg = await bridge.rpc('llm.chat', chatParams('Please trigger error'));
+    assertEqual(msg.error?.code, -32001, 'error code');
+    assertEqual(msg.error?.message, 'Injected mock failure', 'error message');
+  }));
+
+  results.push(await runTest('llm.chat_stream streams scripted tokens', async () => {
+    const events = await bridge.stream('llm.chat_stream', chatParams('Hello again'));
+    const last = events[events.length - 1];
+    assertEqual(last.type, 'done', 'last event');
+    const tokens = events.filter((e) => e.type === 'token').map((e) => e.token);
+    assertEqual(tokens.join(''), 'Hello from the Harbor mock provider!', 'streamed text');
+    assertEqual(tokens.length, 6, 'token count');
+  }));
+
+  results.push(await runTest('llm.chat_stream streams scripted tool calls', async () => {
+    const events = await bridge.stream('llm.chat_stream', chatParams('what time is it'));
+    const toolCalls = events.filter((e) => e.type === 'tool_call');
+    assertEqual(toolCalls.length, 1, 'tool_call events');
+    assertEqual(toolCalls[0].tool_calls?.[0]?.function?.name, 'time__get_current_time', 'tool call name');
+    assertEqual(events[events.length - 1].type, 'done', 'last event');
+  }));
+
+  bridge.stop();
+  fs.rmSync(home, { recursive: true, force: true });
+
+  const passed = results.filter(Boolean).length;
+  const failed = results.length - passed;
+  console.log(`\n${passed} passed, ${failed} failed`);
+  process.exit(failed > 0 ? 1 : 0);
+}
+
+main();